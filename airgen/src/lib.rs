@@ -166,6 +166,7 @@ impl<'a, T: FieldElement> ASMPILConverter<'a, T> {
             degree,
             pil: self.pil,
             links,
+            instruction_flags: input.instruction_flags,
         }
     }
 
@@ -237,6 +237,7 @@ impl<T: FieldElement> TypeChecker<T> {
             pil,
             callable,
             submachines,
+            instruction_flags: vec![],
         };
 
         if !errors.is_empty() {
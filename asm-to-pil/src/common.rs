@@ -7,6 +7,16 @@ use crate::utils::parse_instruction;
 pub const RETURN_NAME: &str = "return";
 /// The name for the `reset` instruction in the PIL constraints
 pub const RESET_NAME: &str = "_reset";
+/// The name of an optional boolean column with which a VM can override its PC update: In rows
+/// in which it is 1, the PC of the next row is [PC_OVERRIDE_TARGET_NAME] instead of the value
+/// set by the instructions of the current row.
+pub const PC_OVERRIDE_NAME: &str = "_pc_override";
+/// The name of the PC that is jumped to if [PC_OVERRIDE_NAME] is set. Has to be declared
+/// by the VM if it declares [PC_OVERRIDE_NAME].
+pub const PC_OVERRIDE_TARGET_NAME: &str = "_pc_override_target";
+/// The name of an optional witness column that is constrained to the PC that would have been
+/// next in rows in which [PC_OVERRIDE_NAME] is set, e.g. to check it against a known value.
+pub const PC_OVERRIDDEN_NAME: &str = "_pc_overridden";
 
 pub fn instruction_flag(name: &str) -> String {
     format!("instr_{name}")
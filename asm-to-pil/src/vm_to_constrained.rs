@@ -25,7 +25,10 @@ use powdr_ast::{
 
 use powdr_number::FieldElement;
 
-use crate::common::{instruction_flag, return_instruction, RETURN_NAME};
+use crate::common::{
+    instruction_flag, return_instruction, PC_OVERRIDDEN_NAME, PC_OVERRIDE_NAME,
+    PC_OVERRIDE_TARGET_NAME, RETURN_NAME,
+};
 
 pub fn convert_machine<T: FieldElement>(machine: Machine<T>, rom: Option<Rom<T>>) -> Machine<T> {
     let output_count = machine
@@ -104,6 +107,16 @@ impl<T: FieldElement> ASMPILConverter<T> {
             self.create_constraints_for_assignment_reg(reg);
         }
 
+        let declares = |name: &str| {
+            input
+                .pil
+                .iter()
+                .flat_map(|s| s.symbol_definition_names())
+                .any(|n| n == name)
+        };
+        let has_pc_override = declares(PC_OVERRIDE_NAME);
+        let has_pc_overridden = declares(PC_OVERRIDDEN_NAME);
+
         // introduce `first_step` which is used for register updates
         self.pil.push(PilStatement::PolynomialConstantDefinition(
             SourceRef::unknown(),
@@ -127,22 +140,47 @@ impl<T: FieldElement> ASMPILConverter<T> {
                                 // this may not be optimal for backends which support higher degree constraints
                                 let pc_update_name = format!("{}_update", name);
 
-                                vec![
-                                    PilStatement::PolynomialDefinition(
-                                        SourceRef::unknown(),
-                                        pc_update_name.to_string(),
-                                        rhs,
-                                    ),
-                                    PilStatement::Expression(
+                                let mut statements = vec![PilStatement::PolynomialDefinition(
+                                    SourceRef::unknown(),
+                                    pc_update_name.to_string(),
+                                    rhs,
+                                )];
+                                // if the machine can override the pc update, do it in another intermediate polynomial
+                                let pc_next_name = if has_pc_override {
+                                    let pc_next_name = format!("{}_next", name);
+                                    statements.push(PilStatement::PolynomialDefinition(
                                         SourceRef::unknown(),
-                                        build::identity(
-                                            lhs,
-                                            (Expression::from(T::one())
-                                                - next_reference("first_step"))
-                                                * direct_reference(pc_update_name),
-                                        ),
+                                        pc_next_name.clone(),
+                                        (Expression::from(T::one())
+                                            - direct_reference(PC_OVERRIDE_NAME))
+                                            * direct_reference(&pc_update_name)
+                                            + direct_reference(PC_OVERRIDE_NAME)
+                                                * direct_reference(PC_OVERRIDE_TARGET_NAME),
+                                    ));
+                                    if has_pc_overridden {
+                                        statements.push(PilStatement::Expression(
+                                            SourceRef::unknown(),
+                                            build::identity(
+                                                direct_reference(PC_OVERRIDE_NAME)
+                                                    * (direct_reference(PC_OVERRIDDEN_NAME)
+                                                        - direct_reference(&pc_update_name)),
+                                                T::zero().into(),
+                                            ),
+                                        ));
+                                    }
+                                    pc_next_name
+                                } else {
+                                    pc_update_name
+                                };
+                                statements.push(PilStatement::Expression(
+                                    SourceRef::unknown(),
+                                    build::identity(
+                                        lhs,
+                                        (Expression::from(T::one()) - next_reference("first_step"))
+                                            * direct_reference(pc_next_name),
                                     ),
-                                ]
+                                ));
+                                statements
                             }
                             // Unconstrain read-only registers when calling `_reset`
                             ReadOnly => {
@@ -318,6 +356,7 @@ impl<T: FieldElement> ASMPILConverter<T> {
         let instruction_name = s.name.clone();
         let instruction_flag = format!("instr_{instruction_name}");
         self.create_witness_fixed_pair(s.source.clone(), &instruction_flag);
+        input.instruction_flags.push(instruction_flag.clone());
 
         let params = s.instruction.params;
 
@@ -1367,4 +1406,37 @@ machine Main {
 ";
         parse_analyse_and_compile::<GoldilocksField>(asm);
     }
+
+    #[test]
+    fn pc_override() {
+        let asm = r"
+machine Main {
+  degree 8;
+  reg pc[@pc];
+  reg X[<=];
+  reg A;
+
+  col witness jump;
+  col witness _pc_overridden;
+  pol _pc_override = jump;
+  pol _pc_override_target = 2;
+
+  function main {
+    A <=X= 1;
+  }
+}
+";
+        let compiled = parse_analyse_and_compile::<GoldilocksField>(asm).to_string();
+        let pc_next =
+            "pol pc_next = (((1 - _pc_override) * pc_update) + (_pc_override * _pc_override_target));";
+        assert!(compiled.contains(pc_next), "{compiled}");
+        assert!(
+            compiled.contains("(pc' = ((1 - first_step') * pc_next));"),
+            "{compiled}"
+        );
+        assert!(
+            compiled.contains("((_pc_override * (_pc_overridden - pc_update)) = 0);"),
+            "{compiled}"
+        );
+    }
 }
@@ -44,6 +44,9 @@ impl<T: Display> Display for Analyzed<T> {
                         match symbol.kind {
                             SymbolKind::Poly(poly_type) => {
                                 let kind = match &poly_type {
+                                    PolynomialType::Committed if symbol.derived => {
+                                        "witness derived "
+                                    }
                                     PolynomialType::Committed => "witness ",
                                     PolynomialType::Constant => "fixed ",
                                     PolynomialType::Intermediate => panic!(),
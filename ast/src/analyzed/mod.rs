@@ -252,10 +252,52 @@ impl<T> Analyzed<T> {
         self.identities
             .push(Identity::from_polynomial_identity(id, source, identity));
         self.source_order
-            .push(StatementIdentifier::Identity(id as usize));
+            .push(StatementIdentifier::Identity(self.identities.len() - 1));
         id
     }
 
+    /// Adds a derived witness column `w` at the end of the source order, together with
+    /// its defining identity `w = definition`, and returns a reference to the column.
+    pub fn append_derived_column(
+        &mut self,
+        absolute_name: String,
+        definition: AlgebraicExpression<T>,
+        source: SourceRef,
+    ) -> AlgebraicReference {
+        assert!(!self.definitions.contains_key(&absolute_name));
+        let symbol = Symbol {
+            id: self.commitment_count() as u64,
+            source: source.clone(),
+            absolute_name: absolute_name.clone(),
+            kind: SymbolKind::Poly(PolynomialType::Committed),
+            length: None,
+            derived: true,
+        };
+        let reference = AlgebraicReference {
+            name: absolute_name.clone(),
+            poly_id: (&symbol).into(),
+            next: false,
+        };
+        self.definitions
+            .insert(absolute_name.clone(), (symbol, None));
+        self.source_order
+            .push(StatementIdentifier::Definition(absolute_name));
+        self.append_polynomial_identity(
+            AlgebraicExpression::Reference(reference.clone()) - definition,
+            source,
+        );
+        reference
+    }
+
+    /// Returns the IDs of the derived witness columns, see [Symbol::derived].
+    pub fn derived_columns(&self) -> BTreeSet<PolyID> {
+        self.definitions
+            .values()
+            .filter(|(symbol, _)| symbol.derived)
+            .map(|(symbol, _)| symbol.into())
+            .collect()
+    }
+
     /// Remove some identities by their index (not their ID).
     /// Does not re-allocate IDs.
     pub fn remove_identities(&mut self, to_remove: &BTreeSet<usize>) {
@@ -425,6 +467,10 @@ pub struct Symbol {
     pub absolute_name: String,
     pub kind: SymbolKind,
     pub length: Option<DegreeType>,
+    /// Whether this is a witness column introduced by the optimizer whose values are
+    /// given by a single defining identity `w = e`, see [Analyzed::append_derived_column].
+    #[serde(default)]
+    pub derived: bool,
 }
 
 impl Symbol {
@@ -12,6 +12,7 @@ use std::{
 use itertools::Either;
 use num_bigint::BigUint;
 use powdr_number::FieldElement;
+use serde::{Deserialize, Serialize};
 
 use crate::parsed::{
     asm::{
@@ -24,14 +25,14 @@ use crate::SourceRef;
 
 pub use crate::parsed::Expression;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RegisterDeclarationStatement {
     pub source: SourceRef,
     pub name: String,
     pub ty: RegisterTy,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum RegisterTy {
     Pc,
     Assignment,
@@ -57,20 +58,20 @@ impl RegisterTy {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InstructionDefinitionStatement<T> {
     pub source: SourceRef,
     pub name: String,
     pub instruction: Instruction<T>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Instruction<T> {
     pub params: Params<T>,
     pub body: InstructionBody<T>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LinkDefinitionStatement<T> {
     pub source: SourceRef,
     /// the flag which activates this link. Should be boolean.
@@ -81,7 +82,7 @@ pub struct LinkDefinitionStatement<T> {
     pub to: CallableRef<T>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct FunctionStatements<T> {
     inner: Vec<FunctionStatement<T>>,
     batches: Option<Vec<BatchMetadata>>,
@@ -217,7 +218,7 @@ impl<T> FromIterator<Batch<T>> for FunctionStatements<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionBody<T> {
     pub statements: FunctionStatements<T>,
 }
@@ -246,7 +247,7 @@ pub struct CallableSymbolDefinition<T> {
     pub symbol: CallableSymbol<T>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CallableSymbolDefinitions<T>(pub BTreeMap<String, CallableSymbol<T>>);
 
 impl<T> IntoIterator for CallableSymbolDefinitions<T> {
@@ -425,7 +426,7 @@ pub struct FunctionDefinitionMut<'a, T> {
     pub function: &'a mut FunctionSymbol<T>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CallableSymbol<T> {
     Function(FunctionSymbol<T>),
     Operation(OperationSymbol<T>),
@@ -509,7 +510,7 @@ impl<'a, T> TryFrom<&'a mut CallableSymbol<T>> for &'a mut OperationSymbol<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionSymbol<T> {
     pub source: SourceRef,
     /// the parameters of this function, in the form of values
@@ -518,7 +519,7 @@ pub struct FunctionSymbol<T> {
     pub body: FunctionBody<T>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OperationSymbol<T> {
     pub source: SourceRef,
     /// the id of this operation. This machine's operation id must be set to this value in order for this operation to be active.
@@ -527,12 +528,12 @@ pub struct OperationSymbol<T> {
     pub params: Params<T>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DegreeStatement {
     pub degree: BigUint,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum FunctionStatement<T> {
     Assignment(AssignmentStatement<T>),
     Instruction(InstructionStatement<T>),
@@ -617,7 +618,7 @@ impl<T> From<Return<T>> for FunctionStatement<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AssignmentStatement<T> {
     pub source: SourceRef,
     pub lhs_with_reg: Vec<(String, AssignmentRegister)>,
@@ -634,32 +635,32 @@ impl<T> AssignmentStatement<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InstructionStatement<T> {
     pub source: SourceRef,
     pub instruction: String,
     pub inputs: Vec<Expression<T>>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LabelStatement {
     pub source: SourceRef,
     pub name: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DebugDirective {
     pub source: SourceRef,
     pub directive: crate::parsed::asm::DebugDirective,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Return<T> {
     pub source: SourceRef,
     pub values: Vec<Expression<T>>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SubmachineDeclaration {
     /// the name of this instance
     pub name: String,
@@ -669,7 +670,7 @@ pub struct SubmachineDeclaration {
 
 /// An item that is part of the module tree after all modules,
 /// imports and references have been resolved.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Item<T> {
     Machine(Machine<T>),
     Expression(ExpressionWithTypeName<T>),
@@ -684,7 +685,7 @@ impl<T> Item<T> {
     }
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Machine<T> {
     /// The degree if any, i.e. the number of rows in instances of this machine type
     pub degree: Option<DegreeStatement>,
@@ -706,6 +707,8 @@ pub struct Machine<T> {
     pub callable: CallableSymbolDefinitions<T>,
     /// The set of submachines
     pub submachines: Vec<SubmachineDeclaration>,
+    /// The names of the instruction flag columns, set when compiling a virtual machine to constraints
+    pub instruction_flags: Vec<String>,
 }
 
 impl<T> Machine<T> {
@@ -784,12 +787,12 @@ impl<T> Machine<T> {
     }
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Rom<T> {
     pub statements: FunctionStatements<T>,
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct AnalysisASMFile<T> {
     pub items: BTreeMap<AbsoluteSymbolPath, Item<T>>,
 }
@@ -809,7 +812,7 @@ impl<T> AnalysisASMFile<T> {
     }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct BatchMetadata {
     // the set of compatible statements
     pub size: usize,
@@ -823,13 +826,13 @@ impl BatchMetadata {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum Incompatible {
     Label,
     Unimplemented,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone, Serialize, Deserialize)]
 pub struct IncompatibleSet(pub BTreeSet<Incompatible>);
 
 impl From<Incompatible> for IncompatibleSet {
@@ -1,5 +1,7 @@
 use std::collections::BTreeMap;
 
+use serde::{Deserialize, Serialize};
+
 use crate::parsed::{
     asm::{AbsoluteSymbolPath, Params},
     Expression, ExpressionWithTypeName, PilStatement,
@@ -7,7 +9,7 @@ use crate::parsed::{
 
 mod display;
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct Location {
     limbs: Vec<String>,
 }
@@ -25,7 +27,7 @@ impl Location {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PILGraph<T> {
     pub main: Machine,
     pub entry_points: Vec<Operation<T>>,
@@ -33,13 +35,15 @@ pub struct PILGraph<T> {
     pub definitions: BTreeMap<AbsoluteSymbolPath, ExpressionWithTypeName<T>>,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Object<T> {
     pub degree: Option<u64>,
     /// the pil identities for this machine
     pub pil: Vec<PilStatement<T>>,
     /// the links from this machine to its children
     pub links: Vec<Link<T>>,
+    /// the names of the instruction flag columns, if this machine was compiled from a virtual machine
+    pub instruction_flags: Vec<String>,
 }
 
 impl<T> Object<T> {
@@ -49,7 +53,7 @@ impl<T> Object<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 /// A link between two machines
 pub struct Link<T> {
     /// the link source, i.e. a flag and some arguments
@@ -58,13 +62,13 @@ pub struct Link<T> {
     pub to: LinkTo<T>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LinkFrom<T> {
     pub flag: Expression<T>,
     pub params: Params<T>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LinkTo<T> {
     /// the machine we link to
     pub machine: Machine,
@@ -72,7 +76,7 @@ pub struct LinkTo<T> {
     pub operation: Operation<T>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Machine {
     /// the location of this instance
     pub location: Location,
@@ -82,7 +86,7 @@ pub struct Machine {
     pub operation_id: Option<String>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Operation<T> {
     /// the name of the operation
     pub name: String,
@@ -8,17 +8,18 @@ use itertools::Itertools;
 use powdr_number::AbstractNumberType;
 
 use derive_more::From;
+use serde::{Deserialize, Serialize};
 
 use crate::SourceRef;
 
 use super::{Expression, ExpressionWithTypeName, PilStatement};
 
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ASMProgram<T> {
     pub main: ASMModule<T>,
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ASMModule<T> {
     pub statements: Vec<ModuleStatement<T>>,
 }
@@ -31,18 +32,18 @@ impl<T> ASMModule<T> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[derive(Debug, Clone, PartialEq, Eq, From, Serialize, Deserialize)]
 pub enum ModuleStatement<T> {
     SymbolDefinition(SymbolDefinition<T>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SymbolDefinition<T> {
     pub name: String,
     pub value: SymbolValue<T>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[derive(Debug, Clone, PartialEq, Eq, From, Serialize, Deserialize)]
 pub enum SymbolValue<T> {
     /// A machine definition
     Machine(Machine<T>),
@@ -77,7 +78,7 @@ pub enum SymbolValueRef<'a, T> {
     Expression(&'a ExpressionWithTypeName<T>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[derive(Debug, Clone, PartialEq, Eq, From, Serialize, Deserialize)]
 pub enum Module<T> {
     External(String),
     Local(ASMModule<T>),
@@ -98,7 +99,7 @@ pub enum ModuleRef<'a, T> {
     Local(&'a ASMModule<T>),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Import {
     /// the path imported in the source
     pub path: SymbolPath,
@@ -107,7 +108,7 @@ pub struct Import {
 /// A symbol path is a sequence of strings separated by ``::`.
 /// It can contain the special word `super`, which goes up a level.
 /// If it does not start with `::`, it is relative.
-#[derive(Default, Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct SymbolPath {
     /// The parts between each `::`.
     parts: Vec<Part>,
@@ -212,7 +213,7 @@ impl Display for SymbolPath {
 /// An absolute symbol path is a resolved SymbolPath,
 /// which means it has to start with `::` and it cannot contain
 /// the word `super`.
-#[derive(Default, Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct AbsoluteSymbolPath {
     /// Contains the parts after the initial `::`.
     parts: Vec<String>,
@@ -341,7 +342,7 @@ impl Display for AbsoluteSymbolPath {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Part {
     Super,
     Named(String),
@@ -368,7 +369,7 @@ impl Display for Part {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Machine<T> {
     pub arguments: MachineArguments,
     pub statements: Vec<MachineStatement<T>>,
@@ -390,13 +391,13 @@ impl<T: Clone> Machine<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone, Serialize, Deserialize)]
 pub struct MachineArguments {
     pub latch: Option<String>,
     pub operation_id: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default, Serialize, Deserialize)]
 pub struct Params<T> {
     pub inputs: Vec<Param<T>>,
     pub outputs: Vec<Param<T>>,
@@ -430,19 +431,19 @@ impl<T: Display> Params<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 /// the operation id necessary to call this function from the outside
 pub struct OperationId<T> {
     pub id: Option<T>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Instruction<T> {
     pub params: Params<T>,
     pub body: InstructionBody<T>,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum MachineStatement<T> {
     Degree(SourceRef, AbstractNumberType),
     Pil(SourceRef, PilStatement<T>),
@@ -454,27 +455,27 @@ pub enum MachineStatement<T> {
     OperationDeclaration(SourceRef, String, OperationId<T>, Params<T>),
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct LinkDeclaration<T> {
     pub flag: Expression<T>,
     pub params: Params<T>,
     pub to: CallableRef<T>,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct CallableRef<T> {
     pub instance: String,
     pub callable: String,
     pub params: Params<T>,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum InstructionBody<T> {
     Local(Vec<PilStatement<T>>),
     CallableRef(CallableRef<T>),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum AssignmentRegister {
     Register(String),
     Wildcard,
@@ -489,7 +490,7 @@ impl AssignmentRegister {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum FunctionStatement<T> {
     Assignment(
         SourceRef,
@@ -503,21 +504,21 @@ pub enum FunctionStatement<T> {
     Return(SourceRef, Vec<Expression<T>>),
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum DebugDirective {
     File(usize, String, String),
     Loc(usize, usize, usize),
     OriginalInstruction(String),
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum RegisterFlag {
     IsPC,
     IsAssignment,
     IsReadOnly,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct Param<T> {
     pub name: String,
     pub index: Option<T>,
@@ -407,6 +407,9 @@ impl<T: Display> Display for PilStatement<T> {
                     value.as_ref().map(|v| format!("{v}")).unwrap_or_default()
                 )
             }
+            PilStatement::PolynomialDerivedDeclaration(_, names) => {
+                write!(f, "    pol commit derived {};", names.iter().format(", "))
+            }
             PilStatement::PlookupIdentity(_, left, right) => write!(f, "    {left} in {right};"),
             PilStatement::PermutationIdentity(_, left, right) => {
                 write!(f, "    {left} is {right};")
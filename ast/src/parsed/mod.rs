@@ -17,10 +17,10 @@ use serde::{Deserialize, Serialize};
 use self::asm::{Part, SymbolPath};
 use crate::SourceRef;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct PILFile<T>(pub Vec<PilStatement<T>>);
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum PilStatement<T> {
     /// File name
     Include(SourceRef, String),
@@ -51,6 +51,9 @@ pub enum PilStatement<T> {
         Vec<PolynomialName<T>>,
         Option<FunctionDefinition<T>>,
     ),
+    /// Witness columns whose values are given by a defining identity `w = e`,
+    /// see [crate::analyzed::Symbol::derived].
+    PolynomialDerivedDeclaration(SourceRef, Vec<PolynomialName<T>>),
     PlookupIdentity(
         SourceRef,
         SelectedExpressions<Expression<T>>,
@@ -76,7 +79,8 @@ impl<T> PilStatement<T> {
             | PilStatement::PublicDeclaration(_, name, _, _, _)
             | PilStatement::LetStatement(_, name, _, _) => Box::new(once(name)),
             PilStatement::PolynomialConstantDeclaration(_, polynomials)
-            | PilStatement::PolynomialCommitDeclaration(_, polynomials, _) => {
+            | PilStatement::PolynomialCommitDeclaration(_, polynomials, _)
+            | PilStatement::PolynomialDerivedDeclaration(_, polynomials) => {
                 Box::new(polynomials.iter().map(|p| &p.name))
             }
 
@@ -114,7 +118,8 @@ impl<T> PilStatement<T> {
             | PilStatement::PolynomialCommitDeclaration(_, _, Some(fundef)) => fundef.expressions(),
             PilStatement::PolynomialCommitDeclaration(_, _, None)
             | PilStatement::Include(_, _)
-            | PilStatement::PolynomialConstantDeclaration(_, _) => Box::new(empty()),
+            | PilStatement::PolynomialConstantDeclaration(_, _)
+            | PilStatement::PolynomialDerivedDeclaration(_, _) => Box::new(empty()),
         }
     }
 
@@ -148,7 +153,8 @@ impl<T> PilStatement<T> {
             }
             PilStatement::PolynomialCommitDeclaration(_, _, None)
             | PilStatement::Include(_, _)
-            | PilStatement::PolynomialConstantDeclaration(_, _) => Box::new(empty()),
+            | PilStatement::PolynomialConstantDeclaration(_, _)
+            | PilStatement::PolynomialDerivedDeclaration(_, _) => Box::new(empty()),
         }
     }
 }
@@ -265,13 +271,13 @@ impl<T> From<NamespacedPolynomialReference> for Expression<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Default, Clone, Serialize, Deserialize)]
 pub struct PolynomialName<T> {
     pub name: String,
     pub array_size: Option<Expression<T>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Default, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 /// A polynomial with an optional namespace
 /// This is different from SymbolPath mainly due to different formatting.
 pub struct NamespacedPolynomialReference {
@@ -383,7 +389,7 @@ pub struct IfExpression<T, Ref = NamespacedPolynomialReference> {
 }
 
 /// The definition of a function (excluding its name):
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum FunctionDefinition<T> {
     /// Array expression.
     Array(ArrayExpression<T>),
@@ -411,7 +417,7 @@ impl<T> FunctionDefinition<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum ArrayExpression<T> {
     Value(Vec<Expression<T>>),
     RepeatedValue(Vec<Expression<T>>),
@@ -512,7 +518,7 @@ impl<T> ArrayExpression<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum TypeName<E> {
     /// Boolean
     Bool,
@@ -584,7 +590,7 @@ impl<E> TypeName<E> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct ArrayTypeName<E> {
     pub base: Box<TypeName<E>>,
     pub length: Option<E>,
@@ -601,7 +607,7 @@ impl<E> ArrayTypeName<E> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct TupleTypeName<E> {
     pub items: Vec<TypeName<E>>,
 }
@@ -617,7 +623,7 @@ impl<E> TupleTypeName<E> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct FunctionTypeName<E> {
     pub params: Vec<TypeName<E>>,
     pub value: Box<TypeName<E>>,
@@ -644,7 +650,7 @@ impl<E> FunctionTypeName<E> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct ExpressionWithTypeName<T, Ref = NamespacedPolynomialReference> {
     pub e: Expression<T, Ref>,
     pub type_name: Option<TypeName<Expression<T, Ref>>>,
@@ -226,7 +226,8 @@ impl<T> ExpressionVisitable<Expression<T, NamespacedPolynomialReference>> for Pi
             }
             PilStatement::PolynomialCommitDeclaration(_, _, None)
             | PilStatement::Include(_, _)
-            | PilStatement::PolynomialConstantDeclaration(_, _) => ControlFlow::Continue(()),
+            | PilStatement::PolynomialConstantDeclaration(_, _)
+            | PilStatement::PolynomialDerivedDeclaration(_, _) => ControlFlow::Continue(()),
         }
     }
 
@@ -270,7 +271,8 @@ impl<T> ExpressionVisitable<Expression<T, NamespacedPolynomialReference>> for Pi
             }
             PilStatement::PolynomialCommitDeclaration(_, _, None)
             | PilStatement::Include(_, _)
-            | PilStatement::PolynomialConstantDeclaration(_, _) => ControlFlow::Continue(()),
+            | PilStatement::PolynomialConstantDeclaration(_, _)
+            | PilStatement::PolynomialDerivedDeclaration(_, _) => ControlFlow::Continue(()),
         }
     }
 }
@@ -3,11 +3,56 @@ use std::{
     path::Path,
 };
 
-use crate::{Backend, BackendFactory, Error, Proof};
+use crate::{Backend, BackendFactory, BackendOptions, Error, Proof};
 use powdr_ast::analyzed::Analyzed;
-use powdr_halo2::{generate_setup, Halo2Prover, Params};
+use powdr_halo2::{
+    generate_setup, Halo2Prover, Params, DEFAULT_MAX_AGGREGATION_K, DEFAULT_MAX_LOOKUP_DEGREE,
+};
 use powdr_number::{DegreeType, FieldElement};
 
+/// The options of the halo2 backends.
+struct Halo2Options {
+    /// The maximum degree of lookup and shuffle expressions, including their selectors.
+    /// Expressions of a higher degree are moved into helper columns.
+    max_lookup_degree: usize,
+    /// Log2 of the maximum number of rows of the aggregation circuit. Only this many
+    /// powers of the setup are read.
+    max_aggregation_k: u32,
+}
+
+impl Halo2Options {
+    fn parse(options: &str) -> Result<Self, Error> {
+        let mut result = Halo2Options {
+            max_lookup_degree: DEFAULT_MAX_LOOKUP_DEGREE,
+            max_aggregation_k: DEFAULT_MAX_AGGREGATION_K,
+        };
+        for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                Some(("max_lookup_degree", degree)) => {
+                    result.max_lookup_degree = degree.trim().parse().map_err(|_| {
+                        Error::InvalidOptions(format!(
+                            "max_lookup_degree has to be a number, got \"{degree}\""
+                        ))
+                    })?;
+                }
+                Some(("max_aggregation_k", k)) => {
+                    result.max_aggregation_k = k.trim().parse().map_err(|_| {
+                        Error::InvalidOptions(format!(
+                            "max_aggregation_k has to be a number, got \"{k}\""
+                        ))
+                    })?;
+                }
+                _ => {
+                    return Err(Error::InvalidOptions(format!(
+                        "unknown halo2 option \"{option}\""
+                    )))
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
 pub(crate) struct Halo2ProverFactory;
 
 impl<F: FieldElement> BackendFactory<F> for Halo2ProverFactory {
@@ -18,8 +63,16 @@ impl<F: FieldElement> BackendFactory<F> for Halo2ProverFactory {
         _output_dir: Option<&'a Path>,
         setup: Option<&mut dyn io::Read>,
         verification_key: Option<&mut dyn io::Read>,
+        options: BackendOptions,
     ) -> Result<Box<dyn crate::Backend<'a, F> + 'a>, Error> {
-        let mut halo2 = Box::new(Halo2Prover::new(pil, fixed, setup)?);
+        let options = Halo2Options::parse(&options)?;
+        let mut halo2 = Box::new(Halo2Prover::new(
+            pil,
+            fixed,
+            setup,
+            options.max_lookup_degree,
+            options.max_aggregation_k,
+        )?);
         if let Some(vk) = verification_key {
             halo2.add_verification_key(vk);
         }
@@ -54,6 +107,38 @@ impl<'a, T: FieldElement> Backend<'a, T> for Halo2Prover<'a, T> {
         Ok(proof?)
     }
 
+    fn aggregate(
+        &self,
+        proofs: Vec<(Proof, Vec<T>)>,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(Proof, Vec<T>), Error> {
+        Ok(self.prove_aggr_chained(proofs, chained_publics)?)
+    }
+
+    fn export_ethereum_verifier(
+        &self,
+        num_proofs: usize,
+        chained_publics: &[(usize, usize)],
+        solidity: &mut dyn io::Write,
+        bytecode: &mut dyn io::Write,
+    ) -> Result<(), Error> {
+        let (source, code) = self.ethereum_verifier(num_proofs, chained_publics)?;
+        solidity.write_all(source.as_bytes())?;
+        bytecode.write_all(&code)?;
+        Ok(())
+    }
+
+    fn ethereum_calldata(
+        &self,
+        proof: &Proof,
+        publics: &[T],
+        verifier: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Ok(Halo2Prover::ethereum_calldata(
+            self, proof, publics, verifier,
+        )?)
+    }
+
     fn export_setup(&self, mut output: &mut dyn io::Write) -> Result<(), Error> {
         Ok(self.write_setup(&mut output)?)
     }
@@ -76,20 +161,27 @@ impl<F: FieldElement> BackendFactory<F> for Halo2MockFactory {
         _output_dir: Option<&'a Path>,
         setup: Option<&mut dyn io::Read>,
         verification_key: Option<&mut dyn io::Read>,
+        options: BackendOptions,
     ) -> Result<Box<dyn crate::Backend<'a, F> + 'a>, Error> {
+        let options = Halo2Options::parse(&options)?;
         if setup.is_some() {
             return Err(Error::NoSetupAvailable);
         }
         if verification_key.is_some() {
             return Err(Error::NoVerificationAvailable);
         }
-        Ok(Box::new(Halo2Mock { pil, fixed }))
+        Ok(Box::new(Halo2Mock {
+            pil,
+            fixed,
+            max_lookup_degree: options.max_lookup_degree,
+        }))
     }
 }
 
 pub struct Halo2Mock<'a, F: FieldElement> {
     pil: &'a Analyzed<F>,
     fixed: &'a [(String, Vec<F>)],
+    max_lookup_degree: usize,
 }
 
 impl<'a, T: FieldElement> Backend<'a, T> for Halo2Mock<'a, T> {
@@ -102,7 +194,7 @@ impl<'a, T: FieldElement> Backend<'a, T> for Halo2Mock<'a, T> {
             return Err(Error::NoAggregationAvailable);
         }
 
-        powdr_halo2::mock_prove(self.pil, self.fixed, witness);
+        powdr_halo2::mock_prove(self.pil, self.fixed, witness, self.max_lookup_degree)?;
 
         Ok(vec![])
     }
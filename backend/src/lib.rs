@@ -55,6 +55,10 @@ pub enum Error {
     NoVerificationAvailable,
     #[error("the backend does not support proof aggregation")]
     NoAggregationAvailable,
+    #[error("the backend does not support Ethereum verifiers")]
+    NoEthereumVerifierAvailable,
+    #[error("invalid backend options: {0}")]
+    InvalidOptions(String),
     #[error("internal backend error")]
     BackendError(String),
 }
@@ -67,6 +71,9 @@ impl From<String> for Error {
 
 pub type Proof = Vec<u8>;
 
+/// Backend specific options, as comma-separated `key=value` pairs.
+pub type BackendOptions = String;
+
 /*
     Bellow are the public interface traits. They are implemented in this
     module, wrapping the traits implemented by each backend.
@@ -82,6 +89,7 @@ pub trait BackendFactory<F: FieldElement> {
         output_dir: Option<&'a Path>,
         setup: Option<&mut dyn io::Read>,
         verification_key: Option<&mut dyn io::Read>,
+        options: BackendOptions,
     ) -> Result<Box<dyn Backend<'a, F> + 'a>, Error>;
 
     /// Generate a new setup.
@@ -108,6 +116,49 @@ pub trait Backend<'a, F: FieldElement> {
         Err(Error::NoVerificationAvailable)
     }
 
+    /// Aggregates several proofs of this circuit, each given together with its
+    /// public values, into a single proof.
+    ///
+    /// For each pair `(initial, final)` in `chained_publics`, the public value at
+    /// index `final` of each proof has to be equal to the public value at index
+    /// `initial` of the next proof. This is checked by the aggregated proof.
+    ///
+    /// Returns the aggregated proof and its public values.
+    fn aggregate(
+        &self,
+        _proofs: Vec<(Proof, Vec<F>)>,
+        _chained_publics: &[(usize, usize)],
+    ) -> Result<(Proof, Vec<F>), Error> {
+        Err(Error::NoAggregationAvailable)
+    }
+
+    /// Exports a verifier for the Ethereum Virtual Machine, as Solidity source and
+    /// as EVM bytecode. It verifies the proofs generated by [Backend::aggregate] from
+    /// `num_proofs` proofs and `chained_publics`. The aggregated proofs generated by
+    /// [Backend::prove] with an existing proof aggregate a single proof without
+    /// chained public values.
+    fn export_ethereum_verifier(
+        &self,
+        _num_proofs: usize,
+        _chained_publics: &[(usize, usize)],
+        _solidity: &mut dyn io::Write,
+        _bytecode: &mut dyn io::Write,
+    ) -> Result<(), Error> {
+        Err(Error::NoEthereumVerifierAvailable)
+    }
+
+    /// Encodes a proof and its public values as calldata for the verifier exported by
+    /// [Backend::export_ethereum_verifier], given as its EVM bytecode. The calldata is
+    /// checked by calling the verifier in a local EVM.
+    fn ethereum_calldata(
+        &self,
+        _proof: &Proof,
+        _publics: &[F],
+        _verifier: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(Error::NoEthereumVerifierAvailable)
+    }
+
     /// Exports the setup in a backend specific format. Can be used to create a
     /// new backend object of the same kind.
     fn export_setup(&self, _output: &mut dyn io::Write) -> Result<(), Error> {
@@ -1,7 +1,7 @@
 use std::iter::{once, repeat};
 use std::time::Instant;
 
-use crate::{pilstark, Backend, BackendFactory, Error};
+use crate::{pilstark, Backend, BackendFactory, BackendOptions, Error};
 use powdr_ast::analyzed::Analyzed;
 use powdr_number::{BigInt, DegreeType, FieldElement, GoldilocksField};
 
@@ -25,6 +25,7 @@ impl<F: FieldElement> BackendFactory<F> for EStarkFactory {
         _output_dir: Option<&std::path::Path>,
         setup: Option<&mut dyn std::io::Read>,
         verification_key: Option<&mut dyn std::io::Read>,
+        options: BackendOptions,
     ) -> Result<Box<dyn crate::Backend<'a, F> + 'a>, Error> {
         if F::modulus().to_arbitrary_integer() != GoldilocksField::modulus().to_arbitrary_integer()
         {
@@ -37,6 +38,11 @@ impl<F: FieldElement> BackendFactory<F> for EStarkFactory {
         if verification_key.is_some() {
             return Err(Error::NoVerificationAvailable);
         }
+        if !options.is_empty() {
+            return Err(Error::InvalidOptions(format!(
+                "the backend has no options, got \"{options}\""
+            )));
+        }
 
         let degree = pil.degree();
         assert!(degree > 1);
@@ -7,7 +7,7 @@ use std::{
     path::Path,
 };
 
-use crate::{Backend, BackendFactory, Error, Proof};
+use crate::{Backend, BackendFactory, BackendOptions, Error, Proof};
 use powdr_ast::analyzed::Analyzed;
 use powdr_number::FieldElement;
 
@@ -21,6 +21,7 @@ impl<F: FieldElement> BackendFactory<F> for PilStarkCliFactory {
         output_dir: Option<&'a Path>,
         setup: Option<&mut dyn std::io::Read>,
         verification_key: Option<&mut dyn std::io::Read>,
+        options: BackendOptions,
     ) -> Result<Box<dyn crate::Backend<'a, F> + 'a>, Error> {
         if setup.is_some() {
             return Err(Error::NoSetupAvailable);
@@ -28,6 +29,11 @@ impl<F: FieldElement> BackendFactory<F> for PilStarkCliFactory {
         if verification_key.is_some() {
             return Err(Error::NoVerificationAvailable);
         }
+        if !options.is_empty() {
+            return Err(Error::InvalidOptions(format!(
+                "the backend has no options, got \"{options}\""
+            )));
+        }
         Ok(Box::new(PilStarkCli {
             analyzed,
             output_dir,
@@ -2,17 +2,27 @@
 
 mod util;
 
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use env_logger::fmt::Color;
 use env_logger::{Builder, Target};
 use log::LevelFilter;
 use powdr_backend::BackendType;
 use powdr_number::{read_polys_csv_file, CsvRenderMode};
 use powdr_number::{Bn254Field, FieldElement, GoldilocksField};
+use powdr_pilopt::CseMode;
 use powdr_pipeline::util::write_or_panic;
 use powdr_pipeline::{Pipeline, Stage};
-use powdr_riscv::continuations::{rust_continuations, rust_continuations_dry_run};
-use powdr_riscv::{compile_riscv_asm, compile_rust};
+use powdr_riscv::continuations::{
+    aggregate_rust_continuations,
+    bootloader::MemoryLayout,
+    check_chunk_chaining, chunk_chained_publics,
+    manifest::{Chunk, ChunkStatus, ContinuationsManifest, ProverSettings},
+    memory_hash::MemoryHashFunction,
+    rust_continuations, rust_continuations_dry_run, rust_continuations_with_manifest,
+    saved_chunk_publics,
+};
+use powdr_riscv::{compile_riscv_asm, compile_riscv_elf, compile_rust};
+use powdr_riscv_executor::gdb::GdbStub;
 use std::io::{self, BufWriter};
 use std::path::PathBuf;
 use std::{borrow::Cow, fs, io::Write, path::Path};
@@ -29,6 +39,7 @@ fn bind_cli_args<F: FieldElement>(
     witness_values: Option<String>,
     export_csv: bool,
     csv_mode: CsvRenderModeCLI,
+    backend_options: String,
 ) -> Pipeline<F> {
     let witness_values = witness_values
         .map(|csv_path| {
@@ -48,6 +59,7 @@ fn bind_cli_args<F: FieldElement>(
         .add_external_witness_values(witness_values.clone())
         .with_witness_csv_settings(export_csv, csv_mode)
         .with_prover_inputs(inputs.clone())
+        .with_backend_options(backend_options)
 }
 
 #[derive(Clone, EnumString, EnumVariantNames, Display)]
@@ -68,6 +80,14 @@ pub enum CsvRenderModeCLI {
     Hex,
 }
 
+#[derive(Clone, Copy, EnumString, EnumVariantNames, Display)]
+pub enum CseModeCLI {
+    #[strum(serialize = "intermediate")]
+    Intermediate,
+    #[strum(serialize = "witness")]
+    Witness,
+}
+
 #[derive(Parser)]
 #[command(name = "powdr", author, version, about, long_about = None)]
 struct Cli {
@@ -78,6 +98,118 @@ struct Cli {
     command: Option<Commands>,
 }
 
+/// Arguments shared by the commands that compile and run RISC-V programs.
+#[derive(Args)]
+struct RiscvArgs {
+    /// The field to use
+    #[arg(long)]
+    #[arg(default_value_t = FieldArgument::Gl)]
+    #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+    field: FieldArgument,
+
+    /// Comma-separated list of free inputs (numbers).
+    #[arg(short, long)]
+    #[arg(default_value_t = String::new())]
+    inputs: String,
+
+    /// Directory for output files.
+    #[arg(short, long)]
+    #[arg(default_value_t = String::from("."))]
+    output_directory: String,
+
+    /// Force overwriting of files in output directory.
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    force: bool,
+
+    /// Generate a proof with a given backend.
+    #[arg(short, long)]
+    #[arg(value_parser = clap_enum_variants!(BackendType))]
+    prove_with: Option<BackendType>,
+
+    /// Backend specific options, as comma-separated `key=value` pairs,
+    /// e.g. "max_lookup_degree=4" for Halo2.
+    #[arg(long)]
+    #[arg(default_value_t = String::new())]
+    backend_options: String,
+
+    /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    export_csv: bool,
+
+    /// How to render field elements in the csv file
+    #[arg(long)]
+    #[arg(default_value_t = CsvRenderModeCLI::Hex)]
+    #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
+    csv_mode: CsvRenderModeCLI,
+
+    /// Comma-separated list of coprocessors: names of built-in coprocessors
+    /// or paths to manifests (`.json`) of user-defined coprocessors.
+    #[arg(long)]
+    coprocessors: Option<String>,
+
+    /// Just execute in the RISCV/Powdr executor
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    just_execute: bool,
+
+    /// Just execute in the RISCV/Powdr executor, controlled by a GDB client
+    /// connecting to the given address (e.g. 127.0.0.1:1234).
+    /// The PC seen by GDB is the index of the instruction batch in the powdr ROM,
+    /// not an address in the ELF file.
+    #[arg(long)]
+    gdb: Option<String>,
+
+    /// Just execute in the RISCV/Powdr executor and write a profile of the rows
+    /// and submachine calls per guest function to the output directory.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    profile: bool,
+
+    /// Compute the witness of the main machine with the RISCV/Powdr executor
+    /// instead of the generic witness generation. Not supported with continuations.
+    /// Columns declared inside the main machine (like `XInv`) and `_operation_id`
+    /// are still computed by the generic witness generation.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    executor_witness: bool,
+
+    #[command(flatten)]
+    continuations: ContinuationsArgs,
+
+    /// Number of chunks to process in parallel when running with continuations
+    #[arg(long)]
+    #[arg(default_value_t = 1)]
+    chunk_workers: usize,
+}
+
+/// Arguments shared by the commands that can run with continuations.
+#[derive(Args)]
+struct ContinuationsArgs {
+    /// Run a long execution in chunks (Experimental and not sound!)
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    continuations: bool,
+
+    /// Log2 of the size in bytes of the memory pages used by continuations
+    #[arg(long)]
+    #[arg(default_value_t = 10)]
+    page_size_log: usize,
+
+    /// Arity of the memory Merkle tree used by continuations
+    #[arg(long)]
+    #[arg(default_value_t = 2)]
+    merkle_tree_arity: usize,
+
+    /// Aggregate the proofs of all chunks into a single proof, written to
+    /// `<name>_proof_aggr.bin`. The given setup file is used to prove the chunks and to
+    /// aggregate them, so it has to be large enough for the aggregation circuit.
+    /// Needs --continuations and --prove-with.
+    #[arg(long)]
+    aggregation_params: Option<String>,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Runs compilation and witness generation for .pil and .asm files.
@@ -118,6 +250,12 @@ enum Commands {
         #[arg(value_parser = clap_enum_variants!(BackendType))]
         prove_with: Option<BackendType>,
 
+        /// Backend specific options, as comma-separated `key=value` pairs,
+        /// e.g. "max_lookup_degree=4" for Halo2.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        backend_options: String,
+
         /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
         #[arg(long)]
         #[arg(default_value_t = false)]
@@ -134,68 +272,40 @@ enum Commands {
         #[arg(default_value_t = false)]
         just_execute: bool,
 
-        /// Run a long execution in chunks (Experimental and not sound!)
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        continuations: bool,
-    },
-    /// Compiles (no-std) rust code to riscv assembly, then to powdr assembly
-    /// and finally to PIL and generates fixed and witness columns.
-    /// Needs `rustup target add riscv32imac-unknown-none-elf`.
-    Rust {
-        /// Input file (rust source file) or directory (containing a crate).
-        file: String,
+        #[command(flatten)]
+        continuations: ContinuationsArgs,
 
-        /// The field to use
+        /// Save the artifacts of all stages to the output directory and reuse
+        /// up-to-date artifacts saved by previous runs.
         #[arg(long)]
-        #[arg(default_value_t = FieldArgument::Gl)]
-        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
-        field: FieldArgument,
-
-        /// Comma-separated list of free inputs (numbers).
-        #[arg(short, long)]
-        #[arg(default_value_t = String::new())]
-        inputs: String,
-
-        /// Directory for  output files.
-        #[arg(short, long)]
-        #[arg(default_value_t = String::from("."))]
-        output_directory: String,
-
-        /// Force overwriting of files in output directory.
-        #[arg(short, long)]
         #[arg(default_value_t = false)]
-        force: bool,
+        save_artifacts: bool,
 
-        /// Generate a proof with a given backend
-        #[arg(short, long)]
-        #[arg(value_parser = clap_enum_variants!(BackendType))]
-        prove_with: Option<BackendType>,
-
-        /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
+        /// Resume from the artifact of this stage saved in the output directory
+        /// by a previous run.
         #[arg(long)]
-        #[arg(default_value_t = false)]
-        export_csv: bool,
+        #[arg(value_parser = clap_enum_variants!(Stage))]
+        from_stage: Option<Stage>,
 
-        /// How to render field elements in the csv file
+        /// Stop after this stage and save its artifact to the output directory.
         #[arg(long)]
-        #[arg(default_value_t = CsvRenderModeCLI::Hex)]
-        #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
-        csv_mode: CsvRenderModeCLI,
+        #[arg(value_parser = clap_enum_variants!(Stage))]
+        to_stage: Option<Stage>,
 
-        /// Comma-separated list of coprocessors.
+        /// Reduce the degree of all polynomial identities to at most this value
+        /// by introducing new witness columns.
         #[arg(long)]
-        coprocessors: Option<String>,
-
-        /// Just execute in the RISCV/Powdr executor
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        just_execute: bool,
+        max_degree: Option<usize>,
+    },
+    /// Compiles (no-std) rust code to riscv assembly, then to powdr assembly
+    /// and finally to PIL and generates fixed and witness columns.
+    /// Needs `rustup target add riscv32imac-unknown-none-elf`.
+    Rust {
+        /// Input file (rust source file) or directory (containing a crate).
+        file: String,
 
-        /// Run a long execution in chunks (Experimental and not sound!)
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        continuations: bool,
+        #[command(flatten)]
+        args: RiscvArgs,
     },
 
     /// Compiles riscv assembly to powdr assembly and then to PIL
@@ -205,56 +315,18 @@ enum Commands {
         #[arg(required = true)]
         files: Vec<String>,
 
-        /// The field to use
-        #[arg(long)]
-        #[arg(default_value_t = FieldArgument::Gl)]
-        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
-        field: FieldArgument,
-
-        /// Comma-separated list of free inputs (numbers).
-        #[arg(short, long)]
-        #[arg(default_value_t = String::new())]
-        inputs: String,
-
-        /// Directory for output files.
-        #[arg(short, long)]
-        #[arg(default_value_t = String::from("."))]
-        output_directory: String,
-
-        /// Force overwriting of files in output directory.
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        force: bool,
-
-        /// Generate a proof with a given backend.
-        #[arg(short, long)]
-        #[arg(value_parser = clap_enum_variants!(BackendType))]
-        prove_with: Option<BackendType>,
-
-        /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
-        #[arg(long)]
-        #[arg(default_value_t = false)]
-        export_csv: bool,
-
-        /// How to render field elements in the csv file
-        #[arg(long)]
-        #[arg(default_value_t = CsvRenderModeCLI::Hex)]
-        #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
-        csv_mode: CsvRenderModeCLI,
-
-        /// Comma-separated list of coprocessors.
-        #[arg(long)]
-        coprocessors: Option<String>,
+        #[command(flatten)]
+        args: RiscvArgs,
+    },
 
-        /// Just execute in the RISCV/Powdr executor
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        just_execute: bool,
+    /// Compiles a statically linked RV32IM ELF executable to powdr assembly
+    /// and then to PIL and generates fixed and witness columns.
+    RiscvElf {
+        /// Input file
+        file: String,
 
-        /// Run a long execution in chunks (Experimental and not sound!)
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        continuations: bool,
+        #[command(flatten)]
+        args: RiscvArgs,
     },
 
     Prove {
@@ -277,6 +349,12 @@ enum Commands {
         #[arg(value_parser = clap_enum_variants!(BackendType))]
         backend: BackendType,
 
+        /// Backend specific options, as comma-separated `key=value` pairs,
+        /// e.g. "max_lookup_degree=4" for Halo2.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        backend_options: String,
+
         /// File containing previously generated proof for aggregation.
         #[arg(long)]
         proof: Option<String>,
@@ -286,8 +364,14 @@ enum Commands {
         vkey: Option<String>,
 
         /// File containing previously generated setup parameters.
+        /// For Halo2, this can also be a powers-of-tau file (`.ptau`).
         #[arg(long)]
         params: Option<String>,
+
+        /// Reduce the degree of all polynomial identities to at most this value.
+        /// Use the same value as for the `pil` command that generated the witness.
+        #[arg(long)]
+        max_degree: Option<usize>,
     },
 
     Verify {
@@ -310,6 +394,12 @@ enum Commands {
         #[arg(value_parser = clap_enum_variants!(BackendType))]
         backend: BackendType,
 
+        /// Backend specific options, as comma-separated `key=value` pairs,
+        /// e.g. "max_lookup_degree=4" for Halo2.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        backend_options: String,
+
         /// File containing the proof.
         #[arg(long)]
         proof: String,
@@ -343,12 +433,77 @@ enum Commands {
         #[arg(value_parser = clap_enum_variants!(BackendType))]
         backend: BackendType,
 
+        /// Backend specific options, as comma-separated `key=value` pairs,
+        /// e.g. "max_lookup_degree=4" for Halo2.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        backend_options: String,
+
         /// File containing previously generated setup parameters.
         /// This will be needed for SNARK verification keys but not for STARK.
         #[arg(long)]
         params: Option<String>,
     },
 
+    /// Exports a verifier contract for the Ethereum Virtual Machine, for the
+    /// aggregated proofs generated with `prove --proof` or `--aggregation-params`.
+    /// Writes the Solidity source to verifier.sol and the EVM bytecode to verifier.bin.
+    ExportVerifier {
+        /// Input PIL file
+        file: String,
+
+        /// Directory to find the fixed values and to write the verifier to
+        #[arg(short, long)]
+        #[arg(default_value_t = String::from("."))]
+        dir: String,
+
+        /// The field to use
+        #[arg(long)]
+        #[arg(default_value_t = FieldArgument::Gl)]
+        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+        field: FieldArgument,
+
+        /// Chosen backend.
+        #[arg(short, long)]
+        #[arg(value_parser = clap_enum_variants!(BackendType))]
+        backend: BackendType,
+
+        /// Backend specific options, as comma-separated `key=value` pairs,
+        /// e.g. "max_lookup_degree=4" for Halo2.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        backend_options: String,
+
+        /// File containing previously generated setup parameters.
+        #[arg(long)]
+        params: String,
+
+        /// File containing previously generated verification key.
+        #[arg(long)]
+        vkey: Option<String>,
+
+        /// Number of proofs that the verified proofs aggregate.
+        #[arg(long)]
+        #[arg(default_value_t = 1)]
+        num_proofs: usize,
+
+        /// Comma-separated pairs `initial:final` of indices of the public values that
+        /// are chained between the aggregated proofs, e.g. "0:1,2:3".
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        chained_publics: String,
+
+        /// File containing an aggregated proof. If provided, it is encoded as
+        /// calldata for the verifier, checked in a local EVM and written to calldata.bin.
+        #[arg(long)]
+        proof: Option<String>,
+
+        /// Comma-separated public values of the aggregated proof.
+        #[arg(long)]
+        #[arg(default_value_t = String::new())]
+        publics: String,
+    },
+
     Setup {
         /// Size of the parameters
         size: u64,
@@ -386,6 +541,29 @@ enum Commands {
         #[arg(default_value_t = FieldArgument::Gl)]
         #[arg(value_parser = clap_enum_variants!(FieldArgument))]
         field: FieldArgument,
+
+        /// Extract repeated subexpressions into new intermediate or witness columns.
+        #[arg(long)]
+        #[arg(value_parser = clap_enum_variants!(CseModeCLI))]
+        cse: Option<CseModeCLI>,
+
+        /// Reduce the degree of all polynomial identities to at most this value
+        /// by introducing new witness columns.
+        #[arg(long)]
+        max_degree: Option<usize>,
+    },
+
+    /// Checks a .pil or .asm file for witness columns that might not be
+    /// uniquely determined by the constraints.
+    Lint {
+        /// Input file
+        file: String,
+
+        /// The field to use
+        #[arg(long)]
+        #[arg(default_value_t = FieldArgument::Gl)]
+        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+        field: FieldArgument,
     },
 }
 
@@ -441,79 +619,17 @@ fn main() -> Result<(), io::Error> {
 #[allow(clippy::print_stderr)]
 fn run_command(command: Commands) {
     let result = match command {
-        Commands::Rust {
-            file,
-            field,
-            inputs,
-            output_directory,
-            force,
-            prove_with,
-            export_csv,
-            csv_mode,
-            coprocessors,
-            just_execute,
-            continuations,
-        } => {
-            let coprocessors = match coprocessors {
-                Some(list) => {
-                    powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
-                        .unwrap()
-                }
-                None => powdr_riscv::CoProcessors::base(),
-            };
-            call_with_field!(run_rust::<field>(
-                &file,
-                split_inputs(&inputs),
-                Path::new(&output_directory),
-                force,
-                prove_with,
-                export_csv,
-                csv_mode,
-                coprocessors,
-                just_execute,
-                continuations
-            ))
+        Commands::Rust { file, args } => {
+            let field = args.field.clone();
+            call_with_field!(run_rust::<field>(&file, args))
         }
-        Commands::RiscvAsm {
-            files,
-            field,
-            inputs,
-            output_directory,
-            force,
-            prove_with,
-            export_csv,
-            csv_mode,
-            coprocessors,
-            just_execute,
-            continuations,
-        } => {
-            assert!(!files.is_empty());
-            let name = if files.len() == 1 {
-                Cow::Owned(files[0].clone())
-            } else {
-                Cow::Borrowed("output")
-            };
-
-            let coprocessors = match coprocessors {
-                Some(list) => {
-                    powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
-                        .unwrap()
-                }
-                None => powdr_riscv::CoProcessors::base(),
-            };
-            call_with_field!(run_riscv_asm::<field>(
-                &name,
-                files.into_iter(),
-                split_inputs(&inputs),
-                Path::new(&output_directory),
-                force,
-                prove_with,
-                export_csv,
-                csv_mode,
-                coprocessors,
-                just_execute,
-                continuations
-            ))
+        Commands::RiscvAsm { files, args } => {
+            let field = args.field.clone();
+            call_with_field!(run_riscv_asm::<field>(files, args))
+        }
+        Commands::RiscvElf { file, args } => {
+            let field = args.field.clone();
+            call_with_field!(run_riscv_elf::<field>(&file, args))
         }
         Commands::Reformat { file } => {
             let contents = fs::read_to_string(&file).unwrap();
@@ -523,10 +639,18 @@ fn run_command(command: Commands) {
             };
             Ok(())
         }
-        Commands::OptimizePIL { file, field } => {
-            call_with_field!(optimize_and_output::<field>(&file));
+        Commands::OptimizePIL {
+            file,
+            field,
+            cse,
+            max_degree,
+        } => {
+            call_with_field!(optimize_and_output::<field>(&file, cse, max_degree));
             Ok(())
         }
+        Commands::Lint { file, field } => {
+            call_with_field!(lint::<field>(&file))
+        }
         Commands::Pil {
             file,
             field,
@@ -535,10 +659,15 @@ fn run_command(command: Commands) {
             inputs,
             force,
             prove_with,
+            backend_options,
             export_csv,
             csv_mode,
             just_execute,
             continuations,
+            save_artifacts,
+            from_stage,
+            to_stage,
+            max_degree,
         } => {
             call_with_field!(run_pil::<field>(
                 file,
@@ -547,10 +676,15 @@ fn run_command(command: Commands) {
                 inputs,
                 force,
                 prove_with,
+                backend_options,
                 export_csv,
                 csv_mode,
                 just_execute,
-                continuations
+                continuations,
+                save_artifacts,
+                from_stage,
+                to_stage,
+                max_degree
             ))
         }
         Commands::Prove {
@@ -558,14 +692,23 @@ fn run_command(command: Commands) {
             dir,
             field,
             backend,
+            backend_options,
             proof,
             vkey,
             params,
+            max_degree,
         } => {
             let pil = Path::new(&file);
             let dir = Path::new(&dir);
             call_with_field!(read_and_prove::<field>(
-                pil, dir, &backend, proof, vkey, params
+                pil,
+                dir,
+                &backend,
+                backend_options,
+                proof,
+                vkey,
+                params,
+                max_degree
             ))
         }
         Commands::Verify {
@@ -573,6 +716,7 @@ fn run_command(command: Commands) {
             dir,
             field,
             backend,
+            backend_options,
             proof,
             params,
             vkey,
@@ -580,7 +724,13 @@ fn run_command(command: Commands) {
             let pil = Path::new(&file);
             let dir = Path::new(&dir);
             call_with_field!(read_and_verify::<field>(
-                pil, dir, &backend, proof, params, vkey
+                pil,
+                dir,
+                &backend,
+                backend_options,
+                proof,
+                params,
+                vkey
             ))
         }
         Commands::VerificationKey {
@@ -588,11 +738,46 @@ fn run_command(command: Commands) {
             dir,
             field,
             backend,
+            backend_options,
             params,
         } => {
             let pil = Path::new(&file);
             let dir = Path::new(&dir);
-            call_with_field!(verification_key::<field>(pil, dir, &backend, params))
+            call_with_field!(verification_key::<field>(
+                pil,
+                dir,
+                &backend,
+                backend_options,
+                params
+            ))
+        }
+        Commands::ExportVerifier {
+            file,
+            dir,
+            field,
+            backend,
+            backend_options,
+            params,
+            vkey,
+            num_proofs,
+            chained_publics,
+            proof,
+            publics,
+        } => {
+            let pil = Path::new(&file);
+            let dir = Path::new(&dir);
+            call_with_field!(export_verifier::<field>(
+                pil,
+                dir,
+                &backend,
+                backend_options,
+                params,
+                vkey,
+                num_proofs,
+                &chained_publics,
+                proof,
+                &publics
+            ))
         }
         Commands::Setup {
             size,
@@ -616,13 +801,15 @@ fn verification_key<T: FieldElement>(
     file: &Path,
     dir: &Path,
     backend_type: &BackendType,
+    backend_options: String,
     params: Option<String>,
 ) -> Result<(), Vec<String>> {
     let mut pipeline = Pipeline::<T>::default()
         .from_file(file.to_path_buf())
         .read_constants(dir)
         .with_setup_file(params.map(PathBuf::from))
-        .with_backend(*backend_type);
+        .with_backend(*backend_type)
+        .with_backend_options(backend_options);
 
     let vkey_file = BufWriter::new(fs::File::create(dir.join("vkey.bin")).unwrap());
     write_or_panic(vkey_file, |w| pipeline.export_verification_key(w))?;
@@ -631,6 +818,72 @@ fn verification_key<T: FieldElement>(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
+fn export_verifier<T: FieldElement>(
+    file: &Path,
+    dir: &Path,
+    backend_type: &BackendType,
+    backend_options: String,
+    params: String,
+    vkey: Option<String>,
+    num_proofs: usize,
+    chained_publics: &str,
+    proof: Option<String>,
+    publics: &str,
+) -> Result<(), Vec<String>> {
+    let chained_publics = parse_chained_publics(chained_publics)?;
+    let publics = publics
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<T>()
+                .map_err(|e| vec![format!("Invalid public value {x}: {e}")])
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut pipeline = Pipeline::<T>::default()
+        .from_file(file.to_path_buf())
+        .read_constants(dir)
+        .with_setup_file(Some(PathBuf::from(params)))
+        .with_vkey_file(vkey.map(PathBuf::from))
+        .with_backend(*backend_type)
+        .with_backend_options(backend_options);
+
+    let solidity_file = BufWriter::new(fs::File::create(dir.join("verifier.sol")).unwrap());
+    let mut bytecode = vec![];
+    write_or_panic(solidity_file, |solidity| {
+        pipeline.export_ethereum_verifier(num_proofs, &chained_publics, solidity, &mut bytecode)
+    })?;
+    fs::write(dir.join("verifier.bin"), &bytecode).unwrap();
+    log::info!("Wrote verifier.sol and verifier.bin.");
+
+    if let Some(proof) = proof {
+        let proof = fs::read(proof).unwrap();
+        let calldata = pipeline.ethereum_calldata(proof, &publics, &bytecode)?;
+        fs::write(dir.join("calldata.bin"), calldata).unwrap();
+        log::info!("Proof is valid! Wrote calldata.bin.");
+    }
+
+    Ok(())
+}
+
+/// Parses comma-separated pairs `initial:final` of indices of public values.
+fn parse_chained_publics(chained_publics: &str) -> Result<Vec<(usize, usize)>, Vec<String>> {
+    chained_publics
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|pair| {
+            pair.split_once(':')
+                .and_then(|(initial, final_)| {
+                    Some((initial.trim().parse().ok()?, final_.trim().parse().ok()?))
+                })
+                .ok_or_else(|| vec![format!("Invalid pair of chained public values: {pair}")])
+        })
+        .collect()
+}
+
 fn setup<F: FieldElement>(size: u64, dir: String, backend_type: BackendType) {
     let dir = Path::new(&dir);
 
@@ -644,85 +897,109 @@ fn setup<F: FieldElement>(size: u64, dir: String, backend_type: BackendType) {
     log::info!("Wrote params.bin.");
 }
 
-#[allow(clippy::too_many_arguments)]
-fn run_rust<F: FieldElement>(
-    file_name: &str,
-    inputs: Vec<F>,
-    output_dir: &Path,
-    force_overwrite: bool,
-    prove_with: Option<BackendType>,
-    export_csv: bool,
-    csv_mode: CsvRenderModeCLI,
-    coprocessors: powdr_riscv::CoProcessors,
-    just_execute: bool,
-    continuations: bool,
-) -> Result<(), Vec<String>> {
+impl RiscvArgs {
+    fn coprocessors(&self) -> Result<powdr_riscv::CoProcessors, Vec<String>> {
+        match &self.coprocessors {
+            Some(list) => powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
+                .map_err(|e| vec![e]),
+            None => Ok(powdr_riscv::CoProcessors::base()),
+        }
+    }
+}
+
+impl ContinuationsArgs {
+    /// The memory layout, hashed with the hash function for the field `F`.
+    fn memory_layout<F: FieldElement>(&self) -> Result<MemoryLayout, Vec<String>> {
+        MemoryLayout::new(self.page_size_log, self.merkle_tree_arity)
+            .map(|layout| layout.with_hash(MemoryHashFunction::for_field::<F>()))
+            .map_err(|e| vec![e])
+    }
+
+    /// The memory layout of the bootloader, if running with continuations.
+    fn bootloader<F: FieldElement>(&self) -> Result<Option<MemoryLayout>, Vec<String>> {
+        self.continuations
+            .then(|| self.memory_layout::<F>())
+            .transpose()
+    }
+}
+
+fn run_rust<F: FieldElement>(file_name: &str, args: RiscvArgs) -> Result<(), Vec<String>> {
     let (asm_file_path, asm_contents) = compile_rust(
         file_name,
-        output_dir,
-        force_overwrite,
-        &coprocessors,
-        continuations,
+        Path::new(&args.output_directory),
+        args.force,
+        &args.coprocessors()?,
+        args.continuations.bootloader::<F>()?,
     )
     .ok_or_else(|| vec!["could not compile rust".to_string()])?;
-
-    let pipeline = Pipeline::<F>::default().from_asm_string(
-        asm_contents.clone(),
-        Some(PathBuf::from(asm_file_path.to_str().unwrap())),
-    );
-
-    let pipeline = bind_cli_args(
-        pipeline,
-        inputs.clone(),
-        output_dir.to_path_buf(),
-        force_overwrite,
-        None,
-        export_csv,
-        csv_mode,
-    );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
-    Ok(())
+    run_riscv_program::<F>(asm_file_path, asm_contents, args)
 }
 
-#[allow(clippy::too_many_arguments)]
-fn run_riscv_asm<F: FieldElement>(
-    original_file_name: &str,
-    file_names: impl Iterator<Item = String>,
-    inputs: Vec<F>,
-    output_dir: &Path,
-    force_overwrite: bool,
-    prove_with: Option<BackendType>,
-    export_csv: bool,
-    csv_mode: CsvRenderModeCLI,
-    coprocessors: powdr_riscv::CoProcessors,
-    just_execute: bool,
-    continuations: bool,
-) -> Result<(), Vec<String>> {
+fn run_riscv_asm<F: FieldElement>(files: Vec<String>, args: RiscvArgs) -> Result<(), Vec<String>> {
+    assert!(!files.is_empty());
+    let name = if files.len() == 1 {
+        Cow::Owned(files[0].clone())
+    } else {
+        Cow::Borrowed("output")
+    };
     let (asm_file_path, asm_contents) = compile_riscv_asm(
-        original_file_name,
-        file_names,
-        output_dir,
-        force_overwrite,
-        &coprocessors,
-        continuations,
+        &name,
+        files.into_iter(),
+        Path::new(&args.output_directory),
+        args.force,
+        &args.coprocessors()?,
+        args.continuations.bootloader::<F>()?,
     )
     .ok_or_else(|| vec!["could not compile RISC-V assembly".to_string()])?;
+    run_riscv_program::<F>(asm_file_path, asm_contents, args)
+}
 
-    let pipeline = Pipeline::<F>::default().from_asm_string(
-        asm_contents.clone(),
-        Some(PathBuf::from(asm_file_path.to_str().unwrap())),
-    );
+fn run_riscv_elf<F: FieldElement>(file_name: &str, args: RiscvArgs) -> Result<(), Vec<String>> {
+    let (asm_file_path, asm_contents) = compile_riscv_elf(
+        file_name,
+        Path::new(&args.output_directory),
+        args.force,
+        &args.coprocessors()?,
+        args.continuations.bootloader::<F>()?,
+    )
+    .map_err(|e| vec![e])?
+    .ok_or_else(|| vec!["could not compile RISC-V ELF file".to_string()])?;
+    run_riscv_program::<F>(asm_file_path, asm_contents, args)
+}
+
+/// Runs a powdr-asm program compiled from a RISC-V program.
+fn run_riscv_program<F: FieldElement>(
+    asm_file_path: PathBuf,
+    asm_contents: String,
+    args: RiscvArgs,
+) -> Result<(), Vec<String>> {
+    let inputs = split_inputs::<F>(&args.inputs);
+    let pipeline = Pipeline::<F>::default().from_asm_string(asm_contents, Some(asm_file_path));
 
     let pipeline = bind_cli_args(
         pipeline,
         inputs.clone(),
-        output_dir.to_path_buf(),
-        force_overwrite,
+        PathBuf::from(&args.output_directory),
+        args.force,
         None,
-        export_csv,
-        csv_mode,
+        args.export_csv,
+        args.csv_mode,
+        args.backend_options,
     );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
+    let memory_layout = args.continuations.memory_layout::<F>()?;
+    run(
+        pipeline,
+        inputs,
+        args.prove_with,
+        args.just_execute,
+        args.gdb,
+        args.profile,
+        args.continuations.continuations,
+        args.chunk_workers,
+        memory_layout,
+        args.continuations.aggregation_params.map(PathBuf::from),
+        args.executor_witness,
+    )?;
     Ok(())
 }
 
@@ -734,14 +1011,19 @@ fn run_pil<F: FieldElement>(
     inputs: String,
     force: bool,
     prove_with: Option<BackendType>,
+    backend_options: String,
     export_csv: bool,
     csv_mode: CsvRenderModeCLI,
     just_execute: bool,
-    continuations: bool,
+    continuations: ContinuationsArgs,
+    save_artifacts: bool,
+    from_stage: Option<Stage>,
+    to_stage: Option<Stage>,
+    max_degree: Option<usize>,
 ) -> Result<(), Vec<String>> {
     let inputs = split_inputs::<F>(&inputs);
 
-    let pipeline = bind_cli_args(
+    let mut pipeline = bind_cli_args(
         Pipeline::<F>::default().from_file(PathBuf::from(&file)),
         inputs.clone(),
         PathBuf::from(output_directory),
@@ -749,32 +1031,111 @@ fn run_pil<F: FieldElement>(
         witness_values,
         export_csv,
         csv_mode,
-    );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
+        backend_options,
+    )
+    // Stopping early is only useful if the run can be resumed later.
+    .with_saved_artifacts(save_artifacts || to_stage.is_some());
+    if let Some(max_degree) = max_degree {
+        pipeline = pipeline.with_max_degree(max_degree);
+    }
+
+    let memory_layout = continuations.memory_layout::<F>()?;
+    let aggregation_params = continuations.aggregation_params.map(PathBuf::from);
+    let continuations = continuations.continuations;
+    if (from_stage.is_some() || to_stage.is_some()) && (just_execute || continuations) {
+        return Err(vec![
+            "--from-stage and --to-stage are not supported with --just-execute or --continuations."
+                .to_string(),
+        ]);
+    }
+    if let Some(stage) = from_stage {
+        pipeline = pipeline.read_artifact(stage)?;
+    }
+    if let Some(stage) = to_stage {
+        if let Some(backend) = prove_with {
+            pipeline = pipeline.with_backend(backend);
+        }
+        return pipeline.advance_to(stage);
+    }
+    run(
+        pipeline,
+        inputs,
+        prove_with,
+        just_execute,
+        None,
+        false,
+        continuations,
+        1,
+        memory_layout,
+        aggregation_params,
+        false,
+    )?;
     Ok(())
 }
 
+/// Runs the pipeline. With `executor_witness`, the main machine witness is
+/// computed by the RISC-V executor instead of the generic witness generation.
+/// With `aggregation_params`, the proofs of all chunks are aggregated into a single proof,
+/// using the setup in that file for all proofs.
+#[allow(clippy::too_many_arguments)]
 fn run<F: FieldElement>(
     mut pipeline: Pipeline<F>,
     inputs: Vec<F>,
     prove_with: Option<BackendType>,
     just_execute: bool,
+    gdb: Option<String>,
+    profile: bool,
     continuations: bool,
+    chunk_workers: usize,
+    memory_layout: MemoryLayout,
+    aggregation_params: Option<PathBuf>,
+    executor_witness: bool,
 ) -> Result<(), Vec<String>> {
+    if gdb.is_some() && continuations {
+        return Err(vec![
+            "Debugging with GDB is not supported with continuations.".to_string(),
+        ]);
+    }
+    if profile && (gdb.is_some() || continuations) {
+        return Err(vec![
+            "Profiling is not supported with GDB or continuations.".to_string(),
+        ]);
+    }
+    if executor_witness && continuations {
+        return Err(vec![
+            "Computing the witness with the executor is not supported with continuations."
+                .to_string(),
+        ]);
+    }
+    if aggregation_params.is_some()
+        && (!continuations || prove_with.is_none() || just_execute || gdb.is_some() || profile)
+    {
+        return Err(vec![
+            "Aggregating proofs needs --continuations and --prove-with.".to_string(),
+        ]);
+    }
+    if just_execute || gdb.is_some() || profile || continuations {
+        // Checked on a copy, because executing needs the pipeline at an earlier stage.
+        powdr_riscv::check_executor_support(&mut pipeline.clone())?;
+    }
     let bootloader_inputs = if continuations {
-        pipeline = pipeline.with_prover_inputs(inputs.clone());
-        rust_continuations_dry_run(&mut pipeline)
+        pipeline = pipeline
+            .with_prover_inputs(inputs.clone())
+            .with_setup_file(aggregation_params.clone());
+        rust_continuations_dry_run(&mut pipeline, &memory_layout)
     } else {
         vec![]
     };
 
     let generate_witness_and_prove_maybe = |mut pipeline: Pipeline<F>| -> Result<(), Vec<String>> {
         pipeline.advance_to(Stage::GeneratedWitness)?;
-        prove_with.map(|backend| pipeline.with_backend(backend).proof().unwrap());
+        if let Some(backend) = prove_with {
+            pipeline.with_backend(backend).proof()?;
+        }
         Ok(())
     };
 
-    match (just_execute, continuations) {
+    match (just_execute || gdb.is_some() || profile, continuations) {
         (true, true) => {
             // Already ran when computing bootloader inputs, nothing else to do.
         }
@@ -782,43 +1143,200 @@ fn run<F: FieldElement>(
             let mut pipeline = pipeline.with_prover_inputs(inputs);
             pipeline.advance_to(Stage::AsmString).unwrap();
             let program = pipeline.artifact().unwrap().to_asm_string().unwrap();
-            powdr_riscv_executor::execute::<F>(
-                program,
-                pipeline.data_callback().unwrap(),
-                &[],
-                powdr_riscv_executor::ExecMode::Fast,
-            );
+            match gdb {
+                Some(address) => {
+                    let mut debugger = GdbStub::listen(&address)
+                        .map_err(|e| vec![format!("Could not start GDB server: {e}")])?;
+                    powdr_riscv_executor::execute_with_gdb::<F>(
+                        program,
+                        pipeline.data_callback().unwrap(),
+                        &[],
+                        powdr_riscv_executor::ExecMode::Fast,
+                        &mut debugger,
+                    );
+                }
+                None if profile => {
+                    let (_, _, profile) = powdr_riscv_executor::execute_with_profiler::<F>(
+                        program,
+                        pipeline.data_callback().unwrap(),
+                        &[],
+                        powdr_riscv_executor::ExecMode::Fast,
+                    );
+                    let output_dir = pipeline.output_dir().unwrap_or(Path::new("."));
+                    profile
+                        .write(output_dir, pipeline.name())
+                        .map_err(|e| vec![format!("Could not write profile: {e}")])?;
+                }
+                None => {
+                    powdr_riscv_executor::execute::<F>(
+                        program,
+                        pipeline.data_callback().unwrap(),
+                        &[],
+                        powdr_riscv_executor::ExecMode::Fast,
+                    );
+                }
+            }
         }
         (false, true) => {
-            rust_continuations(
-                pipeline,
-                generate_witness_and_prove_maybe,
-                bootloader_inputs,
-            )?;
+            let callback = |mut pipeline: Pipeline<F>| -> Result<_, Vec<String>> {
+                let publics = pipeline
+                    .publics()?
+                    .into_iter()
+                    .map(|(_, value)| value)
+                    .collect::<Vec<_>>();
+                generate_witness_and_prove_maybe(pipeline)?;
+                Ok(publics)
+            };
+            let chunk_publics = match pipeline.output_dir().map(Path::to_path_buf) {
+                Some(output_dir) => {
+                    // Record the progress in a manifest, so that an interrupted run can be
+                    // resumed without re-processing the chunks that are already done.
+                    let manifest_path =
+                        ContinuationsManifest::<F>::path(&output_dir, pipeline.name());
+                    let prover = ProverSettings::new(
+                        prove_with.map(|backend| backend.to_string()),
+                        pipeline.backend_options().clone(),
+                        pipeline.setup_file(),
+                    )
+                    .map_err(|e| vec![e])?;
+                    let mut manifest =
+                        ContinuationsManifest::new(pipeline.name(), bootloader_inputs)
+                            .with_prover(prover);
+                    if manifest_path.exists() {
+                        log::info!("Resuming from {}", manifest_path.display());
+                        manifest = manifest.with_progress_from(
+                            &ContinuationsManifest::read(&manifest_path).map_err(|e| vec![e])?,
+                        );
+                    }
+                    let target_status = match prove_with {
+                        Some(_) => ChunkStatus::Proven,
+                        None => ChunkStatus::WitnessGenerated,
+                    };
+                    let chunks = manifest.chunks.clone();
+                    let pil = pipeline.optimized_pil_ref()?.clone();
+                    let aggregation_pipeline = aggregation_params.is_some().then(|| {
+                        pipeline
+                            .clone()
+                            .with_backend(prove_with.expect("checked above"))
+                    });
+                    let mut processed = rust_continuations_with_manifest(
+                        pipeline,
+                        callback,
+                        manifest,
+                        &manifest_path,
+                        target_status,
+                        chunk_workers,
+                    )?;
+                    // The publics of skipped chunks are read from the witnesses they saved.
+                    let chunk_publics = chunks
+                        .iter()
+                        .enumerate()
+                        .map(|(i, chunk)| match processed.remove(&i) {
+                            Some(publics) => Ok(publics),
+                            None => saved_chunk_publics(&pil, chunk, &output_dir),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if let Some(pipeline) = aggregation_pipeline {
+                        aggregate_chunk_proofs(
+                            pipeline,
+                            &chunks,
+                            chunk_publics.clone(),
+                            &output_dir,
+                            &memory_layout,
+                        )?;
+                    }
+                    chunk_publics
+                }
+                None if aggregation_params.is_some() => {
+                    return Err(vec![
+                        "Aggregating proofs needs an output directory.".to_string()
+                    ]);
+                }
+                None => rust_continuations(pipeline, callback, bootloader_inputs)?,
+            };
+            check_chunk_chaining(&chunk_publics, &memory_layout)?;
+            log::info!("All {} chunks are chained correctly.", chunk_publics.len());
         }
         (false, false) => {
+            let pipeline = if executor_witness {
+                powdr_riscv::add_executor_witness(pipeline)?
+            } else {
+                pipeline
+            };
             generate_witness_and_prove_maybe(pipeline)?;
         }
     }
     Ok(())
 }
 
+/// Aggregates the proofs that the chunks wrote to `output_dir` and writes the aggregated
+/// proof to `<name>_proof_aggr.bin`.
+fn aggregate_chunk_proofs<F: FieldElement>(
+    pipeline: Pipeline<F>,
+    chunks: &[Chunk<F>],
+    chunk_publics: Vec<Vec<F>>,
+    output_dir: &Path,
+    memory_layout: &MemoryLayout,
+) -> Result<(), Vec<String>> {
+    let chunks = chunks
+        .iter()
+        .zip(chunk_publics)
+        .map(|(chunk, publics)| {
+            let proof_path = output_dir.join(format!("{}_proof.bin", chunk.name));
+            fs::read(&proof_path)
+                .map(|proof| (proof, publics))
+                .map_err(|e| vec![format!("Could not read {}: {e}", proof_path.display())])
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let name = pipeline.name().to_string();
+    let num_proofs = chunks.len();
+    let (proof, publics) = aggregate_rust_continuations(pipeline, chunks, memory_layout)?;
+    let proof_path = output_dir.join(format!("{name}_proof_aggr.bin"));
+    fs::write(&proof_path, proof)
+        .map_err(|e| vec![format!("Could not write {}: {e}", proof_path.display())])?;
+    // The arguments of `export-verifier` for the aggregated proof.
+    let chained_publics = chunk_chained_publics(memory_layout)
+        .iter()
+        .map(|(initial, final_)| format!("{initial}:{final_}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let publics = publics
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    log::info!(
+        "Wrote the aggregated proof to {}. Its verifier can be exported with \
+        `export-verifier --num-proofs {num_proofs} --chained-publics {chained_publics} --publics {publics}`.",
+        proof_path.display(),
+    );
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn read_and_prove<T: FieldElement>(
     file: &Path,
     dir: &Path,
     backend_type: &BackendType,
+    backend_options: String,
     proof_path: Option<String>,
     vkey: Option<String>,
     params: Option<String>,
+    max_degree: Option<usize>,
 ) -> Result<(), Vec<String>> {
-    Pipeline::<T>::default()
-        .from_maybe_pil_object(file.to_path_buf())?
+    let mut pipeline = Pipeline::<T>::default().from_maybe_pil_object(file.to_path_buf())?;
+    if let Some(max_degree) = max_degree {
+        pipeline = pipeline.with_max_degree(max_degree);
+    }
+    pipeline
         .with_output(dir.to_path_buf(), true)
         .read_generated_witness(dir)
         .with_setup_file(params.map(PathBuf::from))
         .with_vkey_file(vkey.map(PathBuf::from))
         .with_existing_proof_file(proof_path.map(PathBuf::from))
         .with_backend(*backend_type)
+        .with_backend_options(backend_options)
         .proof()?;
     Ok(())
 }
@@ -827,6 +1345,7 @@ fn read_and_verify<T: FieldElement>(
     file: &Path,
     dir: &Path,
     backend_type: &BackendType,
+    backend_options: String,
     proof: String,
     params: Option<String>,
     vkey: String,
@@ -841,7 +1360,8 @@ fn read_and_verify<T: FieldElement>(
         .read_constants(dir)
         .with_setup_file(params.map(PathBuf::from))
         .with_vkey_file(Some(vkey))
-        .with_backend(*backend_type);
+        .with_backend(*backend_type)
+        .with_backend_options(backend_options);
 
     // TODO add support for publics
     pipeline.verify(proof, &[vec![]])?;
@@ -851,20 +1371,48 @@ fn read_and_verify<T: FieldElement>(
 }
 
 #[allow(clippy::print_stdout)]
-fn optimize_and_output<T: FieldElement>(file: &str) {
-    println!(
-        "{}",
-        Pipeline::<T>::default()
-            .from_file(PathBuf::from(file))
-            .optimized_pil()
-            .unwrap()
-    );
+fn optimize_and_output<T: FieldElement>(
+    file: &str,
+    cse: Option<CseModeCLI>,
+    max_degree: Option<usize>,
+) {
+    let mut pipeline = Pipeline::<T>::default().from_file(PathBuf::from(file));
+    if let Some(cse) = cse {
+        pipeline = pipeline.with_cse(match cse {
+            CseModeCLI::Intermediate => CseMode::Intermediate,
+            CseModeCLI::Witness => CseMode::Witness,
+        });
+    }
+    if let Some(max_degree) = max_degree {
+        pipeline = pipeline.with_max_degree(max_degree);
+    }
+    let optimized = pipeline.optimized_pil_ref().unwrap().to_string();
+    if let Some(report) = pipeline.degree_report() {
+        for line in report.to_string().lines() {
+            println!("// {line}");
+        }
+    }
+    println!("{optimized}");
+}
+
+fn lint<T: FieldElement>(file: &str) -> Result<(), Vec<String>> {
+    let warnings = Pipeline::<T>::default()
+        .from_file(PathBuf::from(file))
+        .lint()?;
+    if warnings.is_empty() {
+        log::info!("No issues found.");
+        Ok(())
+    } else {
+        Err(warnings.iter().map(|w| w.to_string()).collect())
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{run_command, Commands, CsvRenderModeCLI, FieldArgument};
+    use crate::{run_command, Cli, Commands, ContinuationsArgs, CsvRenderModeCLI, FieldArgument};
+    use clap::Parser;
     use powdr_backend::BackendType;
+    use powdr_pipeline::Stage;
 
     #[test]
     fn test_simple_sum() {
@@ -883,10 +1431,20 @@ mod test {
             inputs: "3,2,1,2".into(),
             force: false,
             prove_with: Some(BackendType::PilStarkCli),
+            backend_options: String::new(),
             export_csv: true,
             csv_mode: CsvRenderModeCLI::Hex,
             just_execute: false,
-            continuations: false,
+            continuations: ContinuationsArgs {
+                continuations: false,
+                page_size_log: 10,
+                merkle_tree_arity: 2,
+                aggregation_params: None,
+            },
+            save_artifacts: false,
+            from_stage: None,
+            to_stage: None,
+            max_degree: None,
         };
         run_command(pil_command);
 
@@ -902,11 +1460,79 @@ mod test {
                 dir: output_dir_str,
                 field: FieldArgument::Bn254,
                 backend: BackendType::Halo2Mock,
+                backend_options: String::new(),
                 proof: None,
                 vkey: None,
                 params: None,
+                max_degree: None,
             };
             run_command(prove_command);
         }
     }
+
+    #[test]
+    fn test_resume_from_stage() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let output_dir_str = output_dir.path().to_string_lossy().to_string();
+
+        let file = format!(
+            "{}/../test_data/asm/simple_sum.asm",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let pil_command = |from_stage, to_stage| Commands::Pil {
+            file: file.clone(),
+            field: FieldArgument::Gl,
+            output_directory: output_dir_str.clone(),
+            witness_values: None,
+            inputs: "3,2,1,2".into(),
+            force: false,
+            prove_with: None,
+            backend_options: String::new(),
+            export_csv: false,
+            csv_mode: CsvRenderModeCLI::Hex,
+            just_execute: false,
+            continuations: ContinuationsArgs {
+                continuations: false,
+                page_size_log: 10,
+                merkle_tree_arity: 2,
+                aggregation_params: None,
+            },
+            save_artifacts: false,
+            from_stage,
+            to_stage,
+            max_degree: None,
+        };
+
+        run_command(pil_command(None, Some(Stage::OptimizedPil)));
+        assert!(output_dir
+            .path()
+            .join("simple_sum_optimized-pil.artifact")
+            .exists());
+        assert!(!output_dir.path().join("simple_sum_constants.bin").exists());
+
+        run_command(pil_command(Some(Stage::OptimizedPil), None));
+        assert!(output_dir.path().join("simple_sum_commits.bin").exists());
+    }
+
+    #[test]
+    fn test_riscv_elf() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let file = format!(
+            "{}/../riscv/tests/riscv_data/elf/sum.elf",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let cli = Cli::try_parse_from([
+            "powdr",
+            "riscv-elf",
+            &file,
+            "--inputs",
+            "16,4,1,2,8,5",
+            "--output-directory",
+            output_dir.path().to_str().unwrap(),
+            "--just-execute",
+        ])
+        .unwrap();
+        run_command(cli.command.unwrap());
+        assert!(output_dir.path().join("sum.asm").exists());
+    }
 }
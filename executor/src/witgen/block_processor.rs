@@ -1,16 +1,18 @@
 use std::collections::HashSet;
 
 use powdr_ast::analyzed::{
-    AlgebraicExpression as Expression, AlgebraicReference, Identity, PolyID,
+    AlgebraicExpression as Expression, AlgebraicReference, Identity, IdentityKind, PolyID,
 };
 use powdr_number::FieldElement;
 
 use super::{
+    affine_expression::AffineExpression,
+    block_program::{solve_decomposition, BlockProgram, ProgramStep, RecordedStep},
     data_structures::finalizable_data::FinalizableData,
     processor::{OuterQuery, Processor},
-    rows::UnknownStrategy,
+    rows::{CellValue, UnknownStrategy},
     sequence_iterator::{Action, ProcessingSequenceIterator, SequenceStep},
-    EvalError, EvalValue, FixedData, IncompleteCause, MutableState, QueryCallback,
+    Constraint, EvalError, EvalValue, FixedData, IncompleteCause, MutableState, QueryCallback,
 };
 
 /// A basic processor that knows how to determine a unique satisfying witness
@@ -54,38 +56,214 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> BlockProcessor<'a, 'b, 'c
     pub fn solve(
         &mut self,
         sequence_iterator: &mut ProcessingSequenceIterator,
+    ) -> Result<EvalValue<&'a AlgebraicReference, T>, EvalError<T>> {
+        self.solve_internal(sequence_iterator, None)
+    }
+
+    /// Like [BlockProcessor::solve], but also records each step and the cells it
+    /// updated, so that a [BlockProgram] can be compiled.
+    pub fn solve_and_record(
+        &mut self,
+        sequence_iterator: &mut ProcessingSequenceIterator,
+        recorded_steps: &mut Vec<RecordedStep<T>>,
+    ) -> Result<EvalValue<&'a AlgebraicReference, T>, EvalError<T>> {
+        self.solve_internal(sequence_iterator, Some(recorded_steps))
+    }
+
+    fn solve_internal(
+        &mut self,
+        sequence_iterator: &mut ProcessingSequenceIterator,
+        mut recorded_steps: Option<&mut Vec<RecordedStep<T>>>,
     ) -> Result<EvalValue<&'a AlgebraicReference, T>, EvalError<T>> {
         let mut outer_assignments = vec![];
 
-        while let Some(SequenceStep { row_delta, action }) = sequence_iterator.next() {
-            let row_index = (1 + row_delta) as usize;
-            let progress = match action {
-                Action::InternalIdentity(identity_index) => {
-                    self.processor
-                        .process_identity(
-                            row_index,
-                            self.identities[identity_index],
-                            UnknownStrategy::Unknown,
-                        )?
-                        .progress
+        while let Some(step) = sequence_iterator.next() {
+            let row_index = (1 + step.row_delta) as usize;
+            let rows_before = recorded_steps.as_ref().map(|_| {
+                (
+                    self.processor.row(row_index).clone(),
+                    self.processor.row(row_index + 1).clone(),
+                )
+            });
+            let outer_assignment_count = outer_assignments.len();
+
+            let progress = self.process_step(&step, &mut outer_assignments)?;
+            sequence_iterator.report_progress(progress);
+
+            if let (Some(recorded_steps), Some((current, next))) =
+                (recorded_steps.as_mut(), rows_before)
+            {
+                let (mut assigned, mut range_constraints, mut other_updates) =
+                    (vec![], vec![], false);
+                if progress {
+                    for (is_next, before, after) in [
+                        (false, &current, self.processor.row(row_index)),
+                        (true, &next, self.processor.row(row_index + 1)),
+                    ] {
+                        for (poly_id, cell) in after.iter() {
+                            let before = &before[&poly_id].value;
+                            if !before.is_known() && cell.value.is_known() {
+                                assigned.push((poly_id, is_next));
+                                range_constraints.push(match before {
+                                    CellValue::RangeConstraint(c) => Some(c.clone()),
+                                    _ => None,
+                                });
+                            } else if *before != cell.value {
+                                other_updates = true;
+                            }
+                        }
+                    }
+                }
+                recorded_steps.push(RecordedStep {
+                    step,
+                    progress,
+                    assigned,
+                    range_constraints,
+                    other_updates: other_updates
+                        || outer_assignments.len() != outer_assignment_count,
+                });
+            }
+        }
+
+        Ok(self.outer_query_result(outer_assignments))
+    }
+
+    /// Runs a [BlockProgram] compiled from a previous successful run of the solver.
+    /// Returns `None` if the program does not apply to the current inputs (e.g. because a
+    /// cell that was known in the previous run is unknown now, a division by zero would
+    /// be necessary or an identity is not satisfied). In that case, the caller should fall
+    /// back to the generic solver. Since the steps of the program are replayed on the
+    /// machine's state, this is only safe if the identities do not have side effects.
+    pub fn run_program(
+        &mut self,
+        program: &BlockProgram<T>,
+    ) -> Result<Option<EvalValue<&'a AlgebraicReference, T>>, EvalError<T>> {
+        let mut outer_assignments = vec![];
+        for step in program.steps() {
+            match step {
+                ProgramStep::Assign {
+                    row_delta,
+                    target,
+                    coefficient,
+                    offset,
+                } => {
+                    let row_index = (1 + row_delta) as usize;
+                    let coefficient = self.processor.evaluate_to_value(row_index, coefficient);
+                    let offset = self.processor.evaluate_to_value(row_index, offset);
+                    let (Some(coefficient), Some(offset)) = (coefficient, offset) else {
+                        return Ok(None);
+                    };
+                    if coefficient.is_zero()
+                        || !self
+                            .processor
+                            .set_value(row_index, target, -offset / coefficient)
+                    {
+                        return Ok(None);
+                    }
                 }
-                Action::OuterQuery => {
-                    let (progress, new_outer_assignments) =
-                        self.processor.process_outer_query(row_index)?;
-                    outer_assignments.extend(new_outer_assignments);
-                    progress
+                ProgramStep::Decompose {
+                    row_delta,
+                    targets,
+                    range_constraints,
+                    offset,
+                } => {
+                    let row_index = (1 + row_delta) as usize;
+                    let expression = targets
+                        .iter()
+                        .enumerate()
+                        .map(|(index, (_, coefficient))| {
+                            self.processor
+                                .evaluate_to_value(row_index, coefficient)
+                                .map(|c| AffineExpression::from_variable_id(index) * c)
+                        })
+                        .chain([self
+                            .processor
+                            .evaluate_to_value(row_index, offset)
+                            .map(AffineExpression::from)])
+                        .collect::<Option<Vec<_>>>()
+                        .and_then(|summands| summands.into_iter().reduce(|a, b| a + b));
+                    let Some(values) =
+                        expression.and_then(|e| solve_decomposition(&e, range_constraints))
+                    else {
+                        return Ok(None);
+                    };
+                    for ((target, _), value) in targets.iter().zip(values) {
+                        if !self.processor.set_value(row_index, target, value) {
+                            return Ok(None);
+                        }
+                    }
                 }
-                Action::ProverQueries => self.processor.process_queries(row_index)?,
+                ProgramStep::Generic(step) => {
+                    self.process_step(step, &mut outer_assignments)?;
+                }
+            }
+        }
+
+        let result = self.outer_query_result(outer_assignments);
+        if !result.is_complete() {
+            return Ok(None);
+        }
+
+        // The program only contains the steps that made progress in the recorded run,
+        // so identities that were only checked there have to be checked again.
+        // Because the recorded run reached a fixed point, no identity may make progress.
+        // Polynomial identities on known cells are checked by evaluating them directly.
+        for &(row_delta, identity_index) in program.checks() {
+            let row_index = (1 + row_delta) as usize;
+            let identity = self.identities[identity_index];
+            let value = (identity.kind == IdentityKind::Polynomial)
+                .then(|| {
+                    self.processor
+                        .evaluate_to_value(row_index, identity.expression_for_poly_id())
+                })
+                .flatten();
+            let settled = match value {
+                Some(value) => value.is_zero(),
+                None => self.processor.is_identity_settled(row_index, identity),
             };
-            sequence_iterator.report_progress(progress);
+            if !settled {
+                return Ok(None);
+            }
         }
+        Ok(Some(result))
+    }
+
+    fn process_step(
+        &mut self,
+        SequenceStep { row_delta, action }: &SequenceStep,
+        outer_assignments: &mut Vec<(&'a AlgebraicReference, Constraint<T>)>,
+    ) -> Result<bool, EvalError<T>> {
+        let row_index = (1 + row_delta) as usize;
+        Ok(match action {
+            Action::InternalIdentity(identity_index) => {
+                self.processor
+                    .process_identity(
+                        row_index,
+                        self.identities[*identity_index],
+                        UnknownStrategy::Unknown,
+                    )?
+                    .progress
+            }
+            Action::OuterQuery => {
+                let (progress, new_outer_assignments) =
+                    self.processor.process_outer_query(row_index)?;
+                outer_assignments.extend(new_outer_assignments);
+                progress
+            }
+            Action::ProverQueries => self.processor.process_queries(row_index)?,
+        })
+    }
 
+    fn outer_query_result(
+        &self,
+        outer_assignments: Vec<(&'a AlgebraicReference, Constraint<T>)>,
+    ) -> EvalValue<&'a AlgebraicReference, T> {
         match self.processor.finshed_outer_query() {
-            true => Ok(EvalValue::complete(outer_assignments)),
-            false => Ok(EvalValue::incomplete_with_constraints(
+            true => EvalValue::complete(outer_assignments),
+            false => EvalValue::incomplete_with_constraints(
                 outer_assignments,
                 IncompleteCause::BlockMachineLookupIncomplete,
-            )),
+            ),
         }
     }
 
@@ -168,6 +346,7 @@ mod tests {
             fixed_lookup: &mut fixed_lookup,
             machines: Machines::from(machines.iter_mut()),
             query_callback: &mut query_callback,
+            speculative: false,
         };
         let row_offset = 0;
         let identities = analyzed.identities.iter().collect::<Vec<_>>();
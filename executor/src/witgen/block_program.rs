@@ -0,0 +1,611 @@
+use std::collections::BTreeMap;
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, Identity, IdentityKind, PolyID,
+};
+use powdr_ast::parsed::utils::expr_any;
+use powdr_number::FieldElement;
+
+use super::affine_expression::AffineExpression;
+use super::global_constraints::RangeConstraintSet;
+use super::range_constraints::RangeConstraint;
+use super::sequence_iterator::{Action, SequenceCacheKey, SequenceStep};
+use super::Constraint;
+
+/// A step of the generic solver, together with the cells it updated.
+/// Recorded while solving a block with [super::block_processor::BlockProcessor::solve_and_record].
+#[derive(Clone, Debug)]
+pub struct RecordedStep<T: FieldElement> {
+    pub step: SequenceStep,
+    /// Whether the step made progress. Steps without progress only checked their identity.
+    pub progress: bool,
+    /// The cells that became known in this step, as (column, next) relative to the step's row.
+    pub assigned: Vec<(PolyID, bool)>,
+    /// The range constraints the cells in `assigned` had before the step, in the same order.
+    pub range_constraints: Vec<Option<RangeConstraint<T>>>,
+    /// Whether the step also updated range constraints or made progress on the outer query.
+    pub other_updates: bool,
+}
+
+/// A single step of a [BlockProgram].
+#[derive(Clone, Debug)]
+pub enum ProgramStep<T: FieldElement> {
+    /// Directly compute the value of a cell as `-offset / coefficient`, where `coefficient`
+    /// and `offset` only reference cells that are known at this point of the program.
+    Assign {
+        row_delta: i64,
+        target: AlgebraicReference,
+        coefficient: Expression<T>,
+        offset: Expression<T>,
+    },
+    /// Compute the values of several cells from a polynomial identity
+    /// `sum_i coefficient_i * target_i + offset = 0` through the bit masks of their range
+    /// constraints (recorded before the step), like the generic solver does.
+    /// The coefficients and the offset only reference cells that are known at this point.
+    Decompose {
+        row_delta: i64,
+        targets: Vec<(AlgebraicReference, Expression<T>)>,
+        range_constraints: Vec<RangeConstraint<T>>,
+        offset: Expression<T>,
+    },
+    /// Run a step of the generic solver (lookups, queries, outer query...).
+    Generic(SequenceStep),
+}
+
+/// A straight-line program that computes all cells of a block from the inputs.
+/// It is derived from a successful run of the generic solver: Every step that determined
+/// cells from a polynomial identity is turned into a direct evaluation, all other steps
+/// that made progress are replayed using the generic solver.
+/// Steps that only updated range constraints are dropped if all bit decompositions are
+/// evaluated directly, since those use the recorded range constraints instead.
+#[derive(Clone, Debug)]
+pub struct BlockProgram<T: FieldElement> {
+    steps: Vec<ProgramStep<T>>,
+    /// The identities (as row delta and index) that are not guaranteed to hold after running
+    /// the steps, because they were only checked in the recorded run or last made progress
+    /// in a generic step.
+    checks: Vec<(i64, usize)>,
+}
+
+impl<T: FieldElement> BlockProgram<T> {
+    pub fn compile(identities: &[&Identity<Expression<T>>], recorded: &[RecordedStep<T>]) -> Self {
+        let compiled = recorded
+            .iter()
+            .map(|recorded| {
+                recorded
+                    .progress
+                    .then(|| try_compile_step(identities, recorded))
+                    .flatten()
+            })
+            .collect::<Vec<_>>();
+        // Range constraints are only needed by bit decompositions that are not compiled.
+        let keep_range_constraint_updates =
+            recorded.iter().zip(&compiled).any(|(recorded, step)| {
+                let is_polynomial_identity = matches!(
+                    recorded.step.action,
+                    Action::InternalIdentity(index) if identities[index].kind == IdentityKind::Polynomial
+                );
+                is_polynomial_identity && recorded.assigned.len() > 1 && step.is_none()
+            });
+
+        let mut steps = vec![];
+        // Whether an identity on a row is satisfied by construction, because the last step
+        // that made progress on it is a direct evaluation of a single cell.
+        let mut satisfied = BTreeMap::new();
+        for (recorded, step) in recorded.iter().zip(compiled) {
+            let identity = match recorded.step.action {
+                Action::InternalIdentity(identity_index) => {
+                    Some((recorded.step.row_delta, identity_index))
+                }
+                _ => None,
+            };
+            let skip = !recorded.progress
+                || (!keep_range_constraint_updates
+                    && identity.is_some()
+                    && recorded.assigned.is_empty());
+            if skip {
+                if let Some(identity) = identity {
+                    satisfied.entry(identity).or_insert(false);
+                }
+                continue;
+            }
+            let step = step.unwrap_or_else(|| ProgramStep::Generic(recorded.step.clone()));
+            if let Some(identity) = identity {
+                satisfied.insert(identity, matches!(step, ProgramStep::Assign { .. }));
+            }
+            steps.push(step);
+        }
+        let checks = satisfied
+            .into_iter()
+            .filter(|(_, satisfied)| !satisfied)
+            .map(|(identity, _)| identity)
+            .collect();
+        BlockProgram { steps, checks }
+    }
+
+    pub fn steps(&self) -> &[ProgramStep<T>] {
+        &self.steps
+    }
+
+    /// The identities (as row delta and index) that have to be checked after running the steps.
+    pub fn checks(&self) -> &[(i64, usize)] {
+        &self.checks
+    }
+
+    /// The number of steps that are evaluated directly.
+    pub fn compiled_step_count(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|s| !matches!(s, ProgramStep::Generic(_)))
+            .count()
+    }
+}
+
+fn try_compile_step<T: FieldElement>(
+    identities: &[&Identity<Expression<T>>],
+    recorded: &RecordedStep<T>,
+) -> Option<ProgramStep<T>> {
+    let Action::InternalIdentity(identity_index) = recorded.step.action else {
+        return None;
+    };
+    let identity = identities[identity_index];
+    if identity.kind != IdentityKind::Polynomial || recorded.other_updates {
+        return None;
+    }
+    let expression = identity.expression_for_poly_id();
+    let row_delta = recorded.step.row_delta;
+    if let [(poly_id, next)] = recorded.assigned[..] {
+        let target = find_reference(expression, &poly_id, next)?;
+        let (coefficient, offset) = split_affine(expression, target)?;
+        return Some(ProgramStep::Assign {
+            row_delta,
+            target: target.clone(),
+            coefficient: coefficient?,
+            offset: offset.unwrap_or(Expression::Number(T::zero())),
+        });
+    }
+
+    let targets = recorded
+        .assigned
+        .iter()
+        .map(|(poly_id, next)| find_reference(expression, poly_id, *next))
+        .collect::<Option<Vec<_>>>()?;
+    let range_constraints = recorded
+        .range_constraints
+        .iter()
+        .cloned()
+        .collect::<Option<Vec<_>>>()?;
+    let mut offset = Some(expression.clone());
+    let mut decomposition = vec![];
+    for target in &targets {
+        let (coefficient, rest) = split_affine(offset.as_ref()?, target)?;
+        let coefficient = coefficient?;
+        if targets.iter().any(|t| references(&coefficient, t)) {
+            return None;
+        }
+        decomposition.push(((*target).clone(), coefficient));
+        offset = rest;
+    }
+    (!decomposition.is_empty()).then(|| ProgramStep::Decompose {
+        row_delta,
+        targets: decomposition,
+        range_constraints,
+        offset: offset.unwrap_or(Expression::Number(T::zero())),
+    })
+}
+
+struct RecordedRangeConstraints<'a, T: FieldElement>(&'a [RangeConstraint<T>]);
+
+impl<'a, T: FieldElement> RangeConstraintSet<usize, T> for RecordedRangeConstraints<'a, T> {
+    fn range_constraint(&self, id: usize) -> Option<RangeConstraint<T>> {
+        self.0.get(id).cloned()
+    }
+}
+
+/// Solves `expression = 0` for its variables, which are indices into `range_constraints`,
+/// in the same way as the generic solver.
+/// Returns `None` if this does not determine the values of all variables.
+pub fn solve_decomposition<T: FieldElement>(
+    expression: &AffineExpression<usize, T>,
+    range_constraints: &[RangeConstraint<T>],
+) -> Option<Vec<T>> {
+    let result = expression
+        .solve_with_range_constraints(&RecordedRangeConstraints(range_constraints))
+        .ok()?;
+    if !result.is_complete() {
+        return None;
+    }
+    let mut values = vec![None; range_constraints.len()];
+    for (index, constraint) in result.constraints {
+        if let Constraint::Assignment(value) = constraint {
+            values[index] = Some(value);
+        }
+    }
+    values.into_iter().collect()
+}
+
+fn find_reference<'e, T>(
+    expr: &'e Expression<T>,
+    poly_id: &PolyID,
+    next: bool,
+) -> Option<&'e AlgebraicReference> {
+    match expr {
+        Expression::Reference(r) if r.poly_id == *poly_id && r.next == next => Some(r),
+        Expression::BinaryOperation(left, _, right) => {
+            find_reference(left, poly_id, next).or_else(|| find_reference(right, poly_id, next))
+        }
+        Expression::UnaryOperation(_, inner) => find_reference(inner, poly_id, next),
+        _ => None,
+    }
+}
+
+fn references<T>(expr: &Expression<T>, target: &AlgebraicReference) -> bool {
+    expr_any(
+        expr,
+        |e| matches!(e, Expression::Reference(r) if r == target),
+    )
+}
+
+type SplitExpression<T> = (Option<Expression<T>>, Option<Expression<T>>);
+
+/// Splits `expr` into `coefficient * target + offset`, such that neither `coefficient` nor
+/// `offset` reference `target`. `None` components are zero.
+/// Returns `None` if `expr` is not affine in `target`.
+fn split_affine<T: FieldElement>(
+    expr: &Expression<T>,
+    target: &AlgebraicReference,
+) -> Option<SplitExpression<T>> {
+    if !references(expr, target) {
+        return Some((None, Some(expr.clone())));
+    }
+    match expr {
+        Expression::Reference(_) => Some((Some(Expression::Number(T::one())), None)),
+        Expression::BinaryOperation(left, op, right) => match op {
+            AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                let (left_coeff, left_offset) = split_affine(left, target)?;
+                let (right_coeff, right_offset) = split_affine(right, target)?;
+                Some((
+                    combine(left_coeff, *op, right_coeff),
+                    combine(left_offset, *op, right_offset),
+                ))
+            }
+            AlgebraicBinaryOperator::Mul => {
+                let (factor, affine) = match (references(left, target), references(right, target)) {
+                    (true, false) => (right, split_affine(left, target)?),
+                    (false, true) => (left, split_affine(right, target)?),
+                    _ => return None,
+                };
+                let scale = |e: Option<Expression<T>>| {
+                    e.map(|e| match e {
+                        Expression::Number(n) if n.is_one() => (**factor).clone(),
+                        e => (**factor).clone() * e,
+                    })
+                };
+                Some((scale(affine.0), scale(affine.1)))
+            }
+            AlgebraicBinaryOperator::Pow => None,
+        },
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, inner) => {
+            let (coeff, offset) = split_affine(inner, target)?;
+            let negate = |e: Option<Expression<T>>| {
+                e.map(|e| Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, Box::new(e)))
+            };
+            Some((negate(coeff), negate(offset)))
+        }
+        Expression::PublicReference(_) | Expression::Number(_) => unreachable!(),
+    }
+}
+
+fn combine<T>(
+    left: Option<Expression<T>>,
+    op: AlgebraicBinaryOperator,
+    right: Option<Expression<T>>,
+) -> Option<Expression<T>> {
+    match (left, right) {
+        (None, None) => None,
+        (Some(l), None) => Some(l),
+        (None, Some(r)) => Some(match op {
+            AlgebraicBinaryOperator::Sub => {
+                Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, Box::new(r))
+            }
+            _ => r,
+        }),
+        (Some(l), Some(r)) => Some(Expression::new_binary(l, op, r)),
+    }
+}
+
+/// After this many consecutive runs in which the program of an input pattern did not apply,
+/// the generic solver is used for that pattern from then on.
+const MAX_CONSECUTIVE_FAILURES: usize = 8;
+
+/// Compiled programs of a block machine, per input pattern.
+pub struct BlockProgramCache<T: FieldElement> {
+    /// `None` means that compilation was attempted, but the program turned out not to
+    /// be applicable, so the generic solver should be used.
+    programs: BTreeMap<SequenceCacheKey, Option<BlockProgram<T>>>,
+    /// The number of consecutive runs in which the program did not apply, per input pattern.
+    failures: BTreeMap<SequenceCacheKey, usize>,
+}
+
+impl<T: FieldElement> Default for BlockProgramCache<T> {
+    fn default() -> Self {
+        BlockProgramCache {
+            programs: Default::default(),
+            failures: Default::default(),
+        }
+    }
+}
+
+impl<T: FieldElement> BlockProgramCache<T> {
+    pub fn get(&self, key: &SequenceCacheKey) -> Option<&BlockProgram<T>> {
+        self.programs.get(key).and_then(|p| p.as_ref())
+    }
+
+    /// Returns true if we never tried to compile a program for this input pattern.
+    pub fn should_compile(&self, key: &SequenceCacheKey) -> bool {
+        !self.programs.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: SequenceCacheKey, program: BlockProgram<T>) {
+        self.programs.insert(key, Some(program));
+    }
+
+    /// Marks the input pattern as not suitable for compilation.
+    pub fn disable(&mut self, key: SequenceCacheKey) {
+        self.programs.insert(key, None);
+    }
+
+    pub fn report_success(&mut self, key: &SequenceCacheKey) {
+        self.failures.remove(key);
+    }
+
+    /// Reports that the program for the given input pattern did not apply to the inputs.
+    /// Returns true if the program has been disabled as a consequence.
+    pub fn report_failure(&mut self, key: &SequenceCacheKey) -> bool {
+        let failures = self.failures.entry(key.clone()).or_default();
+        *failures += 1;
+        if *failures >= MAX_CONSECUTIVE_FAILURES {
+            self.failures.remove(key);
+            self.disable(key.clone());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_ast::analyzed::PolynomialType;
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use super::*;
+
+    fn recorded(
+        identity_index: usize,
+        assigned: Vec<(PolyID, bool)>,
+        other_updates: bool,
+    ) -> RecordedStep<GoldilocksField> {
+        RecordedStep {
+            step: SequenceStep {
+                row_delta: 0,
+                action: Action::InternalIdentity(identity_index),
+            },
+            progress: true,
+            range_constraints: vec![None; assigned.len()],
+            assigned,
+            other_updates,
+        }
+    }
+
+    fn checked(row_delta: i64, identity_index: usize) -> RecordedStep<GoldilocksField> {
+        RecordedStep {
+            step: SequenceStep {
+                row_delta,
+                action: Action::InternalIdentity(identity_index),
+            },
+            progress: false,
+            assigned: vec![],
+            range_constraints: vec![],
+            other_updates: false,
+        }
+    }
+
+    fn witness(id: u64) -> PolyID {
+        PolyID {
+            id,
+            ptype: PolynomialType::Committed,
+        }
+    }
+
+    #[test]
+    fn compile_affine_steps() {
+        let src = r#"
+            namespace Main(8);
+                col witness a, b, c;
+                col fixed F = [2]*;
+                F * (a + 3) - b * (c' - 7) = 0;
+                a * b = c;
+                { a } in { b };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let identities = analyzed.identities.iter().collect::<Vec<_>>();
+        let program = BlockProgram::compile(
+            &identities,
+            &[
+                // a is determined from the first identity.
+                recorded(0, vec![(witness(0), false)], false),
+                // c' is determined from the first identity.
+                recorded(0, vec![(witness(2), true)], false),
+                // Affine in a, with coefficient b.
+                recorded(1, vec![(witness(0), false)], false),
+                // Lookups are never compiled.
+                recorded(2, vec![(witness(0), false)], false),
+                // Range constraint updates are not reproduced.
+                recorded(0, vec![(witness(0), false)], true),
+            ],
+        );
+        let steps = program.steps();
+        assert_eq!(program.compiled_step_count(), 3);
+        let ProgramStep::Assign {
+            coefficient,
+            offset,
+            target,
+            ..
+        } = &steps[0]
+        else {
+            panic!()
+        };
+        assert_eq!(target.name, "Main.a");
+        assert_eq!(coefficient.to_string(), "Main.F");
+        assert_eq!(
+            offset.to_string(),
+            "(((Main.F * 3) - (Main.b * (Main.c' - 7))) - 0)"
+        );
+        let ProgramStep::Assign {
+            coefficient,
+            offset,
+            target,
+            ..
+        } = &steps[1]
+        else {
+            panic!()
+        };
+        assert_eq!(target.name, "Main.c");
+        assert!(target.next);
+        assert_eq!(coefficient.to_string(), "-Main.b");
+        assert_eq!(
+            offset.to_string(),
+            "(((Main.F * (Main.a + 3)) - (Main.b * -7)) - 0)"
+        );
+        assert!(matches!(&steps[2], ProgramStep::Assign { .. }));
+        assert!(matches!(&steps[3], ProgramStep::Generic(_)));
+        assert!(matches!(&steps[4], ProgramStep::Generic(_)));
+        // The first identity last made progress in a generic step.
+        assert_eq!(program.checks(), &[(0, 0), (0, 2)]);
+    }
+
+    #[test]
+    fn check_identities_without_progress() {
+        let src = r#"
+            namespace Main(8);
+                col witness a, b;
+                a = b + 1;
+                a * b = 6;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let identities = analyzed.identities.iter().collect::<Vec<_>>();
+        let program = BlockProgram::compile(
+            &identities,
+            &[
+                checked(0, 0),
+                checked(0, 1),
+                checked(1, 0),
+                // a is determined from the first identity.
+                recorded(0, vec![(witness(0), false)], false),
+                checked(0, 0),
+                checked(0, 1),
+            ],
+        );
+        assert_eq!(program.steps().len(), 1);
+        assert_eq!(program.checks(), &[(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn compile_decomposition() {
+        let src = r#"
+            namespace Main(8);
+                col witness A, A_byte;
+                col fixed FACTOR = [1, 256]*;
+                A' = A + A_byte * FACTOR;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let identities = analyzed.identities.iter().collect::<Vec<_>>();
+        let byte = RangeConstraint::from_max_bit(7);
+        let decomposition = RecordedStep {
+            range_constraints: vec![Some(byte.clone()), Some(byte.clone())],
+            ..recorded(0, vec![(witness(0), false), (witness(1), false)], false)
+        };
+        // Only updates range constraints.
+        let range_constraint_update = recorded(0, vec![], true);
+
+        let program = BlockProgram::compile(
+            &identities,
+            &[range_constraint_update.clone(), decomposition.clone()],
+        );
+        assert_eq!(program.steps().len(), 1);
+        let ProgramStep::Decompose {
+            targets, offset, ..
+        } = &program.steps()[0]
+        else {
+            panic!()
+        };
+        let targets = targets
+            .iter()
+            .map(|(target, coefficient)| format!("{}: {coefficient}", target.name))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, ["Main.A: -1", "Main.A_byte: -Main.FACTOR"]);
+        assert_eq!(offset.to_string(), "Main.A'");
+        // Decompositions are not guaranteed to satisfy the identity.
+        assert_eq!(program.checks(), &[(0, 0)]);
+
+        // Without range constraints, the decomposition is replayed by the generic solver,
+        // which needs the range constraint updates.
+        let program = BlockProgram::compile(
+            &identities,
+            &[
+                range_constraint_update,
+                recorded(0, vec![(witness(0), false), (witness(1), false)], false),
+            ],
+        );
+        assert_eq!(program.steps().len(), 2);
+        assert_eq!(program.compiled_step_count(), 0);
+    }
+
+    #[test]
+    fn solve_recorded_decomposition() {
+        let expression = AffineExpression::from(GoldilocksField::from(0x1504))
+            - AffineExpression::from_variable_id(0) * 256.into()
+            - AffineExpression::from_variable_id(1);
+        let constraints = [
+            RangeConstraint::from_max_bit(7),
+            RangeConstraint::from_max_bit(7),
+        ];
+        assert_eq!(
+            solve_decomposition(&expression, &constraints),
+            Some(vec![0x15.into(), 0x4.into()])
+        );
+        let constraints = [
+            RangeConstraint::from_max_bit(7),
+            RangeConstraint::from_max_bit(1),
+        ];
+        assert_eq!(solve_decomposition(&expression, &constraints), None);
+    }
+
+    #[test]
+    fn disable_after_consecutive_failures() {
+        let inputs: &[AffineExpression<&AlgebraicReference, GoldilocksField>] = &[];
+        let key = SequenceCacheKey::from(inputs);
+        let mut cache = BlockProgramCache::<GoldilocksField>::default();
+        cache.insert(
+            key.clone(),
+            BlockProgram {
+                steps: vec![],
+                checks: vec![],
+            },
+        );
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            assert!(!cache.report_failure(&key));
+        }
+        cache.report_success(&key);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            assert!(!cache.report_failure(&key));
+        }
+        assert!(cache.get(&key).is_some());
+        assert!(cache.report_failure(&key));
+        assert!(cache.get(&key).is_none());
+        assert!(!cache.should_compile(&key));
+    }
+}
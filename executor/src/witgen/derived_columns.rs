@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, Identity, IdentityKind, PolyID,
+};
+use powdr_ast::parsed::utils::expr_any;
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_number::FieldElement;
+
+use super::FixedData;
+
+/// A witness column whose value is given by an expression in other columns.
+pub struct DerivedColumn<T> {
+    pub reference: AlgebraicReference,
+    /// The defining expression, which does not reference derived columns.
+    pub expression: Expression<T>,
+}
+
+/// Removes the defining identities of the derived columns introduced by the PIL optimizer
+/// (see [powdr_ast::analyzed::Symbol::derived]) and inlines their definitions into the other
+/// identities, so that they do not influence machine detection and solving. Their values are
+/// computed from the other columns after witness generation using [compute_derived_columns].
+pub fn inline_derived_columns<T: FieldElement>(
+    identities: Vec<Identity<Expression<T>>>,
+    derived_columns: &BTreeSet<PolyID>,
+) -> (Vec<Identity<Expression<T>>>, Vec<DerivedColumn<T>>) {
+    let mut derived: Vec<DerivedColumn<T>> = vec![];
+    let mut remaining = vec![];
+    for identity in identities {
+        match try_to_definition(&identity, derived_columns) {
+            Some((reference, mut expression)) => {
+                substitute(&mut expression, &derived);
+                derived.push(DerivedColumn {
+                    reference,
+                    expression,
+                })
+            }
+            None => remaining.push(identity),
+        }
+    }
+    // Derived columns can only be inlined if they are not referenced on the next row.
+    for identity in &remaining {
+        identity.pre_visit_expressions(&mut |e| {
+            if let Expression::Reference(r) = e {
+                assert!(
+                    !(r.next && derived.iter().any(|c| c.reference.poly_id == r.poly_id)),
+                    "Column {r} introduced by the optimizer cannot be referenced on the next row."
+                );
+            }
+        });
+    }
+    for identity in &mut remaining {
+        identity.post_visit_expressions_mut(&mut |e| substitute(e, &derived));
+    }
+    (remaining, derived)
+}
+
+/// If the identity is of the form `w - e = 0` for a derived column `w`, returns `w` and `e`.
+fn try_to_definition<T: Clone>(
+    identity: &Identity<Expression<T>>,
+    derived_columns: &BTreeSet<PolyID>,
+) -> Option<(AlgebraicReference, Expression<T>)> {
+    if identity.kind != IdentityKind::Polynomial {
+        return None;
+    }
+    match identity.expression_for_poly_id() {
+        Expression::BinaryOperation(left, AlgebraicBinaryOperator::Sub, right) => {
+            match left.as_ref() {
+                Expression::Reference(r) if !r.next && derived_columns.contains(&r.poly_id) => {
+                    let references_itself = expr_any(
+                        right.as_ref(),
+                        |e| matches!(e, Expression::Reference(other) if other.poly_id == r.poly_id),
+                    );
+                    (!references_itself).then(|| (r.clone(), (**right).clone()))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Replaces non-next references to derived columns by their definition.
+fn substitute<T: Clone>(e: &mut Expression<T>, derived: &[DerivedColumn<T>]) {
+    e.post_visit_expressions_mut(&mut |e| {
+        if let Expression::Reference(r) = e {
+            if let Some(column) = derived
+                .iter()
+                .find(|c| c.reference.poly_id == r.poly_id && !r.next)
+            {
+                *e = column.expression.clone();
+            }
+        }
+    });
+}
+
+/// Computes the values of the derived columns from the values of the other witness columns.
+pub fn compute_derived_columns<T: FieldElement>(
+    fixed: &FixedData<T>,
+    derived: &[DerivedColumn<T>],
+    columns: &BTreeMap<String, Vec<T>>,
+) -> Vec<(String, Vec<T>)> {
+    let degree = fixed.degree as usize;
+    derived
+        .iter()
+        .map(|column| {
+            let values = (0..degree)
+                .map(|row| evaluate(fixed, columns, &column.expression, row))
+                .collect();
+            (column.reference.name.clone(), values)
+        })
+        .collect()
+}
+
+fn evaluate<T: FieldElement>(
+    fixed: &FixedData<T>,
+    columns: &BTreeMap<String, Vec<T>>,
+    e: &Expression<T>,
+    row: usize,
+) -> T {
+    let evaluate = |e| evaluate(fixed, columns, e, row);
+    match e {
+        Expression::Reference(r) => {
+            let row = (row + r.next as usize) % fixed.degree as usize;
+            if r.is_witness() {
+                columns[&r.name][row]
+            } else {
+                fixed.fixed_cols[&r.poly_id].values[row]
+            }
+        }
+        Expression::PublicReference(name) => {
+            panic!("Public references are not supported in derived columns: {name}")
+        }
+        Expression::Number(n) => *n,
+        Expression::BinaryOperation(left, op, right) => {
+            let left = evaluate(left);
+            match op {
+                AlgebraicBinaryOperator::Add => left + evaluate(right),
+                AlgebraicBinaryOperator::Sub => left - evaluate(right),
+                AlgebraicBinaryOperator::Mul => left * evaluate(right),
+                AlgebraicBinaryOperator::Pow => match right.as_ref() {
+                    Expression::Number(exponent) => left.pow(exponent.to_integer()),
+                    _ => panic!("Exponent has to be a number: {right}"),
+                },
+            }
+        }
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, inner) => -evaluate(inner),
+    }
+}
@@ -47,6 +47,8 @@ pub enum IncompleteCause<K = usize> {
     SolvingFailed,
     /// Some knowledge was learnt, but not a concrete value. Example: `Y = X` if we know that `Y` is boolean. We learn that `X` is boolean, but not its exact value.
     NotConcrete,
+    /// Witness generation was cancelled.
+    Cancelled,
     Multiple(Vec<IncompleteCause<K>>),
 }
 
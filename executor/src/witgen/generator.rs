@@ -15,11 +15,14 @@ use super::affine_expression::AffineExpression;
 use super::block_processor::BlockProcessor;
 use super::data_structures::column_map::WitnessColumnMap;
 use super::global_constraints::GlobalConstraints;
+use super::identity_processor::IdentityProcessor;
 use super::machines::{FixedLookup, Machine};
-use super::rows::{Row, RowFactory};
+use super::rows::{Row, RowFactory, RowPair, UnknownStrategy};
 use super::sequence_iterator::{DefaultSequenceIterator, ProcessingSequenceIterator};
 use super::vm_processor::VmProcessor;
-use super::{EvalResult, FixedData, MutableState, QueryCallback};
+use super::{
+    Cancelled, EvalError, EvalResult, FixedData, MutableState, QueryCallback, WitgenError,
+};
 
 struct ProcessResult<'a, T: FieldElement> {
     eval_value: EvalValue<&'a AlgebraicReference, T>,
@@ -34,6 +37,8 @@ pub struct Generator<'a, T: FieldElement> {
     data: FinalizableData<'a, T>,
     latch: Option<Expression<T>>,
     name: String,
+    /// Whether the witness columns were provided externally instead of being solved.
+    uses_external_witness: bool,
 }
 
 impl<'a, T: FieldElement> Machine<'a, T> for Generator<'a, T> {
@@ -87,23 +92,40 @@ impl<'a, T: FieldElement> Machine<'a, T> for Generator<'a, T> {
         &mut self,
         fixed_lookup: &'b mut FixedLookup<T>,
         query_callback: &'b mut Q,
-    ) -> HashMap<String, Vec<T>> {
+    ) -> Result<HashMap<String, Vec<T>>, EvalError<T>> {
         log::debug!("Finalizing VM: {}", self.name());
 
+        if self.uses_external_witness {
+            return Ok(self
+                .witnesses
+                .iter()
+                .filter_map(|id| {
+                    let values = self.fixed_data.complete_external_witness(id)?;
+                    Some((self.fixed_data.column_name(id).to_string(), values.to_vec()))
+                })
+                .collect());
+        }
+
         // In this stage, we don't have access to other machines, as they might already be finalized.
         let mut mutable_state_no_machines = MutableState {
             fixed_lookup,
             machines: [].into_iter().into(),
             query_callback,
+            speculative: false,
         };
 
         self.fill_remaining_rows(&mut mutable_state_no_machines);
+        if self.fixed_data.is_cancelled() {
+            // The rows are incomplete, but the witness is discarded anyway.
+            return Ok(HashMap::new());
+        }
         self.fix_first_row();
 
-        self.data
+        Ok(self
+            .data
             .take_transposed()
             .map(|(id, (values, _))| (self.fixed_data.column_name(&id).to_string(), values))
-            .collect()
+            .collect())
     }
 }
 
@@ -125,6 +147,7 @@ impl<'a, T: FieldElement> Generator<'a, T> {
             global_range_constraints: global_range_constraints.clone(),
             data,
             latch,
+            uses_external_witness: false,
         }
     }
 
@@ -137,6 +160,85 @@ impl<'a, T: FieldElement> Generator<'a, T> {
         record_end(self.name());
     }
 
+    /// Whether the values of all witness columns of the machine (except for derived columns,
+    /// which are computed afterwards) are provided externally for all rows.
+    pub fn has_complete_external_witness(&self) -> bool {
+        let derived_columns = self.fixed_data.analyzed.derived_columns();
+        self.witnesses
+            .iter()
+            .filter(|id| !derived_columns.contains(id))
+            .all(|id| self.fixed_data.complete_external_witness(id).is_some())
+    }
+
+    /// Like [Generator::run], but takes the values of all witness columns from the external
+    /// witness (see [Generator::has_complete_external_witness]) instead of solving the machine.
+    /// Only the calls to other machines are processed, row by row, so that the other machines
+    /// can compute their witness. Polynomial identities and lookups into fixed columns are
+    /// not checked.
+    pub fn run_with_external_witness<'b, Q: QueryCallback<T>>(
+        &mut self,
+        mutable_state: &mut MutableState<'a, 'b, T, Q>,
+    ) -> Result<(), WitgenError> {
+        record_start(self.name());
+        assert!(self.data.is_empty());
+        let degree = self.fixed_data.degree;
+        log::info!(
+            "Processing the calls of the main machine with the provided witness for {degree} rows"
+        );
+
+        let calls = self
+            .identities
+            .iter()
+            .filter(|identity| {
+                matches!(
+                    identity.kind,
+                    IdentityKind::Plookup | IdentityKind::Permutation
+                ) && identity
+                    .right
+                    .selector
+                    .iter()
+                    .chain(&identity.right.expressions)
+                    .any(|e| e.contains_witness_ref())
+            })
+            .collect::<Vec<_>>();
+        let row_factory = RowFactory::new(self.fixed_data, self.global_range_constraints.clone());
+        let mut current = row_factory.fresh_row(0);
+        for row_index in 0..degree {
+            if self.fixed_data.is_cancelled() {
+                record_end(self.name());
+                return Err(Cancelled.into());
+            }
+            let next = row_factory.fresh_row(row_index + 1);
+            let rows = RowPair::new(
+                &current,
+                &next,
+                row_index,
+                self.fixed_data,
+                UnknownStrategy::Unknown,
+            );
+            for identity in &calls {
+                let message = match IdentityProcessor::new(self.fixed_data, mutable_state)
+                    .process_identity(identity, &rows)
+                {
+                    Ok(result) if result.is_complete() => continue,
+                    Ok(result) => format!("{identity} is incomplete: {:?}", result.status),
+                    Err(e) => format!("{identity} failed: {e}"),
+                };
+                record_end(self.name());
+                return Err(WitgenError::InvalidExternalWitness {
+                    row: row_index,
+                    message,
+                });
+            }
+            current = next;
+        }
+
+        self.uses_external_witness = true;
+        self.fixed_data.report_progress(self.name(), degree);
+        record_end(self.name());
+        Ok(())
+    }
+
     fn fill_remaining_rows<Q: QueryCallback<T>>(
         &mut self,
         mutable_state: &mut MutableState<'a, '_, T, Q>,
@@ -152,7 +254,7 @@ impl<'a, T: FieldElement> Generator<'a, T> {
                 None,
                 false,
             );
-            assert!(eval_value.is_complete());
+            assert!(eval_value.is_complete() || self.fixed_data.is_cancelled());
 
             self.data.extend(block);
         }
@@ -213,6 +315,7 @@ impl<'a, T: FieldElement> Generator<'a, T> {
             [first_row].into_iter(),
         );
         let mut processor = VmProcessor::new(
+            self.name().to_string(),
             row_offset,
             self.fixed_data,
             &self.identities,
@@ -73,6 +73,8 @@ where
 pub struct IdentityProcessor<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> {
     fixed_data: &'a FixedData<'a, T>,
     mutable_state: &'c mut MutableState<'a, 'b, T, Q>,
+    /// Whether a failure to process an identity is handled by the caller.
+    speculative: bool,
 }
 
 impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> IdentityProcessor<'a, 'b, 'c, T, Q> {
@@ -83,6 +85,16 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> IdentityProcessor<'a, 'b,
         Self {
             fixed_data,
             mutable_state,
+            speculative: false,
+        }
+    }
+
+    /// Marks the machine calls made by this processor as speculative, i.e. failures are
+    /// handled by the caller, so machines need to process calls immediately.
+    pub fn speculative(self) -> Self {
+        Self {
+            speculative: true,
+            ..self
         }
     }
 
@@ -172,6 +184,7 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> IdentityProcessor<'a, 'b,
                 fixed_lookup: self.mutable_state.fixed_lookup,
                 machines: others,
                 query_callback: self.mutable_state.query_callback,
+                speculative: self.speculative || self.mutable_state.speculative,
             };
 
             if let Some(result) = current.process_plookup_timed(
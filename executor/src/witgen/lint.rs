@@ -0,0 +1,523 @@
+use std::fmt::{self, Display, Formatter};
+
+use std::collections::{HashMap, HashSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, Analyzed, Identity, IdentityKind, PolyID, PolynomialType,
+};
+use powdr_ast::parsed::utils::expr_any;
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_number::FieldElement;
+
+use super::global_constraints::{determine_global_constraints, GlobalConstraints};
+use super::machines::machine_extractor::{refs_in_identity, refs_in_selected_expressions};
+use super::util::try_to_simple_poly;
+use super::FixedData;
+
+/// A potential soundness issue: A witness column that might not be uniquely
+/// determined by the constraints, so that a malicious prover could choose it freely.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub column: String,
+    pub kind: LintKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// The column is used as a boolean (as a selector, an instruction flag or in `1 - x`),
+    /// but no boolean constraint could be inferred for it.
+    MissingBooleanConstraint,
+    /// The column only appears on the left-hand side of lookups and permutations.
+    OnlyLeftHandSideOfLookups,
+    /// The column only appears on the right-hand side of lookups and permutations.
+    OnlyRightHandSideOfLookups,
+    /// The column is assigned through a prover query and the constraints do not determine its value.
+    OnlyDeterminedByQuery,
+    /// The column does not appear in any constraint.
+    Unconstrained,
+}
+
+impl Display for LintWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let column = &self.column;
+        match self.kind {
+            LintKind::MissingBooleanConstraint => write!(
+                f,
+                "Column {column} is used as a boolean, but is not constrained to be boolean."
+            ),
+            LintKind::OnlyLeftHandSideOfLookups => write!(
+                f,
+                "Column {column} only appears on the left-hand side of lookups, \
+                 make sure it is uniquely determined by them."
+            ),
+            LintKind::OnlyRightHandSideOfLookups => write!(
+                f,
+                "Column {column} only appears on the right-hand side of lookups, \
+                 so its values are not determined by them."
+            ),
+            LintKind::OnlyDeterminedByQuery => write!(
+                f,
+                "Column {column} is assigned through a prover query, \
+                 but its value is not determined by the constraints."
+            ),
+            LintKind::Unconstrained => {
+                write!(f, "Column {column} does not appear in any constraint.")
+            }
+        }
+    }
+}
+
+/// How a witness column is used in the identities.
+#[derive(Clone, Default)]
+struct Usage {
+    /// The column appears in a polynomial identity or is determined by a lookup
+    /// into fixed columns (see [determined_by_lookup]).
+    constrained: bool,
+    in_lookup_left: bool,
+    in_lookup_right: bool,
+    as_boolean: bool,
+}
+
+/// Checks the witness columns of `analyzed` for potential soundness issues,
+/// using the range constraints that witness generation infers.
+/// `instruction_flags` are the names of the instruction flag columns created when compiling
+/// virtual machines to PIL, they need to be boolean even if nothing uses them as booleans.
+/// Returns the warnings in source order of the columns.
+pub fn lint<T: FieldElement>(
+    analyzed: &Analyzed<T>,
+    fixed_col_values: &[(String, Vec<T>)],
+    instruction_flags: &HashSet<String>,
+) -> Vec<LintWarning> {
+    let fixed = FixedData::new(analyzed, fixed_col_values, vec![]);
+    let identities = analyzed.identities_with_inlined_intermediate_polynomials();
+    let (constraints, _) = determine_global_constraints(&fixed, identities.iter().collect());
+
+    let mut usage = fixed.witness_map_with(Usage::default());
+    let witnesses = |refs: HashSet<PolyID>| {
+        refs.into_iter()
+            .filter(|id| id.ptype == PolynomialType::Committed)
+    };
+    for identity in &identities {
+        match identity.kind {
+            IdentityKind::Polynomial | IdentityKind::Connect => {
+                for id in witnesses(refs_in_identity(identity)) {
+                    usage[&id].constrained = true;
+                }
+            }
+            IdentityKind::Plookup | IdentityKind::Permutation => {
+                for id in witnesses(refs_in_selected_expressions(&identity.left)) {
+                    usage[&id].in_lookup_left = true;
+                }
+                if let Some(key) = fixed_lookup_key(&fixed, identity) {
+                    for id in determined_by_lookup(identity, key) {
+                        usage[&id].constrained = true;
+                    }
+                }
+                for id in witnesses(refs_in_selected_expressions(&identity.right)) {
+                    usage[&id].in_lookup_right = true;
+                }
+                for selector in [&identity.left.selector, &identity.right.selector] {
+                    if let Some(Expression::Reference(r)) = selector {
+                        if r.is_witness() {
+                            usage[&r.poly_id].as_boolean = true;
+                        }
+                    }
+                }
+            }
+        }
+        identity.pre_visit_expressions(&mut |e| {
+            if let Some(id) = negated_boolean(e) {
+                usage[&id].as_boolean = true;
+            }
+        });
+    }
+
+    let determined = determined_query_columns(&fixed, &identities);
+
+    fixed
+        .witness_cols
+        .iter()
+        .flat_map(|(id, column)| {
+            let name = &column.poly.name;
+            let usage = &usage[&id];
+            let kind = if column.query.is_some() {
+                (!determined.contains(&id)).then_some(LintKind::OnlyDeterminedByQuery)
+            } else if usage.constrained {
+                None
+            } else if !(usage.in_lookup_left || usage.in_lookup_right) {
+                Some(LintKind::Unconstrained)
+            } else if !usage.in_lookup_right {
+                Some(LintKind::OnlyLeftHandSideOfLookups)
+            } else if !usage.in_lookup_left {
+                Some(LintKind::OnlyRightHandSideOfLookups)
+            } else {
+                None
+            };
+            let missing_boolean_constraint = (usage.as_boolean || instruction_flags.contains(name))
+                && !is_boolean(&constraints, &id);
+            kind.into_iter()
+                .chain(missing_boolean_constraint.then_some(LintKind::MissingBooleanConstraint))
+                .map(|kind| LintWarning {
+                    column: name.clone(),
+                    kind,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// If `identity` is a lookup into fixed columns and one of the fixed columns acts as a key,
+/// i.e. all other fixed columns are a function of it (like the line number in the ROM lookup
+/// of a VM), returns the index of the key.
+fn fixed_lookup_key<T: FieldElement>(
+    fixed: &FixedData<T>,
+    identity: &Identity<Expression<T>>,
+) -> Option<usize> {
+    if identity.kind != IdentityKind::Plookup || identity.right.selector.is_some() {
+        return None;
+    }
+    let right = identity
+        .right
+        .expressions
+        .iter()
+        .map(|e| try_to_simple_poly(e).filter(|r| r.is_fixed()))
+        .collect::<Option<Vec<_>>>()?;
+    let columns = right
+        .iter()
+        .map(|r| fixed.fixed_cols[&r.poly_id].values)
+        .collect::<Vec<_>>();
+    (0..columns.len()).find(|key| is_key(&columns, *key))
+}
+
+/// Returns the witness columns on the left-hand side of a lookup into fixed columns
+/// that are uniquely determined by the left-hand side expression at index `key`.
+fn determined_by_lookup<T: FieldElement>(
+    identity: &Identity<Expression<T>>,
+    key: usize,
+) -> impl Iterator<Item = PolyID> + '_ {
+    identity
+        .left
+        .expressions
+        .iter()
+        .enumerate()
+        .filter(move |(i, _)| *i != key)
+        .filter_map(|(_, e)| try_to_simple_poly(e))
+        .filter(|r| r.is_witness())
+        .map(|r| r.poly_id)
+}
+
+/// Returns the witness columns assigned through a prover query whose values are nevertheless
+/// determined by the constraints, given the values of all columns without a query
+/// (witness generation derives those from the constraints).
+/// A column is determined by
+/// - a polynomial identity that is affine in it, with a nonzero constant coefficient,
+/// - a lookup into fixed columns with a key (see [fixed_lookup_key]), once the key is known,
+/// - a lookup or permutation into another machine, if it corresponds to a known expression
+///   on the other side.
+///
+/// Since determined columns can help to determine further columns, this is repeated until
+/// no new columns are found.
+fn determined_query_columns<T: FieldElement>(
+    fixed: &FixedData<T>,
+    identities: &[Identity<Expression<T>>],
+) -> HashSet<PolyID> {
+    let mut determined = HashSet::new();
+    loop {
+        let unknown = |e: &Expression<T>| {
+            let mut unknown = vec![];
+            e.pre_visit_expressions(&mut |e| {
+                if let Expression::Reference(r) = e {
+                    if r.is_witness()
+                        && fixed.witness_cols[&r.poly_id].query.is_some()
+                        && !determined.contains(&r.poly_id)
+                    {
+                        unknown.push(r.clone());
+                    }
+                }
+            });
+            unknown
+        };
+        let is_known = |e: &Expression<T>| unknown(e).is_empty();
+        let new = identities
+            .iter()
+            .flat_map(|identity| match identity.kind {
+                IdentityKind::Polynomial => {
+                    let expression = identity.expression_for_poly_id();
+                    match &unknown(expression)[..] {
+                        [x, rest @ ..] if rest.iter().all(|r| r == x) => coefficient(expression, x)
+                            .filter(|c| c.is_nonzero())
+                            .map(|_| x.poly_id),
+                        _ => None,
+                    }
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                }
+                IdentityKind::Plookup | IdentityKind::Permutation => {
+                    let (left, right) = (&identity.left.expressions, &identity.right.expressions);
+                    if let Some(key) = fixed_lookup_key(fixed, identity) {
+                        if is_known(&left[key]) {
+                            determined_by_lookup(identity, key).collect()
+                        } else {
+                            vec![]
+                        }
+                    } else {
+                        // The called machine computes the left-hand side from the right-hand side
+                        // (if it references witness columns) and vice versa.
+                        left.iter()
+                            .zip(right)
+                            .flat_map(|(l, r)| {
+                                let left = (references_witness(r) && is_known(r))
+                                    .then(|| try_to_simple_poly(l));
+                                let right = is_known(l).then(|| try_to_simple_poly(r));
+                                [left, right].into_iter().flatten().flatten()
+                            })
+                            .filter(|r| r.is_witness())
+                            .map(|r| r.poly_id)
+                            .collect()
+                    }
+                }
+                IdentityKind::Connect => vec![],
+            })
+            .filter(|id| fixed.witness_cols[id].query.is_some() && !determined.contains(id))
+            .collect::<Vec<_>>();
+        if new.is_empty() {
+            return determined;
+        }
+        determined.extend(new);
+    }
+}
+
+fn references_witness<T: FieldElement>(e: &Expression<T>) -> bool {
+    expr_any(
+        e,
+        |e| matches!(e, Expression::Reference(r) if r.is_witness()),
+    )
+}
+
+/// The coefficient of a variable in an expression that is affine in it.
+#[derive(Clone, Copy)]
+enum Coefficient<T> {
+    Zero,
+    Constant(T),
+    Unknown,
+}
+
+impl<T: FieldElement> Coefficient<T> {
+    fn add(self, other: Self) -> Self {
+        match (self, other) {
+            (Coefficient::Zero, c) | (c, Coefficient::Zero) => c,
+            (Coefficient::Constant(a), Coefficient::Constant(b)) => Coefficient::Constant(a + b),
+            _ => Coefficient::Unknown,
+        }
+    }
+
+    fn neg(self) -> Self {
+        match self {
+            Coefficient::Constant(c) => Coefficient::Constant(-c),
+            c => c,
+        }
+    }
+
+    fn scale(self, factor: &Expression<T>) -> Self {
+        match (self, factor) {
+            (Coefficient::Zero, _) => Coefficient::Zero,
+            (Coefficient::Constant(c), Expression::Number(n)) => Coefficient::Constant(c * *n),
+            _ => Coefficient::Unknown,
+        }
+    }
+
+    fn is_nonzero(&self) -> bool {
+        matches!(self, Coefficient::Constant(c) if !c.is_zero())
+    }
+}
+
+/// Returns the coefficient of `x` in `e`, or None if `e` is not affine in `x`
+/// or also references the column of `x` on another row.
+fn coefficient<T: FieldElement>(
+    e: &Expression<T>,
+    x: &AlgebraicReference,
+) -> Option<Coefficient<T>> {
+    Some(match e {
+        Expression::Reference(r) if r.poly_id == x.poly_id => {
+            if r.next != x.next {
+                return None;
+            }
+            Coefficient::Constant(T::one())
+        }
+        Expression::BinaryOperation(left, op, right) => {
+            let (l, r) = (coefficient(left, x)?, coefficient(right, x)?);
+            match (op, l, r) {
+                (AlgebraicBinaryOperator::Add, l, r) => l.add(r),
+                (AlgebraicBinaryOperator::Sub, l, r) => l.add(r.neg()),
+                (AlgebraicBinaryOperator::Mul, Coefficient::Zero, r) => r.scale(left),
+                (AlgebraicBinaryOperator::Mul, l, Coefficient::Zero) => l.scale(right),
+                (AlgebraicBinaryOperator::Pow, Coefficient::Zero, _) => Coefficient::Zero,
+                _ => return None,
+            }
+        }
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, e) => coefficient(e, x)?.neg(),
+        _ => Coefficient::Zero,
+    })
+}
+
+/// Returns true if the values of all columns in a row are determined by the value of the
+/// column at index `key`.
+fn is_key<T: FieldElement>(columns: &[&Vec<T>], key: usize) -> bool {
+    let mut first_row_with_value = HashMap::new();
+    (0..columns[key].len()).all(|row| {
+        let first = *first_row_with_value.entry(columns[key][row]).or_insert(row);
+        columns.iter().all(|c| c[row] == c[first])
+    })
+}
+
+/// If the expression is of the form `1 - x` for a witness column `x`, returns `x`.
+fn negated_boolean<T: FieldElement>(e: &Expression<T>) -> Option<PolyID> {
+    match e {
+        Expression::BinaryOperation(left, AlgebraicBinaryOperator::Sub, right) => {
+            match (left.as_ref(), right.as_ref()) {
+                (Expression::Number(n), Expression::Reference(r))
+                    if n.is_one() && r.is_witness() =>
+                {
+                    Some(r.poly_id)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_boolean<T: FieldElement>(constraints: &GlobalConstraints<T>, id: &PolyID) -> bool {
+    constraints.witness_constraints[id]
+        .as_ref()
+        .map(|c| *c.mask() <= T::Integer::from(1))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator;
+
+    use super::*;
+
+    fn lint_pil(src: &str, instruction_flags: &[&str]) -> Vec<(String, LintKind)> {
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = constant_evaluator::generate(&analyzed)
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v))
+            .collect::<Vec<_>>();
+        let instruction_flags = instruction_flags.iter().map(|f| f.to_string()).collect();
+        lint(&analyzed, &fixed, &instruction_flags)
+            .into_iter()
+            .map(|w| (w.column, w.kind))
+            .collect()
+    }
+
+    #[test]
+    fn well_constrained() {
+        let src = r#"
+            namespace Main(8);
+                col fixed BYTE(i) { i & 0xff };
+                col witness sel, x, y;
+                sel * (1 - sel) = 0;
+                sel { x } in { BYTE };
+                y = sel * x + (1 - sel) * 7;
+        "#;
+        assert_eq!(lint_pil(src, &[]), vec![]);
+    }
+
+    #[test]
+    fn missing_boolean_constraints() {
+        let src = r#"
+            namespace Main(8);
+                col fixed BYTE(i) { i & 0xff };
+                col fixed BIT(i) { i & 1 };
+                col witness sel, x, y, instr_add, instr_add_param_a, instr_sub, instr_mul;
+                sel { x } in { BYTE };
+                y = instr_add * x + instr_add_param_a;
+                y = (1 - instr_sub) * x + instr_mul;
+                { instr_add } in { BIT };
+        "#;
+        assert_eq!(
+            lint_pil(src, &["Main.instr_add", "Main.instr_mul"]),
+            vec![
+                ("Main.sel".to_string(), LintKind::OnlyLeftHandSideOfLookups),
+                ("Main.sel".to_string(), LintKind::MissingBooleanConstraint),
+                (
+                    "Main.instr_sub".to_string(),
+                    LintKind::MissingBooleanConstraint
+                ),
+                (
+                    "Main.instr_mul".to_string(),
+                    LintKind::MissingBooleanConstraint
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookups_and_queries() {
+        let src = r#"
+            namespace Main(8);
+                col fixed BYTE(i) { i & 0xff };
+                col fixed LINE(i) { i };
+                col fixed INSTR(i) { i % 2 };
+                col witness a, b, c, d;
+                col witness e(i) query ("input", i);
+                col witness f, line, instr_a;
+                a = b + 1;
+                { c } in { BYTE };
+                { a } in { d };
+                line' = line + 1;
+                { line, instr_a } in { LINE, INSTR };
+        "#;
+        assert_eq!(
+            lint_pil(src, &[]),
+            vec![
+                ("Main.c".to_string(), LintKind::OnlyLeftHandSideOfLookups),
+                ("Main.d".to_string(), LintKind::OnlyRightHandSideOfLookups),
+                ("Main.e".to_string(), LintKind::OnlyDeterminedByQuery),
+                ("Main.f".to_string(), LintKind::Unconstrained),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_columns() {
+        let src = r#"
+            namespace Main(8);
+                col fixed BYTE(i) { i & 0xff };
+                col fixed LINE(i) { i };
+                col fixed READ_FREE(i) { i % 2 };
+                col witness line, read_free, X, A;
+                col witness free_value(i) query ("input", i);
+                col witness a(i) query ("hint", 1);
+                col witness b(i) query ("hint", 2);
+                col witness c(i) query ("hint", 3);
+                col witness d(i) query ("hint", 4);
+                line' = line + 1;
+                { line, read_free } in { LINE, READ_FREE };
+                X = read_free * free_value + (1 - read_free) * A;
+                A' = X;
+                b = 2 * a;
+                a = A + 1;
+                c * c = A;
+                { d } in { BYTE };
+        "#;
+        assert_eq!(
+            lint_pil(src, &[]),
+            vec![
+                (
+                    "Main.free_value".to_string(),
+                    LintKind::OnlyDeterminedByQuery
+                ),
+                ("Main.c".to_string(), LintKind::OnlyDeterminedByQuery),
+                ("Main.d".to_string(), LintKind::OnlyDeterminedByQuery),
+            ]
+        );
+    }
+}
@@ -4,12 +4,15 @@ use super::{EvalResult, FixedData, FixedLookup};
 use crate::witgen::affine_expression::AffineExpression;
 
 use crate::witgen::block_processor::BlockProcessor;
+use crate::witgen::block_program::{BlockProgram, BlockProgramCache, RecordedStep};
 use crate::witgen::data_structures::finalizable_data::FinalizableData;
 use crate::witgen::global_constraints::GlobalConstraints;
 use crate::witgen::identity_processor::IdentityProcessor;
 use crate::witgen::processor::OuterQuery;
 use crate::witgen::rows::{CellValue, RowFactory, RowPair, UnknownStrategy};
-use crate::witgen::sequence_iterator::{ProcessingSequenceCache, ProcessingSequenceIterator};
+use crate::witgen::sequence_iterator::{
+    ProcessingSequenceCache, ProcessingSequenceIterator, SequenceCacheKey,
+};
 use crate::witgen::util::try_to_simple_poly;
 use crate::witgen::{machines::Machine, EvalError, EvalValue, IncompleteCause};
 use crate::witgen::{MutableState, QueryCallback};
@@ -61,10 +64,22 @@ pub struct BlockMachine<'a, T: FieldElement> {
     /// Cache that states the order in which to evaluate identities
     /// to make progress most quickly.
     processing_sequence_cache: ProcessingSequenceCache,
+    /// Straight-line programs compiled from successful processing sequences,
+    /// used before falling back to the generic solver.
+    /// `None` if the identities have side effects, so that a program that does not apply
+    /// cannot be undone.
+    programs: Option<BlockProgramCache<T>>,
+    /// Calls whose arguments (inputs and outputs) are all known, which are only processed when
+    /// the witness columns are taken, so that this can happen in parallel with other machines.
+    /// `None` if all calls are processed immediately.
+    deferred_calls: Option<Vec<DeferredCall<'a, T>>>,
     fixed_data: &'a FixedData<'a, T>,
     name: String,
 }
 
+/// The input values and the right-hand side of a call to a block machine.
+type DeferredCall<'a, T> = (Vec<T>, &'a SelectedExpressions<Expression<T>>);
+
 impl<'a, T: FieldElement> BlockMachine<'a, T> {
     pub fn try_new(
         name: String,
@@ -121,7 +136,7 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                     witness_cols,
                     (0..block_size).map(|i| row_factory.fresh_row(i as DegreeType)),
                 );
-                BlockMachine {
+                let mut machine = BlockMachine {
                     name,
                     block_size,
                     connecting_rhs,
@@ -133,10 +148,54 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                         block_size,
                         identities.len(),
                     ),
+                    programs: None,
+                    deferred_calls: None,
                     fixed_data,
+                };
+                if fixed_data.block_programs && machine.is_free_of_side_effects() {
+                    machine.programs = Some(Default::default());
                 }
+                machine
             })
     }
+
+    /// Returns true if the machine does not call into other machines (apart from fixed
+    /// lookups) and does not have prover queries.
+    fn is_free_of_side_effects(&self) -> bool {
+        self.only_fixed_lookups()
+            && self
+                .witness_cols
+                .iter()
+                .all(|poly_id| self.fixed_data.witness_cols[poly_id].query.is_none())
+    }
+
+    fn only_fixed_lookups(&self) -> bool {
+        self.identities.iter().all(|id| match id.kind {
+            IdentityKind::Polynomial => true,
+            IdentityKind::Plookup => {
+                id.right.selector.is_none()
+                    && !id
+                        .right
+                        .expressions
+                        .iter()
+                        .any(|e| e.contains_witness_ref())
+            }
+            _ => false,
+        })
+    }
+
+    /// Defers calls whose arguments (inputs and outputs) are all known until the witness
+    /// columns are taken.
+    /// This is only possible if the machine does not call into other machines (apart from
+    /// fixed lookups), because those might already be finalized by then.
+    /// Returns true if calls are deferred from now on.
+    pub fn defer_calls(&mut self) -> bool {
+        let only_fixed_lookups = self.only_fixed_lookups();
+        if only_fixed_lookups {
+            self.deferred_calls = Some(vec![]);
+        }
+        only_fixed_lookups
+    }
 }
 
 /// Check if `expr` is a reference to a function of the form
@@ -192,6 +251,22 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
         if !self.connecting_rhs.contains(right) || kind != IdentityKind::Plookup {
             return None;
         }
+
+        if let Some(deferred_calls) = &mut self.deferred_calls {
+            if !mutable_state.speculative {
+                if let Some(values) = left.iter().map(|l| l.constant_value()).collect() {
+                    // There is nothing to return to the caller, so we can process the call later.
+                    // Should it fail, witness generation returns an error once the call is processed.
+                    deferred_calls.push((values, right));
+                    return Some(Ok(EvalValue::complete(vec![])));
+                }
+            }
+        }
+        // Calls have to be processed in order, because each block depends on the previous one.
+        if let Err(e) = self.process_deferred_calls(mutable_state) {
+            return Some(Err(e));
+        }
+
         let previous_len = self.rows() as usize;
         Some({
             let result = self.process_plookup_internal(mutable_state, left, right);
@@ -211,9 +286,15 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
 
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
-        _fixed_lookup: &'b mut FixedLookup<T>,
-        _query_callback: &'b mut Q,
-    ) -> HashMap<String, Vec<T>> {
+        fixed_lookup: &'b mut FixedLookup<T>,
+        query_callback: &'b mut Q,
+    ) -> Result<HashMap<String, Vec<T>>, EvalError<T>> {
+        self.process_deferred_calls_without_machines(fixed_lookup, query_callback)?;
+        if self.fixed_data.is_cancelled() {
+            // Deferred calls might be missing, but the witness is discarded anyway.
+            return Ok(HashMap::new());
+        }
+
         if self.data.len() < 2 * self.block_size {
             log::warn!(
                 "Filling empty blocks with zeros, because the block machine is never used. \
@@ -263,9 +344,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
             })
             .collect();
         self.handle_last_row(&mut data);
-        data.into_iter()
+        Ok(data
+            .into_iter()
             .map(|(id, values)| (self.fixed_data.column_name(&id).to_string(), values))
-            .collect()
+            .collect())
     }
 }
 
@@ -296,6 +378,57 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
         self.data.len() as DegreeType
     }
 
+    /// Processes all deferred calls once the witness columns are about to be taken.
+    /// At this stage, we don't have access to other machines, as they might already be
+    /// finalized, but deferring calls requires that only fixed lookups are used.
+    fn process_deferred_calls_without_machines<Q: QueryCallback<T>>(
+        &mut self,
+        fixed_lookup: &mut FixedLookup<T>,
+        query_callback: &mut Q,
+    ) -> Result<(), EvalError<T>> {
+        let mut mutable_state_no_machines = MutableState {
+            fixed_lookup,
+            machines: [].into_iter().into(),
+            query_callback,
+            speculative: false,
+        };
+        self.process_deferred_calls(&mut mutable_state_no_machines)
+    }
+
+    /// Processes all deferred calls, in the order in which they were made.
+    fn process_deferred_calls<'b, Q: QueryCallback<T>>(
+        &mut self,
+        mutable_state: &mut MutableState<'a, 'b, T, Q>,
+    ) -> Result<(), EvalError<T>> {
+        let calls = match &mut self.deferred_calls {
+            Some(calls) if !calls.is_empty() => std::mem::take(calls),
+            _ => return Ok(()),
+        };
+        log::trace!(
+            "Processing {} deferred calls to block machine '{}'",
+            calls.len(),
+            self.name()
+        );
+        for (values, right) in calls {
+            if self.fixed_data.is_cancelled() {
+                return Ok(());
+            }
+            let left = values
+                .into_iter()
+                .map(AffineExpression::from)
+                .collect::<Vec<_>>();
+            let updates = self.process_plookup_internal(mutable_state, &left, right)?;
+            if !updates.is_complete() {
+                return Err(EvalError::Generic(format!(
+                    "Deferred call to block machine '{}' could not be completed. \
+                     Try generating the witness single-threaded.",
+                    self.name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn process_plookup_internal<'b, Q: QueryCallback<T>>(
         &mut self,
         mutable_state: &mut MutableState<'a, 'b, T, Q>,
@@ -340,6 +473,35 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             }
         }
 
+        let key = SequenceCacheKey::from(left);
+        if let Some(program) = self.programs.as_ref().and_then(|p| p.get(&key)) {
+            // Errors are treated like any other mismatch, the generic solver will report them.
+            let result = self
+                .process_with_program(mutable_state, left, right, program)
+                .unwrap_or(None);
+            match result {
+                Some(ProcessResult::Success(new_block, updates)) => {
+                    log::trace!(
+                        "End processing block machine '{}' (compiled program)",
+                        self.name()
+                    );
+                    self.programs.as_mut().unwrap().report_success(&key);
+                    self.append_block(new_block)?;
+                    return Ok(updates);
+                }
+                _ => {
+                    // The machine is free of side effects, so we can just discard the block.
+                    if self.programs.as_mut().unwrap().report_failure(&key) {
+                        log::debug!(
+                            "The compiled program of block machine '{}' repeatedly did not apply to the inputs. \
+                             Using the generic solver for this input pattern from now on.",
+                            self.name()
+                        );
+                    }
+                }
+            }
+        }
+
         // TODO this assumes we are always using the same lookup for this machine.
         let mut sequence_iterator = self.processing_sequence_cache.get_processing_sequence(left);
 
@@ -354,7 +516,20 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             ));
         }
 
-        let process_result = self.process(mutable_state, left, right, &mut sequence_iterator)?;
+        // Record the steps of the generic solver, so that we can compile a program from them.
+        let should_compile = self
+            .programs
+            .as_ref()
+            .is_some_and(|p| p.should_compile(&key));
+        let mut recorded_steps = vec![];
+        let record = should_compile && !sequence_iterator.is_cached();
+        let process_result = self.process(
+            mutable_state,
+            left,
+            right,
+            &mut sequence_iterator,
+            record.then_some(&mut recorded_steps),
+        )?;
 
         let process_result = if sequence_iterator.is_cached() && !process_result.is_success() {
             log::debug!("The cached sequence did not complete the block machine. \
@@ -363,7 +538,13 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             let mut sequence_iterator = self
                 .processing_sequence_cache
                 .get_default_sequence_iterator();
-            self.process(mutable_state, left, right, &mut sequence_iterator)?
+            self.process(
+                mutable_state,
+                left,
+                right,
+                &mut sequence_iterator,
+                should_compile.then_some(&mut recorded_steps),
+            )?
         } else {
             process_result
         };
@@ -376,6 +557,19 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                 );
                 self.append_block(new_block)?;
 
+                if !recorded_steps.is_empty() {
+                    let program = BlockProgram::compile(&self.identities, &recorded_steps);
+                    log::debug!(
+                        "Compiled a program for block machine '{}': {} of {} steps are evaluated directly, \
+                         {} identities are checked afterwards.",
+                        self.name(),
+                        program.compiled_step_count(),
+                        program.steps().len(),
+                        program.checks().len()
+                    );
+                    self.programs.as_mut().unwrap().insert(key, program);
+                }
+
                 // We solved the query, so report it to the cache.
                 self.processing_sequence_cache
                     .report_processing_sequence(left, sequence_iterator);
@@ -398,7 +592,42 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
         left: &[AffineExpression<&'a AlgebraicReference, T>],
         right: &'a SelectedExpressions<Expression<T>>,
         sequence_iterator: &mut ProcessingSequenceIterator,
+        recorded_steps: Option<&mut Vec<RecordedStep<T>>>,
     ) -> Result<ProcessResult<'a, T>, EvalError<T>> {
+        let mut processor = self.block_processor(mutable_state, left, right);
+
+        let outer_assignments = match recorded_steps {
+            Some(recorded_steps) => {
+                processor.solve_and_record(sequence_iterator, recorded_steps)?
+            }
+            None => processor.solve(sequence_iterator)?,
+        };
+        let new_block = processor.finish();
+
+        Ok(ProcessResult::new(new_block, outer_assignments))
+    }
+
+    /// Computes a new block using a compiled program.
+    /// Returns `None` if the program does not apply to the inputs.
+    fn process_with_program<'b, Q: QueryCallback<T>>(
+        &self,
+        mutable_state: &mut MutableState<'a, 'b, T, Q>,
+        left: &[AffineExpression<&'a AlgebraicReference, T>],
+        right: &'a SelectedExpressions<Expression<T>>,
+        program: &BlockProgram<T>,
+    ) -> Result<Option<ProcessResult<'a, T>>, EvalError<T>> {
+        let mut processor = self.block_processor(mutable_state, left, right);
+        Ok(processor
+            .run_program(program)?
+            .map(|updates| ProcessResult::Success(processor.finish(), updates)))
+    }
+
+    fn block_processor<'b, 'c, Q: QueryCallback<T>>(
+        &'c self,
+        mutable_state: &'c mut MutableState<'a, 'b, T, Q>,
+        left: &[AffineExpression<&'a AlgebraicReference, T>],
+        right: &'a SelectedExpressions<Expression<T>>,
+    ) -> BlockProcessor<'a, 'b, 'c, T, Q> {
         // We start at the last row of the previous block.
         let row_offset = self.rows() - 1;
         // Make the block two rows larger than the block size, it includes the last row of the previous block
@@ -408,7 +637,7 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             (0..(self.block_size + 2))
                 .map(|i| self.row_factory.fresh_row(i as DegreeType + row_offset)),
         );
-        let mut processor = BlockProcessor::new(
+        BlockProcessor::new(
             row_offset,
             block,
             mutable_state,
@@ -416,12 +645,7 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             self.fixed_data,
             &self.witness_cols,
         )
-        .with_outer_query(OuterQuery::new(left.to_vec(), right));
-
-        let outer_assignments = processor.solve(sequence_iterator)?;
-        let new_block = processor.finish();
-
-        Ok(ProcessResult::new(new_block, outer_assignments))
+        .with_outer_query(OuterQuery::new(left.to_vec(), right))
     }
 
     /// Takes a block of rows, which contains the last row of its previous block
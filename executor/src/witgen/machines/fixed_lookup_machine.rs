@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 
 use itertools::Itertools;
 use powdr_ast::analyzed::{
@@ -43,9 +44,11 @@ impl IndexValue {
 /// Indices for applications of fixed columns. For each application `(INPUT_COLS, OUTPUT_COLS)`, stores
 /// - `(V, None)` if there exists two different rows where `INPUT_COLS == V` match but `OUTPUT_COLS` differ. TODO: store bitmasks of all possible outputs instead.
 /// - `(V, Some(row)` if the value of `OUTPUT_COLS` is unique when `INPUT_COLS == V`, and `row` is the first row where `INPUT_COLS ==V`
-#[derive(Default)]
+///
+/// The indices are shared between clones, so that they only need to be computed once.
+#[derive(Default, Clone)]
 pub struct IndexedColumns<T> {
-    indices: HashMap<Application, Index<T>>,
+    indices: HashMap<Application, Arc<Index<T>>>,
 }
 
 impl<T: FieldElement> IndexedColumns<T> {
@@ -157,12 +160,13 @@ impl<T: FieldElement> IndexedColumns<T> {
                 sorted_input_fixed_columns.clone(),
                 sorted_output_fixed_columns.clone(),
             ),
-            index,
+            Arc::new(index),
         );
     }
 }
 
 /// Machine to perform a lookup in fixed columns only.
+#[derive(Clone)]
 pub struct FixedLookup<T: FieldElement> {
     global_constraints: GlobalConstraints<T>,
     indices: IndexedColumns<T>,
@@ -16,6 +16,7 @@ use powdr_ast::analyzed::IdentityKind;
 
 use super::affine_expression::AffineExpression;
 use super::generator::Generator;
+use super::EvalError;
 use super::EvalResult;
 use super::FixedData;
 use super::MutableState;
@@ -63,11 +64,12 @@ pub trait Machine<'a, T: FieldElement>: Send + Sync {
     ) -> Option<EvalResult<'a, T>>;
 
     /// Returns the final values of the witness columns.
+    /// Fails if a call that was deferred until now cannot be processed.
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         fixed_lookup: &'b mut FixedLookup<T>,
         query_callback: &'b mut Q,
-    ) -> HashMap<String, Vec<T>>;
+    ) -> Result<HashMap<String, Vec<T>>, EvalError<T>>;
 }
 
 /// All known implementations of [Machine].
@@ -81,6 +83,21 @@ pub enum KnownMachine<'a, T: FieldElement> {
     Vm(Generator<'a, T>),
 }
 
+impl<'a, T: FieldElement> KnownMachine<'a, T> {
+    /// Makes the machine defer calls whose arguments (inputs and outputs) are all known
+    /// until its witness columns are taken, if the machine supports it.
+    /// Returns true if calls are deferred.
+    pub fn defer_calls(&mut self) -> bool {
+        match self {
+            KnownMachine::BlockMachine(m) => m.defer_calls(),
+            KnownMachine::SortedWitnesses(_)
+            | KnownMachine::DoubleSortedWitnesses(_)
+            | KnownMachine::WriteOnceMemory(_)
+            | KnownMachine::Vm(_) => false,
+        }
+    }
+}
+
 impl<'a, T: FieldElement> Machine<'a, T> for KnownMachine<'a, T> {
     fn process_plookup<'b, Q: QueryCallback<T>>(
         &mut self,
@@ -114,7 +131,7 @@ impl<'a, T: FieldElement> Machine<'a, T> for KnownMachine<'a, T> {
         &mut self,
         fixed_lookup: &'b mut FixedLookup<T>,
         query_callback: &'b mut Q,
-    ) -> HashMap<String, Vec<T>> {
+    ) -> Result<HashMap<String, Vec<T>>, EvalError<T>> {
         match self {
             KnownMachine::SortedWitnesses(m) => {
                 m.take_witness_col_values(fixed_lookup, query_callback)
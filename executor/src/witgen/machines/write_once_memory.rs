@@ -219,8 +219,9 @@ impl<'a, T: FieldElement> Machine<'a, T> for WriteOnceMemory<'a, T> {
         &mut self,
         _fixed_lookup: &'b mut FixedLookup<T>,
         _query_callback: &'b mut Q,
-    ) -> HashMap<String, Vec<T>> {
-        self.value_polys
+    ) -> Result<HashMap<String, Vec<T>>, EvalError<T>> {
+        Ok(self
+            .value_polys
             .iter()
             .enumerate()
             .map(|(value_index, poly)| {
@@ -241,6 +242,6 @@ impl<'a, T: FieldElement> Machine<'a, T> for WriteOnceMemory<'a, T> {
                     });
                 (self.fixed_data.column_name(poly).to_string(), column)
             })
-            .collect()
+            .collect())
     }
 }
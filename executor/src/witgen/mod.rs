@@ -1,4 +1,6 @@
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use powdr_ast::analyzed::{
@@ -6,6 +8,7 @@ use powdr_ast::analyzed::{
     SymbolKind,
 };
 use powdr_number::{DegreeType, FieldElement};
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
 
 use self::data_structures::column_map::{FixedColumnMap, WitnessColumnMap};
 pub use self::eval_result::{
@@ -20,13 +23,16 @@ use self::machines::{FixedLookup, Machine};
 
 mod affine_expression;
 mod block_processor;
+mod block_program;
 mod data_structures;
+mod derived_columns;
 mod eval_result;
 mod expression_evaluator;
 pub mod fixed_evaluator;
 mod generator;
 mod global_constraints;
 mod identity_processor;
+pub mod lint;
 mod machines;
 mod processor;
 mod query_processor;
@@ -50,6 +56,84 @@ pub fn chain_callbacks<T: FieldElement>(
     move |query| c1(query).or_else(|_| c2(query))
 }
 
+/// A callback that is informed about the progress of witness generation.
+/// It receives the name of a machine and the number of its rows processed so far.
+pub trait ProgressCallback: Fn(&str, DegreeType) + Send + Sync {}
+impl<F> ProgressCallback for F where F: Fn(&str, DegreeType) + Send + Sync {}
+
+/// A token to cancel witness generation from another thread.
+/// Clones of a token share their state, so cancelling one cancels all of them.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The error returned if a computation was cancelled via a [CancellationToken].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The computation was cancelled.")
+    }
+}
+
+/// The error returned by [WitnessGenerator::try_generate].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WitgenError {
+    Cancelled,
+    /// A call to a secondary machine that was deferred until its witness columns were
+    /// computed failed.
+    DeferredCallFailed {
+        machine: String,
+        message: String,
+    },
+    /// The externally provided witness of the main machine does not satisfy a call
+    /// to another machine.
+    InvalidExternalWitness {
+        row: DegreeType,
+        message: String,
+    },
+}
+
+impl From<Cancelled> for WitgenError {
+    fn from(_: Cancelled) -> Self {
+        WitgenError::Cancelled
+    }
+}
+
+impl fmt::Display for WitgenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WitgenError::Cancelled => write!(f, "{Cancelled}"),
+            WitgenError::DeferredCallFailed { machine, message } => {
+                write!(
+                    f,
+                    "Error processing deferred calls to machine '{machine}': {message}"
+                )
+            }
+            WitgenError::InvalidExternalWitness { row, message } => {
+                write!(
+                    f,
+                    "The provided witness of the main machine is invalid in row {row}: {message}"
+                )
+            }
+        }
+    }
+}
+
 /// @returns a query callback that is never expected to be used.
 pub fn unused_query_callback<T>() -> impl QueryCallback<T> {
     |_| -> _ { unreachable!() }
@@ -60,6 +144,9 @@ pub struct MutableState<'a, 'b, T: FieldElement, Q: QueryCallback<T>> {
     pub fixed_lookup: &'b mut FixedLookup<T>,
     pub machines: Machines<'a, 'b, T>,
     pub query_callback: &'b mut Q,
+    /// Whether the current machine call might fail without aborting witness generation
+    /// (e.g. when checking a proposed row). Such calls are never deferred.
+    pub speculative: bool,
 }
 
 pub struct WitnessGenerator<'a, 'b, T: FieldElement> {
@@ -67,6 +154,10 @@ pub struct WitnessGenerator<'a, 'b, T: FieldElement> {
     fixed_col_values: &'b [(String, Vec<T>)],
     query_callback: &'b dyn QueryCallback<T>,
     external_witness_values: Vec<(String, Vec<T>)>,
+    multi_threaded: bool,
+    progress_callback: Option<Arc<dyn ProgressCallback>>,
+    cancellation_token: CancellationToken,
+    block_programs: bool,
 }
 
 impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
@@ -80,9 +171,17 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             fixed_col_values,
             query_callback,
             external_witness_values: Vec::new(),
+            multi_threaded: false,
+            progress_callback: None,
+            cancellation_token: CancellationToken::default(),
+            block_programs: true,
         }
     }
 
+    /// Sets values of witness columns that are computed outside of witness generation.
+    /// If they cover all witness columns of the main machine (except for derived columns)
+    /// in all rows, the main machine is not solved, but only its calls to other machines
+    /// are processed.
     pub fn with_external_witness_values(
         self,
         external_witness_values: Vec<(String, Vec<T>)>,
@@ -93,18 +192,74 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
         }
     }
 
+    /// If enabled, calls into block machines whose arguments (inputs and outputs) are all
+    /// known are only recorded while the main machine runs. They are processed when the
+    /// witness columns of the secondary machines are computed, which happens in parallel.
+    /// Calls that still have unknown outputs are processed immediately, because the caller
+    /// needs their results. This is the case for most calls if the main machine is solved
+    /// by witness generation, but for none if its witness is provided externally (see
+    /// [WitnessGenerator::with_external_witness_values]).
+    /// Fixed lookups are answered immediately, but the threads share the indices built for
+    /// them so far. The generated witness is the same as in single-threaded mode.
+    pub fn with_multi_threading(self, multi_threaded: bool) -> Self {
+        WitnessGenerator {
+            multi_threaded,
+            ..self
+        }
+    }
+
+    /// Sets a callback that is periodically informed about the number of rows processed
+    /// by the main machine, and about each secondary machine once it is complete.
+    pub fn with_progress_callback(self, progress_callback: Arc<dyn ProgressCallback>) -> Self {
+        WitnessGenerator {
+            progress_callback: Some(progress_callback),
+            ..self
+        }
+    }
+
+    /// Sets a token to cancel witness generation, see [WitnessGenerator::try_generate].
+    pub fn with_cancellation_token(self, cancellation_token: CancellationToken) -> Self {
+        WitnessGenerator {
+            cancellation_token,
+            ..self
+        }
+    }
+
+    /// If enabled (the default), block machines without side effects compile the steps of the
+    /// generic solver into straight-line programs and use them for later calls.
+    /// The generated witness is the same either way.
+    pub fn with_block_programs(self, block_programs: bool) -> Self {
+        WitnessGenerator {
+            block_programs,
+            ..self
+        }
+    }
+
     /// Generates the committed polynomial values
     /// @returns the values (in source order) and the degree of the polynomials.
+    ///
+    /// Panics if witness generation is cancelled via [WitnessGenerator::with_cancellation_token]
+    /// or if a deferred machine call fails. Use [WitnessGenerator::try_generate] to handle these.
     pub fn generate(self) -> Vec<(String, Vec<T>)> {
+        self.try_generate().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [WitnessGenerator::generate], but returns an error if witness generation was
+    /// cancelled via the cancellation token or if a deferred machine call failed.
+    pub fn try_generate(self) -> Result<Vec<(String, Vec<T>)>, WitgenError> {
         record_start(OUTER_CODE_NAME);
         let fixed = FixedData::new(
             self.analyzed,
             self.fixed_col_values,
             self.external_witness_values,
+        )
+        .with_progress(self.progress_callback, self.cancellation_token)
+        .with_block_programs(self.block_programs);
+        let (identities, derived_columns) = derived_columns::inline_derived_columns(
+            self.analyzed
+                .identities_with_inlined_intermediate_polynomials(),
+            &self.analyzed.derived_columns(),
         );
-        let identities = self
-            .analyzed
-            .identities_with_inlined_intermediate_polynomials();
 
         let (
             constraints,
@@ -122,11 +277,19 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             retained_identities,
             &constraints,
         );
+        if self.multi_threaded {
+            for machine in machines.iter_mut() {
+                if machine.defer_calls() {
+                    log::debug!("Deferring calls to machine {}", machine.name());
+                }
+            }
+        }
         let mut query_callback = self.query_callback;
         let mut mutable_state = MutableState {
             fixed_lookup: &mut fixed_lookup,
             machines: Machines::from(machines.iter_mut()),
             query_callback: &mut query_callback,
+            speculative: false,
         };
         let mut generator = Generator::new(
             "Main Machine".to_string(),
@@ -140,20 +303,92 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             None,
         );
 
-        generator.run(&mut mutable_state);
+        if generator.has_complete_external_witness() {
+            // The main machine does not need to be solved, only its calls are processed.
+            if let Err(e) = generator.run_with_external_witness(&mut mutable_state) {
+                record_end(OUTER_CODE_NAME);
+                return Err(e);
+            }
+        } else {
+            generator.run(&mut mutable_state);
+        }
+        if fixed.is_cancelled() {
+            record_end(OUTER_CODE_NAME);
+            return Err(Cancelled.into());
+        }
 
         // Get columns from machines
-        let main_columns = generator
-            .take_witness_col_values(mutable_state.fixed_lookup, mutable_state.query_callback);
-        let mut columns = mutable_state
-            .machines
-            .iter_mut()
-            .flat_map(|m| {
-                m.take_witness_col_values(mutable_state.fixed_lookup, mutable_state.query_callback)
-                    .into_iter()
-            })
+        let deferred_call_failed =
+            |machine: &str, e: EvalError<T>| WitgenError::DeferredCallFailed {
+                machine: machine.to_string(),
+                message: e.to_string(),
+            };
+        let main_columns = match generator
+            .take_witness_col_values(mutable_state.fixed_lookup, mutable_state.query_callback)
+        {
+            Ok(main_columns) => main_columns,
+            Err(e) => {
+                record_end(OUTER_CODE_NAME);
+                return Err(deferred_call_failed(generator.name(), e));
+            }
+        };
+        let machine_columns = if self.multi_threaded {
+            let fixed_lookup = &fixed_lookup;
+            machines
+                .par_iter_mut()
+                .map(|m| {
+                    if fixed.is_cancelled() {
+                        return Err(Cancelled.into());
+                    }
+                    // Each thread gets its own copy of the fixed lookup, which shares the
+                    // indices built so far.
+                    let mut fixed_lookup = fixed_lookup.clone();
+                    let mut query_callback = self.query_callback;
+                    let columns = m
+                        .take_witness_col_values(&mut fixed_lookup, &mut query_callback)
+                        .map_err(|e| deferred_call_failed(m.name(), e))?;
+                    fixed.report_progress(m.name(), fixed.degree);
+                    Ok(columns)
+                })
+                .collect::<Result<Vec<_>, WitgenError>>()
+        } else {
+            mutable_state
+                .machines
+                .iter_mut()
+                .map(|m| {
+                    if fixed.is_cancelled() {
+                        return Err(Cancelled.into());
+                    }
+                    let columns = m
+                        .take_witness_col_values(
+                            mutable_state.fixed_lookup,
+                            mutable_state.query_callback,
+                        )
+                        .map_err(|e| deferred_call_failed(m.name(), e))?;
+                    fixed.report_progress(m.name(), fixed.degree);
+                    Ok(columns)
+                })
+                .collect::<Result<Vec<_>, WitgenError>>()
+        };
+        let machine_columns = match machine_columns {
+            Ok(machine_columns) => machine_columns,
+            Err(e) => {
+                record_end(OUTER_CODE_NAME);
+                return Err(e);
+            }
+        };
+        if fixed.is_cancelled() {
+            record_end(OUTER_CODE_NAME);
+            return Err(Cancelled.into());
+        }
+        let mut columns = machine_columns
+            .into_iter()
+            .flatten()
             .chain(main_columns)
             .collect::<BTreeMap<_, _>>();
+        let derived_values =
+            derived_columns::compute_derived_columns(&fixed, &derived_columns, &columns);
+        columns.extend(derived_values);
 
         record_end(OUTER_CODE_NAME);
         reset_and_print_profile_summary();
@@ -175,7 +410,7 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
         for (name, value) in extract_publics(&witness_cols, self.analyzed) {
             log::debug!("  {name:>30}: {value}");
         }
-        witness_cols
+        Ok(witness_cols)
     }
 }
 
@@ -205,6 +440,10 @@ pub struct FixedData<'a, T> {
     fixed_cols: FixedColumnMap<FixedColumn<'a, T>>,
     witness_cols: WitnessColumnMap<WitnessColumn<'a, T>>,
     column_by_name: HashMap<String, PolyID>,
+    progress_callback: Option<Arc<dyn ProgressCallback>>,
+    cancellation_token: CancellationToken,
+    /// Whether block machines may compile straight-line programs.
+    block_programs: bool,
 }
 
 impl<'a, T: FieldElement> FixedData<'a, T> {
@@ -263,9 +502,41 @@ impl<'a, T: FieldElement> FixedData<'a, T> {
                 .filter(|(_, (symbol, _))| matches!(symbol.kind, SymbolKind::Poly(_)))
                 .map(|(name, (symbol, _))| (name.clone(), symbol.into()))
                 .collect(),
+            progress_callback: None,
+            cancellation_token: CancellationToken::default(),
+            block_programs: true,
+        }
+    }
+
+    pub fn with_progress(
+        self,
+        progress_callback: Option<Arc<dyn ProgressCallback>>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        FixedData {
+            progress_callback,
+            cancellation_token,
+            ..self
+        }
+    }
+
+    pub fn with_block_programs(self, block_programs: bool) -> Self {
+        FixedData {
+            block_programs,
+            ..self
         }
     }
 
+    fn report_progress(&self, machine: &str, rows: DegreeType) {
+        if let Some(progress_callback) = &self.progress_callback {
+            progress_callback(machine, rows);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation_token.is_cancelled()
+    }
+
     fn witness_map_with<V: Clone>(&self, initial_value: V) -> WitnessColumnMap<V> {
         WitnessColumnMap::new(initial_value, self.witness_cols.len())
     }
@@ -282,6 +553,14 @@ impl<'a, T: FieldElement> FixedData<'a, T> {
         self.column_by_name.get(name).cloned()
     }
 
+    /// The externally provided values of the witness column, if they cover all rows.
+    fn complete_external_witness(&self, column: &PolyID) -> Option<&[T]> {
+        self.witness_cols[column]
+            .external_values
+            .as_deref()
+            .filter(|values| values.len() == self.degree as usize)
+    }
+
     fn external_witness(&self, row: DegreeType, column: &PolyID) -> Option<T> {
         let row = row % self.degree;
         self.witness_cols[column]
@@ -333,6 +333,39 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         progress
     }
 
+    /// Evaluates an expression on the given row to a concrete value.
+    /// @returns None if the value depends on an unknown cell.
+    pub fn evaluate_to_value(&self, row_index: usize, expr: &Expression<T>) -> Option<T> {
+        RowPair::new(
+            &self.data[row_index],
+            &self.data[row_index + 1],
+            self.row_offset + row_index as u64,
+            self.fixed_data,
+            UnknownStrategy::Unknown,
+        )
+        .evaluate_to_value(expr)
+    }
+
+    /// Sets the value of a cell without running any identity.
+    /// @returns false if the cell is already known with a different value.
+    pub fn set_value(&mut self, row_index: usize, poly: &AlgebraicReference, value: T) -> bool {
+        let (current, next) = self.data.mutable_row_pair(row_index);
+        let cell = if poly.next { next } else { current };
+        match cell[&poly.poly_id].value {
+            CellValue::Known(existing) => existing == value,
+            _ => {
+                log::trace!(
+                    "      => {} (Row {}) = {}",
+                    poly.name,
+                    self.row_offset + row_index as u64 + poly.next as u64,
+                    value
+                );
+                cell[&poly.poly_id].value = CellValue::Known(value);
+                true
+            }
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -359,6 +392,33 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         }
     }
 
+    /// Returns true if the identity is satisfied on the given row and does not
+    /// determine any further values. Range constraints it would imply are ignored.
+    /// Unlike [Processor::process_identity], a violated identity is not logged.
+    pub fn is_identity_settled(
+        &mut self,
+        row_index: usize,
+        identity: &'a Identity<Expression<T>>,
+    ) -> bool {
+        let row_pair = RowPair::new(
+            &self.data[row_index],
+            &self.data[row_index + 1],
+            self.row_offset + row_index as DegreeType,
+            self.fixed_data,
+            UnknownStrategy::Unknown,
+        );
+        let updates = IdentityProcessor::new(self.fixed_data, self.mutable_state)
+            .speculative()
+            .process_identity(identity, &row_pair);
+        match updates {
+            Ok(updates) => !updates
+                .constraints
+                .iter()
+                .any(|(_, c)| matches!(c, Constraint::Assignment(_))),
+            Err(_) => false,
+        }
+    }
+
     /// Checks whether a given identity is satisfied on a proposed row.
     pub fn check_row_pair(
         &mut self,
@@ -368,7 +428,8 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         // This could be computed from the identity, but should be pre-computed for performance reasons.
         has_next_reference: bool,
     ) -> bool {
-        let mut identity_processor = IdentityProcessor::new(self.fixed_data, self.mutable_state);
+        let mut identity_processor =
+            IdentityProcessor::new(self.fixed_data, self.mutable_state).speculative();
         let row_pair = match has_next_reference {
             // Check whether identities with a reference to the next row are satisfied
             // when applied to the previous row and the proposed row.
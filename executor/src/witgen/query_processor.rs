@@ -1,9 +1,10 @@
-use std::{fmt::Display, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
 
+use num_bigint::BigInt;
 use num_traits::ToPrimitive;
 use powdr_ast::analyzed::{
-    types::TypedExpression, AlgebraicReference, Expression, FunctionValueDefinition, PolyID,
-    PolynomialType,
+    types::{FunctionType, Type, TypedExpression},
+    AlgebraicReference, Expression, FunctionValueDefinition, PolyID, PolynomialType, SymbolKind,
 };
 use powdr_number::{DegreeType, FieldElement};
 use powdr_pil_analyzer::evaluator::{self, Custom, EvalError, SymbolLookup, Value};
@@ -11,11 +12,17 @@ use powdr_pil_analyzer::evaluator::{self, Custom, EvalError, SymbolLookup, Value
 use super::{rows::RowPair, Constraint, EvalResult, EvalValue, FixedData, IncompleteCause};
 
 /// Computes value updates that result from a query.
+/// A query processor is only used for the queries of a single row pair.
 pub struct QueryProcessor<'a, 'b, T: FieldElement, QueryCallback: Send + Sync> {
     fixed_data: &'a FixedData<'a, T>,
     query_callback: &'b mut QueryCallback,
+    /// The results of calls of row functions (see [Reference::RowFunction]),
+    /// shared by the queries of all columns.
+    row_function_results: RefCell<RowFunctionResults<'a, T>>,
 }
 
+type RowFunctionResults<'a, T> = HashMap<(&'a str, BigInt), Value<'a, T, Reference<'a>>>;
+
 impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
     QueryProcessor<'a, 'b, T, QueryCallback>
 {
@@ -23,10 +30,15 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         Self {
             fixed_data,
             query_callback,
+            row_function_results: Default::default(),
         }
     }
 
-    pub fn process_query(&mut self, rows: &RowPair<T>, poly_id: &PolyID) -> EvalResult<'a, T> {
+    pub fn process_query(
+        &mut self,
+        rows: &RowPair<'_, 'a, T>,
+        poly_id: &PolyID,
+    ) -> EvalResult<'a, T> {
         let column = &self.fixed_data.witness_cols[poly_id];
 
         if let Some(query) = column.query.as_ref() {
@@ -42,7 +54,7 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         &mut self,
         query: &'a Expression<T>,
         poly: &'a AlgebraicReference,
-        rows: &RowPair<T>,
+        rows: &RowPair<'_, 'a, T>,
     ) -> EvalResult<'a, T> {
         let query_str = match self.interpolate_query(query, rows) {
             Ok(query) => query,
@@ -78,7 +90,7 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
     fn interpolate_query(
         &self,
         query: &'a Expression<T>,
-        rows: &RowPair<T>,
+        rows: &RowPair<'_, 'a, T>,
     ) -> Result<String, EvalError> {
         let arguments = vec![Rc::new(Value::Integer(num_bigint::BigInt::from(
             rows.current_row_index,
@@ -86,6 +98,7 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         let symbols = Symbols {
             fixed_data: self.fixed_data,
             rows,
+            row_function_results: &self.row_function_results,
         };
         let fun = evaluator::evaluate(query, &symbols)?;
         evaluator::evaluate_function_call(fun, arguments, &symbols).map(|v| v.to_string())
@@ -93,21 +106,84 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
 }
 
 #[derive(Clone)]
-struct Symbols<'a, T: FieldElement> {
+struct Symbols<'a, 'c, T: FieldElement> {
     fixed_data: &'a FixedData<'a, T>,
-    rows: &'a RowPair<'a, 'a, T>,
+    rows: &'c RowPair<'c, 'a, T>,
+    row_function_results: &'c RefCell<RowFunctionResults<'a, T>>,
+}
+
+impl<'a, 'c, T: FieldElement> Symbols<'a, 'c, T> {
+    /// Calls the row function `name`, evaluating it only once per argument.
+    fn call_row_function(
+        &self,
+        name: &'a str,
+        arguments: &[Rc<Value<'a, T, Reference<'a>>>],
+    ) -> Result<Value<'a, T, Reference<'a>>, EvalError> {
+        let [argument] = arguments else {
+            return Err(EvalError::TypeError(format!(
+                "Expected one argument, but got {}",
+                arguments.len()
+            )));
+        };
+        let Value::Integer(argument) = argument.as_ref() else {
+            return Err(EvalError::TypeError(format!(
+                "Expected integer but got {argument}"
+            )));
+        };
+        let key = (name, argument.clone());
+        if let Some(result) = self.row_function_results.borrow().get(&key) {
+            return Ok(result.clone());
+        }
+        let Some(FunctionValueDefinition::Expression(TypedExpression { e, ty: _ })) =
+            &self.fixed_data.analyzed.definitions[name].1
+        else {
+            unreachable!()
+        };
+        let function = evaluator::evaluate(e, self)?;
+        let result = evaluator::evaluate_function_call(function, arguments.to_vec(), self)?;
+        self.row_function_results
+            .borrow_mut()
+            .insert(key, result.clone());
+        Ok(result)
+    }
 }
 
-impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T> {
+impl<'a, 'c, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, 'c, T> {
     fn lookup(&self, name: &'a str) -> Result<Value<'a, T, Reference<'a>>, EvalError> {
+        if let Some((symbol, _)) = self.fixed_data.analyzed.definitions.get(name) {
+            let is_column = matches!(
+                symbol.kind,
+                SymbolKind::Poly(PolynomialType::Committed | PolynomialType::Constant)
+            );
+            if is_column && symbol.is_array() {
+                // Arrays of columns evaluate to arrays of references to their elements.
+                return Ok(Value::Array(
+                    symbol
+                        .array_elements()
+                        .map(|(_, poly_id)| {
+                            Value::Custom(Reference::Column {
+                                name: self.fixed_data.column_name(&poly_id),
+                                poly_id,
+                            })
+                        })
+                        .collect(),
+                ));
+            }
+        }
         match self.fixed_data.try_column_by_name(name) {
-            Some(poly_id) => Ok(Value::Custom(Reference { name, poly_id })),
+            Some(poly_id) => Ok(Value::Custom(Reference::Column { name, poly_id })),
             None => match self.fixed_data.analyzed.definitions.get(&name.to_string()) {
                 Some((_, value)) => {
                     let value = value
                         .as_ref()
                         .expect("Witness columns should have been found by try_column_by_name()");
                     match value {
+                        FunctionValueDefinition::Expression(TypedExpression {
+                            e: _,
+                            ty: Some(Type::Function(FunctionType { params, value: _ })),
+                        }) if params == &[Type::Int] => {
+                            Ok(Value::Custom(Reference::RowFunction(name)))
+                        }
                         FunctionValueDefinition::Expression(TypedExpression { e, ty: _ }) => {
                             evaluator::evaluate(e, self)
                         }
@@ -127,6 +203,10 @@ impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T>
         function: Reference<'a>,
         arguments: &[Rc<Value<'a, T, Reference<'a>>>],
     ) -> Result<Value<'a, T, Reference<'a>>, EvalError> {
+        let (name, poly_id) = match function {
+            Reference::Column { name, poly_id } => (name, poly_id),
+            Reference::RowFunction(name) => return self.call_row_function(name, arguments),
+        };
         if arguments.len() != 1 {
             Err(EvalError::TypeError(format!(
                 "Expected one argument, but got {}",
@@ -139,7 +219,7 @@ impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T>
                 arguments[0]
             )));
         };
-        Ok(Value::FieldElement(match function.poly_id.ptype {
+        Ok(Value::FieldElement(match poly_id.ptype {
             PolynomialType::Committed | PolynomialType::Intermediate => {
                 let next = self
                     .rows
@@ -148,8 +228,8 @@ impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T>
                         EvalError::OutOfBounds(format!("Referenced row outside of window: {row}"))
                     })?;
                 let poly_ref = AlgebraicReference {
-                    name: function.name.to_string(),
-                    poly_id: function.poly_id,
+                    name: name.to_string(),
+                    poly_id,
                     next,
                 };
 
@@ -158,7 +238,7 @@ impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T>
                     .ok_or(EvalError::DataNotAvailable)?
             }
             PolynomialType::Constant => {
-                let values = self.fixed_data.fixed_cols[&function.poly_id].values;
+                let values = self.fixed_data.fixed_cols[&poly_id].values;
                 values[(usize::try_from(row).unwrap() % values.len())
                     .to_u64()
                     .unwrap() as usize]
@@ -168,25 +248,40 @@ impl<'a, T: FieldElement> SymbolLookup<'a, T, Reference<'a>> for Symbols<'a, T>
 }
 
 #[derive(Clone, Debug)]
-struct Reference<'a> {
-    name: &'a str,
-    poly_id: PolyID,
+enum Reference<'a> {
+    Column {
+        name: &'a str,
+        poly_id: PolyID,
+    },
+    /// A function of type `int -> T`, which is usually a function of the row.
+    /// It is only evaluated once per argument for all queries of a row pair,
+    /// which helps if it computes a hint for several columns.
+    RowFunction(&'a str),
 }
 
 impl<'a> PartialEq for Reference<'a> {
     fn eq(&self, other: &Self) -> bool {
-        self.poly_id == other.poly_id
+        match (self, other) {
+            (Reference::Column { poly_id: a, .. }, Reference::Column { poly_id: b, .. }) => a == b,
+            (Reference::RowFunction(a), Reference::RowFunction(b)) => a == b,
+            _ => false,
+        }
     }
 }
 
 impl<'a> Display for Reference<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+        match self {
+            Reference::Column { name, .. } | Reference::RowFunction(name) => write!(f, "{name}"),
+        }
     }
 }
 
 impl<'a> Custom for Reference<'a> {
     fn type_name(&self) -> String {
-        "col".to_string()
+        match self {
+            Reference::Column { .. } => "col".to_string(),
+            Reference::RowFunction(_) => "function".to_string(),
+        }
     }
 }
@@ -1,7 +1,10 @@
 use std::{collections::HashSet, fmt::Debug};
 
 use itertools::Itertools;
-use powdr_ast::analyzed::{AlgebraicExpression as Expression, AlgebraicReference, PolyID};
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, PolyID,
+};
 use powdr_number::{DegreeType, FieldElement};
 
 use crate::witgen::Constraint;
@@ -341,6 +344,41 @@ impl<'row, 'a, T: FieldElement> RowPair<'row, 'a, T> {
         .evaluate(expr)
     }
 
+    /// Evaluates the expression to a concrete value, without building affine expressions.
+    /// Multiplications by zero are short-circuited, like in [RowPair::evaluate].
+    /// @returns None if the value depends on an unknown cell.
+    pub fn evaluate_to_value(&self, expr: &Expression<T>) -> Option<T> {
+        match expr {
+            Expression::Reference(poly) if poly.is_witness() => self.get_value(poly),
+            Expression::Reference(poly) => {
+                let values = self.fixed_data.fixed_cols[&poly.poly_id].values;
+                let row = (self.current_row_index + poly.next as DegreeType)
+                    % (values.len() as DegreeType);
+                Some(values[row as usize])
+            }
+            Expression::Number(n) => Some(*n),
+            Expression::BinaryOperation(left, op, right) => {
+                let left = self.evaluate_to_value(left);
+                if *op == AlgebraicBinaryOperator::Mul && left == Some(T::zero()) {
+                    return left;
+                }
+                let right = self.evaluate_to_value(right);
+                match (left, op, right) {
+                    (_, AlgebraicBinaryOperator::Mul, Some(r)) if r.is_zero() => Some(r),
+                    (Some(l), AlgebraicBinaryOperator::Add, Some(r)) => Some(l + r),
+                    (Some(l), AlgebraicBinaryOperator::Sub, Some(r)) => Some(l - r),
+                    (Some(l), AlgebraicBinaryOperator::Mul, Some(r)) => Some(l * r),
+                    (Some(l), AlgebraicBinaryOperator::Pow, Some(r)) => Some(l.pow(r.to_integer())),
+                    _ => None,
+                }
+            }
+            Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, inner) => {
+                self.evaluate_to_value(inner).map(|v| -v)
+            }
+            Expression::PublicReference(_) => None,
+        }
+    }
+
     /// Returns Ok(true) if the given row number references the "next" row,
     /// Ok(false) if it references the "current" row and Err if it is out of range.
     pub fn is_row_number_next(&self, row_number: DegreeType) -> Result<bool, ()> {
@@ -148,7 +148,7 @@ pub enum Action {
     ProverQueries,
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Debug)]
 pub struct SequenceCacheKey {
     /// For each expression on the left-hand side of the lookup, whether it is a constant.
     known_columns: Vec<bool>,
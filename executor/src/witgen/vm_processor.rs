@@ -44,6 +44,8 @@ impl<'a, T: FieldElement> CompletableIdentities<'a, T> {
 }
 
 pub struct VmProcessor<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> {
+    /// The name of the machine being run.
+    machine_name: String,
     /// The global index of the first row of [VmProcessor::data].
     row_offset: DegreeType,
     /// The witness columns belonging to this machine
@@ -63,7 +65,9 @@ pub struct VmProcessor<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> {
 }
 
 impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T, Q> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        machine_name: String,
         row_offset: DegreeType,
         fixed_data: &'a FixedData<'a, T>,
         identities: &[&'a Identity<Expression<T>>],
@@ -86,6 +90,7 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
         );
 
         VmProcessor {
+            machine_name,
             row_offset,
             witnesses: witnesses.clone(),
             fixed_data,
@@ -132,6 +137,17 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
         let rows_left = self.fixed_data.degree - self.row_offset + 1;
         let mut finalize_start = 1;
         for row_index in 0..rows_left {
+            // Calls from other machines are always completed, as their callers would fail otherwise.
+            if !self.processor.has_outer_query() && self.fixed_data.is_cancelled() {
+                log::info!(
+                    "Witness generation of {} was cancelled at row {row_index}.",
+                    self.machine_name
+                );
+                if is_main_run {
+                    self.progress_bar.abandon();
+                }
+                return EvalValue::incomplete(IncompleteCause::Cancelled);
+            }
             if is_main_run {
                 self.maybe_log_performance(row_index);
             }
@@ -209,6 +225,8 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
 
         if is_main_run {
             self.progress_bar.finish();
+            self.fixed_data
+                .report_progress(&self.machine_name, self.fixed_data.degree);
         }
 
         EvalValue::complete(outer_assignments)
@@ -515,6 +533,7 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
 
             let row = row_index + self.row_offset;
             self.progress_bar.set_position(row);
+            self.fixed_data.report_progress(&self.machine_name, row);
             let message = format!(
                 "{} rows/s, {identities_per_sec}k identities/s, {progress_percentage}% progress",
                 REPORT_FREQUENCY as u128 * 1_000_000 / duration.as_micros()
@@ -4,24 +4,22 @@ use halo2_proofs::{
         bn256::{Bn256, Fq, Fr, G1Affine},
         ff::Field,
     },
-    plonk::{self, Circuit, ConstraintSystem, Error, VerifyingKey},
+    plonk::{
+        self, Advice, Any, Assigned, Assignment, Challenge, Circuit, Column, ConstraintSystem,
+        Error, Fixed, FloorPlanner, Instance, Selector, VerifyingKey,
+    },
     poly::{commitment::ParamsProver, kzg::commitment::ParamsKZG},
 };
 use halo2_wrong_ecc::{
     integer::rns::Rns,
     maingate::{
-        MainGate, MainGateConfig, MainGateInstructions, RangeChip, RangeConfig, RangeInstructions,
-        RegionCtx,
+        AssignedValue, MainGate, MainGateConfig, MainGateInstructions, RangeChip, RangeConfig,
+        RangeInstructions, RegionCtx,
     },
     EccConfig,
 };
 use snark_verifier::{
-    loader::{
-        self,
-        evm::{self, deploy_and_call, encode_calldata, EvmLoader},
-        halo2::halo2_wrong_ecc,
-        native::NativeLoader,
-    },
+    loader::{self, evm::EvmLoader, halo2::halo2_wrong_ecc, native::NativeLoader},
     pcs::{
         kzg::{
             Gwc19, KzgAccumulator, KzgAs, KzgSuccinctVerifyingKey, LimbsEncoding,
@@ -51,6 +49,9 @@ use std::rc::Rc;
 const LIMBS: usize = 4;
 const BITS: usize = 68;
 
+/// The number of instances of the aggregation circuit that encode the KZG accumulator.
+pub const NUM_ACCUMULATOR_INSTANCES: usize = 4 * LIMBS;
+
 type As = KzgAs<Bn256, Gwc19>;
 type PlonkSuccinctVerifier = verifier::plonk::PlonkSuccinctVerifier<As, LimbsEncoding<LIMBS, BITS>>;
 type PlonkVerifier = verifier::plonk::PlonkVerifier<As, LimbsEncoding<LIMBS, BITS>>;
@@ -135,12 +136,18 @@ impl SnarkWitness {
     }
 }
 
+/// Verifies the snarks in-circuit and accumulates their KZG accumulators.
+/// Returns the resulting accumulator and the assigned instances of each snark.
+#[allow(clippy::type_complexity)]
 pub fn aggregate<'a>(
     svk: &Svk,
     loader: &Rc<Halo2Loader<'a>>,
     snarks: &[SnarkWitness],
     as_proof: Value<&'_ [u8]>,
-) -> KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>> {
+) -> (
+    KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>>,
+    Vec<Vec<Vec<AssignedValue<Fr>>>>,
+) {
     let assign_instances = |instances: &[Vec<Value<Fr>>]| {
         instances
             .iter()
@@ -153,6 +160,7 @@ pub fn aggregate<'a>(
             .collect_vec()
     };
 
+    let mut assigned_instances = vec![];
     let accumulators = snarks
         .iter()
         .flat_map(|snark| {
@@ -163,7 +171,20 @@ pub fn aggregate<'a>(
             let proof =
                 PlonkSuccinctVerifier::read_proof(svk, &protocol, &instances, &mut transcript)
                     .unwrap();
-            PlonkSuccinctVerifier::verify(svk, &protocol, &instances, &proof).unwrap()
+            let accumulators =
+                PlonkSuccinctVerifier::verify(svk, &protocol, &instances, &proof).unwrap();
+            assigned_instances.push(
+                instances
+                    .iter()
+                    .map(|instances| {
+                        instances
+                            .iter()
+                            .map(|instance| instance.assigned().clone())
+                            .collect_vec()
+                    })
+                    .collect_vec(),
+            );
+            accumulators
         })
         .collect_vec();
 
@@ -173,7 +194,7 @@ pub fn aggregate<'a>(
         As::verify(&Default::default(), &accumulators, &proof).unwrap()
     };
 
-    accumulator
+    (accumulator, assigned_instances)
 }
 
 #[derive(Clone)]
@@ -213,16 +234,31 @@ impl AggregationConfig {
     }
 }
 
+/// A circuit that verifies several snarks and exposes the resulting KZG accumulator as instances.
+///
+/// The snarks can be chained: For each pair `(initial, final)` in `chained_instances`, the
+/// circuit constrains the instance at index `final` of each snark to be equal to the instance
+/// at index `initial` of the next snark (both in the first instance column). After the
+/// accumulator, the circuit exposes the `initial` instances of the first snark and the `final`
+/// instances of the last snark.
+///
+/// There has to be at least one snark, and the chained instances have to exist in all of
+/// them. The callers check this before building the circuit.
 #[derive(Clone)]
 pub struct AggregationCircuit {
     svk: Svk,
     snarks: Vec<SnarkWitness>,
+    chained_instances: Vec<(usize, usize)>,
     instances: Vec<Fr>,
     as_proof: Value<Vec<u8>>,
 }
 
 impl AggregationCircuit {
-    pub fn new(params: &ParamsKZG<Bn256>, snarks: impl IntoIterator<Item = Snark>) -> Self {
+    pub fn new(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = Snark>,
+        chained_instances: Vec<(usize, usize)>,
+    ) -> Self {
         let svk = params.get_g()[0].into();
         let snarks = snarks.into_iter().collect_vec();
 
@@ -252,13 +288,28 @@ impl AggregationCircuit {
         };
 
         let KzgAccumulator { lhs, rhs } = accumulator;
-        let instances = [lhs.x, lhs.y, rhs.x, rhs.y]
+        let accumulator_limbs = [lhs.x, lhs.y, rhs.x, rhs.y]
             .map(fe_to_limbs::<_, _, LIMBS, BITS>)
             .concat();
+        let (first, last) = (snarks.first().unwrap(), snarks.last().unwrap());
+        let instances = accumulator_limbs
+            .into_iter()
+            .chain(
+                chained_instances
+                    .iter()
+                    .map(|(initial, _)| first.instances[0][*initial]),
+            )
+            .chain(
+                chained_instances
+                    .iter()
+                    .map(|(_, final_)| last.instances[0][*final_]),
+            )
+            .collect();
 
         Self {
             svk,
             snarks: snarks.into_iter().map_into().collect(),
+            chained_instances,
             instances,
             as_proof: Value::known(as_proof),
         }
@@ -267,6 +318,7 @@ impl AggregationCircuit {
     pub fn new_without_witness(
         params: &ParamsKZG<Bn256>,
         snarks: impl IntoIterator<Item = Snark>,
+        chained_instances: Vec<(usize, usize)>,
     ) -> Self {
         let svk = params.get_g()[0].into();
         let snarks = snarks.into_iter().collect_vec();
@@ -274,23 +326,41 @@ impl AggregationCircuit {
         Self {
             svk,
             snarks: snarks.into_iter().map_into().collect(),
+            chained_instances,
             instances: vec![],
             as_proof: Value::unknown(),
         }
     }
 
     pub fn accumulator_indices() -> Vec<(usize, usize)> {
-        (0..4 * LIMBS).map(|idx| (0, idx)).collect()
+        (0..NUM_ACCUMULATOR_INSTANCES).map(|idx| (0, idx)).collect()
     }
 
-    pub fn num_instance() -> Vec<usize> {
-        vec![4 * LIMBS]
+    pub fn num_instance(num_chained_instances: usize) -> Vec<usize> {
+        vec![NUM_ACCUMULATOR_INSTANCES + 2 * num_chained_instances]
     }
 
     pub fn instances(&self) -> Vec<Vec<Fr>> {
         vec![self.instances.clone()]
     }
 
+    /// Returns the smallest `k` such that the circuit fits into `2^k` rows, which
+    /// depends on the number of snarks and on their protocol.
+    pub fn min_k(&self) -> Result<u32, Error> {
+        let mut cs = ConstraintSystem::default();
+        let config = Self::configure(&mut cs);
+        let mut counter = RowCounter::default();
+        <Self as Circuit<Fr>>::FloorPlanner::synthesize(
+            &mut counter,
+            &self.without_witnesses(),
+            config,
+            cs.constants().clone(),
+        )?;
+        // The last rows are reserved for the blinding factors.
+        let rows = std::cmp::max(counter.rows + cs.blinding_factors() + 1, cs.minimum_rows());
+        Ok(rows.next_power_of_two().ilog2())
+    }
+
     pub fn as_proof(&self) -> Value<&[u8]> {
         self.as_proof.as_ref().map(Vec::as_slice)
     }
@@ -309,6 +379,7 @@ impl Circuit<Fr> for AggregationCircuit {
                 .iter()
                 .map(SnarkWitness::without_witnesses)
                 .collect(),
+            chained_instances: self.chained_instances.clone(),
             instances: Vec::new(),
             as_proof: Value::unknown(),
         }
@@ -332,14 +403,37 @@ impl Circuit<Fr> for AggregationCircuit {
 
         range_chip.load_table(&mut layouter)?;
 
-        let accumulator_limbs = layouter.assign_region(
+        let (accumulator_limbs, chained_state) = layouter.assign_region(
             || "",
             |region| {
                 let ctx = RegionCtx::new(region, 0);
 
                 let ecc_chip = config.ecc_chip();
                 let loader = Halo2Loader::new(ecc_chip, ctx);
-                let accumulator = aggregate(&self.svk, &loader, &self.snarks, self.as_proof());
+                let (accumulator, instances) =
+                    aggregate(&self.svk, &loader, &self.snarks, self.as_proof());
+
+                // The final state of each snark has to be the initial state of the next one.
+                for (previous, next) in instances.iter().tuple_windows() {
+                    for (initial, final_) in &self.chained_instances {
+                        main_gate.assert_equal(
+                            &mut loader.ctx_mut(),
+                            &previous[0][*final_],
+                            &next[0][*initial],
+                        )?;
+                    }
+                }
+                let (first, last) = (instances.first().unwrap(), instances.last().unwrap());
+                let chained_state = self
+                    .chained_instances
+                    .iter()
+                    .map(|(initial, _)| first[0][*initial].clone())
+                    .chain(
+                        self.chained_instances
+                            .iter()
+                            .map(|(_, final_)| last[0][*final_].clone()),
+                    )
+                    .collect_vec();
 
                 let accumulator_limbs = [accumulator.lhs, accumulator.rhs]
                     .iter()
@@ -352,24 +446,128 @@ impl Circuit<Fr> for AggregationCircuit {
                     .into_iter()
                     .flatten();
 
-                Ok(accumulator_limbs)
+                Ok((accumulator_limbs, chained_state))
             },
         )?;
 
-        for (row, limb) in accumulator_limbs.enumerate() {
-            main_gate.expose_public(layouter.namespace(|| ""), limb, row)?;
+        for (row, instance) in accumulator_limbs.chain(chained_state).enumerate() {
+            main_gate.expose_public(layouter.namespace(|| ""), instance, row)?;
         }
 
         Ok(())
     }
 }
 
-pub fn gen_aggregation_evm_verifier(
+/// Synthesizes a circuit without assigning any values, only counting the rows it uses.
+#[derive(Default)]
+struct RowCounter {
+    rows: usize,
+}
+
+impl RowCounter {
+    fn use_row(&mut self, row: usize) {
+        self.rows = std::cmp::max(self.rows, row + 1);
+    }
+}
+
+impl Assignment<Fr> for RowCounter {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(&mut self, _: A, _: &Selector, row: usize) -> Result<(), Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn query_instance(&self, _: Column<Instance>, _: usize) -> Result<Value<Fr>, Error> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Advice>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fr>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _: A,
+        _: Column<Fixed>,
+        row: usize,
+        _: V,
+    ) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fr>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn copy(
+        &mut self,
+        _: Column<Any>,
+        left_row: usize,
+        _: Column<Any>,
+        right_row: usize,
+    ) -> Result<(), Error> {
+        self.use_row(std::cmp::max(left_row, right_row));
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _: Column<Fixed>,
+        row: usize,
+        _: Value<Assigned<Fr>>,
+    ) -> Result<(), Error> {
+        self.use_row(row);
+        Ok(())
+    }
+
+    fn get_challenge(&self, _: Challenge) -> Value<Fr> {
+        Value::unknown()
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
+
+pub fn gen_aggregation_solidity_verifier(
     params: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
     num_instance: Vec<usize>,
     accumulator_indices: Vec<(usize, usize)>,
-) -> Vec<u8> {
+) -> String {
     let protocol = compile(
         params,
         vk,
@@ -387,11 +585,5 @@ pub fn gen_aggregation_evm_verifier(
     let proof = PlonkVerifier::read_proof(&vk, &protocol, &instances, &mut transcript).unwrap();
     PlonkVerifier::verify(&vk, &protocol, &instances, &proof).unwrap();
 
-    evm::compile_solidity(&loader.solidity_code())
-}
-
-pub fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: &[u8]) {
-    let calldata = encode_calldata(&instances, proof);
-    let gas_cost = deploy_and_call(deployment_code, calldata).unwrap();
-    dbg!(gas_cost);
+    loader.solidity_code()
 }
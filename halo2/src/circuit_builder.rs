@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 
 use halo2_curves::bn256::Fr;
-use halo2_curves::ff::FromUniformBytes;
+use halo2_curves::ff::{FromUniformBytes, PrimeField};
 use num_bigint::BigUint;
 use polyexen::expr::{ColumnQuery, Expr, PlonkVar};
 use polyexen::plaf::backends::halo2::PlafH2Circuit;
@@ -9,11 +10,13 @@ use polyexen::plaf::{
     ColumnFixed, ColumnPublic, ColumnWitness, Columns, CopyC, Info, Lookup, Plaf, Poly, Shuffle,
     Witness,
 };
+use powdr_ast::parsed::visitor::ExpressionVisitable;
 use powdr_ast::parsed::SelectedExpressions;
 
-use num_traits::{One, ToPrimitive};
+use num_traits::ToPrimitive;
 use powdr_ast::analyzed::{
-    AlgebraicBinaryOperator, AlgebraicExpression as Expression, Analyzed, IdentityKind,
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicUnaryOperator, Analyzed,
+    Identity, IdentityKind,
 };
 use powdr_number::{BigInt, FieldElement};
 
@@ -21,6 +24,10 @@ use super::circuit_data::CircuitData;
 
 /// Name of the __enable column
 const ENABLE_NAME: &str = "__enable";
+/// Prefix of the names of the helper columns for high-degree lookup expressions
+const LOOKUP_HELPER_PREFIX: &str = "__lookup_helper_";
+/// Default maximum degree of lookup expressions, including the __enable selector
+pub const DEFAULT_MAX_LOOKUP_DEGREE: usize = 3;
 
 /// Converts a column of size $2^k$ and type T to a column of size $2^{k+1}$ and type Option<BigUint>:
 /// - The first $2^k$ elements are Some(value.to_arbitrary_integer())
@@ -48,10 +55,20 @@ fn convert_column<T: FieldElement>(column: &[T]) -> Vec<Option<BigUint>> {
 
 /// Converts an analyzed PIL and fixed to a Plaf circuit.
 /// A Plaf circuit only contains the shape of the circuit.
+///
+/// The degree of a lookup or shuffle grows with the degree of its expressions, and it
+/// determines the degree of the whole circuit. Expressions (multiplied by their selectors)
+/// whose degree exceeds `max_lookup_degree` are therefore materialized into helper witness
+/// columns, which are constrained by a polynomial identity to be equal to the expression.
 pub(crate) fn analyzed_to_plaf<T: FieldElement>(
     analyzed: &Analyzed<T>,
     fixed: &[(String, Vec<T>)],
-) -> Plaf {
+    max_lookup_degree: usize,
+) -> Result<Plaf, String> {
+    if max_lookup_degree < 2 {
+        return Err("The maximum degree of lookup expressions has to be at least 2.".to_string());
+    }
+
     // The structure of the table is as following
     //
     // | constant columns | __enable     |  witness columns | \
@@ -91,17 +108,32 @@ pub(crate) fn analyzed_to_plaf<T: FieldElement>(
         ))
         .collect::<Vec<_>>();
 
-    let cd = CircuitData::from(analyzed, &fixed_names);
+    let mut cd = CircuitData::from(analyzed, &fixed_names);
 
     let mut lookups = vec![];
     let mut shuffles = vec![];
     let mut polys = vec![];
 
+    let identities = analyzed.identities_with_inlined_intermediate_polynomials();
+    let helpers = lookup_helper_expressions(&identities, max_lookup_degree)?;
+    let helper_names = (0..helpers.len())
+        .map(|i| format!("{LOOKUP_HELPER_PREFIX}{i}"))
+        .collect::<Vec<_>>();
+    let helper_columns = helper_names
+        .iter()
+        .map(|name| cd.add_witness_column(name))
+        .collect::<Vec<_>>();
+
     let wit_columns: Vec<_> = analyzed
         .committed_polys_in_source_order()
         .into_iter()
         .flat_map(|(p, _)| p.array_elements())
         .map(|(name, _)| ColumnWitness::new(name.to_string(), 0))
+        .chain(
+            helper_names
+                .iter()
+                .map(|name| ColumnWitness::new(name.clone(), 0)),
+        )
         .collect();
 
     // build Plaf columns -------------------------------------------------
@@ -123,23 +155,37 @@ pub(crate) fn analyzed_to_plaf<T: FieldElement>(
     // build Plaf polys. -------------------------------------------------------------------------
 
     let q_enable = query(cd.col(ENABLE_NAME), 0);
-    let apply_selectors_to_set = |set: &SelectedExpressions<Expression<T>>| {
-        let selector = set
-            .selector
-            .clone()
-            .map_or(Expr::Const(BigUint::one()), |expr| {
-                expression_2_expr(&cd, &expr)
-            });
 
-        let selector = Expr::Mul(vec![selector, q_enable.clone()]);
+    // helper = selector * expression, on all enabled rows.
+    for ((helper, column), name) in helpers.iter().zip(&helper_columns).zip(helper_names) {
+        let exp = Expr::Sum(vec![
+            query(*column, 0),
+            Expr::Neg(Box::new(expression_2_expr(&cd, helper)?)),
+        ]);
+        polys.push(Poly {
+            name,
+            exp: Expr::Mul(vec![exp, q_enable.clone()]),
+        });
+    }
 
-        set.expressions
+    // The helper columns are used in the same order as they were created.
+    let mut helper_columns = helper_columns.into_iter();
+    let mut apply_selectors_to_set = |set: &SelectedExpressions<Expression<T>>| {
+        selected_expressions(set)
             .iter()
-            .map(|expr| selector.clone() * expression_2_expr(&cd, expr))
-            .collect()
+            .map(|expr| {
+                Ok(if needs_lookup_helper(expr, max_lookup_degree)? {
+                    Expr::Mul(vec![
+                        query(helper_columns.next().unwrap(), 0),
+                        q_enable.clone(),
+                    ])
+                } else {
+                    Expr::Mul(vec![expression_2_expr(&cd, expr)?, q_enable.clone()])
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()
     };
 
-    let identities = analyzed.identities_with_inlined_intermediate_polynomials();
     for id in &identities {
         match id.kind {
             IdentityKind::Polynomial => {
@@ -150,36 +196,41 @@ pub(crate) fn analyzed_to_plaf<T: FieldElement>(
                 assert_eq!(id.left.expressions.len(), 0);
 
                 let exp = id.expression_for_poly_id();
-                let exp = expression_2_expr(&cd, exp);
+                let exp = expression_2_expr(&cd, exp)?;
 
                 // depending whether this polynomial contains a rotation,
                 // enable for all rows or all except the last one.
 
                 let exp = Expr::Mul(vec![exp, q_enable.clone()]);
                 polys.push(Poly {
-                    name: "".to_string(),
+                    name: identity_name(id),
                     exp,
                 });
             }
             IdentityKind::Plookup => {
-                let left = apply_selectors_to_set(&id.left);
-                let right = apply_selectors_to_set(&id.right);
+                let left = apply_selectors_to_set(&id.left)?;
+                let right = apply_selectors_to_set(&id.right)?;
 
                 lookups.push(Lookup {
-                    name: "".to_string(),
+                    name: identity_name(id),
                     exps: (left, right),
                 });
             }
             IdentityKind::Permutation => {
-                let left = apply_selectors_to_set(&id.left);
-                let right = apply_selectors_to_set(&id.right);
+                let left = apply_selectors_to_set(&id.left)?;
+                let right = apply_selectors_to_set(&id.right)?;
 
                 shuffles.push(Shuffle {
-                    name: "".to_string(),
+                    name: identity_name(id),
                     exps: (left, right),
                 });
             }
-            _ => unimplemented!(),
+            IdentityKind::Connect => {
+                return Err(format!(
+                    "{} is not supported by the halo2 backend.",
+                    identity_name(id)
+                ));
+            }
         }
     }
 
@@ -194,7 +245,7 @@ pub(crate) fn analyzed_to_plaf<T: FieldElement>(
 
     // build Plaf fixed. -------------------------------------------------------------------------
 
-    Plaf {
+    Ok(Plaf {
         info,
         columns,
         polys,
@@ -203,7 +254,139 @@ pub(crate) fn analyzed_to_plaf<T: FieldElement>(
         shuffles,
         copys: copy_constraints(analyzed, &cd),
         fixed,
-    }
+    })
+}
+
+/// Returns the expressions of a lookup or shuffle side, multiplied by its selector.
+fn selected_expressions<T: FieldElement>(
+    set: &SelectedExpressions<Expression<T>>,
+) -> Vec<Expression<T>> {
+    set.expressions
+        .iter()
+        .map(|expr| match &set.selector {
+            Some(selector) => Expression::BinaryOperation(
+                Box::new(selector.clone()),
+                AlgebraicBinaryOperator::Mul,
+                Box::new(expr.clone()),
+            ),
+            None => expr.clone(),
+        })
+        .collect()
+}
+
+/// Returns true if the selected lookup expression has to be materialized into a helper
+/// column, because its degree exceeds `max_lookup_degree` once multiplied by __enable.
+fn needs_lookup_helper<T: FieldElement>(
+    expr: &Expression<T>,
+    max_lookup_degree: usize,
+) -> Result<bool, String> {
+    Ok(expression_degree(expr)? + 1 > max_lookup_degree)
+}
+
+/// Returns the selected expressions of lookups and shuffles that are materialized into
+/// helper columns, in the order of the helper columns.
+fn lookup_helper_expressions<T: FieldElement>(
+    identities: &[Identity<Expression<T>>],
+    max_lookup_degree: usize,
+) -> Result<Vec<Expression<T>>, String> {
+    identities
+        .iter()
+        .filter(|id| matches!(id.kind, IdentityKind::Plookup | IdentityKind::Permutation))
+        .flat_map(|id| {
+            selected_expressions(&id.left)
+                .into_iter()
+                .chain(selected_expressions(&id.right))
+        })
+        .filter_map(|expr| match needs_lookup_helper(&expr, max_lookup_degree) {
+            Ok(true) => Some(Ok(expr)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+/// Returns the degree of the expression in the columns.
+fn expression_degree<T: FieldElement>(expr: &Expression<T>) -> Result<usize, String> {
+    Ok(match expr {
+        Expression::Number(_) => 0,
+        Expression::Reference(_) => 1,
+        Expression::BinaryOperation(lhe, op, rhe) => match op {
+            AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                std::cmp::max(expression_degree(lhe)?, expression_degree(rhe)?)
+            }
+            AlgebraicBinaryOperator::Mul => expression_degree(lhe)? + expression_degree(rhe)?,
+            AlgebraicBinaryOperator::Pow => expression_degree(lhe)? * exponent(rhe)? as usize,
+        },
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, e) => expression_degree(e)?,
+        Expression::PublicReference(_) => return Err(unsupported_expression(expr)),
+    })
+}
+
+/// Returns the exponent of a power, which has to be a number that fits 32 bits.
+fn exponent<T: FieldElement>(expr: &Expression<T>) -> Result<u32, String> {
+    let Expression::Number(e) = expr else {
+        return Err(format!("Expected number in exponent, got {expr}."));
+    };
+    e.to_arbitrary_integer()
+        .to_u32()
+        .ok_or_else(|| format!("Exponent has to fit 32 bits, got {e}."))
+}
+
+fn unsupported_expression<T: FieldElement>(expr: &Expression<T>) -> String {
+    format!("Expression {expr} is not supported by the halo2 backend.")
+}
+
+/// Evaluates the expression in the given row, wrapping around at the end of the columns.
+fn evaluate_expression<T: FieldElement>(
+    expr: &Expression<T>,
+    columns: &HashMap<&str, &[T]>,
+    row: usize,
+) -> Result<T, String> {
+    Ok(match expr {
+        Expression::Number(n) => *n,
+        Expression::Reference(polyref) => {
+            let values = columns[polyref.name.as_str()];
+            values[(row + polyref.next as usize) % values.len()]
+        }
+        Expression::BinaryOperation(lhe, op, rhe) => {
+            let lhe = evaluate_expression(lhe, columns, row)?;
+            let rhe = evaluate_expression(rhe, columns, row)?;
+            match op {
+                AlgebraicBinaryOperator::Add => lhe + rhe,
+                AlgebraicBinaryOperator::Sub => lhe - rhe,
+                AlgebraicBinaryOperator::Mul => lhe * rhe,
+                AlgebraicBinaryOperator::Pow => lhe.pow(rhe.to_integer()),
+            }
+        }
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, e) => {
+            -evaluate_expression(e, columns, row)?
+        }
+        Expression::PublicReference(_) => return Err(unsupported_expression(expr)),
+    })
+}
+
+/// Returns a name for the identity that refers to its namespace and its location in the
+/// PIL source, e.g. `Plookup identity 2 in main (main.asm:12)`.
+/// It is used for gates, lookups and shuffles so that failures reported by halo2 can be
+/// mapped back to the identity. The kind and the ID make the name unique.
+pub(crate) fn identity_name<T>(identity: &Identity<Expression<T>>) -> String {
+    // The namespace of an identity is the namespace of the columns it references.
+    let namespace = identity.pre_visit_expressions_return(&mut |expr| match expr {
+        Expression::Reference(poly) => match poly.name.rsplit_once('.') {
+            Some((namespace, _)) => ControlFlow::Break(namespace.to_string()),
+            None => ControlFlow::Continue(()),
+        },
+        _ => ControlFlow::Continue(()),
+    });
+    let namespace = match namespace {
+        ControlFlow::Break(namespace) => format!(" in {namespace}"),
+        ControlFlow::Continue(()) => String::new(),
+    };
+    let file = identity.source.file.as_deref().unwrap_or("<unknown>");
+    format!(
+        "{:?} identity {}{namespace} ({file}:{})",
+        identity.kind, identity.id, identity.source.line
+    )
 }
 
 fn copy_constraints<T: FieldElement>(pil: &Analyzed<T>, cd: &CircuitData) -> Vec<CopyC> {
@@ -221,7 +404,7 @@ fn copy_constraints<T: FieldElement>(pil: &Analyzed<T>, cd: &CircuitData) -> Vec
     // |  1  |  1  |  6  |  *5*   |
     // |  2  | *2* |  7  |        |
     // |  3  |  4  |  8  |        |
-    for public_declaration in pil.public_declarations.values() {
+    for (_, public_declaration) in pil.public_declarations_in_source_order() {
         let witness_name = public_declaration.referenced_poly_name();
         let witness_col = cd.col(&witness_name);
         let witness_offset = public_declaration.index as usize;
@@ -253,22 +436,15 @@ fn copy_constraints<T: FieldElement>(pil: &Analyzed<T>, cd: &CircuitData) -> Vec
     copies
 }
 
-/// Converts an analyzed PIL and fixed to a PlafH2Circuit.
+/// Converts a Plaf circuit to a PlafH2Circuit.
 /// A PlafH2Circuit contains the witness because Halo2 is like that.
 /// Because of that we just build a witness with the correct length
 /// but with 0s.
-pub(crate) fn analyzed_to_circuit_with_zeroed_witness<T: FieldElement>(
-    plaf: Plaf,
-    analyzed: &Analyzed<T>,
-) -> PlafH2Circuit {
+pub(crate) fn analyzed_to_circuit_with_zeroed_witness(plaf: Plaf) -> PlafH2Circuit {
     let num_rows = plaf.fixed.len();
 
-    let wit_columns: Vec<_> = analyzed
-        .committed_polys_in_source_order()
-        .into_iter()
-        .flat_map(|(p, _)| p.array_elements())
-        .map(|(name, _)| ColumnWitness::new(name.to_string(), 0))
-        .collect();
+    // The witness columns of the Plaf circuit include the helper columns.
+    let wit_columns = plaf.columns.witness.clone();
 
     // build zeroed witness. -------------------------------------------------------------------------
 
@@ -292,12 +468,16 @@ pub fn powdr_ff_to_fr<T: FieldElement>(x: T) -> Fr {
     Fr::from_uniform_bytes(&buffer)
 }
 
+pub fn fr_to_powdr_ff<T: FieldElement>(x: Fr) -> T {
+    T::from_bytes_le(&x.to_repr())
+}
+
 fn public_values<T: FieldElement>(pil: &Analyzed<T>, witness: &[(String, Vec<T>)]) -> Vec<Fr> {
     let witness_map: HashMap<String, Vec<T>> = witness.iter().cloned().collect();
     let eval_witness = |name: &String, row: usize| -> T { witness_map.get(name).unwrap()[row] };
 
     let mut publics = vec![];
-    for public_declaration in pil.public_declarations.values() {
+    for (_, public_declaration) in pil.public_declarations_in_source_order() {
         let witness_name = public_declaration.referenced_poly_name();
         let witness_offset = public_declaration.index as usize;
 
@@ -313,11 +493,15 @@ fn public_values<T: FieldElement>(pil: &Analyzed<T>, witness: &[(String, Vec<T>)
 }
 
 /// Converts an analyzed PIL, fixed and witness columns to a PlafH2Circuit and publics for the halo2 backend.
+/// `max_lookup_degree` has to be the same as for [analyzed_to_plaf], as the values of
+/// the helper columns are computed here.
 pub(crate) fn analyzed_to_circuit_with_witness<T: FieldElement>(
     analyzed: &Analyzed<T>,
     plaf: Plaf,
+    fixed: &[(String, Vec<T>)],
     witness: &[(String, Vec<T>)],
-) -> (PlafH2Circuit, Vec<Vec<Fr>>) {
+    max_lookup_degree: usize,
+) -> Result<(PlafH2Circuit, Vec<Vec<Fr>>), String> {
     let num_rows = plaf.fixed.len();
 
     assert!(
@@ -327,14 +511,32 @@ pub(crate) fn analyzed_to_circuit_with_witness<T: FieldElement>(
 
     // build witness. -------------------------------------------------------------------------
 
-    let converted_witness: Vec<Vec<_>> = witness
+    let columns = fixed
+        .iter()
+        .chain(witness)
+        .map(|(name, values)| (name.as_str(), values.as_slice()))
+        .collect();
+    let identities = analyzed.identities_with_inlined_intermediate_polynomials();
+    let helpers = lookup_helper_expressions(&identities, max_lookup_degree)?
+        .iter()
+        .enumerate()
+        .map(|(i, helper)| {
+            let values = (0..analyzed.degree() as usize)
+                .map(|row| evaluate_expression(helper, &columns, row))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((format!("{LOOKUP_HELPER_PREFIX}{i}"), values))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let witness_and_helpers = witness.iter().chain(&helpers).collect::<Vec<_>>();
+
+    let converted_witness: Vec<Vec<_>> = witness_and_helpers
         .iter()
         .map(|(_, column)| convert_column(column))
         .collect();
 
     let wit = Witness {
-        num_rows: witness.len(),
-        columns: witness
+        num_rows: witness_and_helpers.len(),
+        columns: witness_and_helpers
             .iter()
             .map(|(name, _)| ColumnWitness::new(name.clone(), 0))
             .collect(),
@@ -343,14 +545,17 @@ pub(crate) fn analyzed_to_circuit_with_witness<T: FieldElement>(
 
     // return circuit description + witness. -------------
 
-    (
+    Ok((
         PlafH2Circuit { plaf, wit },
         vec![public_values(analyzed, witness)],
-    )
+    ))
 }
 
-fn expression_2_expr<T: FieldElement>(cd: &CircuitData, expr: &Expression<T>) -> Expr<PlonkVar> {
-    match expr {
+fn expression_2_expr<T: FieldElement>(
+    cd: &CircuitData,
+    expr: &Expression<T>,
+) -> Result<Expr<PlonkVar>, String> {
+    Ok(match expr {
         Expression::Number(n) => Expr::Const(n.to_arbitrary_integer()),
         Expression::Reference(polyref) => {
             let plonkvar = PlonkVar::Query(ColumnQuery {
@@ -361,26 +566,24 @@ fn expression_2_expr<T: FieldElement>(cd: &CircuitData, expr: &Expression<T>) ->
             Expr::Var(plonkvar)
         }
         Expression::BinaryOperation(lhe, op, rhe_powdr) => {
-            let lhe = expression_2_expr(cd, lhe);
-            let rhe = expression_2_expr(cd, rhe_powdr);
+            let lhe = expression_2_expr(cd, lhe)?;
             match op {
-                AlgebraicBinaryOperator::Add => Expr::Sum(vec![lhe, rhe]),
-                AlgebraicBinaryOperator::Sub => Expr::Sum(vec![lhe, Expr::Neg(Box::new(rhe))]),
-                AlgebraicBinaryOperator::Mul => Expr::Mul(vec![lhe, rhe]),
-                AlgebraicBinaryOperator::Pow => {
-                    let Expression::Number(e) = rhe_powdr.as_ref() else {
-                        panic!("Expected number in exponent.")
-                    };
-                    Expr::Pow(
-                        Box::new(lhe),
-                        e.to_arbitrary_integer()
-                            .to_u32()
-                            .unwrap_or_else(|| panic!("Exponent has to fit 32 bits.")),
-                    )
+                AlgebraicBinaryOperator::Add => {
+                    Expr::Sum(vec![lhe, expression_2_expr(cd, rhe_powdr)?])
+                }
+                AlgebraicBinaryOperator::Sub => Expr::Sum(vec![
+                    lhe,
+                    Expr::Neg(Box::new(expression_2_expr(cd, rhe_powdr)?)),
+                ]),
+                AlgebraicBinaryOperator::Mul => {
+                    Expr::Mul(vec![lhe, expression_2_expr(cd, rhe_powdr)?])
                 }
+                AlgebraicBinaryOperator::Pow => Expr::Pow(Box::new(lhe), exponent(rhe_powdr)?),
             }
         }
-
-        _ => unimplemented!("{:?}", expr),
-    }
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, e) => {
+            Expr::Neg(Box::new(expression_2_expr(cd, e)?))
+        }
+        Expression::PublicReference(_) => return Err(unsupported_expression(expr)),
+    })
 }
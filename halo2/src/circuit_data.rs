@@ -53,6 +53,20 @@ impl CircuitData {
         }
     }
 
+    /// Adds a witness column that does not exist in the PIL, e.g. a helper column.
+    pub fn add_witness_column(&mut self, name: &str) -> Column {
+        let column = Column {
+            kind: ColumnKind::Witness,
+            index: self
+                .columns
+                .values()
+                .filter(|column| matches!(column.kind, ColumnKind::Witness))
+                .count(),
+        };
+        self.columns.insert(name.to_string(), column);
+        column
+    }
+
     pub fn col(&self, name: &str) -> Column {
         *self
             .columns
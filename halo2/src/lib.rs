@@ -5,6 +5,9 @@ pub(crate) mod circuit_builder;
 pub(crate) mod circuit_data;
 pub(crate) mod mock_prover;
 pub(crate) mod prover;
+pub(crate) mod setup;
 
+pub use circuit_builder::DEFAULT_MAX_LOOKUP_DEGREE;
 pub use mock_prover::mock_prove;
 pub use prover::*;
+pub use setup::{read_setup, validate_setup, Setup};
@@ -1,22 +1,35 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
 use polyexen::plaf::PlafDisplayBaseTOML;
-use powdr_ast::analyzed::Analyzed;
+use powdr_ast::analyzed::{AlgebraicExpression as Expression, Analyzed, Identity};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
 
-use super::circuit_builder::{analyzed_to_circuit_with_witness, analyzed_to_plaf};
-use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use super::circuit_builder::{analyzed_to_circuit_with_witness, analyzed_to_plaf, identity_name};
+use halo2_proofs::{
+    dev::{FailureLocation, MockProver, VerifyFailure},
+    halo2curves::bn256::Fr,
+};
 use powdr_number::{BigInt, FieldElement};
 
 // Can't depend on compiler::pipeline::GeneratedWitness because of circular dependencies...
+/// Runs the halo2 mock prover on the circuit and returns a description of the failing
+/// identities in terms of the PIL source if the witness does not satisfy it.
+/// Lookup and shuffle expressions of a degree larger than `max_lookup_degree` are moved
+/// into helper columns.
 pub fn mock_prove<T: FieldElement>(
     pil: &Analyzed<T>,
     constants: &[(String, Vec<T>)],
     witness: &[(String, Vec<T>)],
-) {
+    max_lookup_degree: usize,
+) -> Result<(), String> {
     if polyexen::expr::get_field_p::<Fr>() != T::modulus().to_arbitrary_integer() {
         panic!("powdr modulus doesn't match halo2 modulus. Make sure you are using Bn254");
     }
 
-    let plaf_circuit = analyzed_to_plaf(pil, constants);
-    let (circuit, publics) = analyzed_to_circuit_with_witness(pil, plaf_circuit, witness);
+    let plaf_circuit = analyzed_to_plaf(pil, constants, max_lookup_degree)?;
+    let (circuit, publics) =
+        analyzed_to_circuit_with_witness(pil, plaf_circuit, constants, witness, max_lookup_degree)?;
 
     // double the row count in order to make space for the cells introduced by the backend
     // TODO: use a precise count of the extra rows needed to avoid using so many rows
@@ -28,7 +41,80 @@ pub fn mock_prove<T: FieldElement>(
     log::debug!("{}", PlafDisplayBaseTOML(&circuit.plaf));
 
     let mock_prover = MockProver::<Fr>::run(expanded_row_count_log, &circuit, publics).unwrap();
-    mock_prover.assert_satisfied();
+    mock_prover.verify().map_err(|failures| {
+        let identities = pil.identities_with_inlined_intermediate_polynomials();
+        let columns = constants
+            .iter()
+            .chain(witness)
+            .map(|(name, values)| (name.as_str(), values.as_slice()))
+            .collect();
+        let failures = failures
+            .iter()
+            .map(|failure| describe_failure(failure, &identities, &columns))
+            .join("\n");
+        format!("The circuit was not satisfied:\n{failures}")
+    })
+}
+
+/// Translates a failure reported by halo2 back to the PIL identity it originates from,
+/// together with the values of the referenced columns in the failing row.
+/// Failures that do not originate from an identity are described by halo2.
+fn describe_failure<T: FieldElement>(
+    failure: &VerifyFailure,
+    identities: &[Identity<Expression<T>>],
+    columns: &HashMap<&str, &[T]>,
+) -> String {
+    let (identity, location) = match failure {
+        VerifyFailure::ConstraintNotSatisfied {
+            constraint,
+            location,
+            ..
+        } => {
+            // The name of the gate is only accessible through the description of the constraint.
+            let constraint = constraint.to_string();
+            let identity = identities
+                .iter()
+                .find(|&identity| constraint.contains(&format!("'{}'", identity_name(identity))));
+            (identity, location)
+        }
+        VerifyFailure::Lookup { name, location, .. }
+        | VerifyFailure::Shuffle { name, location, .. } => {
+            let identity = identities
+                .iter()
+                .find(|&identity| identity_name(identity) == *name);
+            (identity, location)
+        }
+        _ => return failure.to_string(),
+    };
+    let Some(identity) = identity else {
+        return failure.to_string();
+    };
+    // The circuit is assigned in a single region starting at row 0.
+    let row = match location {
+        FailureLocation::InRegion { offset, .. } => *offset,
+        FailureLocation::OutsideRegion { row } => *row,
+    };
+
+    let mut references = vec![];
+    identity.pre_visit_expressions(&mut |expr| {
+        if let Expression::Reference(poly) = expr {
+            references.push((poly.name.clone(), poly.next));
+        }
+    });
+    let values = references
+        .into_iter()
+        .unique()
+        .filter_map(|(name, next)| {
+            let values = columns.get(name.as_str())?;
+            let value = values[(row + next as usize) % values.len()];
+            let next = if next { "'" } else { "" };
+            Some(format!("    {name}{next} = {value}"))
+        })
+        .join("\n");
+    format!(
+        "{}:\n    {identity}\nnot satisfied in row {row}:\n{values}",
+        identity_name(identity)
+    )
 }
 
 #[cfg(test)]
@@ -38,6 +124,7 @@ mod test {
     use test_log::test;
 
     use super::*;
+    use crate::circuit_builder::DEFAULT_MAX_LOOKUP_DEGREE;
 
     #[allow(clippy::print_stdout)]
     fn mock_prove_asm(file_name: &str, inputs: &[Bn254Field]) {
@@ -50,7 +137,9 @@ mod test {
             &result.pil,
             &result.fixed_cols,
             result.witness.as_ref().unwrap(),
-        );
+            DEFAULT_MAX_LOOKUP_DEGREE,
+        )
+        .unwrap();
     }
 
     #[test]
@@ -65,7 +154,97 @@ mod test {
             &result.pil,
             &result.fixed_cols,
             result.witness.as_ref().unwrap(),
-        );
+            DEFAULT_MAX_LOOKUP_DEGREE,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn negation() {
+        let content = "namespace Global(8); pol fixed z = [1, 2]*; pol witness a; -a = -z - 1;";
+
+        let result = Pipeline::<Bn254Field>::default()
+            .from_pil_string(content.to_string())
+            .generated_witness()
+            .unwrap();
+        mock_prove(
+            &result.pil,
+            &result.fixed_cols,
+            result.witness.as_ref().unwrap(),
+            DEFAULT_MAX_LOOKUP_DEGREE,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unsatisfied_identity() {
+        let content = "namespace Global(8); pol fixed z = [1, 2]*; pol witness a; a = z + 1;";
+
+        let result = Pipeline::<Bn254Field>::default()
+            .from_pil_string(content.to_string())
+            .generated_witness()
+            .unwrap();
+        let mut witness = result.witness.as_ref().unwrap().clone();
+        witness[0].1[3] = 7.into();
+
+        let err = mock_prove(
+            &result.pil,
+            &result.fixed_cols,
+            &witness,
+            DEFAULT_MAX_LOOKUP_DEGREE,
+        )
+        .unwrap_err();
+        assert!(err.contains("Polynomial identity 0 in Global"));
+        assert!(err.contains("not satisfied in row 3"));
+        assert!(err.contains("Global.a = 7"));
+        assert!(err.contains("Global.z = 2"));
+    }
+
+    /// `sel * a * a` has degree 4 including __enable, so it needs a helper column.
+    const HIGH_DEGREE_LOOKUP: &str = "namespace Global(4); pol fixed SQUARES = [0, 1, 4, 9]; pol witness a, sel; sel { a * a } in { SQUARES };";
+
+    fn mock_prove_high_degree_lookup(a: [u32; 4], sel: [u32; 4]) -> Result<(), String> {
+        let witness = vec![
+            ("Global.a".to_string(), a.map(From::from).to_vec()),
+            ("Global.sel".to_string(), sel.map(From::from).to_vec()),
+        ];
+        let result = Pipeline::<Bn254Field>::default()
+            .from_pil_string(HIGH_DEGREE_LOOKUP.to_string())
+            .skip_witness_generation(witness)
+            .generated_witness()
+            .unwrap();
+        mock_prove(
+            &result.pil,
+            &result.fixed_cols,
+            result.witness.as_ref().unwrap(),
+            DEFAULT_MAX_LOOKUP_DEGREE,
+        )
+    }
+
+    #[test]
+    fn lookup_helper_columns() {
+        let result = Pipeline::<Bn254Field>::default()
+            .from_pil_string(HIGH_DEGREE_LOOKUP.to_string())
+            .pil_with_evaluated_fixed_cols()
+            .unwrap();
+
+        let plaf = analyzed_to_plaf(&result.pil, &result.fixed_cols, 3).unwrap();
+        assert_eq!(plaf.columns.witness.len(), 3);
+        assert_eq!(plaf.polys.len(), 1);
+
+        let plaf = analyzed_to_plaf(&result.pil, &result.fixed_cols, 4).unwrap();
+        assert_eq!(plaf.columns.witness.len(), 2);
+        assert!(plaf.polys.is_empty());
+    }
+
+    #[test]
+    fn high_degree_lookup() {
+        mock_prove_high_degree_lookup([0, 1, 2, 3], [1, 1, 1, 1]).unwrap();
+        mock_prove_high_degree_lookup([0, 1, 2, 5], [1, 1, 1, 0]).unwrap();
+
+        let err = mock_prove_high_degree_lookup([0, 1, 2, 5], [1, 1, 1, 1]).unwrap_err();
+        assert!(err.contains("Plookup identity 0 in Global"));
+        assert!(err.contains("Global.a = 5"));
     }
 
     #[test]
@@ -1,5 +1,8 @@
 use halo2_proofs::{
-    halo2curves::bn256::{Fr, G1Affine},
+    halo2curves::{
+        bn256::{Fr, G1Affine},
+        ff::PrimeField,
+    },
     plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ProvingKey, VerifyingKey},
     poly::{
         commitment::ParamsProver,
@@ -17,20 +20,28 @@ use polyexen::plaf::{Plaf, PlafDisplayBaseTOML};
 use powdr_ast::analyzed::Analyzed;
 use powdr_number::{BigInt, DegreeType, FieldElement, KnownField};
 use snark_verifier::{
-    loader::native::NativeLoader,
+    loader::{
+        evm::{self, encode_calldata},
+        native::NativeLoader,
+    },
     system::halo2::{compile, transcript::evm::EvmTranscript, Config},
+    verifier::plonk::PlonkProtocol,
 };
 
 use crate::aggregation;
 use crate::circuit_builder::{
     analyzed_to_circuit_with_witness, analyzed_to_circuit_with_zeroed_witness, analyzed_to_plaf,
-    powdr_ff_to_fr,
+    fr_to_powdr_ff, powdr_ff_to_fr,
 };
+use crate::setup::{read_setup, Setup};
 
 use itertools::Itertools;
 use rand::rngs::OsRng;
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
     io::{self, Cursor},
+    rc::Rc,
     time::Instant,
 };
 
@@ -48,11 +59,24 @@ pub use halo2_proofs::SerdeFormat;
 /// "unsafe" code, and unsafe code is harder to explain and maintain.
 pub struct Halo2Prover<'a, F: FieldElement> {
     pil: &'a Analyzed<F>,
+    fixed: &'a [(String, Vec<F>)],
     plaf: Plaf,
+    /// The maximum degree of lookup expressions the Plaf circuit was built with.
+    max_lookup_degree: usize,
+    /// The parameters of the circuit.
     params: ParamsKZG<Bn256>,
+    /// The setup as provided. It can be larger than needed for the circuit, because
+    /// the aggregation circuit is usually much larger than the circuit it aggregates.
+    setup: Setup,
+    /// The parameters for the aggregation circuit by their `k`, which are only assembled
+    /// if they are needed (see [Self::aggregation_params]).
+    aggregation_params: RefCell<BTreeMap<u32, Rc<ParamsKZG<Bn256>>>>,
     vkey: Option<VerifyingKey<G1Affine>>,
 }
 
+/// The default bound on the size of the aggregation circuit, as log2 of its number of rows.
+pub const DEFAULT_MAX_AGGREGATION_K: u32 = 22;
+
 fn degree_bits(degree: DegreeType) -> u32 {
     DegreeType::BITS - degree.leading_zeros() + 1
 }
@@ -62,28 +86,44 @@ pub fn generate_setup(size: DegreeType) -> ParamsKZG<Bn256> {
 }
 
 impl<'a, F: FieldElement> Halo2Prover<'a, F> {
+    /// Creates a prover for the given PIL and fixed columns. Lookup and shuffle expressions
+    /// of a degree larger than `max_lookup_degree` are moved into helper columns.
+    ///
+    /// Of the setup, at most `2^max_aggregation_k` powers are read, which bounds the size
+    /// of the aggregation circuit.
     pub fn new(
         pil: &'a Analyzed<F>,
-        fixed: &[(String, Vec<F>)],
+        fixed: &'a [(String, Vec<F>)],
         setup: Option<&mut dyn io::Read>,
-    ) -> Result<Self, io::Error> {
+        max_lookup_degree: usize,
+        max_aggregation_k: u32,
+    ) -> Result<Self, String> {
         Self::assert_field_is_bn254();
 
-        let params = setup
-            .map(|mut setup| ParamsKZG::<Bn256>::read(&mut setup))
-            .transpose()?
-            .map(|mut params| {
-                params.downsize(degree_bits(pil.degree()));
-                params
-            })
-            .unwrap_or_else(|| generate_setup(pil.degree()));
+        let k = degree_bits(pil.degree());
+        let (setup, params) = match setup {
+            Some(setup) => {
+                let setup =
+                    read_setup(setup, max_aggregation_k.max(k)).map_err(|e| e.to_string())?;
+                let params = setup.params(k).map_err(|e| e.to_string())?;
+                (setup, params)
+            }
+            None => {
+                let params = generate_setup(pil.degree());
+                (Setup::from(params.clone()), params)
+            }
+        };
 
-        let plaf = analyzed_to_plaf(pil, fixed);
+        let plaf = analyzed_to_plaf(pil, fixed, max_lookup_degree)?;
 
         Ok(Self {
             pil,
+            fixed,
             plaf,
+            max_lookup_degree,
             params,
+            setup,
+            aggregation_params: Default::default(),
             vkey: None,
         })
     }
@@ -95,8 +135,13 @@ impl<'a, F: FieldElement> Halo2Prover<'a, F> {
     pub fn prove_ast(&self, witness: &[(String, Vec<F>)]) -> Result<Vec<u8>, String> {
         log::info!("Starting proof generation...");
 
-        let (circuit, publics) =
-            analyzed_to_circuit_with_witness(self.pil, self.plaf.clone(), witness);
+        let (circuit, publics) = analyzed_to_circuit_with_witness(
+            self.pil,
+            self.plaf.clone(),
+            self.fixed,
+            witness,
+            self.max_lookup_degree,
+        )?;
 
         log::debug!("{}", PlafDisplayBaseTOML(&circuit.plaf));
 
@@ -145,72 +190,263 @@ impl<'a, F: FieldElement> Halo2Prover<'a, F> {
         log::info!("Starting proof aggregation...");
 
         log::info!("Generating circuit for app snark...");
-        let (circuit_app, publics) =
-            analyzed_to_circuit_with_witness(self.pil, self.plaf.clone(), witness);
+        let (circuit_app, publics) = analyzed_to_circuit_with_witness(
+            self.pil,
+            self.plaf.clone(),
+            self.fixed,
+            witness,
+            self.max_lookup_degree,
+        )?;
 
         assert_eq!(publics.len(), 1);
         if !publics[0].is_empty() {
-            unimplemented!("Public inputs are not supported yet");
+            return Err(
+                "Aggregating a proof with public values is not supported, because the aggregated proof would not expose them."
+                    .to_string(),
+            );
         }
 
         log::debug!("{}", PlafDisplayBaseTOML(&circuit_app.plaf));
 
-        log::info!("Generating VK for app snark...");
-        let vk_app = keygen_vk(&self.params, &circuit_app).unwrap();
+        let (proof, _) = self.prove_aggr_chained(vec![(proof, vec![])], &[])?;
 
-        log::info!("Generating circuit for compression snark...");
-        let protocol_app = compile(
-            &self.params,
-            &vk_app,
-            Config::kzg().with_num_instance(vec![]),
-        );
-        let empty_snark = aggregation::Snark::new_without_witness(protocol_app.clone());
-        let agg_circuit =
-            aggregation::AggregationCircuit::new_without_witness(&self.params, [empty_snark]);
+        Ok(proof)
+    }
 
-        log::info!("Generating VK and PK for compression snark...");
-        let vk_aggr = keygen_vk(&self.params, &agg_circuit).unwrap();
-        let pk_aggr = keygen_pk(&self.params, vk_aggr.clone(), &agg_circuit).unwrap();
+    /// Aggregates the proofs of several executions of this circuit (e.g. the chunks
+    /// of a continuations run) into a single proof. `proofs` contains each proof
+    /// together with its public values.
+    ///
+    /// For each pair `(initial, final)` in `chained_publics`, the aggregation circuit
+    /// checks that the public value at index `final` of each proof equals the public
+    /// value at index `initial` of the next proof. The public values of the aggregated
+    /// proof are the `initial` public values of the first proof, followed by the
+    /// `final` public values of the last proof.
+    ///
+    /// Returns the aggregated proof and its public values. The aggregated proof starts
+    /// with the KZG accumulator, which is part of the instances of the aggregation circuit
+    /// (see [Self::ethereum_calldata]).
+    pub fn prove_aggr_chained(
+        &self,
+        proofs: Vec<(Vec<u8>, Vec<F>)>,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(Vec<u8>, Vec<F>), String> {
+        log::info!("Starting aggregation of {} proofs...", proofs.len());
+
+        self.check_aggregation(proofs.len(), chained_publics)?;
+        let num_publics = self.pil.public_declarations.len();
+        for (i, (_, publics)) in proofs.iter().enumerate() {
+            if publics.len() != num_publics {
+                return Err(format!(
+                    "Proof {i} has {} public values, but the circuit has {num_publics}",
+                    publics.len()
+                ));
+            }
+        }
+        for (i, ((_, previous), (_, next))) in proofs.iter().tuple_windows().enumerate() {
+            for (initial, final_) in chained_publics {
+                if previous[*final_] != next[*initial] {
+                    return Err(format!(
+                        "Public value {final_} of proof {i} ({}) does not match public value {initial} of proof {} ({})",
+                        previous[*final_],
+                        i + 1,
+                        next[*initial]
+                    ));
+                }
+            }
+        }
 
-        log::info!("Generating compressed snark verifier...");
-        let deployment_code = aggregation::gen_aggregation_evm_verifier(
-            &self.params,
-            pk_aggr.get_vk(),
-            aggregation::AggregationCircuit::num_instance(),
-            aggregation::AggregationCircuit::accumulator_indices(),
-        );
+        let protocol_app = self.app_protocol()?;
+        let snarks = proofs
+            .into_iter()
+            .map(|(proof, publics)| {
+                let instances = publics.into_iter().map(powdr_ff_to_fr).collect();
+                aggregation::Snark::new(protocol_app.clone(), vec![instances], proof)
+            })
+            .collect_vec();
+
+        let (params, pk_aggr) =
+            self.aggregation_pk(&protocol_app, snarks.len(), chained_publics)?;
 
         log::info!("Generating aggregated proof...");
         let start = Instant::now();
-        let snark = aggregation::Snark::new(protocol_app, vec![], proof);
-        let agg_circuit_with_proof = aggregation::AggregationCircuit::new(&self.params, [snark]);
+        let agg_circuit_with_proof =
+            aggregation::AggregationCircuit::new(&params, snarks, chained_publics.to_vec());
+        let instances = agg_circuit_with_proof.instances();
         let proof = gen_proof::<_, _, EvmTranscript<G1Affine, _, _, _>>(
-            &self.params,
+            &params,
             &pk_aggr,
-            agg_circuit_with_proof.clone(),
-            &agg_circuit_with_proof.instances(),
+            agg_circuit_with_proof,
+            &instances,
         );
         let duration = start.elapsed();
         log::info!("Time taken: {:?}", duration);
 
-        match self.verify_inner::<_, EvmTranscript<G1Affine, _, _, _>>(
-            &vk_aggr,
-            &self.params,
+        self.verify_inner::<_, EvmTranscript<G1Affine, _, _, _>>(
+            pk_aggr.get_vk(),
+            &params,
             &proof,
-            &publics,
-        ) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(e.to_string());
-            }
+            &instances,
+        )?;
+
+        log::info!("Proof aggregation done.");
+
+        let mut instances = instances.into_iter().next().unwrap();
+        let publics = instances.split_off(aggregation::NUM_ACCUMULATOR_INSTANCES);
+        let proof = instances
+            .iter()
+            .flat_map(|accumulator| accumulator.to_repr())
+            .chain(proof)
+            .collect();
+
+        Ok((proof, publics.into_iter().map(fr_to_powdr_ff).collect()))
+    }
+
+    /// Returns a verifier for the proofs that aggregate `num_proofs` proofs chained by
+    /// `chained_publics` (see [Self::prove_aggr_chained]), as Solidity source and as EVM
+    /// bytecode. The proofs generated by [Self::prove_aggr] aggregate a single proof
+    /// without chained public values.
+    pub fn ethereum_verifier(
+        &self,
+        num_proofs: usize,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(String, Vec<u8>), String> {
+        self.check_aggregation(num_proofs, chained_publics)?;
+        let protocol_app = self.app_protocol()?;
+        let (params, pk_aggr) = self.aggregation_pk(&protocol_app, num_proofs, chained_publics)?;
+
+        log::info!("Generating compressed snark verifier...");
+        let solidity = aggregation::gen_aggregation_solidity_verifier(
+            &params,
+            pk_aggr.get_vk(),
+            aggregation::AggregationCircuit::num_instance(chained_publics.len()),
+            aggregation::AggregationCircuit::accumulator_indices(),
+        );
+        let bytecode = evm::compile_solidity(&solidity);
+
+        Ok((solidity, bytecode))
+    }
+
+    /// Encodes an aggregated proof and its public values as calldata for the verifier
+    /// returned by [Self::ethereum_verifier], given as its bytecode. The calldata is
+    /// checked by deploying the verifier in a local EVM and calling it.
+    pub fn ethereum_calldata(
+        &self,
+        proof: &[u8],
+        publics: &[F],
+        verifier: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let accumulator_size = aggregation::NUM_ACCUMULATOR_INSTANCES * 32;
+        if proof.len() < accumulator_size {
+            return Err("The proof is too short to be an aggregated proof".to_string());
         }
+        let (accumulator, proof) = proof.split_at(accumulator_size);
+        let instances = accumulator
+            .chunks(32)
+            .map(|bytes| {
+                Option::from(Fr::from_repr(bytes.try_into().unwrap()))
+                    .ok_or_else(|| "Invalid accumulator in aggregated proof".to_string())
+            })
+            .chain(publics.iter().map(|x| Ok(powdr_ff_to_fr(*x))))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        log::info!("Verifying aggregated proof in the EVM...");
-        aggregation::evm_verify(deployment_code, agg_circuit_with_proof.instances(), &proof);
+        let calldata = encode_calldata(&[instances], proof);
+        let gas_cost = evm::deploy_and_call(verifier.to_vec(), calldata.clone())
+            .map_err(|e| format!("The verifier rejected the proof in a local EVM: {e}"))?;
+        log::info!("The proof was verified in a local EVM, using {gas_cost} gas.");
 
-        log::info!("Proof aggregation done.");
+        Ok(calldata)
+    }
 
-        Ok(proof)
+    /// Checks that there is at least one proof to aggregate and that the chained public
+    /// values exist in this circuit.
+    fn check_aggregation(
+        &self,
+        num_proofs: usize,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(), String> {
+        if num_proofs == 0 {
+            return Err("No proofs to aggregate".to_string());
+        }
+        let num_publics = self.pil.public_declarations.len();
+        match chained_publics
+            .iter()
+            .flat_map(|(initial, final_)| [initial, final_])
+            .find(|index| **index >= num_publics)
+        {
+            Some(index) => Err(format!(
+                "Chained public value {index} does not exist, the circuit has {num_publics} public values"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The protocol of the snarks of this circuit, as verified by the aggregation circuit.
+    fn app_protocol(&self) -> Result<PlonkProtocol<G1Affine>, String> {
+        log::info!("Generating VK for app snark...");
+        let vk_app = match self.vkey {
+            Some(ref vk) => vk.clone(),
+            None => self.verification_key()?,
+        };
+        Ok(compile(
+            &self.params,
+            &vk_app,
+            Config::kzg().with_num_instance(vec![self.pil.public_declarations.len()]),
+        ))
+    }
+
+    /// The parameters for a circuit with `2^k` rows, used for the aggregation circuit.
+    /// They are assembled from the setup when they are first needed.
+    fn aggregation_params(&self, k: u32) -> Result<Rc<ParamsKZG<Bn256>>, String> {
+        let mut cache = self.aggregation_params.borrow_mut();
+        if let Some(params) = cache.get(&k) {
+            return Ok(params.clone());
+        }
+        let params = Rc::new(if k == self.params.k() {
+            self.params.clone()
+        } else {
+            self.setup.params(k).map_err(|e| e.to_string())?
+        });
+        cache.insert(k, params.clone());
+        Ok(params)
+    }
+
+    /// Generates the proving key of the circuit that aggregates `num_proofs` proofs
+    /// of this circuit (see [Self::prove_aggr_chained]), together with the parameters
+    /// it is generated for, which are sized to the aggregation circuit.
+    fn aggregation_pk(
+        &self,
+        protocol_app: &PlonkProtocol<G1Affine>,
+        num_proofs: usize,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(Rc<ParamsKZG<Bn256>>, ProvingKey<G1Affine>), String> {
+        log::info!("Generating circuit for compression snark...");
+        let empty_snarks = (0..num_proofs)
+            .map(|_| aggregation::Snark::new_without_witness(protocol_app.clone()))
+            .collect_vec();
+        let agg_circuit = aggregation::AggregationCircuit::new_without_witness(
+            &self.params,
+            empty_snarks,
+            chained_publics.to_vec(),
+        );
+
+        let k = agg_circuit
+            .min_k()
+            .map_err(|e| format!("Could not lay out the aggregation circuit: {e:?}"))?;
+        if k > self.setup.k() {
+            return Err(format!(
+                "The aggregation circuit needs a setup of size 2^{k}, but the setup has size 2^{}",
+                self.setup.k()
+            ));
+        }
+        let params = self.aggregation_params(k)?;
+
+        log::info!("Generating VK and PK for compression snark of size 2^{k}...");
+        let vk_aggr = keygen_vk(params.as_ref(), &agg_circuit)
+            .map_err(|e| format!("Could not generate the aggregation VK: {e:?}"))?;
+        let pk_aggr = keygen_pk(params.as_ref(), vk_aggr, &agg_circuit)
+            .map_err(|e| format!("Could not generate the aggregation PK: {e:?}"))?;
+        Ok((params, pk_aggr))
     }
 
     pub fn add_verification_key(&mut self, mut vkey: &mut dyn io::Read) {
@@ -224,7 +460,7 @@ impl<'a, F: FieldElement> Halo2Prover<'a, F> {
     }
 
     pub fn verification_key(&self) -> Result<VerifyingKey<G1Affine>, String> {
-        let circuit = analyzed_to_circuit_with_zeroed_witness(self.plaf.clone(), self.pil);
+        let circuit = analyzed_to_circuit_with_zeroed_witness(self.plaf.clone());
         keygen_vk(&self.params, &circuit).map_err(|e| e.to_string())
     }
 
@@ -248,7 +484,7 @@ impl<'a, F: FieldElement> Halo2Prover<'a, F> {
         let res = verify_proof::<_, VerifierGWC<_>, _, TR, _>(
             params.verifier_params(),
             vkey,
-            AccumulatorStrategy::new(self.params.verifier_params()),
+            AccumulatorStrategy::new(params.verifier_params()),
             &[instances.as_slice()],
             &mut transcript,
         )
@@ -325,3 +561,103 @@ fn gen_proof<
 
     proof
 }
+
+#[cfg(test)]
+mod test {
+    use powdr_number::Bn254Field;
+    use powdr_pipeline::{test_util::resolve_test_file, Pipeline};
+    use test_log::test;
+
+    use super::*;
+    use crate::circuit_builder::DEFAULT_MAX_LOOKUP_DEGREE;
+
+    #[test]
+    fn invalid_aggregation() {
+        let result = Pipeline::<Bn254Field>::default()
+            .from_file(resolve_test_file("pil/sum_chained.pil"))
+            .pil_with_evaluated_fixed_cols()
+            .unwrap();
+        let prover = Halo2Prover::new(
+            &result.pil,
+            &result.fixed_cols,
+            None,
+            DEFAULT_MAX_LOOKUP_DEGREE,
+            DEFAULT_MAX_AGGREGATION_K,
+        )
+        .unwrap();
+
+        assert_eq!(
+            prover.ethereum_verifier(0, &[]).unwrap_err(),
+            "No proofs to aggregate"
+        );
+        assert_eq!(
+            prover.ethereum_verifier(2, &[(0, 2)]).unwrap_err(),
+            "Chained public value 2 does not exist, the circuit has 2 public values"
+        );
+        assert_eq!(
+            prover
+                .prove_aggr_chained(vec![(vec![], vec![])], &[(5, 1)])
+                .unwrap_err(),
+            "Chained public value 5 does not exist, the circuit has 2 public values"
+        );
+    }
+
+    /// Aggregates two chained executions of `sum_chained.pil` and checks the aggregated
+    /// proof with the exported verifier in a local EVM.
+    #[test]
+    #[ignore = "Too slow"]
+    fn aggregated_proof_in_evm() {
+        let pipeline =
+            Pipeline::<Bn254Field>::default().from_file(resolve_test_file("pil/sum_chained.pil"));
+        let executions = [[1, 2, 3, 0], [6, 1, 1, 0]].map(|inputs| {
+            let mut pipeline = pipeline
+                .clone()
+                .with_prover_inputs(inputs.map(From::from).to_vec());
+            let publics = pipeline
+                .publics()
+                .unwrap()
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>();
+            (pipeline.generated_witness().unwrap(), publics)
+        });
+
+        // The setup has to be large enough for the aggregation circuit.
+        let mut setup = vec![];
+        generate_setup(1 << 20).write(&mut setup).unwrap();
+        let (result, _) = &executions[0];
+        let prover = Halo2Prover::new(
+            &result.pil,
+            &result.fixed_cols,
+            Some(&mut &setup[..] as &mut dyn io::Read),
+            DEFAULT_MAX_LOOKUP_DEGREE,
+            DEFAULT_MAX_AGGREGATION_K,
+        )
+        .unwrap();
+
+        let proofs = executions
+            .iter()
+            .map(|(result, publics)| {
+                let proof = prover.prove_ast(result.witness.as_ref().unwrap()).unwrap();
+                (proof, publics.clone())
+            })
+            .collect();
+        let chained_publics = [(0, 1)];
+        let (proof, publics) = prover.prove_aggr_chained(proofs, &chained_publics).unwrap();
+        assert_eq!(
+            publics,
+            vec![Bn254Field::from(1u64), Bn254Field::from(8u64)]
+        );
+
+        let (_, bytecode) = prover.ethereum_verifier(2, &chained_publics).unwrap();
+        prover
+            .ethereum_calldata(&proof, &publics, &bytecode)
+            .unwrap();
+
+        // The verifier rejects other public values.
+        let publics = vec![Bn254Field::from(1u64), Bn254Field::from(9u64)];
+        assert!(prover
+            .ethereum_calldata(&proof, &publics, &bytecode)
+            .is_err());
+    }
+}
@@ -0,0 +1,396 @@
+use std::io::{self, Cursor, Read};
+
+use halo2_proofs::{
+    arithmetic::{best_multiexp, g_to_lagrange},
+    halo2curves::{
+        bn256::{Bn256, Fq, Fq2, Fr, G1Affine, G2Affine, G1},
+        ff::{Field, PrimeField},
+        group::{prime::PrimeCurveAffine, Curve},
+        pairing::Engine,
+        serde::SerdeObject,
+        CurveAffine,
+    },
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use num_bigint::BigUint;
+use rand::rngs::OsRng;
+
+/// The magic bytes at the beginning of a snarkjs powers-of-tau file.
+const PTAU_MAGIC: &[u8; 4] = b"ptau";
+/// The size in bytes of a base field element.
+const N8: usize = 32;
+
+/// Universal KZG parameters, as read by [read_setup].
+///
+/// The setup is usually much larger than the circuit it is used for, e.g. if it comes
+/// from a public ceremony. Therefore, the parameters are only assembled and validated
+/// up to the size of the circuit (see [Setup::params]).
+pub struct Setup {
+    /// The powers of tau in G1.
+    g: Vec<G1Affine>,
+    /// The generator and the first power of tau in G2.
+    g2: G2Affine,
+    s_g2: G2Affine,
+}
+
+impl Setup {
+    /// Log2 of the number of powers of the setup.
+    pub fn k(&self) -> u32 {
+        self.g.len().ilog2()
+    }
+
+    /// Returns the parameters for a circuit with `2^k` rows, or the whole setup if
+    /// it is smaller.
+    ///
+    /// The Lagrange basis is always computed from the powers of tau, and the powers
+    /// are validated: They have to start with the generators and consist of consecutive
+    /// powers of the same secret.
+    pub fn params(&self, k: u32) -> Result<ParamsKZG<Bn256>, io::Error> {
+        let k = k.min(self.k());
+        let params = ptau_to_params(&self.g[..1 << k], self.g2, self.s_g2, k)?;
+        validate_setup(&params).map_err(invalid_data)?;
+        Ok(params)
+    }
+}
+
+impl From<ParamsKZG<Bn256>> for Setup {
+    fn from(params: ParamsKZG<Bn256>) -> Self {
+        Setup {
+            g: params.get_g().to_vec(),
+            g2: params.g2(),
+            s_g2: params.s_g2(),
+        }
+    }
+}
+
+/// Reads universal KZG parameters, either in the halo2 format (as written by
+/// `powdr setup`) or in the powers-of-tau format of snarkjs (`.ptau`), as used by
+/// the perpetual powers-of-tau ceremony. The format is detected from the content.
+///
+/// Only the first `2^max_k` powers of tau are kept. Of a powers-of-tau file, which can
+/// be much larger, the other powers are skipped without reading them into memory.
+pub fn read_setup(reader: &mut dyn Read, max_k: u32) -> Result<Setup, io::Error> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+
+    let max_size = 1usize.checked_shl(max_k).unwrap_or(usize::MAX);
+    if &magic == PTAU_MAGIC {
+        read_ptau(reader, max_size)
+    } else {
+        // The Lagrange basis in the file is ignored and recomputed by [Setup::params].
+        let mut setup = Setup::from(ParamsKZG::<Bn256>::read(
+            &mut Cursor::new(magic).chain(reader),
+        )?);
+        setup.g.truncate(max_size);
+        Ok(setup)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the powers of tau from a snarkjs powers-of-tau file, without the magic bytes.
+///
+/// The file consists of sections, of which we need the header (1), the powers of
+/// tau in G1 (2) and the powers of tau in G2 (3). Curve points are stored as
+/// uncompressed affine coordinates in Montgomery form, which is also the internal
+/// representation of halo2curves. At most `max_size` powers in G1 are read.
+fn read_ptau(reader: &mut dyn Read, max_size: usize) -> Result<Setup, io::Error> {
+    let _version = read_u32(reader)?;
+    let num_sections = read_u32(reader)?;
+
+    let mut size = None;
+    let mut g = None;
+    let mut g2 = None;
+    for _ in 0..num_sections {
+        let section_type = read_u32(reader)?;
+        let section_size = read_u64(reader)?;
+        let mut section = (&mut *reader).take(section_size);
+        match section_type {
+            1 => {
+                let n8 = read_u32(&mut section)? as usize;
+                let mut modulus = vec![0u8; n8];
+                section.read_exact(&mut modulus)?;
+                if n8 != N8 || BigUint::from_bytes_le(&modulus) != fq_modulus() {
+                    return Err(invalid_data(
+                        "The powers-of-tau file is not for the bn254 curve".to_string(),
+                    ));
+                }
+                let power = read_u32(&mut section)?;
+                // There are 2^(power + 1) - 1 powers in G1, we only need the first 2^power.
+                size = Some(1usize.checked_shl(power).ok_or_else(|| {
+                    invalid_data(format!(
+                        "The power {power} of the powers-of-tau file is out of range"
+                    ))
+                })?);
+            }
+            2 => {
+                let size = size.ok_or_else(|| {
+                    invalid_data("Expected the header before the powers of tau".to_string())
+                })?;
+                g = Some(
+                    (0..size.min(max_size))
+                        .map(|_| read_g1(&mut section))
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
+            }
+            3 => {
+                // We only need the generator and the first power in G2.
+                g2 = Some((read_g2(&mut section)?, read_g2(&mut section)?));
+            }
+            _ => {}
+        }
+        // Skip the rest of the section.
+        io::copy(&mut section, &mut io::sink())?;
+    }
+
+    let (Some(g), Some((g2, s_g2))) = (g, g2) else {
+        return Err(invalid_data(
+            "The powers-of-tau file is missing a section".to_string(),
+        ));
+    };
+    Ok(Setup { g, g2, s_g2 })
+}
+
+/// Assembles the parameters in the halo2 format from `2^k` powers of tau, which
+/// includes computing the Lagrange basis.
+fn ptau_to_params(
+    g: &[G1Affine],
+    g2: G2Affine,
+    s_g2: G2Affine,
+    k: u32,
+) -> Result<ParamsKZG<Bn256>, io::Error> {
+    let g_lagrange: Vec<G1Affine> =
+        g_to_lagrange(g.iter().map(|p| p.to_curve()).collect::<Vec<G1>>(), k);
+    let mut buffer = k.to_le_bytes().to_vec();
+    for point in g.iter().chain(g_lagrange.iter()) {
+        point.write_raw(&mut buffer)?;
+    }
+    g2.write_raw(&mut buffer)?;
+    s_g2.write_raw(&mut buffer)?;
+    ParamsKZG::<Bn256>::read(&mut Cursor::new(buffer))
+}
+
+fn fq_modulus() -> BigUint {
+    BigUint::from_bytes_le((-Fq::ONE).to_repr().as_ref()) + 1u32
+}
+
+fn read_u32(reader: &mut dyn Read) -> Result<u32, io::Error> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut dyn Read) -> Result<u64, io::Error> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_fq(reader: &mut dyn Read) -> Result<Fq, io::Error> {
+    let mut bytes = [0u8; N8];
+    reader.read_exact(&mut bytes)?;
+    Fq::from_raw_bytes(&bytes)
+        .ok_or_else(|| invalid_data("Invalid field element in powers-of-tau file".to_string()))
+}
+
+fn read_g1(reader: &mut dyn Read) -> Result<G1Affine, io::Error> {
+    let (x, y) = (read_fq(reader)?, read_fq(reader)?);
+    Option::from(G1Affine::from_xy(x, y))
+        .ok_or_else(|| invalid_data("Invalid G1 point in powers-of-tau file".to_string()))
+}
+
+fn read_g2(reader: &mut dyn Read) -> Result<G2Affine, io::Error> {
+    let x = Fq2 {
+        c0: read_fq(reader)?,
+        c1: read_fq(reader)?,
+    };
+    let y = Fq2 {
+        c0: read_fq(reader)?,
+        c1: read_fq(reader)?,
+    };
+    Option::from(G2Affine::from_xy(x, y))
+        .ok_or_else(|| invalid_data("Invalid G2 point in powers-of-tau file".to_string()))
+}
+
+/// Checks that the parameters are of the form `[1]_1, [s]_1, [s^2]_1, ...` and `[1]_2, [s]_2`
+/// for some secret `s`. Instead of checking each pair of consecutive powers, we check a random
+/// linear combination of all of them, using a single pair of pairings.
+pub fn validate_setup(params: &ParamsKZG<Bn256>) -> Result<(), String> {
+    let g = params.get_g();
+    if g[0] != G1Affine::generator() || params.g2() != G2Affine::generator() {
+        return Err("The setup does not start with the generators".to_string());
+    }
+    if params.s_g2() == G2Affine::identity() {
+        return Err("The secret of the setup is zero".to_string());
+    }
+
+    let r = Fr::random(OsRng);
+    let coeffs = std::iter::successors(Some(Fr::ONE), |c| Some(*c * r))
+        .take(g.len() - 1)
+        .collect::<Vec<_>>();
+    // sum_i r^i * [s^i]_1 and sum_i r^i * [s^(i+1)]_1
+    let lhs = best_multiexp(&coeffs, &g[..g.len() - 1]).to_affine();
+    let rhs = best_multiexp(&coeffs, &g[1..]).to_affine();
+    if Bn256::pairing(&lhs, &params.s_g2()) != Bn256::pairing(&rhs, &params.g2()) {
+        return Err("The setup does not consist of consecutive powers of a secret".to_string());
+    }
+
+    log::info!("Validated the setup of size 2^{}.", params.k());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::poly::commitment::ParamsProver;
+
+    use super::*;
+
+    /// Writes the powers of the secret of `params` in the powers-of-tau format.
+    /// Only the first two powers in G2 are written, as the others are not used.
+    fn to_ptau(g: &[G1Affine], g2: &[G2Affine], power: u32) -> Vec<u8> {
+        let mut header = (N8 as u32).to_le_bytes().to_vec();
+        header.extend(fq_modulus().to_bytes_le());
+        header.extend(power.to_le_bytes());
+        header.extend(power.to_le_bytes());
+        let mut tau_g1 = vec![];
+        for point in g {
+            point.write_raw(&mut tau_g1).unwrap();
+        }
+        let mut tau_g2 = vec![];
+        for point in g2 {
+            point.write_raw(&mut tau_g2).unwrap();
+        }
+
+        let mut ptau = PTAU_MAGIC.to_vec();
+        ptau.extend(1u32.to_le_bytes());
+        ptau.extend(3u32.to_le_bytes());
+        for (section_type, data) in [(1u32, header), (2, tau_g1), (3, tau_g2)] {
+            ptau.extend(section_type.to_le_bytes());
+            ptau.extend((data.len() as u64).to_le_bytes());
+            ptau.extend(data);
+        }
+        ptau
+    }
+
+    #[test]
+    fn read_halo2_params() {
+        let params = ParamsKZG::<Bn256>::new(4);
+        let mut buffer = vec![];
+        params.write(&mut buffer).unwrap();
+
+        let setup = read_setup(&mut buffer.as_slice(), 10).unwrap();
+        assert_eq!(setup.k(), 4);
+        let read = setup.params(5).unwrap();
+        assert_eq!(read.k(), 4);
+        assert_eq!(read.get_g(), params.get_g());
+        assert_eq!(setup.params(2).unwrap().k(), 2);
+
+        assert_eq!(read_setup(&mut buffer.as_slice(), 3).unwrap().k(), 3);
+    }
+
+    #[test]
+    fn halo2_params_lagrange_basis() {
+        let params = ParamsKZG::<Bn256>::new(4);
+        let mut expected = vec![];
+        params.write(&mut expected).unwrap();
+
+        // Swap the first two points of the Lagrange basis, which follows k and the
+        // 16 powers of tau (64 bytes each).
+        let mut buffer = expected.clone();
+        let lagrange = 4 + 16 * 64;
+        let (first, second) = buffer[lagrange..lagrange + 128].split_at_mut(64);
+        first.swap_with_slice(second);
+        assert_ne!(buffer, expected);
+
+        // The Lagrange basis is recomputed from the powers of tau.
+        let read = read_setup(&mut buffer.as_slice(), 10)
+            .unwrap()
+            .params(4)
+            .unwrap();
+        let mut actual = vec![];
+        read.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn read_ptau() {
+        let params = ParamsKZG::<Bn256>::new(4);
+        // A file with power 3 contains 2^4 - 1 powers in G1.
+        let ptau = to_ptau(&params.get_g()[..15], &[params.g2(), params.s_g2()], 3);
+
+        let read = read_setup(&mut ptau.as_slice(), 10)
+            .unwrap()
+            .params(3)
+            .unwrap();
+        assert_eq!(read.k(), 3);
+        assert_eq!(read.get_g(), &params.get_g()[..8]);
+        assert_eq!(read.s_g2(), params.s_g2());
+
+        // The Lagrange basis is consistent with the downsized parameters.
+        let mut downsized = params.clone();
+        downsized.downsize(3);
+        let mut expected = vec![];
+        downsized.write(&mut expected).unwrap();
+        let mut actual = vec![];
+        read.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+
+        // Only the powers up to the maximum size are read.
+        let setup = read_setup(&mut ptau.as_slice(), 2).unwrap();
+        assert_eq!(setup.k(), 2);
+        assert_eq!(setup.params(3).unwrap().get_g(), &params.get_g()[..4]);
+    }
+
+    #[test]
+    fn invalid_ptau() {
+        let params = ParamsKZG::<Bn256>::new(4);
+        let mut g = params.get_g()[..15].to_vec();
+        g.swap(4, 5);
+        let ptau = to_ptau(&g, &[params.g2(), params.s_g2()], 3);
+        let setup = read_setup(&mut ptau.as_slice(), 10).unwrap();
+
+        let err = setup.params(3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The setup does not consist of consecutive powers of a secret"
+        );
+        // Only the powers needed for the circuit are validated.
+        assert_eq!(setup.params(2).unwrap().k(), 2);
+    }
+
+    #[test]
+    fn ptau_power_out_of_range() {
+        let params = ParamsKZG::<Bn256>::new(2);
+        let ptau = to_ptau(params.get_g(), &[params.g2(), params.s_g2()], 64);
+        let err = read_setup(&mut ptau.as_slice(), 10).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "The power 64 of the powers-of-tau file is out of range"
+        );
+    }
+
+    /// A powers-of-tau file in the format of snarkjs with all of its sections, see
+    /// `test_data/ptau/generate.js`.
+    #[test]
+    fn read_snarkjs_ptau() {
+        let ptau = include_bytes!("../../test_data/ptau/bn254_4.ptau");
+        let setup = read_setup(&mut ptau.as_slice(), 10).unwrap();
+        assert_eq!(setup.k(), 4);
+
+        let params = setup.params(3).unwrap();
+        assert_eq!(params.k(), 3);
+        // The secret of the fixture is 7.
+        let tau = Fr::from(7u64);
+        let expected = std::iter::successors(Some(G1Affine::generator()), |p| {
+            Some((*p * tau).to_affine())
+        })
+        .take(8)
+        .collect::<Vec<_>>();
+        assert_eq!(params.get_g(), &expected[..]);
+        assert_eq!(params.s_g2(), (G2Affine::generator() * tau).to_affine());
+
+        assert_eq!(setup.params(4).unwrap().k(), 4);
+    }
+}
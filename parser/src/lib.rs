@@ -235,6 +235,7 @@ mod test {
             | PilStatement::PolynomialConstantDeclaration(s, _)
             | PilStatement::PolynomialConstantDefinition(s, _, _)
             | PilStatement::PolynomialCommitDeclaration(s, _, _)
+            | PilStatement::PolynomialDerivedDeclaration(s, _)
             | PilStatement::PlookupIdentity(s, _, _)
             | PilStatement::PermutationIdentity(s, _, _)
             | PilStatement::ConnectIdentity(s, _, _)
@@ -425,6 +426,16 @@ namespace Fibonacci(%N);
             assert_eq!(input.trim(), printed.trim());
         }
 
+        #[test]
+        fn reparse_derived() {
+            let input = "    pol commit derived x, y[2];\n    pol commit derived;";
+            let printed = format!(
+                "{}",
+                parse::<GoldilocksField>(Some("input"), input).unwrap()
+            );
+            assert_eq!(input.trim(), printed.trim());
+        }
+
         #[test]
         fn reparse_strings_and_tuples() {
             let input = r#"constant %N = ("abc", 3);"#;
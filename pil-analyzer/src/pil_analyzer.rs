@@ -382,6 +382,21 @@ namespace N(16);
         assert_eq!(formatted, input);
     }
 
+    #[test]
+    fn reparse_derived() {
+        let input = r#"namespace N(16);
+    col witness x;
+    col witness derived y;
+    N.y = (N.x * N.x);
+"#;
+        let analyzed = analyze_string::<GoldilocksField>(input);
+        assert_eq!(analyzed.derived_columns().len(), 1);
+        let formatted = analyzed.to_string();
+        assert_eq!(formatted, input);
+        let reparsed = analyze_string::<GoldilocksField>(&formatted);
+        assert_eq!(reparsed.derived_columns(), analyzed.derived_columns());
+    }
+
     #[test]
     #[should_panic = "Operator - not supported on types"]
     fn no_direct_array_references() {
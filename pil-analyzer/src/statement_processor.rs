@@ -125,6 +125,20 @@ where
             PilStatement::PolynomialCommitDeclaration(source, polynomials, None) => {
                 self.handle_polynomial_declarations(source, polynomials, PolynomialType::Committed)
             }
+            PilStatement::PolynomialDerivedDeclaration(source, polynomials) => self
+                .handle_polynomial_declarations(source, polynomials, PolynomialType::Committed)
+                .into_iter()
+                .map(|item| match item {
+                    PILItem::Definition(symbol, value) => PILItem::Definition(
+                        Symbol {
+                            derived: true,
+                            ..symbol
+                        },
+                        value,
+                    ),
+                    item => item,
+                })
+                .collect(),
             PilStatement::PolynomialCommitDeclaration(
                 source,
                 mut polynomials,
@@ -374,6 +388,7 @@ where
             absolute_name: name.clone(),
             kind: symbol_kind,
             length,
+            derived: false,
         };
 
         let value = value.map(|v| match v {
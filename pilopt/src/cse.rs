@@ -0,0 +1,509 @@
+//! Common subexpression elimination across identities.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicExpression, AlgebraicReference, Analyzed, Identity, PolyID, PolynomialType,
+    StatementIdentifier, Symbol, SymbolKind,
+};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_ast::SourceRef;
+use powdr_number::FieldElement;
+
+use crate::degree_reduction::namespace_of;
+
+/// The kind of columns common subexpressions are extracted into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CseMode {
+    /// Intermediate columns, which do not increase the number of committed columns.
+    /// This only reduces the cost of backends that evaluate every intermediate column
+    /// once per row (like eStark, which exports them as expressions). Backends that
+    /// inline intermediate columns (like Halo2) evaluate the same expressions as before,
+    /// see [evaluation_cost].
+    Intermediate,
+    /// Witness columns that are constrained to be equal to the subexpression.
+    /// They are computed by witness generation.
+    Witness,
+}
+
+/// Finds subexpressions that occur more than once in the identities and replaces them
+/// by references to new columns, which are named `_cse_<i>` in the namespace of the
+/// first column referenced by the subexpression.
+/// Subexpressions of extracted subexpressions are extracted as well if they are repeated.
+pub fn eliminate_common_subexpressions<T: FieldElement>(
+    mut pil_file: Analyzed<T>,
+    mode: CseMode,
+) -> Analyzed<T> {
+    let ptype = match mode {
+        CseMode::Intermediate => PolynomialType::Intermediate,
+        CseMode::Witness => PolynomialType::Committed,
+    };
+    let first_id = match mode {
+        CseMode::Intermediate => pil_file.intermediate_count(),
+        CseMode::Witness => pil_file.commitment_count(),
+    } as u64;
+    let mut extractor = Extractor {
+        ptype,
+        first_id,
+        columns: Default::default(),
+        definitions: vec![],
+    };
+
+    // Every round extracts at least one subexpression, so this terminates.
+    loop {
+        let repeated = extractor.repeated_subexpressions(&pil_file.identities);
+        // Columns extracted in this round are only processed in the next round,
+        // because the counts of their subexpressions have changed.
+        let column_count = extractor.definitions.len();
+        let mut changed = false;
+        for identity in &mut pil_file.identities {
+            changed |= extractor.extract_in_identity(identity, &repeated);
+        }
+        for index in 0..column_count {
+            let mut definition = extractor.definitions[index].clone();
+            changed |= extractor.extract_in_children(&mut definition, &repeated);
+            extractor.definitions[index] = definition;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let column_count = extractor.definitions.len();
+    add_columns(&mut pil_file, extractor, mode);
+    log::info!(
+        "Extracted {column_count} common subexpressions into {} columns.",
+        match mode {
+            CseMode::Intermediate => "intermediate",
+            CseMode::Witness => "witness",
+        }
+    );
+    pil_file
+}
+
+struct Extractor<T> {
+    ptype: PolynomialType,
+    /// The ID of the first new column. New columns temporarily get IDs in the
+    /// order of extraction, they are re-assigned in [add_columns].
+    first_id: u64,
+    /// The index of the column for each extracted subexpression.
+    columns: BTreeMap<AlgebraicExpression<T>, usize>,
+    /// The definitions of the new columns. Repeated subexpressions in the definitions
+    /// are extracted as well, so they differ from the keys of `columns`.
+    definitions: Vec<AlgebraicExpression<T>>,
+}
+
+impl<T: FieldElement> Extractor<T> {
+    /// Returns all subexpressions that are worth extracting and occur more than once
+    /// in the identities and the definitions of the new columns.
+    fn repeated_subexpressions(
+        &self,
+        identities: &[Identity<AlgebraicExpression<T>>],
+    ) -> BTreeSet<AlgebraicExpression<T>> {
+        let mut counts = BTreeMap::new();
+        for identity in identities {
+            for side in [&identity.left, &identity.right] {
+                for e in side.selector.iter().chain(&side.expressions) {
+                    count_subexpressions(e, &mut counts);
+                }
+            }
+        }
+        for definition in &self.definitions {
+            // The definition itself was extracted already, only count its children.
+            for child in children(definition) {
+                count_subexpressions(child, &mut counts);
+            }
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(e, _)| e.clone())
+            .collect()
+    }
+
+    fn extract_in_identity(
+        &mut self,
+        identity: &mut Identity<AlgebraicExpression<T>>,
+        repeated: &BTreeSet<AlgebraicExpression<T>>,
+    ) -> bool {
+        let mut changed = false;
+        for side in [&mut identity.left, &mut identity.right] {
+            for e in side.selector.iter_mut().chain(side.expressions.iter_mut()) {
+                changed |= self.extract(e, repeated);
+            }
+        }
+        changed
+    }
+
+    fn extract_in_children(
+        &mut self,
+        e: &mut AlgebraicExpression<T>,
+        repeated: &BTreeSet<AlgebraicExpression<T>>,
+    ) -> bool {
+        let mut changed = false;
+        match e {
+            AlgebraicExpression::BinaryOperation(left, _, right) => {
+                changed |= self.extract(left, repeated);
+                changed |= self.extract(right, repeated);
+            }
+            AlgebraicExpression::UnaryOperation(_, inner) => {
+                changed |= self.extract(inner, repeated);
+            }
+            _ => {}
+        }
+        changed
+    }
+
+    /// Replaces the outermost repeated or already extracted subexpressions of `e`
+    /// by references to columns.
+    fn extract(
+        &mut self,
+        e: &mut AlgebraicExpression<T>,
+        repeated: &BTreeSet<AlgebraicExpression<T>>,
+    ) -> bool {
+        if !self.columns.contains_key(e) && !repeated.contains(e) {
+            return self.extract_in_children(e, repeated);
+        }
+        let index = *self.columns.entry(e.clone()).or_insert_with(|| {
+            self.definitions.push(e.clone());
+            self.definitions.len() - 1
+        });
+        *e = AlgebraicExpression::Reference(AlgebraicReference {
+            name: format!("_cse_{index}"),
+            poly_id: PolyID {
+                id: self.first_id + index as u64,
+                ptype: self.ptype,
+            },
+            next: false,
+        });
+        true
+    }
+
+    /// Returns the index of the new column referenced by `r`, if any.
+    fn column_index(&self, r: &AlgebraicReference) -> Option<usize> {
+        (r.poly_id.ptype == self.ptype && r.poly_id.id >= self.first_id)
+            .then(|| (r.poly_id.id - self.first_id) as usize)
+    }
+
+    /// Returns the new columns directly referenced by `e`.
+    fn referenced_columns(&self, e: &AlgebraicExpression<T>) -> Vec<usize> {
+        let mut columns = vec![];
+        e.pre_visit_expressions(&mut |e| {
+            if let AlgebraicExpression::Reference(r) = e {
+                columns.extend(self.column_index(r));
+            }
+        });
+        columns
+    }
+}
+
+/// Counts the subexpressions of `e` that are worth extracting.
+/// Subexpressions are extracted if they contain at least one operation and one column.
+/// Expressions referencing public values are not extracted since they cannot be
+/// computed by witness generation.
+/// Returns whether `e` references a column and whether it references a public value.
+fn count_subexpressions<'a, T: FieldElement>(
+    e: &'a AlgebraicExpression<T>,
+    counts: &mut BTreeMap<&'a AlgebraicExpression<T>, usize>,
+) -> (bool, bool) {
+    let (mut references_column, mut references_public) = match e {
+        AlgebraicExpression::Reference(_) => (true, false),
+        AlgebraicExpression::PublicReference(_) => (false, true),
+        _ => (false, false),
+    };
+    for child in children(e) {
+        let (column, public) = count_subexpressions(child, counts);
+        references_column |= column;
+        references_public |= public;
+    }
+    if is_operation(e) && references_column && !references_public {
+        *counts.entry(e).or_default() += 1;
+    }
+    (references_column, references_public)
+}
+
+fn children<T>(e: &AlgebraicExpression<T>) -> Vec<&AlgebraicExpression<T>> {
+    match e {
+        AlgebraicExpression::BinaryOperation(left, _, right) => vec![left, right],
+        AlgebraicExpression::UnaryOperation(_, inner) => vec![inner],
+        _ => vec![],
+    }
+}
+
+fn is_operation<T>(e: &AlgebraicExpression<T>) -> bool {
+    matches!(
+        e,
+        AlgebraicExpression::BinaryOperation(..) | AlgebraicExpression::UnaryOperation(..)
+    )
+}
+
+/// Returns the number of operations needed to evaluate all identities in one row.
+/// If `inline_intermediates` is true, the definitions of intermediate columns are inlined
+/// into every identity that uses them, as done by backends that do not support intermediate
+/// columns (like Halo2). Otherwise, every definition is evaluated once (like in eStark).
+pub fn evaluation_cost<T: FieldElement>(
+    pil_file: &Analyzed<T>,
+    inline_intermediates: bool,
+) -> usize {
+    let operation_count = |identities: &[Identity<AlgebraicExpression<T>>]| {
+        let mut count = 0;
+        for identity in identities {
+            identity.pre_visit_expressions(&mut |e| count += is_operation(e) as usize);
+        }
+        count
+    };
+    if inline_intermediates {
+        operation_count(&pil_file.identities_with_inlined_intermediate_polynomials())
+    } else {
+        let mut count = operation_count(&pil_file.identities);
+        for (_, definitions) in pil_file.intermediate_columns.values() {
+            for definition in definitions {
+                definition.pre_visit_expressions(&mut |e| count += is_operation(e) as usize);
+            }
+        }
+        count
+    }
+}
+
+/// Adds the new columns to the PIL file, such that every column is defined after
+/// the columns it depends on.
+/// Intermediate columns are defined right before the first identity that uses them,
+/// witness columns and their defining identities are appended at the end.
+fn add_columns<T: FieldElement>(
+    pil_file: &mut Analyzed<T>,
+    extractor: Extractor<T>,
+    mode: CseMode,
+) {
+    // Determine the order in which the columns are defined and the statements
+    // in front of which they are defined (by index in the source order).
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    let mut defined_before = BTreeMap::new();
+    let mut sources = vec![SourceRef::unknown(); extractor.definitions.len()];
+    for (statement_index, statement) in pil_file.source_order.iter().enumerate() {
+        if let StatementIdentifier::Identity(index) = statement {
+            let identity = &pil_file.identities[*index];
+            let start = order.len();
+            identity.pre_visit_expressions(&mut |e| {
+                if let AlgebraicExpression::Reference(r) = e {
+                    if let Some(column) = extractor.column_index(r) {
+                        visit_column(&extractor, column, &mut visited, &mut order);
+                    }
+                }
+            });
+            for column in &order[start..] {
+                sources[*column] = identity.source.clone();
+            }
+            defined_before.insert(statement_index, start..order.len());
+        }
+    }
+    // Columns are only referenced by the identities and other new columns.
+    assert_eq!(order.len(), extractor.definitions.len());
+    let position = order
+        .iter()
+        .enumerate()
+        .map(|(position, column)| (*column, position))
+        .collect::<BTreeMap<_, _>>();
+
+    // Re-assign IDs and names in the order of definition.
+    let names = order
+        .iter()
+        .map(|column| {
+            let namespace = namespace_of(&extractor.definitions[*column]);
+            let position = position[column];
+            match namespace.as_str() {
+                "" => format!("_cse_{position}"),
+                namespace => format!("{namespace}._cse_{position}"),
+            }
+        })
+        .collect::<Vec<_>>();
+    let remap = &mut |e: &mut AlgebraicExpression<T>| {
+        if let AlgebraicExpression::Reference(r) = e {
+            if let Some(column) = extractor.column_index(r) {
+                let position = position[&column];
+                r.poly_id.id = extractor.first_id + position as u64;
+                r.name = names[position].clone();
+            }
+        }
+    };
+    pil_file
+        .identities
+        .iter_mut()
+        .for_each(|i| i.post_visit_expressions_mut(remap));
+    let mut definitions = extractor.definitions.clone();
+    definitions
+        .iter_mut()
+        .for_each(|d| d.post_visit_expressions_mut(remap));
+
+    match mode {
+        CseMode::Intermediate => {
+            pil_file.source_order = std::mem::take(&mut pil_file.source_order)
+                .into_iter()
+                .enumerate()
+                .flat_map(|(statement_index, statement)| {
+                    let definitions = defined_before
+                        .remove(&statement_index)
+                        .unwrap_or_default()
+                        .map(|position| StatementIdentifier::Definition(names[position].clone()));
+                    definitions
+                        .chain(std::iter::once(statement))
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            for (position, column) in order.into_iter().enumerate() {
+                let symbol = Symbol {
+                    id: extractor.first_id + position as u64,
+                    source: sources[column].clone(),
+                    absolute_name: names[position].clone(),
+                    kind: SymbolKind::Poly(PolynomialType::Intermediate),
+                    length: None,
+                    derived: false,
+                };
+                pil_file.intermediate_columns.insert(
+                    names[position].clone(),
+                    (symbol, vec![definitions[column].clone()]),
+                );
+            }
+        }
+        CseMode::Witness => {
+            for (position, column) in order.into_iter().enumerate() {
+                let reference = pil_file.append_derived_column(
+                    names[position].clone(),
+                    definitions[column].clone(),
+                    sources[column].clone(),
+                );
+                assert_eq!(reference.poly_id.id, extractor.first_id + position as u64);
+            }
+        }
+    }
+}
+
+/// Adds the column and the columns it depends on to `order` in post-order,
+/// unless they have been visited already.
+fn visit_column<T: FieldElement>(
+    extractor: &Extractor<T>,
+    column: usize,
+    visited: &mut HashSet<usize>,
+    order: &mut Vec<usize>,
+) {
+    if !visited.insert(column) {
+        return;
+    }
+    for dependency in extractor.referenced_columns(&extractor.definitions[column]) {
+        visit_column(extractor, dependency, visited, order);
+    }
+    order.push(column);
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn cse(input: &str, mode: CseMode) -> String {
+        eliminate_common_subexpressions(analyze_string::<GoldilocksField>(input), mode).to_string()
+    }
+
+    #[test]
+    fn intermediate() {
+        let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    Z = (X + Y) * (X + Y) + 1;
+    X' = (1 - Z) * X;
+    Y' = (1 - Z) * Y;
+    (1 - Z) { X + Y } in { Z };
+"#;
+        let expectation = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col _cse_0 = (N.X + N.Y);
+    N.Z = ((N._cse_0 * N._cse_0) + 1);
+    col _cse_1 = (1 - N.Z);
+    N.X' = (N._cse_1 * N.X);
+    N.Y' = (N._cse_1 * N.Y);
+    N._cse_1 { N._cse_0 } in { N.Z };
+"#;
+        assert_eq!(cse(input, CseMode::Intermediate), expectation);
+    }
+
+    #[test]
+    fn nested_witness() {
+        let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    X = (Z * (Y + 1)) * Y;
+    Y = (Z * (Y + 1)) * X;
+    Z = (Y + 1) * X;
+"#;
+        let expectation = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    N.X = (N._cse_1 * N.Y);
+    N.Y = (N._cse_1 * N.X);
+    N.Z = (N._cse_0 * N.X);
+    col witness derived _cse_0;
+    N._cse_0 = (N.Y + 1);
+    col witness derived _cse_1;
+    N._cse_1 = (N.Z * N._cse_0);
+"#;
+        assert_eq!(cse(input, CseMode::Witness), expectation);
+    }
+
+    #[test]
+    fn derived_column_count() {
+        let input = r#"namespace N(65536);
+    col witness A;
+    col witness B;
+    A' = (A + B) * (A + B);
+    B' = (A + B) * A - A * B;
+    { A * B } in { B };
+"#;
+        let analyzed = analyze_string::<GoldilocksField>(input);
+        assert_eq!(analyzed.commitment_count(), 2);
+        assert_eq!(analyzed.identities.len(), 3);
+        assert!(analyzed.derived_columns().is_empty());
+
+        let optimized = eliminate_common_subexpressions(analyzed, CseMode::Witness);
+        assert_eq!(optimized.commitment_count(), 4);
+        assert_eq!(optimized.identities.len(), 5);
+        let derived = optimized
+            .derived_columns()
+            .into_iter()
+            .map(|poly_id| poly_id.id)
+            .collect::<Vec<_>>();
+        assert_eq!(derived, vec![2, 3]);
+    }
+
+    #[test]
+    fn cost() {
+        let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    Z = (X + 2 * Y) * (X + 2 * Y);
+    X' = (X + 2 * Y) * Z;
+"#;
+        let analyzed = analyze_string::<GoldilocksField>(input);
+        assert_eq!(evaluation_cost(&analyzed, false), 10);
+        assert_eq!(evaluation_cost(&analyzed, true), 10);
+
+        // The definition is evaluated once, unless it is inlined.
+        let intermediate = eliminate_common_subexpressions(analyzed.clone(), CseMode::Intermediate);
+        assert_eq!(evaluation_cost(&intermediate, false), 6);
+        assert_eq!(evaluation_cost(&intermediate, true), 10);
+
+        // The defining identity costs an additional operation.
+        let witness = eliminate_common_subexpressions(analyzed, CseMode::Witness);
+        assert_eq!(evaluation_cost(&witness, false), 7);
+        assert_eq!(evaluation_cost(&witness, true), 7);
+    }
+}
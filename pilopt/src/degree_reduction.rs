@@ -0,0 +1,320 @@
+//! Reduction of the degree of polynomial identities by introducing intermediate witness columns.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference, AlgebraicUnaryOperator,
+    Analyzed, IdentityKind, PolyID, PolynomialType,
+};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_ast::SourceRef;
+use powdr_number::FieldElement;
+
+/// Rewrites all polynomial identities of degree larger than `max_degree` such that their
+/// degree is at most `max_degree`. This is done by replacing factors of high-degree products
+/// by new witness columns `w` and adding the identity `w = factor`.
+/// The new columns are marked as derived, so witness generation does not solve these
+/// identities: It inlines their definitions into the other identities and computes
+/// their values once all other columns are known.
+/// Intermediate columns are inlined into the identities that are rewritten.
+/// Returns the rewritten PIL file and a report on the degrees before and after.
+pub fn reduce_degree<T: FieldElement>(
+    mut pil_file: Analyzed<T>,
+    max_degree: usize,
+) -> (Analyzed<T>, DegreeReport) {
+    assert!(
+        max_degree >= 2,
+        "The degree bound has to be at least 2, but is {max_degree}."
+    );
+    let degrees_pre = max_degree_per_namespace(&pil_file);
+
+    let inlined = pil_file.identities_with_inlined_intermediate_polynomials();
+    let mut reducer = DegreeReducer {
+        max_degree,
+        first_id: pil_file.commitment_count() as u64,
+        namespace: String::new(),
+        source: SourceRef::unknown(),
+        new_columns: Default::default(),
+        extracted: Default::default(),
+    };
+    for (identity, inlined) in pil_file.identities.iter_mut().zip(inlined) {
+        if identity.kind != IdentityKind::Polynomial {
+            continue;
+        }
+        let expression = inlined.expression_for_poly_id();
+        if degree(expression) <= max_degree {
+            continue;
+        }
+        reducer.namespace = namespace_of(expression);
+        reducer.source = identity.source.clone();
+        identity.left.selector = Some(reducer.reduce(expression.clone()));
+    }
+
+    let column_count = reducer.new_columns.len();
+    for (reference, expression, source) in reducer.new_columns {
+        let column = pil_file.append_derived_column(reference.name.clone(), expression, source);
+        assert_eq!(column, reference);
+    }
+
+    let degrees_post = max_degree_per_namespace(&pil_file);
+    let report = DegreeReport {
+        max_degree,
+        column_count,
+        degrees: degrees_pre
+            .into_iter()
+            .map(|(namespace, pre)| {
+                let post = degrees_post[&namespace];
+                (namespace, (pre, post))
+            })
+            .collect(),
+    };
+    (pil_file, report)
+}
+
+/// The result of [reduce_degree].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DegreeReport {
+    /// The requested degree bound.
+    pub max_degree: usize,
+    /// The number of witness columns that were introduced.
+    pub column_count: usize,
+    /// The maximum degree of the polynomial identities of each namespace,
+    /// before and after the reduction.
+    pub degrees: BTreeMap<String, (usize, usize)>,
+}
+
+impl Display for DegreeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Introduced {} witness columns to reduce the degree to at most {}.",
+            self.column_count, self.max_degree
+        )?;
+        for (namespace, (pre, post)) in &self.degrees {
+            writeln!(
+                f,
+                "Maximum degree of namespace {namespace}: {pre} -> {post}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the maximum degree of the polynomial identities of each namespace,
+/// with intermediate columns inlined.
+/// An identity belongs to the namespace of the first column it references.
+pub fn max_degree_per_namespace<T: FieldElement>(
+    pil_file: &Analyzed<T>,
+) -> BTreeMap<String, usize> {
+    let mut degrees: BTreeMap<_, usize> = BTreeMap::new();
+    for identity in pil_file.identities_with_inlined_intermediate_polynomials() {
+        if identity.kind != IdentityKind::Polynomial {
+            continue;
+        }
+        let expression = identity.expression_for_poly_id();
+        let max = degrees.entry(namespace_of(expression)).or_default();
+        *max = (*max).max(degree(expression));
+    }
+    degrees
+}
+
+/// Returns the degree of an expression without intermediate column references.
+fn degree<T: FieldElement>(e: &AlgebraicExpression<T>) -> usize {
+    match e {
+        AlgebraicExpression::Reference(_) => 1,
+        AlgebraicExpression::PublicReference(_) | AlgebraicExpression::Number(_) => 0,
+        AlgebraicExpression::BinaryOperation(left, op, right) => match op {
+            AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                degree(left).max(degree(right))
+            }
+            AlgebraicBinaryOperator::Mul => degree(left) + degree(right),
+            AlgebraicBinaryOperator::Pow => match right.as_ref() {
+                AlgebraicExpression::Number(n) => degree(left) * n.to_degree() as usize,
+                _ => panic!("Exponent has to be a number: {right}"),
+            },
+        },
+        AlgebraicExpression::UnaryOperation(_, inner) => degree(inner),
+    }
+}
+
+/// Returns the namespace of the first column referenced in the expression.
+pub(crate) fn namespace_of<T>(e: &AlgebraicExpression<T>) -> String {
+    let mut name = None;
+    e.pre_visit_expressions(&mut |e| {
+        if let AlgebraicExpression::Reference(r) = e {
+            name.get_or_insert_with(|| r.name.clone());
+        }
+    });
+    name.and_then(|name| {
+        name.rsplit_once('.')
+            .map(|(namespace, _)| namespace.to_string())
+    })
+    .unwrap_or_default()
+}
+
+struct DegreeReducer<T> {
+    max_degree: usize,
+    /// The ID of the first new witness column.
+    first_id: u64,
+    /// The namespace of the identity that is currently rewritten.
+    namespace: String,
+    /// The source of the identity that is currently rewritten.
+    source: SourceRef,
+    /// The new witness columns, the expressions they are equal to
+    /// and the source of the identity they were extracted from.
+    new_columns: Vec<(AlgebraicReference, AlgebraicExpression<T>, SourceRef)>,
+    /// Maps already extracted expressions to their columns so that they can be re-used.
+    extracted: BTreeMap<AlgebraicExpression<T>, AlgebraicReference>,
+}
+
+impl<T: FieldElement> DegreeReducer<T> {
+    /// Rewrites the expression such that its degree is at most `max_degree`.
+    fn reduce(&mut self, e: AlgebraicExpression<T>) -> AlgebraicExpression<T> {
+        if degree(&e) <= self.max_degree {
+            return e;
+        }
+        match e {
+            AlgebraicExpression::BinaryOperation(left, op, right) => match op {
+                AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                    let left = self.reduce(*left);
+                    let right = self.reduce(*right);
+                    AlgebraicExpression::new_binary(left, op, right)
+                }
+                AlgebraicBinaryOperator::Mul => {
+                    let mut left = self.reduce(*left);
+                    let mut right = self.reduce(*right);
+                    // Extract the factor of higher degree first, and the other one
+                    // only if that was not enough.
+                    for _ in 0..2 {
+                        if degree(&left) + degree(&right) <= self.max_degree {
+                            break;
+                        }
+                        if degree(&left) >= degree(&right) {
+                            left = self.extract(left);
+                        } else {
+                            right = self.extract(right);
+                        }
+                    }
+                    left * right
+                }
+                AlgebraicBinaryOperator::Pow => {
+                    let AlgebraicExpression::Number(exponent) = right.as_ref() else {
+                        panic!("Exponent has to be a number: {right}");
+                    };
+                    let product = (0..exponent.to_degree())
+                        .map(|_| (*left).clone())
+                        .reduce(|acc, factor| acc * factor)
+                        .unwrap_or(AlgebraicExpression::Number(T::one()));
+                    self.reduce(product)
+                }
+            },
+            AlgebraicExpression::UnaryOperation(AlgebraicUnaryOperator::Minus, inner) => {
+                AlgebraicExpression::UnaryOperation(
+                    AlgebraicUnaryOperator::Minus,
+                    Box::new(self.reduce(*inner)),
+                )
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a reference to a witness column that is constrained to be equal to `e`.
+    fn extract(&mut self, e: AlgebraicExpression<T>) -> AlgebraicExpression<T> {
+        if degree(&e) <= 1 {
+            return e;
+        }
+        let reference = self.extracted.entry(e).or_insert_with_key(|e| {
+            let index = self.new_columns.len();
+            let name = match self.namespace.as_str() {
+                "" => format!("_degree_reduction_{index}"),
+                namespace => format!("{namespace}._degree_reduction_{index}"),
+            };
+            let reference = AlgebraicReference {
+                name,
+                poly_id: PolyID {
+                    id: self.first_id + index as u64,
+                    ptype: PolynomialType::Committed,
+                },
+                next: false,
+            };
+            self.new_columns
+                .push((reference.clone(), e.clone(), self.source.clone()));
+            reference
+        });
+        AlgebraicExpression::Reference(reference.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn reduce_products() {
+        let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col inter = X * Y;
+    inter * Z * X = Y;
+    X * Y = Z;
+    X**4 = Z;
+"#;
+        let expectation = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    col witness Z;
+    col inter = (N.X * N.Y);
+    (N._degree_reduction_0 * N.X) = N.Y;
+    (N.X * N.Y) = N.Z;
+    (N._degree_reduction_1 * N.X) = N.Z;
+    col witness derived _degree_reduction_0;
+    N._degree_reduction_0 = ((N.X * N.Y) * N.Z);
+    col witness derived _degree_reduction_1;
+    N._degree_reduction_1 = ((N.X * N.X) * N.X);
+"#;
+        let (reduced, _) = reduce_degree(analyze_string::<GoldilocksField>(input), 3);
+        assert_eq!(reduced.to_string(), expectation);
+    }
+
+    #[test]
+    fn degree_report() {
+        let input = r#"namespace N(65536);
+    col witness X;
+    col witness Y;
+    X * X * X * X * X = Y;
+namespace M(65536);
+    col witness A;
+    A * A = A;
+"#;
+        let pil = analyze_string::<GoldilocksField>(input);
+        let report = |pil: &Analyzed<GoldilocksField>| {
+            max_degree_per_namespace(pil)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            report(&pil),
+            vec![("M".to_string(), 2), ("N".to_string(), 5)]
+        );
+        let (reduced, degree_report) = reduce_degree(pil, 2);
+        assert_eq!(reduced.commitment_count(), 6);
+        assert_eq!(
+            report(&reduced),
+            vec![("M".to_string(), 2), ("N".to_string(), 2)]
+        );
+        assert_eq!(degree_report.column_count, 3);
+        assert_eq!(
+            degree_report.to_string(),
+            "Introduced 3 witness columns to reduce the degree to at most 2.\n\
+             Maximum degree of namespace M: 2 -> 2\n\
+             Maximum degree of namespace N: 5 -> 2\n"
+        );
+    }
+}
@@ -14,6 +14,12 @@ use powdr_ast::parsed::visitor::ExpressionVisitable;
 
 use powdr_number::FieldElement;
 
+mod cse;
+mod degree_reduction;
+
+pub use cse::{eliminate_common_subexpressions, evaluation_cost, CseMode};
+pub use degree_reduction::{max_degree_per_namespace, reduce_degree, DegreeReport};
+
 pub fn optimize<T: FieldElement>(mut pil_file: Analyzed<T>) -> Analyzed<T> {
     let col_count_pre = (pil_file.commitment_count(), pil_file.constant_count());
     remove_constant_fixed_columns(&mut pil_file);
@@ -1,12 +1,14 @@
-use ::powdr_pipeline::{inputs_to_query_callback, Pipeline};
+use ::powdr_pipeline::{inputs_to_query_callback, Pipeline, Stage};
 use powdr_ast::analyzed::Analyzed;
 use powdr_number::{FieldElement, GoldilocksField};
 
 use powdr_pipeline::test_util::{evaluate_integer_function, std_analyzed};
 use powdr_riscv::{
     compile_rust_crate_to_riscv_asm, compile_rust_to_riscv_asm, compiler,
-    continuations::bootloader::default_input, CoProcessors,
+    continuations::{bootloader::MemoryLayout, rust_continuations_dry_run},
+    CoProcessors,
 };
+use powdr_riscv_executor::ExecMode;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use mktemp::Temp;
@@ -33,7 +35,7 @@ fn executor_benchmark(c: &mut Criterion) {
     let tmp_dir = Temp::new_dir().unwrap();
     let riscv_asm_files =
         compile_rust_crate_to_riscv_asm("../riscv/tests/riscv_data/keccak/Cargo.toml", &tmp_dir);
-    let contents = compiler::compile(riscv_asm_files, &CoProcessors::base(), false);
+    let contents = compiler::compile(riscv_asm_files, &CoProcessors::base(), None);
     let pil_with_constants = Pipeline::<T>::default()
         .from_asm_string(contents, None)
         .pil_with_evaluated_fixed_cols()
@@ -52,27 +54,123 @@ fn executor_benchmark(c: &mut Criterion) {
     // The first chunk of `many_chunks`, with Poseidon co-processor & bootloader
     let riscv_asm_files =
         compile_rust_to_riscv_asm("../riscv/tests/riscv_data/many_chunks.rs", &tmp_dir);
-    let contents = compiler::compile(riscv_asm_files, &CoProcessors::base().with_poseidon(), true);
-    let pil_with_constants = Pipeline::<T>::default()
+    let contents = compiler::compile(
+        riscv_asm_files,
+        &CoProcessors::base().with_poseidon(),
+        Some(MemoryLayout::default()),
+    );
+    let mut pipeline = Pipeline::<T>::default()
         .from_asm_string(contents, None)
-        .pil_with_evaluated_fixed_cols()
-        .unwrap();
+        .with_prover_inputs(vec![]);
+    let (bootloader_inputs, start_of_shutdown_routine) =
+        rust_continuations_dry_run(&mut pipeline, &MemoryLayout::default()).remove(0);
+    let pil_with_constants = pipeline.pil_with_evaluated_fixed_cols().unwrap();
+    let jump_to_shutdown_routine = (0..pil_with_constants.pil.degree())
+        .map(|i| (i == start_of_shutdown_routine - 1).into())
+        .collect::<Vec<_>>();
 
     group.bench_function("many_chunks_chunk_0", |b| {
         b.iter(|| {
             run_witgen(
                 &pil_with_constants.pil,
                 &pil_with_constants.fixed_cols,
-                vec![(
-                    "main.bootloader_input_value".to_string(),
-                    default_input(&[63, 64, 65]),
-                )],
+                vec![
+                    (
+                        "main.bootloader_input_value".to_string(),
+                        bootloader_inputs.clone(),
+                    ),
+                    (
+                        "main.jump_to_shutdown_routine".to_string(),
+                        jump_to_shutdown_routine.clone(),
+                    ),
+                ],
             )
         })
     });
     group.finish();
 }
 
+/// A loop of arithmetic and memory instructions, which does not need a Rust toolchain.
+fn arithmetic_loop(iterations: u32) -> String {
+    format!(
+        r#"
+.globl __runtime_start
+__runtime_start:
+    li s0, 0
+    li s1, {iterations}
+    li s2, 1
+    lui s4, 0x20
+loop:
+    mul t0, s2, s0
+    add s2, s2, t0
+    xor s2, s2, s0
+    andi t1, s0, 255
+    slli t1, t1, 2
+    add t1, t1, s4
+    sw s2, 0(t1)
+    lw t2, 0(t1)
+    add s2, s2, t2
+    srli s2, s2, 1
+    addi s0, s0, 1
+    bne s0, s1, loop
+    ret
+"#
+    )
+}
+
+/// Compares the pre-decoded RISC-V executor with the reference interpreter.
+fn riscv_executor_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("riscv-executor-benchmark");
+    group.sample_size(10);
+
+    let contents = compiler::compile(
+        [("main".to_string(), arithmetic_loop(300000))].into(),
+        &CoProcessors::base(),
+        None,
+    );
+    let mut pipeline = Pipeline::<T>::default()
+        .with_prover_inputs(vec![])
+        .from_asm_string(contents, None);
+    pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
+    let analyzed = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+    let callback = pipeline.data_callback().unwrap();
+
+    for mode in [ExecMode::Fast, ExecMode::Trace] {
+        group.bench_function(format!("arithmetic_loop_decoded_{mode:?}"), |b| {
+            b.iter(|| powdr_riscv_executor::execute_ast(analyzed, callback, &[], usize::MAX, mode))
+        });
+    }
+
+    let tmp_dir = Temp::new_dir().unwrap();
+    let riscv_asm_files =
+        compile_rust_crate_to_riscv_asm("../riscv/tests/riscv_data/keccak/Cargo.toml", &tmp_dir);
+    let contents = compiler::compile(riscv_asm_files, &CoProcessors::base(), None);
+    let mut pipeline = Pipeline::<T>::default()
+        .with_prover_inputs(vec![])
+        .from_asm_string(contents, None);
+    pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
+    let analyzed = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+    let callback = pipeline.data_callback().unwrap();
+
+    for mode in [ExecMode::Fast, ExecMode::Trace] {
+        group.bench_function(format!("keccak_decoded_{mode:?}"), |b| {
+            b.iter(|| powdr_riscv_executor::execute_ast(analyzed, callback, &[], usize::MAX, mode))
+        });
+        group.bench_function(format!("keccak_reference_{mode:?}"), |b| {
+            b.iter(|| {
+                powdr_riscv_executor::execute_ast_reference(
+                    analyzed,
+                    callback,
+                    &[],
+                    usize::MAX,
+                    mode,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
 fn evaluator_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("evaluator-benchmark");
 
@@ -133,5 +231,137 @@ fn evaluator_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, evaluator_benchmark, executor_benchmark);
+/// Compares generic witness generation with computing the main machine
+/// witness in the RISC-V executor, which leaves only the submachines to
+/// witness generation.
+fn main_machine_witness_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("main-machine-witness-benchmark");
+    group.sample_size(10);
+
+    let contents = compiler::compile(
+        [("main".to_string(), arithmetic_loop(5000))].into(),
+        &CoProcessors::base(),
+        None,
+    );
+    let mut pipeline = Pipeline::<T>::default()
+        .with_prover_inputs(vec![])
+        .from_asm_string(contents, None);
+    pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
+    let analyzed = pipeline
+        .artifact()
+        .unwrap()
+        .to_analyzed_asm()
+        .unwrap()
+        .clone();
+    let pil_with_constants = pipeline.clone().pil_with_evaluated_fixed_cols().unwrap();
+    let callback = pipeline.data_callback().unwrap();
+
+    group.bench_function("arithmetic_loop_generic", |b| {
+        b.iter(|| {
+            run_witgen(
+                &pil_with_constants.pil,
+                &pil_with_constants.fixed_cols,
+                vec![],
+            )
+        })
+    });
+
+    // With the main machine witness, all calls to the submachines can be deferred
+    // and processed in parallel.
+    for multi_threaded in [false, true] {
+        group.bench_function(
+            format!("arithmetic_loop_executor_multi_threaded_{multi_threaded}"),
+            |b| {
+                b.iter(|| {
+                    let (trace, _) = powdr_riscv_executor::execute_ast(
+                        &analyzed,
+                        callback,
+                        &[],
+                        usize::MAX,
+                        ExecMode::Trace,
+                    );
+                    let witness = powdr_riscv_executor::witness::main_machine_witness(
+                        &trace,
+                        &pil_with_constants.pil,
+                        &pil_with_constants.fixed_cols,
+                    )
+                    .unwrap();
+                    let query_callback = inputs_to_query_callback(vec![]);
+                    powdr_executor::witgen::WitnessGenerator::new(
+                        &pil_with_constants.pil,
+                        &pil_with_constants.fixed_cols,
+                        &query_callback,
+                    )
+                    .with_external_witness_values(witness)
+                    .with_multi_threading(multi_threaded)
+                    .generate();
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Compares the generic solver with compiled block programs for the submachines,
+/// with the main machine witness computed by the RISC-V executor.
+fn block_program_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block-program-benchmark");
+    group.sample_size(10);
+
+    let contents = compiler::compile(
+        [("main".to_string(), arithmetic_loop(5000))].into(),
+        &CoProcessors::base(),
+        None,
+    );
+    let mut pipeline = Pipeline::<T>::default()
+        .with_prover_inputs(vec![])
+        .from_asm_string(contents, None);
+    pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
+    let analyzed = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+    let (trace, _) = powdr_riscv_executor::execute_ast(
+        analyzed,
+        pipeline.data_callback().unwrap(),
+        &[],
+        usize::MAX,
+        ExecMode::Trace,
+    );
+    let pil_with_constants = pipeline.pil_with_evaluated_fixed_cols().unwrap();
+    let witness = powdr_riscv_executor::witness::main_machine_witness(
+        &trace,
+        &pil_with_constants.pil,
+        &pil_with_constants.fixed_cols,
+    )
+    .unwrap();
+
+    for block_programs in [false, true] {
+        group.bench_function(
+            format!("arithmetic_loop_block_programs_{block_programs}"),
+            |b| {
+                b.iter(|| {
+                    let query_callback = inputs_to_query_callback(vec![]);
+                    powdr_executor::witgen::WitnessGenerator::new(
+                        &pil_with_constants.pil,
+                        &pil_with_constants.fixed_cols,
+                        &query_callback,
+                    )
+                    .with_external_witness_values(witness.clone())
+                    .with_block_programs(block_programs)
+                    .generate();
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    evaluator_benchmark,
+    executor_benchmark,
+    riscv_executor_benchmark,
+    main_machine_witness_benchmark,
+    block_program_benchmark
+);
 criterion_main!(benches);
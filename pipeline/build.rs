@@ -2,12 +2,33 @@ use std::env;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::process::Command;
 
 use walkdir::WalkDir;
 
 fn main() {
     build_book_tests("asm");
     build_book_tests("pil");
+    export_git_hash();
+}
+
+/// Exports the hash of the current git commit as `POWDR_GIT_HASH`, which is empty if
+/// powdr is not built from a git checkout.
+#[allow(clippy::print_stdout)]
+fn export_git_hash() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=POWDR_GIT_HASH={}", git_hash.trim());
+    for path in ["../.git/HEAD", "../.git/refs", "../.git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
 }
 
 #[allow(clippy::print_stdout)]
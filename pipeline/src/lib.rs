@@ -10,11 +10,12 @@ pub mod util;
 pub mod verify;
 
 pub use pipeline::Pipeline;
-pub use pipeline::Stage;
+pub use pipeline::{PipelineError, PipelineEvent, PipelineObserver, ProofPhase, Stage};
 
 use itertools::Itertools;
 pub use powdr_backend::{BackendType, Proof};
 use powdr_executor::witgen::QueryCallback;
+pub use powdr_executor::witgen::{CancellationToken, Cancelled};
 
 use powdr_number::FieldElement;
 
@@ -1,13 +1,13 @@
 use std::{
     borrow::Borrow,
+    collections::HashSet,
     fmt::Display,
     fs,
-    io::{self, BufReader, BufWriter},
+    io::{self, BufReader, BufWriter, Write},
     marker::Send,
     path::{Path, PathBuf},
-    rc::Rc,
     sync::Arc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use log::Level;
@@ -18,43 +18,56 @@ use powdr_ast::{
     parsed::{asm::ASMProgram, PILFile},
     DiffMonitor,
 };
-use powdr_backend::{BackendType, Proof};
+use powdr_backend::{BackendOptions, BackendType, Proof};
 use powdr_executor::{
     constant_evaluator,
-    witgen::{chain_callbacks, QueryCallback},
+    witgen::{
+        chain_callbacks, extract_publics, lint::LintWarning, CancellationToken, Cancelled,
+        QueryCallback, WitgenError,
+    },
 };
-use powdr_number::{write_polys_csv_file, write_polys_file, CsvRenderMode, FieldElement};
+use powdr_number::{
+    write_polys_csv_file, write_polys_file, CsvRenderMode, DegreeType, FieldElement, KnownField,
+};
+use powdr_pilopt::{CseMode, DegreeReport};
 use powdr_schemas::SerializedAnalyzed;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use strum::{Display as StrumDisplay, EnumString, EnumVariantNames};
 
 use crate::{
     inputs_to_query_callback, serde_data_to_query_callback,
     util::{read_poly_set, write_or_panic, FixedPolySet, WitnessPolySet},
 };
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct GeneratedWitness<T: FieldElement> {
-    pub pil: Rc<Analyzed<T>>,
-    pub fixed_cols: Rc<Vec<(String, Vec<T>)>>,
+    pub pil: Arc<Analyzed<T>>,
+    pub fixed_cols: Arc<Vec<(String, Vec<T>)>>,
     pub witness: Option<Vec<(String, Vec<T>)>>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct PilWithEvaluatedFixedCols<T: FieldElement> {
-    pub pil: Rc<Analyzed<T>>,
-    pub fixed_cols: Rc<Vec<(String, Vec<T>)>>,
+    pub pil: Arc<Analyzed<T>>,
+    pub fixed_cols: Arc<Vec<(String, Vec<T>)>>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct ProofResult<T: FieldElement> {
     /// Fixed columns, potentially incomplete (if success is false)
-    pub fixed_cols: Rc<Vec<(String, Vec<T>)>>,
+    pub fixed_cols: Arc<Vec<(String, Vec<T>)>>,
     /// Witness columns, potentially None (if success is false)
     pub witness: Option<Vec<(String, Vec<T>)>>,
     /// Proof, potentially None (if success is false)
     pub proof: Option<Proof>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, EnumVariantNames, StrumDisplay)]
+#[strum(serialize_all = "kebab-case")]
 pub enum Stage {
     AsmFilePath,
     AsmString,
@@ -73,7 +86,105 @@ pub enum Stage {
     Proof,
 }
 
-#[derive(Clone)]
+impl Stage {
+    /// The stage computed from this stage by [Pipeline::advance], if any.
+    fn next(self) -> Option<Stage> {
+        Some(match self {
+            Stage::AsmFilePath => Stage::AsmString,
+            Stage::AsmString => Stage::ParsedAsmFile,
+            Stage::ParsedAsmFile => Stage::ResolvedModuleTree,
+            Stage::ResolvedModuleTree => Stage::AnalyzedAsm,
+            Stage::AnalyzedAsm => Stage::ConstrainedMachineCollection,
+            Stage::ConstrainedMachineCollection => Stage::LinkedMachineGraph,
+            Stage::LinkedMachineGraph => Stage::ParsedPilFile,
+            Stage::ParsedPilFile | Stage::PilFilePath | Stage::PilString => Stage::AnalyzedPil,
+            Stage::AnalyzedPil => Stage::OptimizedPil,
+            Stage::OptimizedPil => Stage::PilWithEvaluatedFixedCols,
+            Stage::PilWithEvaluatedFixedCols => Stage::GeneratedWitness,
+            Stage::GeneratedWitness => Stage::Proof,
+            Stage::Proof => return None,
+        })
+    }
+
+    /// Whether `target` is computed from this stage by repeatedly calling [Pipeline::advance].
+    fn reaches(self, target: Stage) -> bool {
+        let mut stage = self;
+        while stage != target {
+            match stage.next() {
+                Some(next) => stage = next,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether the artifact of this stage is fully determined by the input of the pipeline
+    /// and the optimizer arguments. Witness generation also depends on the prover inputs,
+    /// so saved witnesses and proofs are only used if requested explicitly.
+    fn depends_only_on_input(self) -> bool {
+        !matches!(self, Stage::GeneratedWitness | Stage::Proof)
+    }
+}
+
+/// The phases of proof generation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProofPhase {
+    /// The backend is created, which includes reading the setup and the verification key.
+    Setup,
+    /// The proof is generated.
+    Proving,
+    /// Several proofs are aggregated, see [Pipeline::aggregate_proofs].
+    Aggregation,
+}
+
+/// Events about the progress of the pipeline, see [Pipeline::with_observer].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PipelineEvent {
+    /// The pipeline started to compute the artifact of the given stage.
+    StageStarted(Stage),
+    /// The pipeline finished computing the artifact of the given stage.
+    StageFinished { stage: Stage, duration: Duration },
+    /// Witness generation processed the given number of rows of a machine.
+    WitgenProgress { machine: String, rows: DegreeType },
+    /// A phase of proof generation started.
+    ProofPhaseStarted(ProofPhase),
+    /// A phase of proof generation finished.
+    ProofPhaseFinished {
+        phase: ProofPhase,
+        duration: Duration,
+    },
+}
+
+/// The error returned by [Pipeline::try_advance_to].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PipelineError {
+    /// The pipeline was cancelled, see [Pipeline::with_cancellation_token].
+    Cancelled,
+    /// The pipeline failed with the given errors.
+    Failed(Vec<String>),
+}
+
+impl From<Vec<String>> for PipelineError {
+    fn from(errors: Vec<String>) -> Self {
+        PipelineError::Failed(errors)
+    }
+}
+
+impl From<PipelineError> for Vec<String> {
+    fn from(error: PipelineError) -> Self {
+        match error {
+            PipelineError::Cancelled => vec![Cancelled.to_string()],
+            PipelineError::Failed(errors) => errors,
+        }
+    }
+}
+
+/// An observer that is notified about the progress of the pipeline.
+pub trait PipelineObserver: Fn(&PipelineEvent) + Send + Sync {}
+impl<F> PipelineObserver for F where F: Fn(&PipelineEvent) + Send + Sync {}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub enum Artifact<T: FieldElement> {
     /// The path to a single .asm file.
     AsmFilePath(PathBuf),
@@ -110,6 +221,65 @@ pub enum Artifact<T: FieldElement> {
     Proof(ProofResult<T>),
 }
 
+/// A SHA-256 hash, which is stable across builds and platforms.
+type Fingerprint = [u8; 32];
+
+/// The header of an artifact saved to the output directory, see [Pipeline::with_saved_artifacts].
+#[derive(Serialize, Deserialize)]
+struct ArtifactHeader {
+    /// The version of powdr that saved the artifact.
+    version: String,
+    /// The git commit powdr was built from, if known. Artifacts of other commits
+    /// are not used, because the artifact formats might differ.
+    git_hash: String,
+    field: Option<KnownField>,
+    /// The fingerprint of the input and arguments the artifact was computed from, if known.
+    fingerprint: Option<Fingerprint>,
+}
+
+impl ArtifactHeader {
+    fn new<T: FieldElement>(fingerprint: Option<Fingerprint>) -> Self {
+        ArtifactHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: env!("POWDR_GIT_HASH").to_string(),
+            field: T::known_field(),
+            fingerprint,
+        }
+    }
+
+    /// Checks that the artifact can be read by this pipeline and, if `fingerprint`
+    /// is given, that it is up to date.
+    fn check<T: FieldElement>(&self, fingerprint: Option<Fingerprint>) -> Result<(), String> {
+        let expected = Self::new::<T>(fingerprint);
+        if self.version != expected.version || self.git_hash != expected.git_hash {
+            return Err(format!(
+                "Artifact was saved by powdr {}, but this is powdr {}",
+                self.build(),
+                expected.build()
+            ));
+        }
+        if self.field != expected.field {
+            return Err(format!(
+                "Expected field {:?} but got {:?}",
+                expected.field, self.field
+            ));
+        }
+        if fingerprint.is_some() && self.fingerprint != fingerprint {
+            return Err("Artifact is out of date".to_string());
+        }
+        Ok(())
+    }
+
+    /// The version and, if known, the git commit of the build that saved the artifact.
+    fn build(&self) -> String {
+        if self.git_hash.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{} ({})", self.version, self.git_hash)
+        }
+    }
+}
+
 /// Helper trait to make it prettier to get an `Option<&mut dyn io::Read>`` from
 /// an `Option<F: io::Read>`.
 trait AsIoRead {
@@ -126,6 +296,26 @@ impl<R: io::Read> AsIoRead for Option<R> {
 // from Pipeline, in a way that allows us to get immutable references
 // to the artifacts.
 impl<T: FieldElement> Artifact<T> {
+    pub fn stage(&self) -> Stage {
+        match self {
+            Artifact::AsmFilePath(_) => Stage::AsmFilePath,
+            Artifact::AsmString(_, _) => Stage::AsmString,
+            Artifact::ParsedAsmFile(_, _) => Stage::ParsedAsmFile,
+            Artifact::ResolvedModuleTree(_) => Stage::ResolvedModuleTree,
+            Artifact::AnalyzedAsm(_) => Stage::AnalyzedAsm,
+            Artifact::ConstrainedMachineCollection(_) => Stage::ConstrainedMachineCollection,
+            Artifact::LinkedMachineGraph(_) => Stage::LinkedMachineGraph,
+            Artifact::ParsedPilFile(_) => Stage::ParsedPilFile,
+            Artifact::PilFilePath(_) => Stage::PilFilePath,
+            Artifact::PilString(_) => Stage::PilString,
+            Artifact::AnalyzedPil(_) => Stage::AnalyzedPil,
+            Artifact::OptimzedPil(_) => Stage::OptimizedPil,
+            Artifact::PilWithEvaluatedFixedCols(_) => Stage::PilWithEvaluatedFixedCols,
+            Artifact::GeneratedWitness(_) => Stage::GeneratedWitness,
+            Artifact::Proof(_) => Stage::Proof,
+        }
+    }
+
     pub fn to_asm_string(&self) -> Option<&String> {
         match self {
             Artifact::AsmString(_, asm_string) => Some(asm_string),
@@ -183,8 +373,18 @@ struct Arguments<T: FieldElement> {
     external_witness_values: Vec<(String, Vec<T>)>,
     /// Callback for queries for witness generation.
     query_callback: Option<Arc<dyn QueryCallback<T>>>,
+    /// Whether to compute the witness of secondary machines in parallel.
+    multi_threaded_witgen: bool,
+    /// Whether block machines always use the generic solver instead of compiled programs.
+    disable_block_programs: bool,
+    /// If set, the optimizer extracts common subexpressions into new columns of this kind.
+    cse: Option<CseMode>,
+    /// If set, the optimizer reduces the degree of all polynomial identities to at most this value.
+    max_degree: Option<usize>,
     /// Backend to use for proving. If None, proving will fail.
     backend: Option<BackendType>,
+    /// Backend specific options.
+    backend_options: BackendOptions,
     /// CSV render mode for witness generation.
     csv_render_mode: CsvRenderMode,
     /// Whether to export the witness as a CSV file.
@@ -195,6 +395,21 @@ struct Arguments<T: FieldElement> {
     vkey_file: Option<PathBuf>,
     /// The optional existing proof file to use for aggregation.
     existing_proof_file: Option<PathBuf>,
+    /// The optional observer that is notified about the progress of the pipeline.
+    observer: Option<Arc<dyn PipelineObserver>>,
+    /// Token to cancel the pipeline. Never cancelled, unless set by the user.
+    cancellation_token: CancellationToken,
+    /// Whether to save the artifacts of all stages to the output directory and
+    /// to reuse up-to-date artifacts saved by previous runs.
+    save_artifacts: bool,
+}
+
+/// Converts an error of a backend into the errors of the pipeline.
+fn backend_errors(error: powdr_backend::Error) -> Vec<String> {
+    match error {
+        powdr_backend::Error::BackendError(e) => vec![e],
+        e => vec![e.to_string()],
+    }
 }
 
 #[derive(Clone)]
@@ -215,6 +430,11 @@ pub struct Pipeline<T: FieldElement> {
     log_level: Level,
     /// Optional arguments for various stages of the pipeline.
     arguments: Arguments<T>,
+    /// Hash of the resolved sources of the pipeline, used to detect whether saved artifacts
+    /// are up to date. Only computed if artifacts are saved.
+    input_hash: Option<Fingerprint>,
+    /// The report of the degree reduction, if it was run by this pipeline.
+    degree_report: Option<DegreeReport>,
 }
 
 impl<T> Default for Pipeline<T>
@@ -230,6 +450,8 @@ where
             name: None,
             force_overwrite: false,
             arguments: Arguments::default(),
+            input_hash: None,
+            degree_report: None,
         }
     }
 }
@@ -342,11 +564,42 @@ impl<T: FieldElement> Pipeline<T> {
         self.add_query_callback(Arc::new(inputs_to_query_callback(inputs)))
     }
 
+    pub fn with_multi_threaded_witgen(mut self, multi_threaded: bool) -> Self {
+        self.arguments.multi_threaded_witgen = multi_threaded;
+        self
+    }
+
+    /// Whether block machines may compile the steps of the generic solver into
+    /// straight-line programs (enabled by default).
+    pub fn with_block_programs(mut self, enabled: bool) -> Self {
+        self.arguments.disable_block_programs = !enabled;
+        self
+    }
+
+    /// Extracts subexpressions that occur more than once into new columns.
+    pub fn with_cse(mut self, mode: CseMode) -> Self {
+        self.arguments.cse = Some(mode);
+        self
+    }
+
+    /// Reduces the degree of all polynomial identities to at most `max_degree`
+    /// by introducing new witness columns.
+    pub fn with_max_degree(mut self, max_degree: usize) -> Self {
+        self.arguments.max_degree = Some(max_degree);
+        self
+    }
+
     pub fn with_backend(mut self, backend: BackendType) -> Self {
         self.arguments.backend = Some(backend);
         self
     }
 
+    /// Sets backend specific options, as comma-separated `key=value` pairs.
+    pub fn with_backend_options(mut self, options: BackendOptions) -> Self {
+        self.arguments.backend_options = options;
+        self
+    }
+
     pub fn with_setup_file(mut self, setup_file: Option<PathBuf>) -> Self {
         self.arguments.setup_file = setup_file;
         self
@@ -362,6 +615,33 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Sets an observer that is notified about the progress of the pipeline,
+    /// see [PipelineEvent].
+    pub fn with_observer(mut self, observer: Arc<dyn PipelineObserver>) -> Self {
+        self.arguments.observer = Some(observer);
+        self
+    }
+
+    /// Sets a token to cancel the pipeline from another thread. Cancellation is checked
+    /// between stages and during witness generation. Once cancelled, [Pipeline::try_advance_to]
+    /// returns [PipelineError::Cancelled], and the other methods return an error consisting
+    /// of the message of [Cancelled].
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.arguments.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Saves the artifact of every computed stage to `<name>_<stage>.artifact` in the
+    /// output directory. When advancing, the pipeline skips ahead to the latest saved
+    /// artifact that was computed from the same sources and optimizer arguments.
+    /// The sources are compared once their imports are resolved, so the stages up to
+    /// the resolved module tree (or the analyzed PIL) are always computed. Witnesses and
+    /// proofs are never skipped ahead to, use [Pipeline::read_artifact] to resume from them.
+    pub fn with_saved_artifacts(mut self, save_artifacts: bool) -> Self {
+        self.arguments.save_artifacts = save_artifacts;
+        self
+    }
+
     pub fn with_name(mut self, name: String) -> Self {
         self.name = Some(name);
         self
@@ -453,8 +733,8 @@ impl<T: FieldElement> Pipeline<T> {
         Pipeline {
             artifact: Some(Artifact::PilWithEvaluatedFixedCols(
                 PilWithEvaluatedFixedCols {
-                    pil: Rc::new(pil),
-                    fixed_cols: Rc::new(fixed),
+                    pil: Arc::new(pil),
+                    fixed_cols: Arc::new(fixed),
                 },
             )),
             ..self
@@ -479,14 +759,30 @@ impl<T: FieldElement> Pipeline<T> {
 
         Pipeline {
             artifact: Some(Artifact::GeneratedWitness(GeneratedWitness {
-                pil: Rc::new(pil),
-                fixed_cols: Rc::new(fixed),
+                pil: Arc::new(pil),
+                fixed_cols: Arc::new(fixed),
                 witness: Some(witness),
             })),
             ..self
         }
     }
 
+    /// Replaces the current artifact by the artifact of the given stage saved in the
+    /// output directory, see [Pipeline::with_saved_artifacts]. Unlike the automatic
+    /// detection of saved artifacts, this does not check that the artifact is up to date.
+    pub fn read_artifact(self, stage: Stage) -> Result<Self, Vec<String>> {
+        let path = self
+            .artifact_path(stage)
+            .ok_or_else(|| vec!["Output directory must be set to read artifacts.".to_string()])?;
+        let artifact = Self::read_saved_artifact(&path, stage, None)
+            .map_err(|e| vec![format!("Error reading {}: {e}", path.display())])?;
+        log::info!("Resuming from {}.", path.display());
+        Ok(Pipeline {
+            artifact: Some(artifact),
+            ..self
+        })
+    }
+
     /// Advances to PilWithEvaluatedFixedCols and then sets the witness to the provided value,
     /// skipping witness generation.
     pub fn skip_witness_generation(mut self, witness: Vec<(String, Vec<T>)>) -> Self {
@@ -525,8 +821,148 @@ impl<T: FieldElement> Pipeline<T> {
         log::log!(self.log_level, "{}", msg);
     }
 
+    fn notify(&self, event: PipelineEvent) {
+        if let Some(observer) = &self.arguments.observer {
+            observer(&event);
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<(), PipelineError> {
+        if self.arguments.cancellation_token.is_cancelled() {
+            self.log("Pipeline was cancelled.");
+            Err(PipelineError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs `f` as the given phase of proof generation, notifying the observer.
+    fn in_proof_phase<R>(&self, phase: ProofPhase, f: impl FnOnce() -> R) -> R {
+        self.notify(PipelineEvent::ProofPhaseStarted(phase));
+        let start = Instant::now();
+        let result = f();
+        self.notify(PipelineEvent::ProofPhaseFinished {
+            phase,
+            duration: start.elapsed(),
+        });
+        result
+    }
+
+    fn advance(&mut self) -> Result<(), PipelineError> {
+        let stage = self.stage().next().expect("Last pipeline step!");
+        self.notify(PipelineEvent::StageStarted(stage));
+        let start = Instant::now();
+        self.compute_next_artifact()?;
+        assert_eq!(self.stage(), stage);
+        self.maybe_save_artifact()?;
+        self.notify(PipelineEvent::StageFinished {
+            stage,
+            duration: start.elapsed(),
+        });
+        Ok(())
+    }
+
+    /// Hashes the sources of the pipeline, if the given artifact contains them with all
+    /// imports (including the standard library) resolved: the module tree of an .asm program
+    /// or the analyzed .pil file.
+    fn hash_input(artifact: &Artifact<T>) -> Option<Fingerprint> {
+        let sources = match artifact {
+            Artifact::ResolvedModuleTree(resolved) => format!("asm:{resolved}"),
+            Artifact::AnalyzedPil(analyzed) => format!("pil:{analyzed}"),
+            _ => return None,
+        };
+        Some(Sha256::digest(sources).into())
+    }
+
+    /// The fingerprint of the input and of the arguments that affect the artifact
+    /// of the given stage.
+    fn fingerprint(&self, stage: Stage) -> Option<Fingerprint> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.input_hash?);
+        if matches!(
+            stage,
+            Stage::OptimizedPil
+                | Stage::PilWithEvaluatedFixedCols
+                | Stage::GeneratedWitness
+                | Stage::Proof
+        ) {
+            hasher.update(format!(
+                "cse:{:?},max_degree:{:?}",
+                self.arguments.cse, self.arguments.max_degree
+            ));
+        }
+        Some(hasher.finalize().into())
+    }
+
+    fn artifact_path(&self, stage: Stage) -> Option<PathBuf> {
+        self.output_dir.as_ref().map(|output_dir| {
+            let name = self
+                .name
+                .as_ref()
+                .expect("name must be set if output_dir is set");
+            output_dir.join(format!("{name}_{stage}.artifact"))
+        })
+    }
+
+    /// Reads the artifact of the given stage from `path`. If `fingerprint` is given,
+    /// fails if the artifact is not up to date.
+    fn read_saved_artifact(
+        path: &Path,
+        stage: Stage,
+        fingerprint: Option<Fingerprint>,
+    ) -> Result<Artifact<T>, String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut deserializer = serde_cbor::Deserializer::from_reader(BufReader::new(file));
+        ArtifactHeader::deserialize(&mut deserializer)
+            .map_err(|e| e.to_string())?
+            .check::<T>(fingerprint)?;
+        let artifact = Artifact::<T>::deserialize(&mut deserializer).map_err(|e| e.to_string())?;
+        if artifact.stage() != stage {
+            return Err(format!(
+                "Expected an artifact of stage {stage} but got {}",
+                artifact.stage()
+            ));
+        }
+        Ok(artifact)
+    }
+
+    /// Skips ahead to the latest up-to-date artifact saved in the output directory for
+    /// the stages up to `target_stage`, if any. Only considers stages that only depend
+    /// on the input.
+    fn maybe_load_saved_artifact(&mut self, target_stage: Stage) {
+        let mut stages = vec![];
+        let mut stage = self.stage();
+        while stage != target_stage {
+            let Some(next) = stage.next() else {
+                return;
+            };
+            stages.push(next);
+            stage = next;
+        }
+
+        for stage in stages.into_iter().rev() {
+            if !stage.depends_only_on_input() {
+                continue;
+            }
+            let Some(fingerprint) = self.fingerprint(stage) else {
+                return;
+            };
+            let Some(path) = self.artifact_path(stage).filter(|path| path.exists()) else {
+                continue;
+            };
+            match Self::read_saved_artifact(&path, stage, Some(fingerprint)) {
+                Ok(artifact) => {
+                    self.log(&format!("Using saved artifact {}", path.display()));
+                    self.artifact = Some(artifact);
+                    return;
+                }
+                Err(e) => self.log(&format!("Not using {}: {e}", path.display())),
+            }
+        }
+    }
+
     #[allow(clippy::print_stderr)]
-    fn advance(&mut self) -> Result<(), Vec<String>> {
+    fn compute_next_artifact(&mut self) -> Result<(), PipelineError> {
         let artifact = std::mem::take(&mut self.artifact).unwrap();
         self.artifact = Some(match artifact {
             Artifact::AsmFilePath(path) => Artifact::AsmString(
@@ -600,7 +1036,18 @@ impl<T: FieldElement> Pipeline<T> {
             }
             Artifact::AnalyzedPil(analyzed_pil) => {
                 self.log("Optimizing pil...");
-                let optimized = powdr_pilopt::optimize(analyzed_pil);
+                let mut optimized = powdr_pilopt::optimize(analyzed_pil);
+                if let Some(mode) = self.arguments.cse {
+                    self.log("Eliminating common subexpressions...");
+                    optimized = powdr_pilopt::eliminate_common_subexpressions(optimized, mode);
+                }
+                if let Some(max_degree) = self.arguments.max_degree {
+                    self.log(&format!("Reducing degree to at most {max_degree}..."));
+                    let (reduced, report) = powdr_pilopt::reduce_degree(optimized, max_degree);
+                    optimized = reduced;
+                    self.maybe_write_degree_report(&report)?;
+                    self.degree_report = Some(report);
+                }
                 self.maybe_write_pil(&optimized, "_opt")?;
                 self.maybe_write_pil_object(&optimized, "_opt")?;
                 Artifact::OptimzedPil(optimized)
@@ -616,34 +1063,57 @@ impl<T: FieldElement> Pipeline<T> {
                 self.maybe_write_constants(&fixed_cols)?;
                 self.log(&format!("Took {}", start.elapsed().as_secs_f32()));
                 Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols {
-                    pil: Rc::new(pil),
-                    fixed_cols: Rc::new(fixed_cols),
+                    pil: Arc::new(pil),
+                    fixed_cols: Arc::new(fixed_cols),
                 })
             }
             Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols { pil, fixed_cols }) => {
-                let witness = (pil.constant_count() == fixed_cols.len()).then(|| {
-                    self.log("Deducing witness columns...");
-                    let start = Instant::now();
-                    let external_witness_values =
-                        std::mem::take(&mut self.arguments.external_witness_values);
-                    let query_callback =
-                        self.arguments.query_callback.take().unwrap_or_else(|| {
-                            Arc::new(powdr_executor::witgen::unused_query_callback())
-                        });
-                    let witness = powdr_executor::witgen::WitnessGenerator::new(
-                        &pil,
-                        &fixed_cols,
-                        query_callback.borrow(),
-                    )
-                    .with_external_witness_values(external_witness_values)
-                    .generate();
-
-                    self.log(&format!("Took {}", start.elapsed().as_secs_f32()));
-                    witness
-                        .into_iter()
-                        .map(|(name, c)| (name.to_string(), c))
-                        .collect::<Vec<_>>()
-                });
+                let witness = (pil.constant_count() == fixed_cols.len())
+                    .then(|| {
+                        self.log("Deducing witness columns...");
+                        let start = Instant::now();
+                        let external_witness_values =
+                            std::mem::take(&mut self.arguments.external_witness_values);
+                        let query_callback =
+                            self.arguments.query_callback.take().unwrap_or_else(|| {
+                                Arc::new(powdr_executor::witgen::unused_query_callback())
+                            });
+                        let mut generator = powdr_executor::witgen::WitnessGenerator::new(
+                            &pil,
+                            &fixed_cols,
+                            query_callback.borrow(),
+                        )
+                        .with_external_witness_values(external_witness_values)
+                        .with_multi_threading(self.arguments.multi_threaded_witgen)
+                        .with_block_programs(!self.arguments.disable_block_programs)
+                        .with_cancellation_token(self.arguments.cancellation_token.clone());
+                        if let Some(observer) = self.arguments.observer.clone() {
+                            generator = generator.with_progress_callback(Arc::new(
+                                move |machine: &str, rows| {
+                                    observer(&PipelineEvent::WitgenProgress {
+                                        machine: machine.to_string(),
+                                        rows,
+                                    })
+                                },
+                            ));
+                        }
+                        let witness = generator.try_generate().map_err(|e| match e {
+                            WitgenError::Cancelled => {
+                                self.log("Witness generation was cancelled.");
+                                PipelineError::Cancelled
+                            }
+                            e => PipelineError::Failed(vec![e.to_string()]),
+                        })?;
+
+                        self.log(&format!("Took {}", start.elapsed().as_secs_f32()));
+                        Ok::<_, PipelineError>(
+                            witness
+                                .into_iter()
+                                .map(|(name, c)| (name.to_string(), c))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .transpose()?;
 
                 self.maybe_write_witness(&fixed_cols, &witness)?;
                 Artifact::GeneratedWitness(GeneratedWitness {
@@ -678,15 +1148,18 @@ impl<T: FieldElement> Pipeline<T> {
                     .map(|path| BufReader::new(fs::File::open(path).unwrap()));
 
                 /* Create the backend */
-                let backend = factory
-                    .create(
-                        pil.borrow(),
-                        &fixed_cols[..],
-                        self.output_dir(),
-                        setup.as_io_read(),
-                        vkey.as_io_read(),
-                    )
-                    .unwrap();
+                let backend = self.in_proof_phase(ProofPhase::Setup, || {
+                    factory
+                        .create(
+                            pil.borrow(),
+                            &fixed_cols[..],
+                            self.output_dir(),
+                            setup.as_io_read(),
+                            vkey.as_io_read(),
+                            self.arguments.backend_options.clone(),
+                        )
+                        .map_err(backend_errors)
+                })?;
 
                 // Reads the existing proof file, if set.
                 let existing_proof = self
@@ -697,14 +1170,15 @@ impl<T: FieldElement> Pipeline<T> {
 
                 // Even if we don't have all constants and witnesses, some backends will
                 // still output the constraint serialization.
-                let proof =
-                    match backend.prove(witness.as_deref().unwrap_or_default(), existing_proof) {
-                        Ok(proof) => proof,
-                        Err(powdr_backend::Error::BackendError(e)) => {
-                            return Err(vec![e.to_string()]);
-                        }
-                        _ => panic!(),
-                    };
+                let proof = match self.in_proof_phase(ProofPhase::Proving, || {
+                    backend.prove(witness.as_deref().unwrap_or_default(), existing_proof)
+                }) {
+                    Ok(proof) => proof,
+                    Err(powdr_backend::Error::BackendError(e)) => {
+                        return Err(vec![e.to_string()].into());
+                    }
+                    _ => panic!(),
+                };
                 drop(backend);
 
                 let proof_result = ProofResult {
@@ -748,6 +1222,28 @@ impl<T: FieldElement> Pipeline<T> {
             .transpose()
     }
 
+    /// Saves the current artifact if saving artifacts is enabled. Saved artifacts
+    /// are always overwritten, as they are only used if they are up to date.
+    fn maybe_save_artifact(&self) -> Result<(), Vec<String>> {
+        if !self.arguments.save_artifacts {
+            return Ok(());
+        }
+        if let Some(path) = self.artifact_path(self.stage()) {
+            log::info!("Writing {}.", path.to_str().unwrap());
+            let header = ArtifactHeader::new::<T>(self.fingerprint(self.stage()));
+            let write = || -> Result<(), String> {
+                let mut writer =
+                    BufWriter::new(fs::File::create(&path).map_err(|e| e.to_string())?);
+                serde_cbor::to_writer(&mut writer, &header).map_err(|e| e.to_string())?;
+                serde_cbor::to_writer(&mut writer, self.artifact.as_ref().unwrap())
+                    .map_err(|e| e.to_string())?;
+                writer.flush().map_err(|e| e.to_string())
+            };
+            write().map_err(|e| vec![format!("Error writing {}: {e}", path.display())])?;
+        }
+        Ok(())
+    }
+
     fn maybe_write_pil<C: Display>(&self, content: &C, suffix: &str) -> Result<(), Vec<String>> {
         if let Some(path) = self.path_if_should_write(|name| format!("{name}{suffix}.pil"))? {
             fs::write(&path, format!("{content}"))
@@ -766,6 +1262,14 @@ impl<T: FieldElement> Pipeline<T> {
         Ok(())
     }
 
+    fn maybe_write_degree_report(&self, report: &DegreeReport) -> Result<(), Vec<String>> {
+        if let Some(path) = self.path_if_should_write(|name| format!("{name}_degree_report.txt"))? {
+            fs::write(&path, report.to_string())
+                .map_err(|e| vec![format!("Error writing {}: {e}", path.to_str().unwrap())])?;
+        }
+        Ok(())
+    }
+
     fn maybe_write_constants(&self, constants: &[(String, Vec<T>)]) -> Result<(), Vec<String>> {
         if let Some(path) = self.path_if_should_write(|name| format!("{name}_constants.bin"))? {
             let writer = BufWriter::new(fs::File::create(path).unwrap());
@@ -820,27 +1324,31 @@ impl<T: FieldElement> Pipeline<T> {
     }
 
     pub fn stage(&self) -> Stage {
-        match self.artifact.as_ref().unwrap() {
-            Artifact::AsmFilePath(_) => Stage::AsmFilePath,
-            Artifact::AsmString(_, _) => Stage::AsmString,
-            Artifact::ParsedAsmFile(_, _) => Stage::ParsedAsmFile,
-            Artifact::ResolvedModuleTree(_) => Stage::ResolvedModuleTree,
-            Artifact::AnalyzedAsm(_) => Stage::AnalyzedAsm,
-            Artifact::ConstrainedMachineCollection(_) => Stage::ConstrainedMachineCollection,
-            Artifact::LinkedMachineGraph(_) => Stage::LinkedMachineGraph,
-            Artifact::ParsedPilFile(_) => Stage::ParsedPilFile,
-            Artifact::PilFilePath(_) => Stage::PilFilePath,
-            Artifact::PilString(_) => Stage::PilString,
-            Artifact::AnalyzedPil(_) => Stage::AnalyzedPil,
-            Artifact::OptimzedPil(_) => Stage::OptimizedPil,
-            Artifact::PilWithEvaluatedFixedCols(_) => Stage::PilWithEvaluatedFixedCols,
-            Artifact::GeneratedWitness(_) => Stage::GeneratedWitness,
-            Artifact::Proof(_) => Stage::Proof,
-        }
+        self.artifact.as_ref().unwrap().stage()
     }
 
     pub fn advance_to(&mut self, target_stage: Stage) -> Result<(), Vec<String>> {
+        Ok(self.try_advance_to(target_stage)?)
+    }
+
+    /// Like [Pipeline::advance_to], but distinguishes a cancelled pipeline from one that failed.
+    pub fn try_advance_to(&mut self, target_stage: Stage) -> Result<(), PipelineError> {
+        if !self.stage().reaches(target_stage) {
+            return Err(vec![format!(
+                "Stage {target_stage} cannot be reached from stage {}",
+                self.stage()
+            )]
+            .into());
+        }
         while self.stage() != target_stage {
+            self.check_cancelled()?;
+            if self.arguments.save_artifacts && self.input_hash.is_none() {
+                self.input_hash = Self::hash_input(self.artifact.as_ref().unwrap());
+                if self.input_hash.is_some() {
+                    self.maybe_load_saved_artifact(target_stage);
+                    continue;
+                }
+            }
             self.advance()?;
         }
         Ok(())
@@ -886,6 +1394,12 @@ impl<T: FieldElement> Pipeline<T> {
         Ok(optimized_pil)
     }
 
+    /// Returns the report of the degree reduction (see [Pipeline::with_max_degree]),
+    /// if it was run by this pipeline and not loaded from a saved artifact.
+    pub fn degree_report(&self) -> Option<&DegreeReport> {
+        self.degree_report.as_ref()
+    }
+
     pub fn optimized_pil_ref(&mut self) -> Result<&Analyzed<T>, Vec<String>> {
         self.advance_to(Stage::OptimizedPil)?;
         match self.artifact.as_ref().unwrap() {
@@ -922,6 +1436,18 @@ impl<T: FieldElement> Pipeline<T> {
         Ok(generated_witness)
     }
 
+    /// Returns the public values of the generated witness, in the order in which
+    /// the backends expect them as instances when verifying a proof.
+    pub fn publics(&mut self) -> Result<Vec<(String, T)>, Vec<String>> {
+        self.advance_to(Stage::GeneratedWitness)?;
+        let Artifact::GeneratedWitness(GeneratedWitness { pil, witness, .. }) =
+            self.artifact.as_ref().unwrap()
+        else {
+            panic!()
+        };
+        Ok(extract_publics(witness.as_ref().unwrap(), pil))
+    }
+
     pub fn proof(mut self) -> Result<ProofResult<T>, Vec<String>> {
         self.advance_to(Stage::Proof)?;
         let Artifact::Proof(proof) = self.artifact.unwrap() else {
@@ -930,6 +1456,14 @@ impl<T: FieldElement> Pipeline<T> {
         Ok(proof)
     }
 
+    pub fn backend_options(&self) -> &BackendOptions {
+        &self.arguments.backend_options
+    }
+
+    pub fn setup_file(&self) -> Option<&Path> {
+        self.arguments.setup_file.as_deref()
+    }
+
     pub fn output_dir(&self) -> Option<&Path> {
         self.output_dir.as_ref().map(|p| p.as_ref())
     }
@@ -946,6 +1480,37 @@ impl<T: FieldElement> Pipeline<T> {
         self.arguments.query_callback.as_deref()
     }
 
+    /// Checks the witness columns for potential soundness issues, like columns that
+    /// are used as booleans but not constrained to be boolean.
+    pub fn lint(&mut self) -> Result<Vec<LintWarning>, Vec<String>> {
+        // Instruction flags are only known before the machines are linked to PIL.
+        let instruction_flags = if self.stage().reaches(Stage::LinkedMachineGraph) {
+            self.advance_to(Stage::LinkedMachineGraph)?;
+            let Some(Artifact::LinkedMachineGraph(graph)) = &self.artifact else {
+                unreachable!()
+            };
+            graph
+                .objects
+                .iter()
+                .flat_map(|(location, object)| {
+                    object
+                        .instruction_flags
+                        .iter()
+                        .map(move |flag| format!("{location}.{flag}"))
+                })
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let PilWithEvaluatedFixedCols { pil, fixed_cols } =
+            self.pil_with_evaluated_fixed_cols_ref()?;
+        Ok(powdr_executor::witgen::lint::lint(
+            pil,
+            fixed_cols,
+            &instruction_flags,
+        ))
+    }
+
     pub fn export_verification_key<W: io::Write>(
         &mut self,
         mut writer: W,
@@ -974,8 +1539,9 @@ impl<T: FieldElement> Pipeline<T> {
                             .as_mut()
                             .map(|file| file as &mut dyn std::io::Read),
                         None,
+                        self.arguments.backend_options.clone(),
                     )
-                    .unwrap();
+                    .map_err(backend_errors)?;
 
                 match backend.export_verification_key(&mut writer) {
                     Ok(()) => Ok(()),
@@ -1016,8 +1582,9 @@ impl<T: FieldElement> Pipeline<T> {
                         self.output_dir(),
                         Some(&mut setup_file),
                         Some(&mut vkey_file),
+                        self.arguments.backend_options.clone(),
                     )
-                    .unwrap();
+                    .map_err(backend_errors)?;
 
                 match backend.verify(&proof, instances) {
                     Ok(_) => Ok(()),
@@ -1028,4 +1595,173 @@ impl<T: FieldElement> Pipeline<T> {
             _ => panic!(),
         }
     }
+
+    /// Exports a verifier for the Ethereum Virtual Machine, as Solidity source and as
+    /// EVM bytecode, for the proofs aggregated from `num_proofs` proofs chained by
+    /// `chained_publics` (see [powdr_backend::Backend::export_ethereum_verifier]).
+    pub fn export_ethereum_verifier<W1: io::Write, W2: io::Write>(
+        &mut self,
+        num_proofs: usize,
+        chained_publics: &[(usize, usize)],
+        mut solidity: W1,
+        mut bytecode: W2,
+    ) -> Result<(), Vec<String>> {
+        self.advance_to(Stage::PilWithEvaluatedFixedCols)?;
+        match self.artifact.as_ref().unwrap() {
+            Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols { pil, fixed_cols }) => {
+                let backend = self
+                    .arguments
+                    .backend
+                    .expect("backend must be set before exporting a verifier!");
+                let factory = backend.factory::<T>();
+
+                let mut setup_file = if let Some(path) = &self.arguments.setup_file {
+                    BufReader::new(fs::File::open(path).unwrap())
+                } else {
+                    panic!("Setup should have been provided for exporting a verifier")
+                };
+
+                let mut vkey_file = self
+                    .arguments
+                    .vkey_file
+                    .as_ref()
+                    .map(|path| BufReader::new(fs::File::open(path).unwrap()));
+
+                let backend = factory
+                    .create(
+                        pil.borrow(),
+                        &fixed_cols[..],
+                        self.output_dir(),
+                        Some(&mut setup_file),
+                        vkey_file
+                            .as_mut()
+                            .map(|file| file as &mut dyn std::io::Read),
+                        self.arguments.backend_options.clone(),
+                    )
+                    .map_err(backend_errors)?;
+
+                match backend.export_ethereum_verifier(
+                    num_proofs,
+                    chained_publics,
+                    &mut solidity,
+                    &mut bytecode,
+                ) {
+                    Ok(()) => Ok(()),
+                    Err(powdr_backend::Error::BackendError(e)) => Err(vec![e]),
+                    Err(e) => Err(vec![e.to_string()]),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Encodes a proof and its public values as calldata for the verifier exported by
+    /// [Pipeline::export_ethereum_verifier], given as its bytecode. The calldata is checked
+    /// by calling the verifier in a local EVM (see [powdr_backend::Backend::ethereum_calldata]).
+    pub fn ethereum_calldata(
+        &mut self,
+        proof: Proof,
+        publics: &[T],
+        verifier: &[u8],
+    ) -> Result<Vec<u8>, Vec<String>> {
+        self.advance_to(Stage::PilWithEvaluatedFixedCols)?;
+        match self.artifact.as_ref().unwrap() {
+            Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols { pil, fixed_cols }) => {
+                let backend = self
+                    .arguments
+                    .backend
+                    .expect("backend must be set before encoding calldata!");
+                let factory = backend.factory::<T>();
+
+                let mut setup_file = if let Some(path) = &self.arguments.setup_file {
+                    BufReader::new(fs::File::open(path).unwrap())
+                } else {
+                    panic!("Setup should have been provided for encoding calldata")
+                };
+
+                let mut vkey_file = self
+                    .arguments
+                    .vkey_file
+                    .as_ref()
+                    .map(|path| BufReader::new(fs::File::open(path).unwrap()));
+
+                let backend = factory
+                    .create(
+                        pil.borrow(),
+                        &fixed_cols[..],
+                        self.output_dir(),
+                        Some(&mut setup_file),
+                        vkey_file
+                            .as_mut()
+                            .map(|file| file as &mut dyn std::io::Read),
+                        self.arguments.backend_options.clone(),
+                    )
+                    .map_err(backend_errors)?;
+
+                match backend.ethereum_calldata(&proof, publics, verifier) {
+                    Ok(calldata) => Ok(calldata),
+                    Err(powdr_backend::Error::BackendError(e)) => Err(vec![e]),
+                    Err(e) => Err(vec![e.to_string()]),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    /// Aggregates several proofs of the circuit of this pipeline, each given together
+    /// with its public values, into a single proof (see [powdr_backend::Backend::aggregate]).
+    /// All proofs have to be generated with the setup of this pipeline.
+    ///
+    /// Returns the aggregated proof and its public values.
+    pub fn aggregate_proofs(
+        &mut self,
+        proofs: Vec<(Proof, Vec<T>)>,
+        chained_publics: &[(usize, usize)],
+    ) -> Result<(Proof, Vec<T>), Vec<String>> {
+        self.advance_to(Stage::PilWithEvaluatedFixedCols)?;
+        match self.artifact.as_ref().unwrap() {
+            Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols { pil, fixed_cols }) => {
+                let backend = self
+                    .arguments
+                    .backend
+                    .expect("backend must be set before aggregating proofs!");
+                let factory = backend.factory::<T>();
+
+                let mut setup_file = if let Some(path) = &self.arguments.setup_file {
+                    BufReader::new(fs::File::open(path).unwrap())
+                } else {
+                    panic!("Setup should have been provided for aggregation")
+                };
+
+                let mut vkey_file = self
+                    .arguments
+                    .vkey_file
+                    .as_ref()
+                    .map(|path| BufReader::new(fs::File::open(path).unwrap()));
+
+                let backend = factory
+                    .create(
+                        pil.borrow(),
+                        &fixed_cols[..],
+                        self.output_dir(),
+                        Some(&mut setup_file),
+                        vkey_file
+                            .as_mut()
+                            .map(|file| file as &mut dyn std::io::Read),
+                        self.arguments.backend_options.clone(),
+                    )
+                    .map_err(backend_errors)?;
+
+                self.check_cancelled()?;
+                match self.in_proof_phase(ProofPhase::Aggregation, || {
+                    backend.aggregate(proofs, chained_publics)
+                }) {
+                    Ok(aggregated) => Ok(aggregated),
+                    Err(powdr_backend::Error::BackendError(e)) => Err(vec![e]),
+                    Err(e) => Err(vec![e.to_string()]),
+                }
+            }
+            _ => panic!(),
+        }
+    }
 }
@@ -152,6 +152,53 @@ pub fn gen_halo2_proof(file_name: &str, inputs: Vec<Bn254Field>) {
 #[cfg(not(feature = "halo2"))]
 pub fn gen_halo2_proof(_file_name: &str, _inputs: Vec<Bn254Field>) {}
 
+/// Generates a Halo2 proof for the given file with each of the given inputs and aggregates
+/// the proofs in order, chaining the given pairs of public values
+/// (see [Pipeline::aggregate_proofs]). Returns the public values of the aggregated proof.
+#[cfg(feature = "halo2")]
+pub fn gen_halo2_aggregated_proof(
+    file_name: &str,
+    inputs: Vec<Vec<Bn254Field>>,
+    chained_publics: &[(usize, usize)],
+) -> Result<Vec<Bn254Field>, Vec<String>> {
+    use crate::util::write_or_panic;
+    use powdr_executor::witgen::extract_publics;
+
+    let tmp_dir = mktemp::Temp::new_dir().unwrap();
+    let mut pipeline = Pipeline::default()
+        .with_tmp_output(&tmp_dir)
+        .from_file(resolve_test_file(file_name))
+        .with_backend(powdr_backend::BackendType::Halo2);
+    let pil = pipeline.optimized_pil_ref().unwrap().clone();
+
+    // All proofs have to share the same setup, which also has to be large enough
+    // for the aggregation circuit.
+    let setup_file_path = tmp_dir.as_path().join("params.bin");
+    let setup_file = BufWriter::new(File::create(&setup_file_path).unwrap());
+    write_or_panic(setup_file, |writer| {
+        powdr_backend::BackendType::Halo2
+            .factory::<Bn254Field>()
+            .generate_setup(1 << 20, writer)
+            .unwrap()
+    });
+    let mut pipeline = pipeline.with_setup_file(Some(setup_file_path));
+
+    let proofs = inputs
+        .into_iter()
+        .map(|inputs| {
+            let proof_artifact = pipeline.clone().with_prover_inputs(inputs).proof().unwrap();
+            let publics = extract_publics(proof_artifact.witness.as_ref().unwrap(), &pil)
+                .iter()
+                .map(|(_name, v)| *v)
+                .collect();
+            (proof_artifact.proof.unwrap(), publics)
+        })
+        .collect();
+
+    let (_, publics) = pipeline.aggregate_proofs(proofs, chained_publics)?;
+    Ok(publics)
+}
+
 /// Returns the analyzed PIL containing only the std library.
 pub fn std_analyzed<T: FieldElement>() -> Analyzed<T> {
     // airgen needs a main machine.
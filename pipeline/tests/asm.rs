@@ -1,5 +1,11 @@
+use std::sync::{Arc, Mutex};
+
 use powdr_number::{FieldElement, GoldilocksField};
-use powdr_pipeline::test_util::{gen_estark_proof, test_halo2, verify_test_file};
+use powdr_pilopt::{evaluation_cost, CseMode};
+use powdr_pipeline::test_util::{
+    gen_estark_proof, resolve_test_file, test_halo2, verify_pipeline, verify_test_file,
+};
+use powdr_pipeline::{Pipeline, PipelineEvent, Stage};
 use test_log::test;
 
 fn verify_asm<T: FieldElement>(file_name: &str, inputs: Vec<T>) {
@@ -130,6 +136,198 @@ fn block_to_block() {
     gen_estark_proof(f, slice_to_vec(&i));
 }
 
+#[test]
+fn multi_threaded_witgen() {
+    for f in [
+        "asm/vm_to_block_to_block.asm",
+        "asm/vm_to_block_multiple_interfaces.asm",
+        "asm/mem_read_write.asm",
+    ] {
+        let witness = |multi_threaded| {
+            Pipeline::<GoldilocksField>::default()
+                .from_file(resolve_test_file(f))
+                .with_prover_inputs(vec![])
+                .with_multi_threaded_witgen(multi_threaded)
+                .generated_witness()
+                .unwrap()
+                .witness
+                .unwrap()
+        };
+        assert_eq!(witness(false), witness(true), "{f}");
+    }
+}
+
+#[test]
+fn multi_threaded_witgen_failing_deferred_call() {
+    let asm = r#"
+machine Lookup(latch, operation_id) {
+    degree 8;
+
+    operation check<0> x ->;
+
+    col witness operation_id;
+    col fixed latch = [1]*;
+    col fixed X = [0, 1]*;
+    col fixed Z = [1, 0]*;
+    col witness x;
+    col witness z;
+    { x, z } in { X, Z };
+}
+
+machine Main {
+    degree 8;
+
+    Lookup lookup;
+
+    reg pc[@pc];
+    reg X[<=];
+
+    instr check X -> = lookup.check;
+
+    instr loop {
+        pc' = pc
+    }
+
+    function main {
+        check 0;
+        check 5;
+        loop;
+    }
+}
+"#;
+    let err = Pipeline::<GoldilocksField>::default()
+        .from_asm_string(asm.to_string(), None)
+        .with_prover_inputs(vec![])
+        .with_multi_threaded_witgen(true)
+        .generated_witness()
+        .err()
+        .unwrap();
+    assert_eq!(err.len(), 1);
+    assert!(
+        err[0].starts_with("Error processing deferred calls to machine"),
+        "{}",
+        err[0]
+    );
+}
+
+#[test]
+fn block_programs() {
+    for f in [
+        "asm/vm_to_block_to_block.asm",
+        "asm/vm_to_block_multiple_interfaces.asm",
+        "asm/secondary_block_machine_add2.asm",
+        "asm/block_to_block.asm",
+        "asm/block_machine_cache_miss.asm",
+    ] {
+        let witness = |block_programs| {
+            Pipeline::<GoldilocksField>::default()
+                .from_file(resolve_test_file(f))
+                .with_prover_inputs(vec![])
+                .with_block_programs(block_programs)
+                .generated_witness()
+                .unwrap()
+                .witness
+                .unwrap()
+        };
+        assert_eq!(witness(false), witness(true), "{f}");
+    }
+}
+
+#[test]
+fn degree_reduction() {
+    for f in ["asm/vm_to_block_to_block.asm", "asm/mem_read_write.asm"] {
+        let pipeline = Pipeline::<GoldilocksField>::default()
+            .from_file(resolve_test_file(f))
+            .with_prover_inputs(vec![])
+            .with_max_degree(2);
+        verify_pipeline(pipeline);
+    }
+}
+
+#[test]
+fn common_subexpression_elimination() {
+    let f = "asm/mem_read_write.asm";
+    for mode in [CseMode::Intermediate, CseMode::Witness] {
+        let pipeline = Pipeline::<GoldilocksField>::default()
+            .from_file(resolve_test_file(f))
+            .with_prover_inputs(vec![])
+            .with_cse(mode);
+        verify_pipeline(pipeline);
+    }
+}
+
+#[test]
+fn common_subexpression_elimination_cost() {
+    let f = "asm/mem_read_write.asm";
+    let optimized = |mode: Option<CseMode>| {
+        let mut pipeline = Pipeline::<GoldilocksField>::default().from_file(resolve_test_file(f));
+        if let Some(mode) = mode {
+            pipeline = pipeline.with_cse(mode);
+        }
+        pipeline.optimized_pil().unwrap()
+    };
+    let original = optimized(None);
+    let intermediate = optimized(Some(CseMode::Intermediate));
+    let witness = optimized(Some(CseMode::Witness));
+
+    // Intermediate columns only help backends that evaluate them once per row.
+    assert!(evaluation_cost(&intermediate, false) < evaluation_cost(&original, false));
+    assert_eq!(
+        evaluation_cost(&intermediate, true),
+        evaluation_cost(&original, true)
+    );
+    assert!(evaluation_cost(&witness, true) < evaluation_cost(&original, true));
+}
+
+/// Advances the pipeline to `target` and returns the stages that were computed.
+fn computed_stages(pipeline: Pipeline<GoldilocksField>, target: Stage) -> Vec<Stage> {
+    let started = Arc::new(Mutex::new(vec![]));
+    let observer = {
+        let started = started.clone();
+        move |event: &PipelineEvent| {
+            if let PipelineEvent::StageStarted(stage) = event {
+                started.lock().unwrap().push(*stage);
+            }
+        }
+    };
+    let mut pipeline = pipeline.with_observer(Arc::new(observer));
+    pipeline.advance_to(target).unwrap();
+    let started = started.lock().unwrap().clone();
+    started
+}
+
+#[test]
+fn saved_artifacts() {
+    let f = "asm/simple_sum.asm";
+    let tmp_dir = mktemp::Temp::new_dir().unwrap();
+    let pipeline = || {
+        Pipeline::<GoldilocksField>::default()
+            .from_file(resolve_test_file(f))
+            .with_tmp_output(&tmp_dir)
+            .with_saved_artifacts(true)
+    };
+    let optimized = pipeline().optimized_pil().unwrap().to_string();
+
+    // The second run starts from the saved optimized PIL.
+    assert_eq!(
+        computed_stages(pipeline(), Stage::PilWithEvaluatedFixedCols),
+        vec![Stage::PilWithEvaluatedFixedCols]
+    );
+
+    // Different optimizer arguments make the optimized PIL out of date.
+    assert_eq!(
+        computed_stages(pipeline().with_cse(CseMode::Witness), Stage::OptimizedPil),
+        vec![Stage::OptimizedPil]
+    );
+
+    let resumed = pipeline()
+        .read_artifact(Stage::AnalyzedAsm)
+        .unwrap()
+        .optimized_pil()
+        .unwrap();
+    assert_eq!(resumed.to_string(), optimized);
+}
+
 #[test]
 fn vm_instr_param_mapping() {
     let f = "asm/vm_instr_param_mapping.asm";
@@ -1,11 +1,13 @@
 #[cfg(feature = "halo2")]
 use powdr_number::Bn254Field;
+use std::sync::{Arc, Mutex};
+
 use powdr_number::GoldilocksField;
 use powdr_pipeline::{
     test_util::{
         gen_estark_proof, resolve_test_file, test_halo2, verify_pipeline, verify_test_file,
     },
-    Pipeline,
+    CancellationToken, Pipeline, PipelineError, PipelineEvent, Stage,
 };
 use test_log::test;
 
@@ -75,6 +77,92 @@ fn test_fibonacci() {
     gen_estark_proof(f, Default::default());
 }
 
+#[test]
+fn test_pipeline_events() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let observer = {
+        let events = events.clone();
+        move |event: &PipelineEvent| events.lock().unwrap().push(event.clone())
+    };
+    Pipeline::<GoldilocksField>::default()
+        .from_file(resolve_test_file("pil/fibonacci.pil"))
+        .with_observer(Arc::new(observer))
+        .generated_witness()
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events[0], PipelineEvent::StageStarted(Stage::AnalyzedPil));
+    assert!(events.contains(&PipelineEvent::WitgenProgress {
+        machine: "Main Machine".to_string(),
+        rows: 16
+    }));
+    assert!(matches!(
+        events.last(),
+        Some(PipelineEvent::StageFinished {
+            stage: Stage::GeneratedWitness,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_pipeline_cancellation() {
+    let token = CancellationToken::new();
+    // Cancels the pipeline once witness generation starts.
+    let observer = {
+        let token = token.clone();
+        move |event: &PipelineEvent| {
+            if *event == PipelineEvent::StageStarted(Stage::GeneratedWitness) {
+                token.cancel();
+            }
+        }
+    };
+    let mut pipeline = Pipeline::<GoldilocksField>::default()
+        .from_file(resolve_test_file("pil/fibonacci.pil"))
+        .with_observer(Arc::new(observer))
+        .with_cancellation_token(token);
+    assert_eq!(
+        pipeline.try_advance_to(Stage::GeneratedWitness),
+        Err(PipelineError::Cancelled)
+    );
+}
+
+#[test]
+fn test_unreachable_stage() {
+    let mut pipeline =
+        Pipeline::<GoldilocksField>::default().from_file(resolve_test_file("pil/fibonacci.pil"));
+    assert_eq!(
+        pipeline.advance_to(Stage::AsmString),
+        Err(vec![
+            "Stage asm-string cannot be reached from stage pil-file-path".to_string()
+        ])
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+#[cfg(feature = "halo2")]
+fn test_sum_chained_aggregation() {
+    use powdr_pipeline::test_util::gen_halo2_aggregated_proof;
+
+    let f = "pil/sum_chained.pil";
+    // The public `out` (index 1) of each execution is the public `initial` (index 0) of the next.
+    let chained_publics = [(0, 1)];
+    let inputs = |inputs: [u64; 4]| inputs.map(Bn254Field::from).to_vec();
+    let (first, second) = (inputs([1, 2, 3, 0]), inputs([6, 1, 1, 0]));
+
+    let publics =
+        gen_halo2_aggregated_proof(f, vec![first.clone(), second.clone()], &chained_publics)
+            .unwrap();
+    assert_eq!(
+        publics,
+        vec![Bn254Field::from(1u64), Bn254Field::from(8u64)]
+    );
+
+    // In the other order, the executions are not chained.
+    assert!(gen_halo2_aggregated_proof(f, vec![second, first], &chained_publics).is_err());
+}
+
 #[test]
 fn test_constant_in_identity() {
     let f = "pil/constant_in_identity.pil";
@@ -152,6 +240,13 @@ fn test_sum_via_witness_query() {
     // so we cannot directly use the halo2_proof and estark functions here.
 }
 
+#[test]
+fn test_row_function_hint() {
+    let f = "pil/row_function_hint.pil";
+    verify_pil(f, Default::default());
+    gen_estark_proof(f, Default::default());
+}
+
 #[test]
 fn test_witness_lookup() {
     let f = "pil/witness_lookup.pil";
@@ -26,6 +26,18 @@ fn poseidon_gl_test() {
     gen_estark_proof(f, Default::default());
 }
 
+#[test]
+fn keccakf_test() {
+    let f = "std/keccakf_test.asm";
+    verify_test_file::<GoldilocksField>(f, Default::default(), vec![]);
+}
+
+#[test]
+fn sha256_test() {
+    let f = "std/sha256_test.asm";
+    verify_test_file::<GoldilocksField>(f, Default::default(), vec![]);
+}
+
 #[test]
 fn split_bn254_test() {
     let f = "std/split_bn254_test.asm";
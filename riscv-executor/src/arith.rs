@@ -0,0 +1,132 @@
+use num_bigint::BigUint;
+
+/// The modulus of the base field of the secp256k1 curve.
+fn secp_modulus() -> BigUint {
+    (BigUint::from(1u32) << 256) - (BigUint::from(1u32) << 32) - BigUint::from(977u32)
+}
+
+fn to_biguint(limbs: &[u32; 8]) -> BigUint {
+    BigUint::from_slice(limbs)
+}
+
+fn to_limbs(x: &BigUint) -> [u32; 8] {
+    let digits = x.to_u32_digits();
+    assert!(digits.len() <= 8);
+    std::array::from_fn(|i| digits.get(i).copied().unwrap_or_default())
+}
+
+/// Computes `a * b + c = hi * 2**256 + lo` for 256-bit numbers given as
+/// 32-bit limbs in little-endian order and returns `(hi, lo)`.
+/// It's equivalent to the affine_256 operation of std::arith::Arith from the Powdr standard library.
+pub fn affine_256(a: &[u32; 8], b: &[u32; 8], c: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    let result = to_biguint(a) * to_biguint(b) + to_biguint(c);
+    let lo = &result % (BigUint::from(1u32) << 256);
+    let hi = result >> 256;
+    (to_limbs(&hi), to_limbs(&lo))
+}
+
+/// Adds the points (x1, y1) and (x2, y2) on the secp256k1 curve, where x1 != x2.
+/// It's equivalent to the ec_add operation of std::arith::Arith from the Powdr standard library.
+pub fn ec_add(
+    (x1, y1): (&[u32; 8], &[u32; 8]),
+    (x2, y2): (&[u32; 8], &[u32; 8]),
+) -> ([u32; 8], [u32; 8]) {
+    let p = secp_modulus();
+    let (x1, y1) = (to_biguint(x1), to_biguint(y1));
+    let (x2, y2) = (to_biguint(x2), to_biguint(y2));
+    let s = (&y2 + &p - &y1) * inverse(&(&x2 + &p - &x1), &p) % &p;
+    result_point(&p, &s, &x1, &y1, &x2)
+}
+
+/// Doubles the point (x1, y1) on the secp256k1 curve, where y1 != 0.
+/// It's equivalent to the ec_double operation of std::arith::Arith from the Powdr standard library.
+pub fn ec_double((x1, y1): (&[u32; 8], &[u32; 8])) -> ([u32; 8], [u32; 8]) {
+    let p = secp_modulus();
+    let (x1, y1) = (to_biguint(x1), to_biguint(y1));
+    let s = BigUint::from(3u32) * &x1 * &x1 * inverse(&(BigUint::from(2u32) * &y1), &p) % &p;
+    result_point(&p, &s, &x1, &y1, &x1)
+}
+
+/// Computes the point (x3, y3) of the addition or doubling with slope s.
+fn result_point(
+    p: &BigUint,
+    s: &BigUint,
+    x1: &BigUint,
+    y1: &BigUint,
+    x2: &BigUint,
+) -> ([u32; 8], [u32; 8]) {
+    let x3 = (s * s + BigUint::from(2u32) * p - x1 - x2) % p;
+    let y3 = (s * (x1 + p - &x3) + p - y1) % p;
+    (to_limbs(&x3), to_limbs(&y3))
+}
+
+/// Inverts x modulo the prime p.
+fn inverse(x: &BigUint, p: &BigUint) -> BigUint {
+    let x = x % p;
+    assert!(
+        x != BigUint::default(),
+        "Tried to compute the inverse of zero."
+    );
+    x.modpow(&(p - BigUint::from(2u32)), p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const G: ([u32; 8], [u32; 8]) = (
+        [
+            0x16f81798, 0x59f2815b, 0x2dce28d9, 0x029bfcdb, 0xce870b07, 0x55a06295, 0xf9dcbbac,
+            0x79be667e,
+        ],
+        [
+            0xfb10d4b8, 0x9c47d08f, 0xa6855419, 0xfd17b448, 0x0e1108a8, 0x5da4fbfc, 0x26a3c465,
+            0x483ada77,
+        ],
+    );
+
+    const G2: ([u32; 8], [u32; 8]) = (
+        [
+            0x5c709ee5, 0xabac09b9, 0x8cef3ca7, 0x5c778e4b, 0x95c07cd8, 0x3045406e, 0x41ed7d6d,
+            0xc6047f94,
+        ],
+        [
+            0x50cfe52a, 0x236431a9, 0x3266d0e1, 0xf7f63265, 0x466ceaee, 0xa3c58419, 0xa63dc339,
+            0x1ae168fe,
+        ],
+    );
+
+    #[test]
+    fn test_affine_256() {
+        let max = [0xffffffff; 8];
+        let one = [1, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            affine_256(&max, &one, &max),
+            (
+                one,
+                [
+                    0xfffffffe, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff,
+                    0xffffffff, 0xffffffff
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_ec_ops() {
+        assert_eq!(ec_double((&G.0, &G.1)), G2);
+        assert_eq!(
+            ec_add((&G.0, &G.1), (&G2.0, &G2.1)),
+            (
+                [
+                    0xbce036f9, 0x8601f113, 0x836f99b0, 0xb531c845, 0xf89d5229, 0x49344f85,
+                    0x9258c310, 0xf9308a01
+                ],
+                [
+                    0x84b8e672, 0x6cb9fd75, 0x34c2231b, 0x6500a999, 0x2a37f356, 0x0fe337e6,
+                    0x632de814, 0x388f7b0f
+                ]
+            )
+        );
+    }
+}
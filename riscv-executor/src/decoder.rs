@@ -0,0 +1,464 @@
+//! The statements of the main function, decoded into a compact form that can
+//! be interpreted without looking up instruction, register and label names.
+//!
+//! Every statement is decoded to exactly one [Op], so that the statement
+//! indices used by the [TraceBuilder](crate::builder::TraceBuilder) can be
+//! used to index the decoded program.
+
+use std::collections::HashMap;
+
+use powdr_ast::{
+    asm_analysis::FunctionStatement,
+    parsed::{BinaryOperator, Expression, FunctionCall, UnaryOperator},
+};
+use powdr_number::FieldElement;
+
+use crate::{builder::TraceBuilder, to_u32, Elem, Executor};
+
+/// The maximum number of values returned by an instruction.
+const MAX_OUTPUTS: usize = 4;
+
+/// The maximum number of arguments of an instruction.
+const MAX_ARGS: usize = 12;
+
+/// The instructions known to the executor.
+pub(crate) enum Opcode {
+    Mstore,
+    Mload,
+    LoadBootloaderInput,
+    AssertBootloaderInput,
+    LoadLabel,
+    Jump,
+    JumpToBootloaderInput,
+    BranchIfNonzero,
+    BranchIfZero,
+    SkipIfZero,
+    BranchIfPositive,
+    IsPositive,
+    IsEqualZero,
+    IsNotEqualZero,
+    Wrap,
+    WrapSigned,
+    SignExtendByte,
+    SignExtend16Bits,
+    ToSigned,
+    Fail,
+    Divremu,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    SplitGl,
+    PoseidonGl,
+    SplitBn254,
+    PoseidonBn254,
+    KeccakF,
+    Sha256Compress,
+    Affine256,
+    EcAdd,
+    EcDouble,
+    /// Fails when executed.
+    Unknown(String),
+}
+
+impl Opcode {
+    pub(crate) fn from_name(name: &str) -> Self {
+        match name {
+            "mstore" | "mstore_bootloader" => Opcode::Mstore,
+            "mload" => Opcode::Mload,
+            "load_bootloader_input" => Opcode::LoadBootloaderInput,
+            "assert_bootloader_input" => Opcode::AssertBootloaderInput,
+            "load_label" => Opcode::LoadLabel,
+            "jump" | "jump_dyn" => Opcode::Jump,
+            "jump_to_bootloader_input" => Opcode::JumpToBootloaderInput,
+            "branch_if_nonzero" => Opcode::BranchIfNonzero,
+            "branch_if_zero" => Opcode::BranchIfZero,
+            "skip_if_zero" => Opcode::SkipIfZero,
+            "branch_if_positive" => Opcode::BranchIfPositive,
+            "is_positive" => Opcode::IsPositive,
+            "is_equal_zero" => Opcode::IsEqualZero,
+            "is_not_equal_zero" => Opcode::IsNotEqualZero,
+            "wrap" | "wrap16" => Opcode::Wrap,
+            "wrap_signed" => Opcode::WrapSigned,
+            "sign_extend_byte" => Opcode::SignExtendByte,
+            "sign_extend_16_bits" => Opcode::SignExtend16Bits,
+            "to_signed" => Opcode::ToSigned,
+            "fail" => Opcode::Fail,
+            "divremu" => Opcode::Divremu,
+            "mul" => Opcode::Mul,
+            "and" => Opcode::And,
+            "or" => Opcode::Or,
+            "xor" => Opcode::Xor,
+            "shl" => Opcode::Shl,
+            "shr" => Opcode::Shr,
+            "split_gl" => Opcode::SplitGl,
+            "poseidon_gl" => Opcode::PoseidonGl,
+            "split_bn254" => Opcode::SplitBn254,
+            "poseidon_bn254" => Opcode::PoseidonBn254,
+            "keccakf" => Opcode::KeccakF,
+            "sha256_compress" => Opcode::Sha256Compress,
+            "affine_256" => Opcode::Affine256,
+            "ec_add" => Opcode::EcAdd,
+            "ec_double" => Opcode::EcDouble,
+            name => Opcode::Unknown(name.to_string()),
+        }
+    }
+}
+
+/// The values returned by an instruction, stored inline to avoid allocations.
+#[derive(Clone, Copy)]
+pub(crate) struct Outputs<F: FieldElement> {
+    values: [Elem<F>; MAX_OUTPUTS],
+    len: usize,
+}
+
+impl<F: FieldElement> Outputs<F> {
+    pub(crate) fn none() -> Self {
+        Self {
+            values: Default::default(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[Elem<F>] {
+        &self.values[..self.len]
+    }
+
+    pub(crate) fn to_vec(self) -> Vec<Elem<F>> {
+        self.as_slice().to_vec()
+    }
+}
+
+impl<F: FieldElement, const N: usize> From<[Elem<F>; N]> for Outputs<F> {
+    fn from(values: [Elem<F>; N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<F: FieldElement> FromIterator<Elem<F>> for Outputs<F> {
+    fn from_iter<I: IntoIterator<Item = Elem<F>>>(iter: I) -> Self {
+        let mut outputs = Outputs::none();
+        for value in iter {
+            outputs.values[outputs.len] = value;
+            outputs.len += 1;
+        }
+        outputs
+    }
+}
+
+pub(crate) enum Op<F: FieldElement> {
+    Assignment {
+        /// The indices of the assigned registers.
+        registers: Vec<u16>,
+        rhs: Rhs<F>,
+    },
+    Instruction(Opcode, Vec<Expr<F>>),
+    Return,
+    /// Debug directives.
+    Nop,
+}
+
+pub(crate) enum Rhs<F: FieldElement> {
+    Instruction(Opcode, Vec<Expr<F>>),
+    Expression(Expr<F>),
+}
+
+pub(crate) enum Expr<F: FieldElement> {
+    Register(u16),
+    Constant(Elem<F>),
+    Binary(Box<Expr<F>>, BinaryOp, Box<Expr<F>>),
+    Neg(Box<Expr<F>>),
+    Instruction(Opcode, Vec<Expr<F>>),
+    FreeInput(Vec<QueryPart<F>>),
+    /// Fails with the given message when evaluated.
+    Unsupported(String),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+pub(crate) enum QueryPart<F: FieldElement> {
+    String(String),
+    Value(Expr<F>),
+}
+
+/// Decodes the statements of the main function.
+pub(crate) fn decode<T: FieldElement>(
+    statements: &[&FunctionStatement<T>],
+    label_map: &HashMap<&str, Elem<T>>,
+    proc: &TraceBuilder<T>,
+) -> Vec<Op<T>> {
+    let decoder = Decoder { label_map, proc };
+    statements
+        .iter()
+        .map(|statement| decoder.decode_statement(statement))
+        .collect()
+}
+
+struct Decoder<'a, 'b, T: FieldElement> {
+    label_map: &'a HashMap<&'a str, Elem<T>>,
+    proc: &'a TraceBuilder<'b, T>,
+}
+
+impl<'a, 'b, T: FieldElement> Decoder<'a, 'b, T> {
+    fn decode_statement(&self, statement: &FunctionStatement<T>) -> Op<T> {
+        match statement {
+            FunctionStatement::Assignment(a) => Op::Assignment {
+                registers: a
+                    .lhs_with_reg
+                    .iter()
+                    .map(|(dest, _)| self.register(dest))
+                    .collect(),
+                rhs: match self.decode_expression(&a.rhs) {
+                    Expr::Instruction(opcode, args) => Rhs::Instruction(opcode, args),
+                    e => Rhs::Expression(e),
+                },
+            },
+            FunctionStatement::Instruction(i) => Op::Instruction(
+                Opcode::from_name(&i.instruction),
+                self.decode_arguments(&i.inputs),
+            ),
+            FunctionStatement::Return(_) => Op::Return,
+            FunctionStatement::DebugDirective(_) => Op::Nop,
+            FunctionStatement::Label(_) => unreachable!(),
+        }
+    }
+
+    fn register(&self, name: &str) -> u16 {
+        self.proc
+            .reg_idx(name)
+            .unwrap_or_else(|| panic!("unknown register: {name}"))
+    }
+
+    fn decode_arguments(&self, args: &[Expression<T>]) -> Vec<Expr<T>> {
+        assert!(args.len() <= MAX_ARGS);
+        args.iter().map(|a| self.decode_expression(a)).collect()
+    }
+
+    fn decode_expression(&self, expression: &Expression<T>) -> Expr<T> {
+        match expression {
+            Expression::Reference(r) => {
+                let name = r.try_to_identifier().unwrap();
+                // labels share the identifier space with registers:
+                // try one, then the other
+                match self.label_map.get(name.as_str()) {
+                    Some(pc) => Expr::Constant(*pc),
+                    None => match self.proc.reg_idx(name) {
+                        Some(idx) => Expr::Register(idx),
+                        None => Expr::Unsupported(format!("unknown register: {name}")),
+                    },
+                }
+            }
+            Expression::Number(n) => match to_u32(n) {
+                Some(unsigned) => Expr::Constant(unsigned.into()),
+                None => Expr::Unsupported("Value does not fit in 32 bits.".to_string()),
+            },
+            Expression::BinaryOperation(l, op, r) => {
+                let op = match op {
+                    BinaryOperator::Add => BinaryOp::Add,
+                    BinaryOperator::Sub => BinaryOp::Sub,
+                    BinaryOperator::Mul => BinaryOp::Mul,
+                    BinaryOperator::Div => BinaryOp::Div,
+                    BinaryOperator::Mod => BinaryOp::Mod,
+                    BinaryOperator::Pow => BinaryOp::Pow,
+                    op => return Expr::Unsupported(format!("operator not supported: {op}")),
+                };
+                Expr::Binary(
+                    Box::new(self.decode_expression(l)),
+                    op,
+                    Box::new(self.decode_expression(r)),
+                )
+            }
+            Expression::UnaryOperation(UnaryOperator::Minus, arg) => {
+                Expr::Neg(Box::new(self.decode_expression(arg)))
+            }
+            Expression::FunctionCall(FunctionCall {
+                function,
+                arguments,
+            }) => match function.as_ref() {
+                Expression::Reference(f) => Expr::Instruction(
+                    Opcode::from_name(f.try_to_identifier().unwrap()),
+                    self.decode_arguments(arguments),
+                ),
+                _ => Expr::Unsupported(format!("not an instruction: {function}")),
+            },
+            Expression::FreeInput(expr) => match expr.as_ref() {
+                Expression::Tuple(t) => Expr::FreeInput(
+                    t.iter()
+                        .map(|e| match e {
+                            Expression::String(_) => QueryPart::String(e.to_string()),
+                            e => QueryPart::Value(self.decode_expression(e)),
+                        })
+                        .collect(),
+                ),
+                _ => Expr::Unsupported("does not match IO pattern".to_string()),
+            },
+            e => Expr::Unsupported(format!("expression not supported: {e}")),
+        }
+    }
+}
+
+impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
+    /// Runs the decoded program until it returns or the maximum number of rows
+    /// is reached.
+    pub(crate) fn run_decoded(&mut self, program: &[Op<F>]) {
+        let mut curr_pc = 0u32;
+        loop {
+            match &program[curr_pc as usize] {
+                Op::Assignment { registers, rhs } => {
+                    let results = match rhs {
+                        Rhs::Instruction(opcode, args) => self.exec_decoded(opcode, args),
+                        Rhs::Expression(e) => [self.eval_decoded(e)].into(),
+                    };
+                    assert_eq!(registers.len(), results.len);
+                    for (dest, val) in registers.iter().zip(results.as_slice()) {
+                        self.proc.set_reg_by_idx(*dest, *val);
+                    }
+                }
+                Op::Instruction(opcode, args) => {
+                    self.exec_decoded(opcode, args);
+                }
+                Op::Return => break,
+                Op::Nop => {}
+            }
+
+            curr_pc = match self.proc.advance() {
+                Some(pc) => pc,
+                None => break,
+            };
+        }
+    }
+
+    fn exec_decoded(&mut self, opcode: &Opcode, args: &[Expr<F>]) -> Outputs<F> {
+        let mut values = [Elem::default(); MAX_ARGS];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = self.eval_decoded(arg);
+        }
+        self.exec_opcode(opcode, &values[..args.len()])
+    }
+
+    fn eval_decoded(&mut self, expression: &Expr<F>) -> Elem<F> {
+        match expression {
+            Expr::Register(idx) => self.proc.get_reg_idx(*idx),
+            Expr::Constant(value) => *value,
+            Expr::Binary(l, op, r) => {
+                let l = self.eval_decoded(l);
+                let r = self.eval_decoded(r);
+                match (op, l, r) {
+                    (BinaryOp::Add, l, r) => l.add(&r),
+                    (BinaryOp::Sub, l, r) => l.sub(&r),
+                    (BinaryOp::Mul, Elem::Binary(l), Elem::Binary(r)) => {
+                        // The multiplication is done in the field, but the result
+                        // is the same as the integer product if it is small enough.
+                        let product = l as i128 * r as i128;
+                        match i64::try_from(product) {
+                            Ok(product) if product.unsigned_abs() as u128 <= self.half_modulus => {
+                                product.into()
+                            }
+                            _ => Elem::from_fe(F::from(l) * F::from(r)),
+                        }
+                    }
+                    (BinaryOp::Mul, l, r) => Elem::from_fe(l.fe() * r.fe()),
+                    (BinaryOp::Div, l, r) => (l.bin() / r.bin()).into(),
+                    (BinaryOp::Mod, l, r) => (l.bin() % r.bin()).into(),
+                    (BinaryOp::Pow, l, r) => l.pow(r.u()),
+                }
+            }
+            Expr::Neg(arg) => self.eval_decoded(arg).neg(),
+            Expr::Instruction(opcode, args) => self.exec_decoded(opcode, args).values[0],
+            Expr::FreeInput(parts) => {
+                let parts = parts
+                    .iter()
+                    .map(|part| match part {
+                        QueryPart::String(s) => s.clone(),
+                        QueryPart::Value(e) => self.eval_decoded(e).to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                let query = format!("({})", parts.join(","));
+                match (self.inputs)(&query).unwrap() {
+                    Some(val) => Elem::from_fe(val),
+                    None => {
+                        panic!("unknown query command: {query}");
+                    }
+                }
+            }
+            Expr::Unsupported(message) => panic!("{message}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+
+    use crate::{analyze, execute_ast, execute_ast_reference, ExecMode};
+
+    const PROGRAM: &str = r#"
+machine Main {
+    degree 256;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg Z[<=];
+    reg x0;
+    reg x1;
+    reg tmp1;
+    reg A;
+    reg B;
+    reg C;
+    reg CNT;
+
+    instr jump l: label -> Y { pc' = l, Y = pc + 1 }
+    instr branch_if_nonzero X, l: label { pc' = l }
+    instr wrap Y -> X { Y = X }
+    instr mstore Y, Z { Y = Z }
+    instr mload Y -> X, Z { Y = X + Z }
+
+    function main {
+        CNT <=X= ${ ("input", 0) };
+        A <=X= 1;
+        B <=X= 0;
+    loop:
+        A <== wrap(A * 3 + 1);
+        // The product does not fit into 64 bits.
+        C <=X= A * A * A * A;
+        mstore B * 4, A;
+        B <=X= B + 1;
+        C, tmp1 <== mload(B * 4 - 3);
+        x0 <=X= C;
+        CNT <=X= CNT - 1;
+        branch_if_nonzero CNT, loop;
+        x1 <== jump(end);
+        A <=X= 0;
+    end:
+        return;
+    }
+}
+"#;
+
+    #[test]
+    fn same_trace_as_reference() {
+        let iterations = 100;
+        let analyzed = analyze::<GoldilocksField>(PROGRAM);
+        let inputs = |_: &str| Ok(Some(iterations.into()));
+        for mode in [ExecMode::Fast, ExecMode::Trace] {
+            let reference = execute_ast_reference(&analyzed, &inputs, &[], usize::MAX, mode);
+            let decoded = execute_ast(&analyzed, &inputs, &[], usize::MAX, mode);
+            assert_eq!(decoded, reference);
+        }
+        // Stop early.
+        let reference = execute_ast_reference(&analyzed, &inputs, &[], 50, ExecMode::Trace);
+        let decoded = execute_ast(&analyzed, &inputs, &[], 50, ExecMode::Trace);
+        assert_eq!(decoded, reference);
+    }
+}
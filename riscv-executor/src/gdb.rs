@@ -0,0 +1,564 @@
+//! A stub for the GDB remote serial protocol, so that guest programs can be
+//! debugged with `riscv32-gdb` (or `gdb-multiarch`) while they run in the executor.
+//!
+//! The program counter exposed to GDB is the PC of the powdr machine, i.e. the
+//! index of the instruction batch in the ROM, and not the address of the RISC-V
+//! instruction in the original ELF file: Guest programs are usually translated
+//! from assembly, which has no addresses, so GDB's ELF symbols and line tables
+//! cannot be used. Execution stops at the start of every batch, so breakpoints
+//! are set on batch indices: `monitor label <name>` returns the PC of a label,
+//! which can then be used with `break *<pc>`. `monitor where` shows the current
+//! PC, its labels and the source location of the last `.loc` directive.
+//!
+//! Registers `x1` to `x31` and memory can be written, but the PC cannot. Note that
+//! after a write, the execution trace no longer belongs to the program, so it
+//! cannot be proven.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Access to the state of the executor.
+pub(crate) trait DebugTarget {
+    /// The PC of the batch that is about to be executed.
+    fn pc(&self) -> u32;
+    /// The value of a register of the powdr machine.
+    fn register(&self, name: &str) -> Option<u32>;
+    /// The memory word at the given (aligned) address.
+    fn memory_word(&self, address: u32) -> u32;
+    /// Sets a register of the powdr machine. Returns false if there is no such register.
+    fn set_register(&mut self, name: &str, value: u32) -> bool;
+    /// Sets the memory word at the given (aligned) address.
+    fn set_memory_word(&mut self, address: u32, value: u32);
+    /// The PC of a label.
+    fn label(&self, name: &str) -> Option<u32>;
+    /// All labels pointing to the given PC.
+    fn labels_at(&self, pc: u32) -> Vec<&str>;
+    /// The source location of the last executed `.loc` directive.
+    fn location(&self) -> Option<String>;
+}
+
+/// What the executor should do after a call to the debugger.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Resume {
+    Run,
+    Kill,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum State {
+    /// Stop at the next batch.
+    Stepping,
+    /// Stop at the next breakpoint or when the user interrupts.
+    Continuing,
+    /// The debugger detached, run to the end.
+    Detached,
+}
+
+enum Packet {
+    Command(String),
+    /// The user pressed Ctrl-C.
+    Interrupt,
+}
+
+/// Number of batches executed between two checks for an interrupt from the debugger.
+const INTERRUPT_CHECK_INTERVAL: u32 = 1 << 14;
+
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    breakpoints: BTreeSet<u32>,
+    state: State,
+    /// Whether GDB already knows that we are stopped and thus expects no stop reply.
+    initial_stop: bool,
+    batches_since_interrupt_check: u32,
+}
+
+impl GdbStub {
+    /// Listens on the given address (e.g. `127.0.0.1:1234`) and waits for GDB to connect.
+    pub fn listen(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        log::info!(
+            "Waiting for GDB to connect to {}...",
+            listener.local_addr()?
+        );
+        let (stream, peer) = listener.accept()?;
+        log::info!("GDB connected from {peer}.");
+        Self::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            breakpoints: Default::default(),
+            state: State::Stepping,
+            initial_stop: true,
+            batches_since_interrupt_check: 0,
+        })
+    }
+
+    /// Called at the start of every batch. Blocks while the program is stopped.
+    pub(crate) fn on_batch(&mut self, target: &mut impl DebugTarget) -> io::Result<Resume> {
+        let stop = match self.state {
+            State::Detached => false,
+            State::Stepping => true,
+            State::Continuing => {
+                self.breakpoints.contains(&target.pc()) || self.check_interrupt()?
+            }
+        };
+        if !stop {
+            return Ok(Resume::Run);
+        }
+        if !std::mem::take(&mut self.initial_stop) {
+            self.send("S05")?;
+        }
+        self.serve(target)
+    }
+
+    /// Called when the program terminated.
+    pub(crate) fn on_exit(&mut self) -> io::Result<()> {
+        if self.state != State::Detached {
+            self.send("W00")?;
+        }
+        Ok(())
+    }
+
+    fn check_interrupt(&mut self) -> io::Result<bool> {
+        self.batches_since_interrupt_check += 1;
+        if self.batches_since_interrupt_check < INTERRUPT_CHECK_INTERVAL {
+            return Ok(false);
+        }
+        self.batches_since_interrupt_check = 0;
+        if self.reader.buffer().is_empty() {
+            self.writer.set_nonblocking(true)?;
+            let result = self.reader.fill_buf().map(|_| ());
+            self.writer.set_nonblocking(false)?;
+            match result {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        if self.reader.buffer().first() == Some(&0x03) {
+            self.reader.consume(1);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Processes commands until the program should continue.
+    fn serve(&mut self, target: &mut impl DebugTarget) -> io::Result<Resume> {
+        loop {
+            let command = match self.read_packet()? {
+                // The connection was closed.
+                None => {
+                    self.state = State::Detached;
+                    return Ok(Resume::Run);
+                }
+                Some(Packet::Interrupt) => {
+                    self.send("S05")?;
+                    continue;
+                }
+                Some(Packet::Command(command)) => command,
+            };
+            log::trace!("GDB command: {command}");
+            let (kind, args) = command.split_at(command.len().min(1));
+            let response = match kind {
+                "?" => "S05".to_string(),
+                "g" => (0..33).map(|i| register_hex(target, i).unwrap()).collect(),
+                "G" => write_registers(target, args),
+                "p" => u32::from_str_radix(args, 16)
+                    .ok()
+                    .and_then(|i| register_hex(target, i))
+                    .unwrap_or_else(|| "E01".to_string()),
+                "P" => args
+                    .split_once('=')
+                    .and_then(|(index, value)| {
+                        let index = u32::from_str_radix(index, 16).ok()?;
+                        write_register(target, index, parse_le_word(value)?).then_some(())
+                    })
+                    .map_or_else(|| "E01".to_string(), |()| "OK".to_string()),
+                "m" => parse_memory_range(args)
+                    .map(|(address, length)| {
+                        (address..address.wrapping_add(length))
+                            .map(|a| {
+                                let word = target.memory_word(a & !3);
+                                format!("{:02x}", (word >> (8 * (a % 4))) & 0xff)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_else(|| "E01".to_string()),
+                "M" => args
+                    .split_once(':')
+                    .and_then(|(range, data)| {
+                        let (address, length) = parse_memory_range(range)?;
+                        let bytes = unhex_bytes(data)?;
+                        (bytes.len() == length as usize).then_some((address, bytes))
+                    })
+                    .map_or_else(
+                        || "E01".to_string(),
+                        |(address, bytes)| {
+                            write_memory(target, address, &bytes);
+                            "OK".to_string()
+                        },
+                    ),
+                "Z" | "z" => match parse_breakpoint(args) {
+                    Some(pc) => {
+                        if kind == "Z" {
+                            self.breakpoints.insert(pc);
+                        } else {
+                            self.breakpoints.remove(&pc);
+                        }
+                        "OK".to_string()
+                    }
+                    None => String::new(),
+                },
+                "c" => {
+                    self.state = State::Continuing;
+                    return Ok(Resume::Run);
+                }
+                "s" => {
+                    self.state = State::Stepping;
+                    return Ok(Resume::Run);
+                }
+                "D" => {
+                    self.send("OK")?;
+                    self.state = State::Detached;
+                    return Ok(Resume::Run);
+                }
+                "k" => return Ok(Resume::Kill),
+                "H" | "T" => "OK".to_string(),
+                "q" => query(target, args),
+                _ => String::new(),
+            };
+            self.send(&response)?;
+        }
+    }
+
+    /// Reads the next packet and acknowledges it.
+    /// Returns `None` if the connection was closed.
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut byte = [0u8];
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                b'$' => break,
+                0x03 => return Ok(Some(Packet::Interrupt)),
+                // Acknowledgements and anything outside of packets.
+                _ => continue,
+            }
+        }
+        let mut data = vec![];
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0u8; 2];
+        self.reader.read_exact(&mut checksum)?;
+        self.writer.write_all(b"+")?;
+        Ok(Some(Packet::Command(
+            String::from_utf8_lossy(&data).into_owned(),
+        )))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${data}#{checksum:02x}")?;
+        self.writer.flush()?;
+        // Wait for the acknowledgement.
+        let mut byte = [0u8];
+        self.reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'+' => Ok(()),
+            b'-' => self.send(data),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected acknowledgement from GDB, got {other:#x}."),
+            )),
+        }
+    }
+}
+
+/// Returns the register with the given GDB number as little-endian hex:
+/// `x0` to `x31`, followed by the PC.
+fn register_hex(target: &impl DebugTarget, index: u32) -> Option<String> {
+    let value = match index {
+        0..=31 => target.register(&format!("x{index}")).unwrap_or_default(),
+        32 => target.pc(),
+        _ => return None,
+    };
+    Some(hex(&value.to_le_bytes()))
+}
+
+/// Writes the register with the given GDB number, see [register_hex].
+/// The PC can only be "written" with its current value.
+fn write_register(target: &mut impl DebugTarget, index: u32, value: u32) -> bool {
+    match index {
+        // x0 is hard-wired to zero.
+        0 => value == 0,
+        1..=31 => target.set_register(&format!("x{index}"), value),
+        32 => value == target.pc(),
+        _ => false,
+    }
+}
+
+/// Writes all registers in the format of the `g` packet.
+fn write_registers(target: &mut impl DebugTarget, data: &str) -> String {
+    let values = (0..33)
+        .map(|i| parse_le_word(data.get(8 * i..8 * (i + 1))?))
+        .collect::<Option<Vec<_>>>();
+    match values {
+        Some(values) if data.len() == 33 * 8 && values[32] == target.pc() => {
+            for (index, value) in values.into_iter().enumerate().take(32).skip(1) {
+                target.set_register(&format!("x{index}"), value);
+            }
+            "OK".to_string()
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+fn write_memory(target: &mut impl DebugTarget, address: u32, bytes: &[u8]) {
+    for (a, byte) in (address..).zip(bytes) {
+        let shift = 8 * (a % 4);
+        let word = target.memory_word(a & !3);
+        let word = (word & !(0xff << shift)) | ((*byte as u32) << shift);
+        target.set_memory_word(a & !3, word);
+    }
+}
+
+fn query(target: &impl DebugTarget, query: &str) -> String {
+    let (name, args) = query.split_once([':', ',']).unwrap_or((query, ""));
+    match name {
+        "Supported" => "PacketSize=4000".to_string(),
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        "Rcmd" => {
+            let output = unhex(args)
+                .map(|command| monitor_command(target, &command))
+                .unwrap_or_else(|| "Invalid command.\n".to_string());
+            // The output is sent as hex-encoded string.
+            hex(output.as_bytes())
+        }
+        _ => String::new(),
+    }
+}
+
+fn monitor_command(target: &impl DebugTarget, command: &str) -> String {
+    let mut parts = command.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("where"), None) => {
+            let pc = target.pc();
+            let labels = target.labels_at(pc);
+            let labels = if labels.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", labels.join(", "))
+            };
+            let location = target
+                .location()
+                .map(|l| format!(" at {l}"))
+                .unwrap_or_default();
+            format!("pc {pc}{labels}{location}\n")
+        }
+        (Some("label"), Some(name)) => match target.label(name) {
+            Some(pc) => format!("{pc}\n"),
+            None => format!("Unknown label: {name}\n"),
+        },
+        _ => "Supported commands: where, label <name>\n".to_string(),
+    }
+}
+
+fn parse_memory_range(args: &str) -> Option<(u32, u32)> {
+    let (address, length) = args.split_once(',')?;
+    let length = u32::from_str_radix(length, 16).ok()?;
+    Some((u32::from_str_radix(address, 16).ok()?, length.min(0x1000)))
+}
+
+/// Parses software and hardware breakpoints (`<type>,<address>,<kind>`).
+fn parse_breakpoint(args: &str) -> Option<u32> {
+    let mut parts = args.split(',');
+    match parts.next()? {
+        "0" | "1" => u32::from_str_radix(parts.next()?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex_bytes(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn unhex(s: &str) -> Option<String> {
+    String::from_utf8(unhex_bytes(s)?).ok()
+}
+
+/// Parses a 32-bit value in the little-endian hex format of register packets.
+fn parse_le_word(s: &str) -> Option<u32> {
+    let bytes: [u8; 4] = unhex_bytes(s)?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::thread;
+
+    use super::*;
+
+    struct TestTarget {
+        pc: u32,
+        registers: HashMap<String, u32>,
+        memory: HashMap<u32, u32>,
+    }
+
+    impl DebugTarget for TestTarget {
+        fn pc(&self) -> u32 {
+            self.pc
+        }
+        fn register(&self, name: &str) -> Option<u32> {
+            self.registers.get(name).cloned()
+        }
+        fn memory_word(&self, address: u32) -> u32 {
+            self.memory.get(&address).cloned().unwrap_or_default()
+        }
+        fn set_register(&mut self, name: &str, value: u32) -> bool {
+            self.registers.insert(name.to_string(), value).is_some()
+        }
+        fn set_memory_word(&mut self, address: u32, value: u32) {
+            self.memory.insert(address, value);
+        }
+        fn label(&self, name: &str) -> Option<u32> {
+            (name == "loop").then_some(5)
+        }
+        fn labels_at(&self, pc: u32) -> Vec<&str> {
+            if pc == 5 {
+                vec!["loop"]
+            } else {
+                vec![]
+            }
+        }
+        fn location(&self) -> Option<String> {
+            Some("main.rs:3:5".to_string())
+        }
+    }
+
+    /// A minimal GDB client.
+    struct Client(BufReader<TcpStream>);
+
+    impl Client {
+        fn send(&mut self, command: &str) {
+            let checksum = command.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            write!(self.0.get_mut(), "${command}#{checksum:02x}").unwrap();
+            self.expect(b'+');
+        }
+
+        fn expect(&mut self, expected: u8) {
+            let mut byte = [0u8];
+            self.0.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], expected);
+        }
+
+        fn receive(&mut self) -> String {
+            self.expect(b'$');
+            let mut data = vec![];
+            let mut byte = [0u8];
+            loop {
+                self.0.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0u8; 2];
+            self.0.read_exact(&mut checksum).unwrap();
+            self.0.get_mut().write_all(b"+").unwrap();
+            String::from_utf8(data).unwrap()
+        }
+
+        fn request(&mut self, command: &str) -> String {
+            self.send(command);
+            self.receive()
+        }
+    }
+
+    #[test]
+    fn breakpoints_and_inspection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut client = Client(BufReader::new(TcpStream::connect(address).unwrap()));
+            assert_eq!(client.request("?"), "S05");
+            assert_eq!(client.request("p20"), "02000000");
+            let registers = client.request("g");
+            assert_eq!(registers.len(), 33 * 8);
+            assert_eq!(&registers[16..24], "00010000");
+            assert_eq!(client.request("m5,3"), "bbccdd");
+            assert_eq!(client.request("P3=78563412"), "OK");
+            assert_eq!(client.request("p3"), "78563412");
+            // The PC cannot be changed.
+            assert_eq!(client.request("P20=03000000"), "E01");
+            assert_eq!(client.request("M6,3:112233"), "OK");
+            assert_eq!(client.request("m4,5"), "aabb112233");
+            assert_eq!(
+                client.request(&format!("qRcmd,{}", hex(b"label loop"))),
+                hex(b"5\n")
+            );
+            assert_eq!(client.request("Z0,5,4"), "OK");
+            client.send("c");
+            // Stopped at the breakpoint.
+            assert_eq!(client.receive(), "S05");
+            assert_eq!(
+                client.request(&format!("qRcmd,{}", hex(b"where"))),
+                hex(b"pc 5 (loop) at main.rs:3:5\n")
+            );
+            assert_eq!(client.request("z0,5,4"), "OK");
+            client.send("s");
+            assert_eq!(client.receive(), "S05");
+            client.send("c");
+            assert_eq!(client.receive(), "W00");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        let mut target = TestTarget {
+            pc: 2,
+            registers: (1..32).map(|i| (format!("x{i}"), 0)).collect(),
+            memory: [(4, 0xddccbbaa)].into_iter().collect(),
+        };
+        target.registers.insert("x2".to_string(), 0x100);
+        let mut stops = vec![];
+        for pc in 2..10 {
+            target.pc = pc;
+            let stopped = stub.state != State::Continuing || stub.breakpoints.contains(&pc);
+            assert_eq!(stub.on_batch(&mut target).unwrap(), Resume::Run);
+            if stopped {
+                stops.push(pc);
+            }
+        }
+        stub.on_exit().unwrap();
+        client.join().unwrap();
+        assert_eq!(stops, vec![2, 5, 6]);
+        assert_eq!(target.registers["x3"], 0x12345678);
+        assert_eq!(target.memory[&4], 0x2211bbaa);
+        assert_eq!(target.memory[&8], 0x33);
+    }
+}
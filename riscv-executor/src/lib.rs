@@ -9,18 +9,32 @@
 //! TODO: perform determinism verification for each instruction independently
 //! from execution.
 
-use std::{collections::HashMap, io};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, io,
+};
 
 use builder::TraceBuilder;
+use decoder::{Opcode, Outputs};
+use gdb::{DebugTarget, GdbStub, Resume};
 use powdr_ast::{
     asm_analysis::{
         AnalysisASMFile, CallableSymbol, FunctionStatement, Item, LabelStatement, Machine,
     },
-    parsed::{asm::DebugDirective, Expression, FunctionCall},
+    parsed::{asm::DebugDirective, visitor::ExpressionVisitable, Expression, FunctionCall},
 };
-use powdr_number::{BigInt, FieldElement, GoldilocksField};
-
+use powdr_number::{BigInt, FieldElement};
+use profiler::{Profile, Profiler};
+
+pub mod arith;
+mod decoder;
+pub mod gdb;
+pub mod keccakf;
+pub mod poseidon_bn254;
 pub mod poseidon_gl;
+pub mod profiler;
+pub mod sha256;
+pub mod witness;
 
 /// Initial value of the PC.
 ///
@@ -30,74 +44,159 @@ pub mod poseidon_gl;
 /// TODO: get this value from some authoritative place
 const PC_INITIAL_VAL: usize = 2;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Elem(pub i64);
+/// The value of a register: Either an integer, which is enough for all values of
+/// the RISC-V machine itself, or a field element that does not fit into an `i64`.
+/// The latter only happens for fields with a modulus larger than 64 bits, for
+/// example for hash values on the BN254 field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Elem<F: FieldElement> {
+    Binary(i64),
+    Field(F),
+}
+
+impl<F: FieldElement> Default for Elem<F> {
+    fn default() -> Self {
+        Self::Binary(0)
+    }
+}
+
+impl<F: FieldElement> Elem<F> {
+    /// Returns the integer value, panics for field elements that do not fit into an `i64`.
+    fn bin(&self) -> i64 {
+        match self {
+            Self::Binary(v) => *v,
+            Self::Field(v) => panic!("Value does not fit in 64 bits: {v}"),
+        }
+    }
 
-impl Elem {
     fn u(&self) -> u32 {
-        self.0.try_into().unwrap()
+        self.bin().try_into().unwrap()
     }
 
     fn s(&self) -> i32 {
-        self.0.try_into().unwrap()
+        self.bin().try_into().unwrap()
+    }
+
+    /// Returns the value as a field element.
+    pub fn into_fe(self) -> F {
+        match self {
+            Self::Binary(v) => F::from(v),
+            Self::Field(v) => v,
+        }
     }
 
-    fn fe<F: FieldElement>(&self) -> F {
-        F::from(self.0)
+    fn fe(&self) -> F {
+        self.into_fe()
     }
 
     // Rust doesn't allow us to implement From<F> for Elem...
-    // Panics if the value can't be represented as i64, i.e., the value interpreted
-    // as a signed integer (-(p - v) if v > p/2) is not in the range of i64.
-    // This can happen for some fields with a modulus larger than 64 bit.
-    fn from_fe<F: FieldElement>(value: F) -> Self {
-        if value.is_in_lower_half() {
-            (i64::try_from(value.to_arbitrary_integer()).unwrap()).into()
+    // The value is interpreted as a signed integer (-(p - v) if v > p/2). If it is not in
+    // the range of i64, which can happen for some fields with a modulus larger than 64 bit,
+    // it is kept as a field element.
+    fn from_fe(value: F) -> Self {
+        let int = if value.is_in_lower_half() {
+            i64::try_from(value.to_arbitrary_integer()).ok()
         } else {
-            (-(i64::try_from((-value).to_arbitrary_integer()).unwrap())).into()
+            i64::try_from((-value).to_arbitrary_integer())
+                .ok()
+                .map(|v| -v)
+        };
+        int.map_or(Self::Field(value), Self::Binary)
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Self::Binary(v) => *v == 0,
+            Self::Field(v) => v.is_zero(),
+        }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Binary(l), Self::Binary(r)) => match l.checked_add(*r) {
+                Some(v) => Self::Binary(v),
+                None => Self::from_fe(self.fe() + other.fe()),
+            },
+            _ => Self::from_fe(self.fe() + other.fe()),
+        }
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Binary(l), Self::Binary(r)) => match l.checked_sub(*r) {
+                Some(v) => Self::Binary(v),
+                None => Self::from_fe(self.fe() - other.fe()),
+            },
+            _ => Self::from_fe(self.fe() - other.fe()),
+        }
+    }
+
+    fn neg(&self) -> Self {
+        match self {
+            Self::Binary(v) => Self::Binary(-v),
+            Self::Field(v) => Self::from_fe(-*v),
+        }
+    }
+
+    fn pow(&self, exponent: u32) -> Self {
+        match self {
+            Self::Binary(v) => match v.checked_pow(exponent) {
+                Some(v) => Self::Binary(v),
+                None => Self::from_fe(self.fe().pow((exponent as u64).into())),
+            },
+            Self::Field(v) => Self::from_fe(v.pow((exponent as u64).into())),
+        }
+    }
+}
+
+impl<F: FieldElement> fmt::Display for Elem<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Binary(v) => write!(f, "{v}"),
+            Self::Field(v) => write!(f, "{v}"),
         }
     }
 }
 
-impl From<u32> for Elem {
+impl<F: FieldElement> From<u32> for Elem<F> {
     fn from(value: u32) -> Self {
-        Self(value as i64)
+        Self::Binary(value as i64)
     }
 }
 
-impl From<u64> for Elem {
+impl<F: FieldElement> From<u64> for Elem<F> {
     fn from(value: u64) -> Self {
-        Self(value as i64)
+        Self::Binary(value as i64)
     }
 }
 
-impl From<i32> for Elem {
+impl<F: FieldElement> From<i32> for Elem<F> {
     fn from(value: i32) -> Self {
-        Self(value as i64)
+        Self::Binary(value as i64)
     }
 }
 
-impl From<i64> for Elem {
+impl<F: FieldElement> From<i64> for Elem<F> {
     fn from(value: i64) -> Self {
-        Self(value)
+        Self::Binary(value)
     }
 }
 
-impl From<usize> for Elem {
+impl<F: FieldElement> From<usize> for Elem<F> {
     fn from(value: usize) -> Self {
-        Self(value as i64)
+        Self::Binary(value as i64)
     }
 }
 
 pub type MemoryState = HashMap<u32, u32>;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum MemOperationKind {
     Read,
     Write,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct MemOperation {
     /// The row of the execution trace the memory operation happened.
     pub row: usize,
@@ -105,24 +204,26 @@ pub struct MemOperation {
     pub address: u32,
 }
 
-pub struct RegWrite {
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegWrite<F: FieldElement> {
     /// The row of the execution trace this write will result into. Multiple
     /// writes at the same row are valid: the last write to a given reg_idx will
     /// define the final value of the register in that row.
     row: usize,
     /// Index of the register in the register bank.
     reg_idx: u16,
-    val: Elem,
+    val: Elem<F>,
 }
 
-pub struct ExecutionTrace {
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExecutionTrace<F: FieldElement> {
     pub reg_map: HashMap<String, u16>,
 
     /// Values of the registers in the execution trace.
     ///
     /// Each N elements is a row with all registers, where N is the number of
     /// registers.
-    pub reg_writes: Vec<RegWrite>,
+    pub reg_writes: Vec<RegWrite<F>>,
 
     /// Writes and reads to memory.
     pub mem_ops: Vec<MemOperation>,
@@ -131,9 +232,9 @@ pub struct ExecutionTrace {
     pub len: usize,
 }
 
-impl ExecutionTrace {
+impl<F: FieldElement> ExecutionTrace<F> {
     /// Replay the execution and get the register values per trace row.
-    pub fn replay(&self) -> TraceReplay {
+    pub fn replay(&self) -> TraceReplay<F> {
         TraceReplay {
             trace: self,
             regs: vec![0.into(); self.reg_map.len()],
@@ -144,26 +245,42 @@ impl ExecutionTrace {
     }
 }
 
-pub struct TraceReplay<'a> {
-    trace: &'a ExecutionTrace,
-    regs: Vec<Elem>,
+pub struct TraceReplay<'a, F: FieldElement> {
+    trace: &'a ExecutionTrace<F>,
+    regs: Vec<Elem<F>>,
     pc_idx: usize,
     next_write: usize,
     next_r: usize,
 }
 
-impl<'a> TraceReplay<'a> {
+impl<'a, F: FieldElement> TraceReplay<'a, F> {
     /// Returns the next row's registers value.
     ///
     /// Just like an iterator's next(), but returns the value borrowed from self.
-    pub fn next_row(&mut self) -> Option<&[Elem]> {
+    pub fn next_row(&mut self) -> Option<&[Elem<F>]> {
         if self.next_r == self.trace.len {
             return None;
         }
 
+        self.apply_writes_of_next_row();
+        Some(&self.regs[..])
+    }
+
+    /// Returns the register values after the last row, i.e., including the writes of
+    /// the instructions executed in the last row, which are not part of the trace.
+    ///
+    /// Note that if the execution stopped because of a `return`, the PC is just
+    /// incremented.
+    pub fn final_row(mut self) -> Vec<Elem<F>> {
+        while self.next_row().is_some() {}
+        self.apply_writes_of_next_row();
+        self.regs
+    }
+
+    fn apply_writes_of_next_row(&mut self) {
         // we optimistically increment the PC, if it is a jump or special case,
         // one of the writes will overwrite it
-        self.regs[self.pc_idx].0 += 1;
+        self.regs[self.pc_idx] = (self.regs[self.pc_idx].bin() + 1).into();
 
         while let Some(next_write) = self.trace.reg_writes.get(self.next_write) {
             if next_write.row > self.next_r {
@@ -175,12 +292,15 @@ impl<'a> TraceReplay<'a> {
         }
 
         self.next_r += 1;
-        Some(&self.regs[..])
     }
 }
 
 mod builder {
-    use std::{cmp, collections::HashMap};
+    use std::{
+        cmp,
+        collections::HashMap,
+        hash::{BuildHasherDefault, Hasher},
+    };
 
     use powdr_ast::asm_analysis::{Machine, RegisterTy};
     use powdr_number::FieldElement;
@@ -203,8 +323,29 @@ mod builder {
             .collect()
     }
 
-    pub struct TraceBuilder<'b> {
-        trace: ExecutionTrace,
+    /// A hasher for memory addresses, which is much faster than the default one.
+    #[derive(Default)]
+    struct AddressHasher(u64);
+
+    impl Hasher for AddressHasher {
+        fn write(&mut self, _: &[u8]) {
+            unreachable!("only u32 keys are hashed")
+        }
+
+        fn write_u32(&mut self, address: u32) {
+            // Fibonacci hashing, folded so that the low bits also depend on all bits
+            // of the address.
+            let hash = (address as u64).wrapping_mul(0x9e3779b97f4a7c15);
+            self.0 = hash ^ (hash >> 32);
+        }
+
+        fn finish(&self) -> u64 {
+            self.0
+        }
+    }
+
+    pub struct TraceBuilder<'b, F: FieldElement> {
+        trace: ExecutionTrace<F>,
 
         /// Maximum rows we can run before we stop the execution.
         max_rows: usize,
@@ -214,7 +355,7 @@ mod builder {
         pc_idx: u16,
 
         /// The value of PC at the start of the execution of the current row.
-        curr_pc: Elem,
+        curr_pc: Elem<F>,
 
         /// The PC in the register bank refers to the batches, we have to track our
         /// actual program counter independently.
@@ -225,10 +366,10 @@ mod builder {
         batch_to_line_map: &'b [u32],
 
         /// Current register bank
-        regs: Vec<Elem>,
+        regs: Vec<Elem<F>>,
 
         /// Current memory.
-        mem: HashMap<u32, u32>,
+        mem: HashMap<u32, u32, BuildHasherDefault<AddressHasher>>,
 
         /// The execution mode we running.
         /// Fast: do not save the register's trace and memory accesses.
@@ -236,18 +377,18 @@ mod builder {
         mode: ExecMode,
     }
 
-    impl<'a, 'b> TraceBuilder<'b> {
+    impl<'a, 'b, F: FieldElement> TraceBuilder<'b, F> {
         /// Creates a new builder.
         ///
         /// May fail if max_rows_len is too small or if the main machine is
         /// empty. In this case, the final (empty) execution trace is returned
         /// in Err.
-        pub fn new<T: FieldElement>(
-            main: &'a Machine<T>,
+        pub fn new(
+            main: &'a Machine<F>,
             batch_to_line_map: &'b [u32],
             max_rows_len: usize,
             mode: ExecMode,
-        ) -> Result<Self, Box<(ExecutionTrace, MemoryState)>> {
+        ) -> Result<Self, Box<(ExecutionTrace<F>, MemoryState)>> {
             let reg_map = register_names(main)
                 .into_iter()
                 .enumerate()
@@ -286,7 +427,7 @@ mod builder {
                 batch_to_line_map,
                 max_rows: max_rows_len,
                 regs,
-                mem: HashMap::new(),
+                mem: HashMap::default(),
                 mode,
             };
 
@@ -298,22 +439,35 @@ mod builder {
         }
 
         /// get current value of PC
-        pub(crate) fn get_pc(&self) -> Elem {
+        pub(crate) fn get_pc(&self) -> Elem<F> {
             self.curr_pc
         }
 
         /// get current value of register
-        pub(crate) fn get_reg(&self, idx: &str) -> Elem {
+        pub(crate) fn get_reg(&self, idx: &str) -> Elem<F> {
             self.get_reg_idx(self.trace.reg_map[idx])
         }
 
+        /// get current value of register, if the register exists
+        pub(crate) fn try_get_reg(&self, idx: &str) -> Option<Elem<F>> {
+            self.trace
+                .reg_map
+                .get(idx)
+                .map(|idx| self.get_reg_idx(*idx))
+        }
+
         /// get current value of register by register index instead of name
-        fn get_reg_idx(&self, idx: u16) -> Elem {
+        pub(crate) fn get_reg_idx(&self, idx: u16) -> Elem<F> {
             self.regs[idx as usize]
         }
 
+        /// get the index of a register, if the register exists
+        pub(crate) fn reg_idx(&self, name: &str) -> Option<u16> {
+            self.trace.reg_map.get(name).copied()
+        }
+
         /// sets the PC
-        pub(crate) fn set_pc(&mut self, value: Elem) {
+        pub(crate) fn set_pc(&mut self, value: Elem<F>) {
             // updates the internal statement-based program counter accordingly:
             self.next_statement_line = self.batch_to_line_map[value.u() as usize];
             self.set_reg_idx(self.pc_idx, value);
@@ -322,12 +476,16 @@ mod builder {
         /// set next value of register, accounting to x0 writes
         ///
         /// to set the PC, use set_pc() instead of this
-        pub(crate) fn set_reg(&mut self, idx: &str, value: impl Into<Elem>) {
+        pub(crate) fn set_reg(&mut self, idx: &str, value: impl Into<Elem<F>>) {
             self.set_reg_impl(idx, value.into())
         }
 
-        fn set_reg_impl(&mut self, idx: &str, value: Elem) {
-            let idx = self.trace.reg_map[idx];
+        fn set_reg_impl(&mut self, idx: &str, value: Elem<F>) {
+            self.set_reg_by_idx(self.trace.reg_map[idx], value);
+        }
+
+        /// set next value of register by register index, accounting to x0 writes
+        pub(crate) fn set_reg_by_idx(&mut self, idx: u16, value: Elem<F>) {
             assert!(idx != self.pc_idx);
             if idx == self.x0_idx {
                 return;
@@ -336,7 +494,7 @@ mod builder {
         }
 
         /// raw set next value of register by register index instead of name
-        fn set_reg_idx(&mut self, idx: u16, value: Elem) {
+        fn set_reg_idx(&mut self, idx: u16, value: Elem<F>) {
             // Record register write in trace.
             if let ExecMode::Trace = self.mode {
                 self.trace.reg_writes.push(RegWrite {
@@ -395,8 +553,13 @@ mod builder {
             *self.mem.get(&addr).unwrap_or(&0)
         }
 
-        pub fn finish(self) -> (ExecutionTrace, MemoryState) {
-            (self.trace, self.mem)
+        /// get a memory word without recording the access in the trace
+        pub(crate) fn peek_mem(&self, addr: u32) -> u32 {
+            *self.mem.get(&addr).unwrap_or(&0)
+        }
+
+        pub fn finish(self) -> (ExecutionTrace<F>, MemoryState) {
+            (self.trace, self.mem.into_iter().collect())
         }
 
         /// Should we stop the execution because the maximum number of rows has
@@ -446,7 +609,7 @@ fn get_main_machine<T: FieldElement>(program: &AnalysisASMFile<T>) -> &Machine<T
 
 struct PreprocessedMain<'a, T: FieldElement> {
     statements: Vec<&'a FunctionStatement<T>>,
-    label_map: HashMap<&'a str, Elem>,
+    label_map: HashMap<&'a str, Elem<T>>,
     batch_to_line_map: Vec<u32>,
     debug_files: Vec<(&'a str, &'a str)>,
 }
@@ -514,138 +677,144 @@ fn preprocess_main_function<T: FieldElement>(machine: &Machine<T>) -> Preprocess
 type Callback<'a, F> = dyn powdr_executor::witgen::QueryCallback<F> + 'a;
 
 struct Executor<'a, 'b, F: FieldElement> {
-    proc: TraceBuilder<'b>,
-    label_map: HashMap<&'a str, Elem>,
+    proc: TraceBuilder<'b, F>,
+    label_map: HashMap<&'a str, Elem<F>>,
     inputs: &'b Callback<'b, F>,
     bootloader_inputs: &'b [F],
+    /// Half of the field modulus, saturated to `u128::MAX`.
+    half_modulus: u128,
     _stdout: io::Stdout,
 }
 
 impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
-    fn exec_instruction(&mut self, name: &str, args: &[Expression<F>]) -> Vec<Elem> {
+    fn exec_instruction(&mut self, name: &str, args: &[Expression<F>]) -> Vec<Elem<F>> {
         let args = args
             .iter()
             .map(|expr| self.eval_expression(expr)[0])
             .collect::<Vec<_>>();
 
-        match name {
-            "mstore" | "mstore_bootloader" => {
-                let addr = args[0].0 as u32;
+        self.exec_opcode(&Opcode::from_name(name), &args).to_vec()
+    }
+
+    fn exec_opcode(&mut self, opcode: &Opcode, args: &[Elem<F>]) -> Outputs<F> {
+        match opcode {
+            Opcode::Mstore => {
+                let addr = args[0].bin() as u32;
                 assert_eq!(addr % 4, 0);
-                self.proc.set_mem(args[0].0 as u32, args[1].u());
+                self.proc.set_mem(addr, args[1].u());
 
-                Vec::new()
+                Outputs::none()
             }
-            "mload" => {
-                let addr = args[0].0 as u32;
+            Opcode::Mload => {
+                let addr = args[0].bin() as u32;
                 let val = self.proc.get_mem(addr & 0xfffffffc);
                 let rem = addr % 4;
 
-                vec![val.into(), rem.into()]
+                [val.into(), rem.into()].into()
             }
-            "load_bootloader_input" => {
-                let addr = args[0].0 as usize;
-                let val = self.bootloader_inputs[addr].to_degree();
+            Opcode::LoadBootloaderInput => {
+                let addr = args[0].bin() as usize;
+                let val = self.bootloader_inputs[addr];
 
-                vec![Elem::from_fe(GoldilocksField::from(val))]
+                [Elem::from_fe(val)].into()
             }
-            "assert_bootloader_input" => {
-                let addr = args[0].0 as usize;
-                let expected_val = args[1].fe::<F>();
+            Opcode::AssertBootloaderInput => {
+                let addr = args[0].bin() as usize;
+                let expected_val = args[1].fe();
                 let actual_val = self.bootloader_inputs[addr];
 
                 assert_eq!(expected_val, actual_val);
 
-                vec![]
+                Outputs::none()
             }
-            "load_label" => args,
-            "jump" | "jump_dyn" => {
+            Opcode::LoadLabel => args.iter().copied().collect(),
+            Opcode::Jump => {
                 let next_pc = self.proc.get_pc().u() + 1;
                 self.proc.set_pc(args[0]);
 
-                vec![next_pc.into()]
+                [next_pc.into()].into()
             }
-            "jump_to_bootloader_input" => {
-                let bootloader_input_idx = args[0].0 as usize;
+            Opcode::JumpToBootloaderInput => {
+                let bootloader_input_idx = args[0].bin() as usize;
                 let addr = self.bootloader_inputs[bootloader_input_idx].to_degree();
                 self.proc.set_pc(addr.into());
 
-                Vec::new()
+                Outputs::none()
             }
-            "branch_if_nonzero" => {
-                if args[0].0 != 0 {
+            Opcode::BranchIfNonzero => {
+                if !args[0].is_zero() {
                     self.proc.set_pc(args[1]);
                 }
 
-                Vec::new()
+                Outputs::none()
             }
-            "branch_if_zero" => {
-                if args[0].0 == 0 {
+            Opcode::BranchIfZero => {
+                if args[0].is_zero() {
                     self.proc.set_pc(args[1]);
                 }
 
-                Vec::new()
+                Outputs::none()
             }
-            "skip_if_zero" => {
-                if args[0].0 == 0 {
+            Opcode::SkipIfZero => {
+                if args[0].is_zero() {
                     let pc = self.proc.get_pc().s();
                     self.proc.set_pc((pc + args[1].s() + 1).into());
                 }
 
-                Vec::new()
+                Outputs::none()
             }
-            "branch_if_positive" => {
-                if args[0].0 > 0 {
+            Opcode::BranchIfPositive => {
+                if args[0].bin() > 0 {
                     self.proc.set_pc(args[1]);
                 }
 
-                Vec::new()
+                Outputs::none()
             }
-            "is_positive" => {
-                let r = if args[0].0 > 0 { 1 } else { 0 };
+            Opcode::IsPositive => {
+                let r = if args[0].bin() > 0 { 1 } else { 0 };
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "is_equal_zero" => {
-                let r = if args[0].0 == 0 { 1 } else { 0 };
+            Opcode::IsEqualZero => {
+                let r = if args[0].is_zero() { 1 } else { 0 };
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "is_not_equal_zero" => {
-                let r = if args[0].0 != 0 { 1 } else { 0 };
+            Opcode::IsNotEqualZero => {
+                let r = if args[0].is_zero() { 0 } else { 1 };
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "wrap" | "wrap16" => {
-                let r = args[0].0 as u32;
+            Opcode::Wrap => {
+                let r = args[0].bin() as u32;
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "wrap_signed" => {
-                let r = (args[0].0 + 0x100000000) as u32;
+            Opcode::WrapSigned => {
+                let r = (args[0].bin() + 0x100000000) as u32;
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "sign_extend_byte" => {
+            Opcode::SignExtendByte => {
                 let r = args[0].u() as i8 as u32;
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "sign_extend_16_bits" => {
+            Opcode::SignExtend16Bits => {
                 let r = args[0].u() as i16 as u32;
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "to_signed" => {
+            Opcode::ToSigned => {
                 let r = args[0].u() as i32;
 
-                vec![r.into()]
+                [r.into()].into()
             }
-            "fail" => {
+            Opcode::Fail => {
                 // TODO: handle it better
                 panic!("reached a fail instruction")
             }
-            "divremu" => {
+            Opcode::Divremu => {
                 let y = args[0].u();
                 let x = args[1].u();
                 let div;
@@ -658,43 +827,128 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                     rem = y;
                 }
 
-                vec![div.into(), rem.into()]
+                [div.into(), rem.into()].into()
             }
-            "mul" => {
+            Opcode::Mul => {
                 let r = args[0].u() as u64 * args[1].u() as u64;
                 let lo = r as u32;
                 let hi = (r >> 32) as u32;
 
-                vec![lo.into(), hi.into()]
+                [lo.into(), hi.into()].into()
             }
-            "and" => vec![(args[0].u() & args[1].u()).into()],
-            "or" => vec![(args[0].u() | args[1].u()).into()],
-            "xor" => vec![(args[0].u() ^ args[1].u()).into()],
-            "shl" => vec![(args[0].u() << args[1].u()).into()],
-            "shr" => vec![(args[0].u() >> args[1].u()).into()],
-            "split_gl" => {
-                let arg: u64 = args[0].fe::<F>().to_degree();
+            Opcode::And => [(args[0].u() & args[1].u()).into()].into(),
+            Opcode::Or => [(args[0].u() | args[1].u()).into()].into(),
+            Opcode::Xor => [(args[0].u() ^ args[1].u()).into()].into(),
+            Opcode::Shl => [(args[0].u() << args[1].u()).into()].into(),
+            Opcode::Shr => [(args[0].u() >> args[1].u()).into()].into(),
+            Opcode::SplitGl => {
+                let arg: u64 = args[0].fe().to_degree();
                 let lo = (arg & 0xffffffff) as u32;
                 let hi = (arg >> 32) as u32;
 
-                vec![lo.into(), hi.into()]
+                [lo.into(), hi.into()].into()
             }
-            "poseidon_gl" => {
-                let inputs = args
-                    .iter()
-                    .take(12)
-                    .map(|arg| arg.fe::<F>())
-                    .collect::<Vec<_>>();
+            Opcode::PoseidonGl => {
+                let inputs = args.iter().take(12).map(|arg| arg.fe()).collect::<Vec<_>>();
                 let result = poseidon_gl::poseidon_gl(&inputs);
                 result.into_iter().map(Elem::from_fe).collect()
             }
-            instr => {
+            Opcode::SplitBn254 => {
+                // The input is passed in P0 and the output is returned in P0, ..., P7,
+                // as 8 32-bit limbs (least significant first).
+                let arg = self.proc.get_reg("P0").fe().to_arbitrary_integer();
+                let mut limbs = arg.iter_u32_digits();
+                for i in 0..8 {
+                    self.proc
+                        .set_reg(&format!("P{i}"), limbs.next().unwrap_or_default());
+                }
+
+                Outputs::none()
+            }
+            Opcode::PoseidonBn254 => {
+                // The inputs are passed in P0, P1 and P2, the output is returned in P0.
+                let inputs = std::array::from_fn(|i| self.proc.get_reg(&format!("P{i}")).fe());
+                let result = poseidon_bn254::poseidon_bn254(&inputs);
+                self.proc.set_reg("P0", Elem::from_fe(result));
+
+                Outputs::none()
+            }
+            Opcode::KeccakF => {
+                // The state is passed in and returned via the registers K0, ..., K49,
+                // each holding a 32-bit limb (least significant first) of a lane.
+                let limb = |i: usize| self.proc.get_reg(&format!("K{i}")).u() as u64;
+                let mut state: [u64; 25] =
+                    std::array::from_fn(|i| limb(2 * i) | (limb(2 * i + 1) << 32));
+                keccakf::keccakf(&mut state);
+                for (i, lane) in state.into_iter().enumerate() {
+                    self.proc.set_reg(&format!("K{}", 2 * i), lane as u32);
+                    self.proc
+                        .set_reg(&format!("K{}", 2 * i + 1), (lane >> 32) as u32);
+                }
+
+                Outputs::none()
+            }
+            Opcode::Sha256Compress => {
+                // The state is passed in and returned via the registers H0, ..., H7,
+                // the message block is passed via the registers M0, ..., M15.
+                let mut state: [u32; 8] =
+                    std::array::from_fn(|i| self.proc.get_reg(&format!("H{i}")).u());
+                let block: [u32; 16] =
+                    std::array::from_fn(|i| self.proc.get_reg(&format!("M{i}")).u());
+                sha256::sha256_compress(&mut state, &block);
+                for (i, word) in state.into_iter().enumerate() {
+                    self.proc.set_reg(&format!("H{i}"), word);
+                }
+
+                Outputs::none()
+            }
+            Opcode::Affine256 => {
+                // The inputs are passed in the registers L0, ..., L23 and the outputs are
+                // returned in the registers L0, ..., L15, as 8 32-bit limbs per number.
+                let (hi, lo) =
+                    arith::affine_256(&self.get_limbs(0), &self.get_limbs(8), &self.get_limbs(16));
+                self.set_limbs(0, hi);
+                self.set_limbs(8, lo);
+
+                Outputs::none()
+            }
+            Opcode::EcAdd => {
+                let (x3, y3) = arith::ec_add(
+                    (&self.get_limbs(0), &self.get_limbs(8)),
+                    (&self.get_limbs(16), &self.get_limbs(24)),
+                );
+                self.set_limbs(0, x3);
+                self.set_limbs(8, y3);
+
+                Outputs::none()
+            }
+            Opcode::EcDouble => {
+                let (x3, y3) = arith::ec_double((&self.get_limbs(0), &self.get_limbs(8)));
+                self.set_limbs(0, x3);
+                self.set_limbs(8, y3);
+
+                Outputs::none()
+            }
+            Opcode::Unknown(instr) => {
+                // See `unsupported_instructions`.
                 panic!("unknown instruction: {instr}");
             }
         }
     }
 
-    fn eval_expression(&mut self, expression: &Expression<F>) -> Vec<Elem> {
+    /// Reads the 8 limbs of a 256-bit number from the registers L{offset}, ..., L{offset + 7}.
+    fn get_limbs(&self, offset: usize) -> [u32; 8] {
+        std::array::from_fn(|i| self.proc.get_reg(&format!("L{}", offset + i)).u())
+    }
+
+    /// Writes the 8 limbs of a 256-bit number to the registers L{offset}, ..., L{offset + 7}.
+    fn set_limbs(&mut self, offset: usize, limbs: [u32; 8]) {
+        for (i, limb) in limbs.into_iter().enumerate() {
+            self.proc.set_reg(&format!("L{}", offset + i), limb);
+        }
+    }
+
+    fn eval_expression(&mut self, expression: &Expression<F>) -> Vec<Elem<F>> {
         match expression {
             Expression::Reference(r) => {
                 // an identifier looks like this:
@@ -726,18 +980,18 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                 let r = self.eval_expression(r)[0];
 
                 let result = match op {
-                    powdr_ast::parsed::BinaryOperator::Add => l.0 + r.0,
-                    powdr_ast::parsed::BinaryOperator::Sub => l.0 - r.0,
+                    powdr_ast::parsed::BinaryOperator::Add => l.add(&r),
+                    powdr_ast::parsed::BinaryOperator::Sub => l.sub(&r),
                     powdr_ast::parsed::BinaryOperator::Mul => {
                         // Do multiplication in the field, in case we overflow.
                         let l: F = l.fe();
                         let r: F = r.fe();
                         let res = l * r;
-                        Elem::from_fe(res).0
+                        Elem::from_fe(res)
                     }
-                    powdr_ast::parsed::BinaryOperator::Div => l.0 / r.0,
-                    powdr_ast::parsed::BinaryOperator::Mod => l.0 % r.0,
-                    powdr_ast::parsed::BinaryOperator::Pow => l.0.pow(r.u()),
+                    powdr_ast::parsed::BinaryOperator::Div => (l.bin() / r.bin()).into(),
+                    powdr_ast::parsed::BinaryOperator::Mod => (l.bin() % r.bin()).into(),
+                    powdr_ast::parsed::BinaryOperator::Pow => l.pow(r.u()),
                     powdr_ast::parsed::BinaryOperator::BinaryAnd => todo!(),
                     powdr_ast::parsed::BinaryOperator::BinaryXor => todo!(),
                     powdr_ast::parsed::BinaryOperator::BinaryOr => todo!(),
@@ -754,17 +1008,17 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                     powdr_ast::parsed::BinaryOperator::Greater => todo!(),
                 };
 
-                vec![result.into()]
+                vec![result]
             }
             Expression::UnaryOperation(op, arg) => {
                 let arg = self.eval_expression(arg)[0];
                 let result = match op {
-                    powdr_ast::parsed::UnaryOperator::Minus => -arg.0,
+                    powdr_ast::parsed::UnaryOperator::Minus => arg.neg(),
                     powdr_ast::parsed::UnaryOperator::LogicalNot => todo!(),
                     powdr_ast::parsed::UnaryOperator::Next => unreachable!(),
                 };
 
-                vec![result.into()]
+                vec![result]
             }
             Expression::FunctionCall(FunctionCall {
                 function,
@@ -783,7 +1037,7 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                             all_strings.push(expr.to_string());
                         } else {
                             let val = self.eval_expression(expr)[0];
-                            all_strings.push(val.0.to_string());
+                            all_strings.push(val.to_string());
                         }
                     }
                     let query = format!("({})", all_strings.join(","));
@@ -804,13 +1058,149 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
     }
 }
 
+/// The state of the executor as seen by the debugger.
+struct DebugView<'x, 'a, 'b, F: FieldElement> {
+    executor: &'x mut Executor<'a, 'b, F>,
+    debug_files: &'x [(&'a str, &'a str)],
+    location: Option<(usize, usize, usize)>,
+}
+
+impl<'x, 'a, 'b, F: FieldElement> DebugTarget for DebugView<'x, 'a, 'b, F> {
+    fn pc(&self) -> u32 {
+        self.executor.proc.get_pc().u()
+    }
+
+    fn register(&self, name: &str) -> Option<u32> {
+        self.executor.proc.try_get_reg(name).map(|v| v.bin() as u32)
+    }
+
+    fn memory_word(&self, address: u32) -> u32 {
+        self.executor.proc.peek_mem(address)
+    }
+
+    fn set_register(&mut self, name: &str, value: u32) -> bool {
+        match self.executor.proc.reg_idx(name) {
+            Some(idx) => {
+                self.executor.proc.set_reg_by_idx(idx, value.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_memory_word(&mut self, address: u32, value: u32) {
+        self.executor.proc.set_mem(address, value)
+    }
+
+    fn label(&self, name: &str) -> Option<u32> {
+        self.executor.label_map.get(name).map(|pc| pc.u())
+    }
+
+    fn labels_at(&self, pc: u32) -> Vec<&str> {
+        let mut labels = self
+            .executor
+            .label_map
+            .iter()
+            .filter(|(_, label_pc)| label_pc.u() == pc)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+
+    fn location(&self) -> Option<String> {
+        self.location.map(|(file, line, column)| {
+            let (dir, file) = self.debug_files[file - 1];
+            format!("{dir}/{file}:{line}:{column}")
+        })
+    }
+}
+
+/// Returns the instructions used by the main machine of the program that the
+/// executor cannot execute, like the ones of user-defined coprocessors.
+pub fn unsupported_instructions<T: FieldElement>(program: &AnalysisASMFile<T>) -> BTreeSet<String> {
+    let PreprocessedMain { statements, .. } = preprocess_main_function(get_main_machine(program));
+    let mut instructions = BTreeSet::new();
+    for s in statements {
+        match s {
+            FunctionStatement::Instruction(i) => {
+                instructions.insert(i.instruction.clone());
+            }
+            FunctionStatement::Assignment(a) => a.rhs.pre_visit_expressions(&mut |e| {
+                if let Expression::FunctionCall(FunctionCall { function, .. }) = e {
+                    if let Expression::Reference(f) = function.as_ref() {
+                        instructions.extend(f.try_to_identifier().cloned());
+                    }
+                }
+            }),
+            _ => {}
+        }
+    }
+    instructions.retain(|name| matches!(Opcode::from_name(name), Opcode::Unknown(_)));
+    instructions
+}
+
 pub fn execute_ast<T: FieldElement>(
     program: &AnalysisASMFile<T>,
     inputs: &Callback<T>,
     bootloader_inputs: &[T],
     max_steps_to_execute: usize,
     mode: ExecMode,
-) -> (ExecutionTrace, MemoryState) {
+) -> (ExecutionTrace<T>, MemoryState) {
+    execute_ast_impl(
+        program,
+        inputs,
+        bootloader_inputs,
+        max_steps_to_execute,
+        mode,
+        Interpreter::Decoded,
+    )
+    .0
+}
+
+/// Like [execute_ast], but interprets the statements one by one instead of
+/// decoding them first. This is much slower and only meant to cross-check the
+/// results of [execute_ast].
+pub fn execute_ast_reference<T: FieldElement>(
+    program: &AnalysisASMFile<T>,
+    inputs: &Callback<T>,
+    bootloader_inputs: &[T],
+    max_steps_to_execute: usize,
+    mode: ExecMode,
+) -> (ExecutionTrace<T>, MemoryState) {
+    execute_ast_impl(
+        program,
+        inputs,
+        bootloader_inputs,
+        max_steps_to_execute,
+        mode,
+        Interpreter::Statements {
+            debugger: None,
+            profile: false,
+        },
+    )
+    .0
+}
+
+/// How the main function is executed.
+enum Interpreter<'g> {
+    /// Decode the statements once and run them in a tight loop.
+    Decoded,
+    /// Interpret the statements one by one, which allows to hook into the execution.
+    Statements {
+        debugger: Option<&'g mut GdbStub>,
+        profile: bool,
+    },
+}
+
+fn execute_ast_impl<T: FieldElement>(
+    program: &AnalysisASMFile<T>,
+    inputs: &Callback<T>,
+    bootloader_inputs: &[T],
+    max_steps_to_execute: usize,
+    mode: ExecMode,
+    interpreter: Interpreter,
+) -> ((ExecutionTrace<T>, MemoryState), Option<Profile>) {
     let main_machine = get_main_machine(program);
     let PreprocessedMain {
         statements,
@@ -822,7 +1212,10 @@ pub fn execute_ast<T: FieldElement>(
     let proc = match TraceBuilder::new(main_machine, &batch_to_line_map, max_steps_to_execute, mode)
     {
         Ok(proc) => proc,
-        Err(ret) => return *ret,
+        Err(ret) => {
+            let profile = matches!(interpreter, Interpreter::Statements { profile: true, .. });
+            return (*ret, profile.then(Profile::default));
+        }
     };
 
     let mut e = Executor {
@@ -830,15 +1223,57 @@ pub fn execute_ast<T: FieldElement>(
         label_map,
         inputs,
         bootloader_inputs,
+        half_modulus: (T::modulus().to_arbitrary_integer() / 2u32)
+            .try_into()
+            .unwrap_or(u128::MAX),
         _stdout: io::stdout(),
     };
 
+    let (mut debugger, mut profiler) = match interpreter {
+        Interpreter::Decoded => {
+            let program = decoder::decode(&statements, &e.label_map, &e.proc);
+            e.run_decoded(&program);
+            return (e.proc.finish(), None);
+        }
+        Interpreter::Statements { debugger, profile } => (
+            debugger,
+            profile.then(|| Profiler::new(main_machine, &statements, &e.label_map)),
+        ),
+    };
+
+    let mut location = None;
     let mut curr_pc = 0u32;
     loop {
         let stm = statements[curr_pc as usize];
 
         log::trace!("l {curr_pc}: {stm}",);
 
+        let batch_start = batch_to_line_map[e.proc.get_pc().u() as usize] == curr_pc;
+        if let Some(profiler) = &mut profiler {
+            if batch_start {
+                profiler.on_batch();
+            }
+        }
+
+        if let Some(gdb) = &mut debugger {
+            // Only stop at the start of a batch.
+            if batch_start {
+                let mut view = DebugView {
+                    executor: &mut e,
+                    debug_files: &debug_files,
+                    location,
+                };
+                match gdb.on_batch(&mut view) {
+                    Ok(Resume::Run) => {}
+                    Ok(Resume::Kill) => return (e.proc.finish(), profiler.map(Profiler::finish)),
+                    Err(err) => {
+                        log::error!("Lost connection to GDB: {err}");
+                        debugger = None;
+                    }
+                }
+            }
+        }
+
         match stm {
             FunctionStatement::Assignment(a) => {
                 let results = e.eval_expression(a.rhs.as_ref());
@@ -854,8 +1289,12 @@ pub fn execute_ast<T: FieldElement>(
             FunctionStatement::DebugDirective(dd) => {
                 match &dd.directive {
                     DebugDirective::Loc(file, line, column) => {
-                        let (dir, file) = debug_files[file - 1];
-                        log::trace!("Executed {dir}/{file}:{line}:{column}");
+                        let (dir, file_name) = debug_files[file - 1];
+                        log::trace!("Executed {dir}/{file_name}:{line}:{column}");
+                        location = Some((*file, *line, *column));
+                        if let Some(profiler) = &mut profiler {
+                            profiler.on_location(|| format!("{dir}/{file_name}:{line}"));
+                        }
                     }
                     DebugDirective::OriginalInstruction(insn) => {
                         log::trace!("  {insn}");
@@ -868,15 +1307,26 @@ pub fn execute_ast<T: FieldElement>(
             }
         };
 
+        if let Some(profiler) = &mut profiler {
+            profiler.on_statement(stm, e.proc.get_reg("pc").u());
+        }
+
         curr_pc = match e.proc.advance() {
             Some(pc) => pc,
             None => break,
         };
     }
 
-    e.proc.finish()
+    if let Some(gdb) = debugger {
+        if let Err(err) = gdb.on_exit() {
+            log::error!("Lost connection to GDB: {err}");
+        }
+    }
+
+    (e.proc.finish(), profiler.map(Profiler::finish))
 }
 
+#[derive(Clone, Copy, Debug)]
 pub enum ExecMode {
     Fast,
     Trace,
@@ -891,17 +1341,68 @@ pub fn execute<F: FieldElement>(
     inputs: &Callback<F>,
     bootloader_inputs: &[F],
     mode: ExecMode,
-) -> (ExecutionTrace, MemoryState) {
+) -> (ExecutionTrace<F>, MemoryState) {
+    let analyzed = analyze::<F>(asm_source);
+    log::info!("Executing...");
+    execute_ast(&analyzed, inputs, bootloader_inputs, usize::MAX, mode)
+}
+
+/// Execute a Powdr/RISCV assembly source under the control of a GDB client
+/// connected to `debugger`. The execution stops before the first instruction.
+pub fn execute_with_gdb<F: FieldElement>(
+    asm_source: &str,
+    inputs: &Callback<F>,
+    bootloader_inputs: &[F],
+    mode: ExecMode,
+    debugger: &mut GdbStub,
+) -> (ExecutionTrace<F>, MemoryState) {
+    let analyzed = analyze::<F>(asm_source);
+    log::info!("Executing under GDB...");
+    execute_ast_impl(
+        &analyzed,
+        inputs,
+        bootloader_inputs,
+        usize::MAX,
+        mode,
+        Interpreter::Statements {
+            debugger: Some(debugger),
+            profile: false,
+        },
+    )
+    .0
+}
+
+/// Execute a Powdr/RISCV assembly source and profile which guest functions
+/// the rows of the main machine and the submachine calls are spent in.
+pub fn execute_with_profiler<F: FieldElement>(
+    asm_source: &str,
+    inputs: &Callback<F>,
+    bootloader_inputs: &[F],
+    mode: ExecMode,
+) -> (ExecutionTrace<F>, MemoryState, Profile) {
+    let analyzed = analyze::<F>(asm_source);
+    log::info!("Executing with profiler...");
+    let ((trace, memory), profile) = execute_ast_impl(
+        &analyzed,
+        inputs,
+        bootloader_inputs,
+        usize::MAX,
+        mode,
+        Interpreter::Statements {
+            debugger: None,
+            profile: true,
+        },
+    );
+    (trace, memory, profile.unwrap())
+}
+
+fn analyze<F: FieldElement>(asm_source: &str) -> AnalysisASMFile<F> {
     log::info!("Parsing...");
     let parsed = powdr_parser::parse_asm::<F>(None, asm_source).unwrap();
     log::info!("Resolving imports...");
     let resolved = powdr_importer::load_dependencies_and_resolve(None, parsed).unwrap();
     log::info!("Analyzing...");
-    let analyzed =
-        powdr_analysis::analyze(resolved, &mut powdr_ast::DiffMonitor::default()).unwrap();
-
-    log::info!("Executing...");
-    execute_ast(&analyzed, inputs, bootloader_inputs, usize::MAX, mode)
+    powdr_analysis::analyze(resolved, &mut powdr_ast::DiffMonitor::default()).unwrap()
 }
 
 fn to_u32<F: FieldElement>(val: &F) -> Option<u32> {
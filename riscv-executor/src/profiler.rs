@@ -0,0 +1,509 @@
+//! A profiler attributing the rows of the main machine, and the calls to
+//! submachines such as `binary`, `shift` or `poseidon_gl`, to guest functions.
+//!
+//! The call stack is reconstructed from the code generated for the RISC-V
+//! instructions: `x1 <== jump(f)` and `x1 <== jump_dyn(r)` are calls,
+//! `tmp1 <== jump_dyn(x1)` is a return and `tmp1 <== jump(f)` to a function
+//! that is called elsewhere is a tail call. Functions are identified by the
+//! labels of the batches they start at.
+//!
+//! The result can be written as folded stacks, which can be turned into
+//! flamegraphs with `flamegraph.pl` or `inferno-flamegraph`, and as a table
+//! summarizing the cost of each function.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use itertools::Itertools;
+use powdr_ast::{
+    asm_analysis::{FunctionStatement, Machine},
+    parsed::{asm::InstructionBody, Expression, FunctionCall},
+};
+use powdr_number::FieldElement;
+
+use crate::Elem;
+
+/// Name of the frame at the bottom of every call stack, i.e. the powdr
+/// function `main` the guest program is embedded in.
+const ROOT_FRAME: &str = "[main]";
+
+#[derive(Default)]
+struct Function {
+    name: String,
+    /// Source location of the first `.loc` directive executed in this function.
+    source: Option<String>,
+    calls: u64,
+}
+
+/// The profile of an execution.
+#[derive(Default)]
+pub struct Profile {
+    functions: Vec<Function>,
+    /// The names of the submachines called from the main machine.
+    submachines: Vec<String>,
+    /// Number of rows of the main machine, by call stack (indices into `functions`).
+    rows: HashMap<Vec<usize>, u64>,
+    /// Number of calls to each submachine, by call stack.
+    submachine_calls: HashMap<Vec<usize>, Vec<u64>>,
+}
+
+impl Profile {
+    /// The total number of rows of the main machine.
+    pub fn total_rows(&self) -> u64 {
+        self.rows.values().sum()
+    }
+
+    /// The number of rows executed in the function with the given name,
+    /// excluding and including the functions it calls.
+    pub fn rows_of(&self, function: &str) -> Option<(u64, u64)> {
+        let id = self.functions.iter().position(|f| f.name == function)?;
+        Some(self.function_rows()[id])
+    }
+
+    /// The number of calls from the function with the given name to a submachine.
+    pub fn submachine_calls_of(&self, function: &str, submachine: &str) -> u64 {
+        let (Some(id), Some(submachine)) = (
+            self.functions.iter().position(|f| f.name == function),
+            self.submachines.iter().position(|s| s == submachine),
+        ) else {
+            return 0;
+        };
+        self.submachine_calls
+            .iter()
+            .filter(|(stack, _)| stack.last() == Some(&id))
+            .map(|(_, calls)| calls[submachine])
+            .sum()
+    }
+
+    /// Writes `<name>_rows.folded`, `<name>_submachines.folded` and
+    /// `<name>_profile.txt` to the given directory.
+    pub fn write(&self, directory: &Path, name: &str) -> io::Result<()> {
+        let mut rows = BufWriter::new(File::create(directory.join(format!("{name}_rows.folded")))?);
+        self.write_folded_rows(&mut rows)?;
+        rows.flush()?;
+
+        let mut submachines = BufWriter::new(File::create(
+            directory.join(format!("{name}_submachines.folded")),
+        )?);
+        self.write_folded_submachine_calls(&mut submachines)?;
+        submachines.flush()?;
+
+        let summary_path = directory.join(format!("{name}_profile.txt"));
+        std::fs::write(&summary_path, self.summary())?;
+        log::info!("Wrote profile to {}", summary_path.display());
+        Ok(())
+    }
+
+    /// Writes the number of rows by call stack in the folded stack format.
+    pub fn write_folded_rows(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (stack, rows) in self.sorted(&self.rows) {
+            writeln!(writer, "{} {rows}", self.format_stack(stack))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the number of submachine calls by call stack in the folded stack
+    /// format, with the submachine as the innermost frame.
+    pub fn write_folded_submachine_calls(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (stack, calls) in self.sorted(&self.submachine_calls) {
+            for (submachine, count) in self.submachines.iter().zip_eq(calls) {
+                if *count > 0 {
+                    writeln!(
+                        writer,
+                        "{};[{submachine}] {count}",
+                        self.format_stack(stack)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A table with the cost of each function, sorted by the number of rows
+    /// spent in the function itself.
+    pub fn summary(&self) -> String {
+        let function_rows = self.function_rows();
+        let mut submachine_calls = vec![vec![0; self.submachines.len()]; self.functions.len()];
+        for (stack, calls) in &self.submachine_calls {
+            for (total, count) in submachine_calls[*stack.last().unwrap()]
+                .iter_mut()
+                .zip(calls)
+            {
+                *total += count;
+            }
+        }
+        let total_rows = self.total_rows().max(1);
+
+        let header = ["function", "rows", "rows (incl.)", "%", "calls"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .chain(self.submachines.iter().cloned())
+            .chain(["source".to_string()])
+            .collect::<Vec<_>>();
+        let lines = (0..self.functions.len())
+            .sorted_by_key(|id| (std::cmp::Reverse(function_rows[*id].0), *id))
+            .map(|id| {
+                let function = &self.functions[id];
+                let (own, inclusive) = function_rows[id];
+                [
+                    function.name.clone(),
+                    own.to_string(),
+                    inclusive.to_string(),
+                    format!("{:.2}", own as f64 * 100.0 / total_rows as f64),
+                    function.calls.to_string(),
+                ]
+                .into_iter()
+                .chain(submachine_calls[id].iter().map(|c| c.to_string()))
+                .chain([function.source.clone().unwrap_or_default()])
+                .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let widths = (0..header.len())
+            .map(|i| {
+                std::iter::once(&header)
+                    .chain(&lines)
+                    .map(|line| line[i].len())
+                    .max()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        std::iter::once(&header)
+            .chain(&lines)
+            .map(|line| {
+                line.iter()
+                    .zip(&widths)
+                    .enumerate()
+                    .map(|(i, (cell, width))| {
+                        // Left-align the names, right-align the numbers.
+                        if i == 0 || i == line.len() - 1 {
+                            format!("{cell:<width$}")
+                        } else {
+                            format!("{cell:>width$}")
+                        }
+                    })
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    /// The number of rows of each function, excluding and including the
+    /// functions it calls. Recursive calls are only counted once.
+    fn function_rows(&self) -> Vec<(u64, u64)> {
+        let mut result = vec![(0, 0); self.functions.len()];
+        for (stack, rows) in &self.rows {
+            result[*stack.last().unwrap()].0 += rows;
+            for id in stack.iter().unique() {
+                result[*id].1 += rows;
+            }
+        }
+        result
+    }
+
+    fn sorted<'a, V>(&'a self, map: &'a HashMap<Vec<usize>, V>) -> Vec<(&'a [usize], &'a V)> {
+        map.iter()
+            .map(|(stack, value)| (stack.as_slice(), value))
+            .sorted_by_key(|(stack, _)| self.format_stack(stack))
+            .collect()
+    }
+
+    fn format_stack(&self, stack: &[usize]) -> String {
+        stack
+            .iter()
+            .map(|id| self.functions[*id].name.as_str())
+            .join(";")
+    }
+}
+
+/// Builds a [Profile] while the executor runs.
+pub(crate) struct Profiler<'a> {
+    /// The labels of the batches at which a function starts.
+    functions_at: HashMap<u32, &'a str>,
+    /// Index into the submachines of the profile, by instruction name.
+    submachine_of: HashMap<&'a str, usize>,
+    function_ids: HashMap<String, usize>,
+    stack: Vec<usize>,
+    profile: Profile,
+}
+
+impl<'a> Profiler<'a> {
+    pub(crate) fn new<T: FieldElement>(
+        machine: &'a Machine<T>,
+        statements: &[&'a FunctionStatement<T>],
+        label_map: &HashMap<&'a str, Elem<T>>,
+    ) -> Self {
+        let mut submachines = vec![];
+        let submachine_of = machine
+            .instructions
+            .iter()
+            .filter_map(|instr| match &instr.instruction.body {
+                InstructionBody::CallableRef(callable) => {
+                    let index = submachines
+                        .iter()
+                        .position(|s| s == &callable.instance)
+                        .unwrap_or_else(|| {
+                            submachines.push(callable.instance.clone());
+                            submachines.len() - 1
+                        });
+                    Some((instr.name.as_str(), index))
+                }
+                InstructionBody::Local(_) => None,
+            })
+            .collect();
+
+        // Labels that are called are function names, the others are usually
+        // labels inside of functions.
+        let called = statements
+            .iter()
+            .filter_map(|s| match jump(s)? {
+                ("x1", "jump", [Expression::Reference(label)]) => label.try_to_identifier(),
+                _ => None,
+            })
+            .map(|label| label.as_str())
+            .collect::<HashSet<_>>();
+        let functions_at = label_map
+            .iter()
+            .map(|(label, pc)| (pc.u(), *label))
+            .into_group_map()
+            .into_iter()
+            .map(|(pc, labels)| {
+                let label = labels
+                    .into_iter()
+                    .min_by_key(|label| (!called.contains(label), *label))
+                    .unwrap();
+                (pc, label)
+            })
+            .collect();
+
+        let mut profiler = Self {
+            functions_at,
+            submachine_of,
+            function_ids: Default::default(),
+            stack: vec![],
+            profile: Profile {
+                submachines,
+                ..Default::default()
+            },
+        };
+        let root = profiler.function_id(ROOT_FRAME.to_string());
+        profiler.stack.push(root);
+        profiler
+    }
+
+    /// Called at the start of each batch, i.e. for each row of the main machine.
+    pub(crate) fn on_batch(&mut self) {
+        match self.profile.rows.get_mut(self.stack.as_slice()) {
+            Some(rows) => *rows += 1,
+            None => {
+                self.profile.rows.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+
+    /// Called when a `.loc` directive is executed.
+    pub(crate) fn on_location(&mut self, location: impl FnOnce() -> String) {
+        let function = &mut self.profile.functions[*self.stack.last().unwrap()];
+        if function.source.is_none() {
+            function.source = Some(location());
+        }
+    }
+
+    /// Called after a statement has been executed, with the PC of the next batch.
+    pub(crate) fn on_statement<T>(&mut self, statement: &FunctionStatement<T>, next_pc: u32) {
+        if let Some(instruction) = instruction_name(statement) {
+            if let Some(submachine) = self.submachine_of.get(instruction) {
+                let count = self.profile.submachines.len();
+                let calls = match self.profile.submachine_calls.get_mut(self.stack.as_slice()) {
+                    Some(calls) => calls,
+                    None => self
+                        .profile
+                        .submachine_calls
+                        .entry(self.stack.clone())
+                        .or_insert_with(|| vec![0; count]),
+                };
+                calls[*submachine] += 1;
+            }
+        }
+
+        match jump(statement) {
+            Some(("x1", _, _)) => {
+                let callee = self.callee(next_pc);
+                self.stack.push(callee);
+            }
+            // Never pop the root frame, even if the guest returns from it.
+            Some((_, "jump_dyn", [Expression::Reference(r)]))
+                if r.try_to_identifier().map(|r| r.as_str()) == Some("x1")
+                    && self.stack.len() > 1 =>
+            {
+                self.stack.pop();
+            }
+            Some((_, "jump", _)) if self.functions_at.contains_key(&next_pc) => {
+                // A jump to the start of a function is a tail call.
+                let callee = self.callee(next_pc);
+                if self.stack.len() > 1 {
+                    self.stack.pop();
+                }
+                self.stack.push(callee);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn finish(self) -> Profile {
+        self.profile
+    }
+
+    fn callee(&mut self, pc: u32) -> usize {
+        let name = match self.functions_at.get(&pc) {
+            Some(label) => unescape_label(label),
+            None => format!("pc_{pc}"),
+        };
+        let id = self.function_id(name);
+        self.profile.functions[id].calls += 1;
+        id
+    }
+
+    fn function_id(&mut self, name: String) -> usize {
+        if let Some(id) = self.function_ids.get(&name) {
+            return *id;
+        }
+        let id = self.profile.functions.len();
+        self.profile.functions.push(Function {
+            name: name.clone(),
+            ..Default::default()
+        });
+        self.function_ids.insert(name, id);
+        id
+    }
+}
+
+/// The destination register, the instruction and its arguments, if the
+/// statement is an assignment of the result of `jump` or `jump_dyn`.
+fn jump<T>(statement: &FunctionStatement<T>) -> Option<(&str, &str, &[Expression<T>])> {
+    let FunctionStatement::Assignment(assignment) = statement else {
+        return None;
+    };
+    let Expression::FunctionCall(FunctionCall {
+        function,
+        arguments,
+    }) = assignment.rhs.as_ref()
+    else {
+        return None;
+    };
+    let Expression::Reference(function) = function.as_ref() else {
+        return None;
+    };
+    let instruction = function.try_to_identifier()?.as_str();
+    match (assignment.lhs_with_reg.as_slice(), instruction) {
+        ([(destination, _)], "jump" | "jump_dyn") => {
+            Some((destination.as_str(), instruction, arguments.as_slice()))
+        }
+        _ => None,
+    }
+}
+
+fn instruction_name<T>(statement: &FunctionStatement<T>) -> Option<&str> {
+    match statement {
+        FunctionStatement::Instruction(instruction) => Some(&instruction.instruction),
+        FunctionStatement::Assignment(assignment) => match assignment.rhs.as_ref() {
+            Expression::FunctionCall(FunctionCall { function, .. }) => match function.as_ref() {
+                Expression::Reference(r) => r.try_to_identifier().map(|r| r.as_str()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Reverts the escaping of symbols done by the RISC-V compiler.
+fn unescape_label(label: &str) -> String {
+    label.replace("_dot_", ".").replace("_slash_", "/")
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+
+    use crate::{execute_with_profiler, ExecMode};
+
+    #[test]
+    fn call_stacks() {
+        let program = r#"
+use std::binary::Binary;
+
+machine Main {
+    degree 256;
+
+    Binary binary;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg Z[<=];
+    reg x0;
+    reg x1;
+    reg tmp1;
+    reg A;
+    reg B;
+
+    instr jump l: label -> Y { pc' = l, Y = pc + 1 }
+    instr jump_dyn X -> Y { pc' = X, Y = pc + 1 }
+    instr and Y, Z -> X = binary.and;
+
+    function main {
+        x1 <== jump(outer);
+        x1 <== jump(inner);
+        return;
+    outer:
+        A <=X= 7;
+        B <=X= x1;
+        x1 <== jump(inner);
+        x1 <=X= B;
+        tmp1 <== jump(tail_callee);
+    inner:
+        A <== and(A, 3);
+        A <== and(A, 1);
+        tmp1 <== jump_dyn(x1);
+    tail_callee:
+        A <=X= A + 1;
+        tmp1 <== jump_dyn(x1);
+    }
+}
+"#;
+        let (_, _, profile) =
+            execute_with_profiler::<GoldilocksField>(program, &|_| Ok(None), &[], ExecMode::Fast);
+
+        // `inner` is called twice, each call takes three rows.
+        assert_eq!(profile.rows_of("inner"), Some((6, 6)));
+        assert_eq!(profile.rows_of("tail_callee"), Some((2, 2)));
+        // The tail call replaces `outer` on the call stack.
+        assert_eq!(profile.rows_of("outer"), Some((5, 8)));
+        assert_eq!(profile.submachine_calls_of("inner", "binary"), 4);
+        assert_eq!(profile.submachine_calls_of("outer", "binary"), 0);
+
+        let mut folded = vec![];
+        profile.write_folded_rows(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert!(folded.contains("[main];outer;inner 3\n"));
+        assert!(folded.contains("[main];inner 3\n"));
+        assert!(folded.contains("[main];tail_callee 2\n"));
+
+        let mut folded = vec![];
+        profile.write_folded_submachine_calls(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert_eq!(
+            folded,
+            "[main];inner;[binary] 2\n[main];outer;inner;[binary] 2\n"
+        );
+
+        let summary = profile.summary();
+        assert!(summary.starts_with("function"));
+        assert!(summary.lines().nth(1).unwrap().starts_with("inner"));
+    }
+}
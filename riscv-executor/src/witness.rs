@@ -0,0 +1,470 @@
+//! Computes the witness columns of the main machine from an execution trace,
+//! so that generic witness generation only has to solve the submachines.
+//!
+//! Every column that `asm-to-pil` and the RISC-V compiler create for the main
+//! machine is determined by the register values and the ROM row selected by the PC:
+//! - registers are replayed from the trace,
+//! - instruction flags, instruction parameters and the assignment register
+//!   read/write selectors are copied from their `p_` fixed column,
+//! - assignment registers are computed from their ROM coefficients and, when
+//!   they read a free value, from the register they are written to,
+//! - the columns declared by the RISC-V compiler to constrain the results of
+//!   some instructions (like `XInv` or `X_b1`) are computed from the assignment
+//!   registers of the instruction,
+//! - `_operation_id` is the operation of the main machine until it returns,
+//!   and the sink operation afterwards.
+//!
+//! After the `return` in the last row of the trace, the machine runs `_reset`,
+//! which sets all registers to zero, `_jump_to_operation`, and then loops in
+//! `_loop` until the last row.
+//!
+//! The columns of the memory machine are declared in the main machine as well
+//! (their names start with `m_`), but they are left to witness generation.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use itertools::Itertools;
+use powdr_ast::analyzed::Analyzed;
+use powdr_number::FieldElement;
+
+use crate::ExecutionTrace;
+
+/// Name of the main machine instance after linking.
+const MAIN: &str = "main";
+
+/// Prefix of the columns of the memory machine.
+const MEMORY_PREFIX: &str = "m_";
+
+/// Columns declared by the RISC-V compiler to constrain the results of some
+/// instructions. They are zero in rows whose instruction does not use them,
+/// except for `XInv` and `XIsZero`, which are constrained in every row.
+const HELPER_COLUMNS: [&str; 17] = [
+    "XInv", "XIsZero", "X_b1", "X_b2", "X_b3", "X_b4", "wrap_bit", "Y_7bit", "Y_15bit", "Y_b5",
+    "Y_b6", "Y_b7", "Y_b8", "REM_b1", "REM_b2", "REM_b3", "REM_b4",
+];
+
+/// The instructions that use the columns in [HELPER_COLUMNS] besides `XInv` and `XIsZero`.
+const HELPER_INSTRUCTIONS: [&str; 13] = [
+    "branch_if_positive",
+    "is_positive",
+    "wrap",
+    "wrap_signed",
+    "sign_extend_byte",
+    "sign_extend_16_bits",
+    "to_signed",
+    "wrap16",
+    "divremu",
+    "mul",
+    "mload",
+    "mstore",
+    "mstore_bootloader",
+];
+
+/// Returns the witness columns of the main machine for all rows, in the format
+/// expected by `WitnessGenerator::with_external_witness_values`. Witness
+/// generation then only processes the calls of the main machine to the other
+/// machines, instead of solving it.
+///
+/// The trace needs to have been generated with `ExecMode::Trace` and to end
+/// with the `return` of the main operation. Fails if the trace does not fit into
+/// the degree of `pil` or if some column cannot be computed.
+pub fn main_machine_witness<F: FieldElement>(
+    trace: &ExecutionTrace<F>,
+    pil: &Analyzed<F>,
+    fixed: &[(String, Vec<F>)],
+) -> Result<Vec<(String, Vec<F>)>, String> {
+    let degree = pil.degree() as usize;
+    if trace.len > degree {
+        return Err(format!(
+            "The execution needs {} rows, but the degree is {degree}.",
+            trace.len
+        ));
+    }
+
+    // Derived columns are computed by witness generation from the other columns.
+    let derived_columns = pil.derived_columns();
+    let witness_names = pil
+        .committed_polys_in_source_order()
+        .into_iter()
+        .flat_map(|(symbol, _)| symbol.array_elements())
+        .filter(|(_, poly_id)| !derived_columns.contains(poly_id))
+        .filter_map(|(name, _)| {
+            name.strip_prefix(MAIN)?
+                .strip_prefix('.')
+                .filter(|name| !name.starts_with(MEMORY_PREFIX))
+                .map(|name| name.to_string())
+        })
+        .collect::<BTreeSet<_>>();
+    let rom = Rom::new(fixed);
+    let sink_pc = rom
+        .line_of("instr__loop")
+        .ok_or_else(|| "The ROM of the main machine does not contain `_loop`.".to_string())?;
+
+    let registers = register_columns::<F>(trace, degree, sink_pc);
+    let pc = registers["pc"]
+        .iter()
+        .map(|pc| pc.to_degree() as usize)
+        .collect::<Vec<_>>();
+    if trace.len == 0 || !rom.is_active("instr_return", pc[trace.len - 1]) {
+        return Err("The execution did not end with a return.".to_string());
+    }
+
+    let mut columns = BTreeMap::new();
+    for name in &witness_names {
+        if let Some(values) = registers.get(name.as_str()) {
+            columns.insert(name.clone(), values.clone());
+        } else if let Some(rom_column) = rom.column(&format!("p_{name}")) {
+            columns.insert(name.clone(), pc.iter().map(|&pc| rom_column[pc]).collect());
+        }
+    }
+
+    for assignment_reg in witness_names
+        .iter()
+        .filter_map(|name| name.strip_suffix("_free_value"))
+        .filter(|reg| witness_names.contains(*reg))
+    {
+        let (values, free_values) =
+            assignment_register_columns(assignment_reg, &registers, &pc, &rom)?;
+        columns.insert(assignment_reg.to_string(), values);
+        columns.insert(format!("{assignment_reg}_free_value"), free_values);
+    }
+
+    columns.extend(helper_columns(&columns, &pc, &rom)?);
+    columns.extend(operation_id_columns(trace.len, &pc, &rom, sink_pc));
+
+    let missing = witness_names
+        .iter()
+        .filter(|name| !columns.contains_key(*name))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Cannot compute the columns {} of the main machine.",
+            missing.iter().format(", ")
+        ));
+    }
+
+    Ok(columns
+        .into_iter()
+        .filter(|(name, _)| witness_names.contains(name))
+        .map(|(name, values)| (format!("{MAIN}.{name}"), values))
+        .collect())
+}
+
+/// The fixed columns of the main machine's ROM, indexed by the PC.
+struct Rom<'a, F> {
+    columns: HashMap<&'a str, &'a [F]>,
+}
+
+impl<'a, F: FieldElement> Rom<'a, F> {
+    fn new(fixed: &'a [(String, Vec<F>)]) -> Self {
+        Self {
+            columns: fixed
+                .iter()
+                .filter_map(|(name, values)| {
+                    let name = name.strip_prefix(MAIN)?.strip_prefix('.')?;
+                    Some((name, &values[..]))
+                })
+                .collect(),
+        }
+    }
+
+    fn column(&self, name: &str) -> Option<&'a [F]> {
+        self.columns.get(name).copied()
+    }
+
+    /// The ROM column of the given flag, e.g. `instr_return`.
+    fn flag(&self, flag: &str) -> Option<&'a [F]> {
+        self.column(&format!("p_{flag}"))
+    }
+
+    /// Whether the given flag is set for the PC. ROM columns that were removed by the
+    /// optimizer because they are constant are zero.
+    fn is_active(&self, flag: &str, pc: usize) -> bool {
+        self.flag(flag).is_some_and(|column| column[pc].is_one())
+    }
+
+    /// The first PC for which the given flag is set.
+    fn line_of(&self, flag: &str) -> Option<usize> {
+        self.flag(flag)?.iter().position(|value| value.is_one())
+    }
+
+    /// Pairs each register with its column in the ROM for the given prefix,
+    /// skipping registers whose column does not exist.
+    fn register_columns<'r>(
+        &self,
+        prefix: &str,
+        registers: &'r HashMap<&str, Vec<F>>,
+    ) -> Vec<(&'a [F], &'r [F])> {
+        registers
+            .iter()
+            .filter_map(|(name, values)| {
+                Some((self.column(&format!("{prefix}{name}"))?, &values[..]))
+            })
+            .collect()
+    }
+}
+
+/// Replays the trace and returns the values of all registers per row, for all `degree` rows.
+fn register_columns<F: FieldElement>(
+    trace: &ExecutionTrace<F>,
+    degree: usize,
+    sink_pc: usize,
+) -> HashMap<&str, Vec<F>> {
+    let mut columns: HashMap<&str, Vec<F>> = trace
+        .reg_map
+        .keys()
+        .map(|name| (&name[..], Vec::with_capacity(degree)))
+        .collect();
+
+    let mut rows = trace.replay();
+    for _ in 0..trace.len {
+        let row = rows.next_row().unwrap();
+        for (name, &index) in &trace.reg_map {
+            columns
+                .get_mut(&name[..])
+                .unwrap()
+                .push(row[index as usize].into_fe());
+        }
+    }
+
+    // The registers keep the values written by the last instructions in the `_reset`
+    // row, which zeroes them for the following rows.
+    let final_row = rows.final_row();
+    for row in 0..degree - trace.len {
+        for (name, &index) in &trace.reg_map {
+            let value = match (&name[..], row) {
+                ("pc", 0) => F::zero(),
+                ("pc", 1) => F::one(),
+                ("pc", _) => F::from(sink_pc as u64),
+                (_, 0) => final_row[index as usize].into_fe(),
+                _ => F::zero(),
+            };
+            columns.get_mut(&name[..]).unwrap().push(value);
+        }
+    }
+    columns
+}
+
+/// Computes an assignment register and its free value column from the
+/// constraint `X = sum(read_X_R * R) + X_const + X_read_free * X_free_value`.
+///
+/// The free value is only read if the ROM says so, either for a prover query or
+/// as the output of an instruction. In both cases, the value of the assignment
+/// register is written to some register, which lets us recover it from the
+/// next row.
+fn assignment_register_columns<F: FieldElement>(
+    reg: &str,
+    registers: &HashMap<&str, Vec<F>>,
+    pc: &[usize],
+    rom: &Rom<F>,
+) -> Result<(Vec<F>, Vec<F>), String> {
+    let reads = rom.register_columns(&format!("p_read_{reg}_"), registers);
+    let writes = rom.register_columns(&format!("p_reg_write_{reg}_"), registers);
+    let constant = rom.column(&format!("p_{reg}_const"));
+    let read_free = rom.column(&format!("p_{reg}_read_free"));
+    let at = |column: Option<&[F]>, pc: usize| column.map_or(F::zero(), |column| column[pc]);
+
+    let mut values = Vec::with_capacity(pc.len());
+    let mut free_values = Vec::with_capacity(pc.len());
+
+    for (row, &pc) in pc.iter().enumerate() {
+        let known_part = reads
+            .iter()
+            .fold(at(constant, pc), |acc, (coefficient, values)| {
+                acc + coefficient[pc] * values[row]
+            });
+        let read_free = at(read_free, pc);
+
+        if read_free.is_zero() {
+            values.push(known_part);
+            free_values.push(F::zero());
+            continue;
+        }
+
+        let value = writes
+            .iter()
+            .find(|(selector, _)| !selector[pc].is_zero())
+            .and_then(|(_, values)| values.get(row + 1))
+            .ok_or_else(|| format!("Cannot determine the value of {reg} in row {row}."))?;
+        values.push(*value);
+        free_values.push((*value - known_part) / read_free);
+    }
+
+    Ok((values, free_values))
+}
+
+/// Computes the columns in [HELPER_COLUMNS] from the assignment registers, in the way
+/// the instructions of the RISC-V compiler constrain them.
+fn helper_columns<F: FieldElement>(
+    columns: &BTreeMap<String, Vec<F>>,
+    pc: &[usize],
+    rom: &Rom<F>,
+) -> Result<BTreeMap<String, Vec<F>>, String> {
+    let zero = vec![F::zero(); pc.len()];
+    let [x, y, z, w] = ["X", "Y", "Z", "W"].map(|reg| columns.get(reg).unwrap_or(&zero));
+    let instructions = HELPER_INSTRUCTIONS
+        .iter()
+        .filter_map(|&instruction| Some((instruction, rom.flag(&format!("instr_{instruction}"))?)))
+        .collect::<Vec<_>>();
+
+    let mut helpers: BTreeMap<String, Vec<F>> = HELPER_COLUMNS
+        .iter()
+        .map(|name| (name.to_string(), zero.clone()))
+        .collect();
+    for (row, &pc) in pc.iter().enumerate() {
+        let (x, y, z, w) = (x[row], y[row], z[row], w[row]);
+        let x_inv = if x.is_zero() { F::zero() } else { F::one() / x };
+        let mut values = vec![("XInv", x_inv), ("XIsZero", F::from(x.is_zero() as u64))];
+
+        if let Some((instruction, _)) = instructions.iter().find(|(_, flag)| flag[pc].is_one()) {
+            let values_of_instruction = instruction_helper_values(instruction, x, y, z, w)
+                .ok_or_else(|| {
+                    format!("Invalid assignment registers for {instruction} in row {row}.")
+                })?;
+            values.extend(
+                values_of_instruction
+                    .into_iter()
+                    .map(|(name, value)| (name, F::from(value))),
+            );
+        }
+
+        for (name, value) in values {
+            helpers.get_mut(name).unwrap()[row] = value;
+        }
+    }
+    Ok(helpers)
+}
+
+/// The values of the helper columns used by the given instruction, given its assignment
+/// registers. Returns `None` if the registers are out of the range the instruction allows.
+fn instruction_helper_values<F: FieldElement>(
+    instruction: &str,
+    x: F,
+    y: F,
+    z: F,
+    w: F,
+) -> Option<Vec<(&'static str, u64)>> {
+    let bytes = |names: [&'static str; 4], value: u64| {
+        names
+            .into_iter()
+            .enumerate()
+            .map(move |(i, name)| (name, (value >> (8 * i)) & 0xff))
+    };
+    let x_bytes = |value| bytes(["X_b1", "X_b2", "X_b3", "X_b4"], value);
+    let y_bytes = |value| bytes(["Y_b5", "Y_b6", "Y_b7", "Y_b8"], value);
+    let rem_bytes = |value| bytes(["REM_b1", "REM_b2", "REM_b3", "REM_b4"], value);
+    let two_32 = F::from(1u64 << 32);
+
+    let values = match instruction {
+        "branch_if_positive" | "is_positive" => {
+            let value = to_u64(x + two_32 - F::one())?;
+            x_bytes(value).chain([("wrap_bit", value >> 32)]).collect()
+        }
+        "wrap" => x_bytes(to_u64(x)?)
+            .chain([("wrap_bit", to_u64(y)? >> 32)])
+            .collect(),
+        "wrap_signed" => x_bytes(to_u64(x)?)
+            .chain([("wrap_bit", to_u64(y + two_32)? >> 32)])
+            .collect(),
+        "sign_extend_byte" => {
+            let y = to_u64(y)?;
+            x_bytes(y)
+                .skip(1)
+                .chain([("Y_7bit", y & 0x7f), ("wrap_bit", (y >> 7) & 1)])
+                .collect()
+        }
+        "sign_extend_16_bits" => {
+            let y = to_u64(y)?;
+            x_bytes(y)
+                .filter(|(name, _)| *name != "X_b2")
+                .chain([
+                    ("Y_7bit", (y >> 8) & 0x7f),
+                    ("Y_15bit", y & 0x7fff),
+                    ("wrap_bit", (y >> 15) & 1),
+                ])
+                .collect()
+        }
+        "to_signed" => {
+            let y = to_u64(y)?;
+            x_bytes(y)
+                .take(3)
+                .chain([("Y_7bit", (y >> 24) & 0x7f), ("wrap_bit", (y >> 31) & 1)])
+                .collect()
+        }
+        "wrap16" => {
+            let y = to_u64(y)?;
+            x_bytes(to_u64(x)?)
+                .chain([("Y_b5", (y >> 32) & 0xff), ("Y_b6", (y >> 40) & 0xff)])
+                .collect()
+        }
+        "divremu" => {
+            let remainder_bound = if x.is_zero() {
+                0
+            } else {
+                to_u64(x - w - F::one())?
+            };
+            x_bytes(to_u64(z)?)
+                .chain(rem_bytes(to_u64(w)?))
+                .chain(y_bytes(remainder_bound))
+                .collect()
+        }
+        "mul" => x_bytes(to_u64(x)?).chain(y_bytes(to_u64(y)?)).collect(),
+        "mload" => {
+            let y = to_u64(y)?;
+            x_bytes(y)
+                .map(|(name, value)| match name {
+                    "X_b1" => (name, (y >> 2) & 0x3f),
+                    _ => (name, value),
+                })
+                .chain([("wrap_bit", y >> 32)])
+                .collect()
+        }
+        "mstore" | "mstore_bootloader" => {
+            let y = to_u64(y)?;
+            x_bytes(y).chain([("wrap_bit", y >> 32)]).collect()
+        }
+        _ => unreachable!(),
+    };
+    Some(values)
+}
+
+/// The value as an unsigned integer, if it is smaller than 2**64.
+fn to_u64<F: FieldElement>(value: F) -> Option<u64> {
+    value.to_arbitrary_integer().try_into().ok()
+}
+
+/// Computes `_operation_id`, which is the operation of the main machine until it
+/// returns in the last row of the trace and the sink afterwards, and
+/// `_operation_id_no_change`, which is only zero in the row of the return and in the last row.
+fn operation_id_columns<F: FieldElement>(
+    trace_len: usize,
+    pc: &[usize],
+    rom: &Rom<F>,
+    sink_pc: usize,
+) -> BTreeMap<String, Vec<F>> {
+    // `_jump_to_operation` jumps to the operation.
+    let operation = pc
+        .iter()
+        .position(|&pc| rom.is_active("instr__jump_to_operation", pc))
+        .map_or(0, |row| pc[row + 1]);
+    let operation_id = (0..pc.len())
+        .map(|row| F::from(if row < trace_len { operation } else { sink_pc } as u64))
+        .collect();
+    let returns = rom.flag("instr_return").unwrap();
+    let operation_id_no_change = pc
+        .iter()
+        .enumerate()
+        .map(|(row, &pc_value)| {
+            let change = row == pc.len() - 1 || returns[pc_value].is_one();
+            F::from(!change as u64)
+        })
+        .collect();
+    [
+        ("_operation_id".to_string(), operation_id),
+        (
+            "_operation_id_no_change".to_string(),
+            operation_id_no_change,
+        ),
+    ]
+    .into()
+}
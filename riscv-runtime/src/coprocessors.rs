@@ -6,6 +6,20 @@ extern "C" {
     // during the reachability analysis.
     fn poseidon_gl_coprocessor(data: *mut [u64; 12]);
 
+    // This will be replaced by a call to the Poseidon BN254 coprocessor.
+    fn poseidon_bn254_coprocessor(data: *mut [[u32; 8]; 3]);
+
+    // This will be replaced by a call to the Keccak-f coprocessor.
+    fn keccakf_coprocessor(data: *mut [u64; 25]);
+
+    // This will be replaced by a call to the SHA-256 coprocessor.
+    fn sha256_compress_coprocessor(state: *mut [u32; 8], block: *const [u32; 16]);
+
+    // These will be replaced by calls to the 256-bit arithmetic coprocessor.
+    fn affine_256_coprocessor(data: *mut [u32; 24]);
+    fn ec_add_coprocessor(data: *mut [u32; 32]);
+    fn ec_double_coprocessor(data: *mut [u32; 16]);
+
     // This will be replaced by a call to prover input.
     fn input_coprocessor(index: u32, channel: u32) -> u32;
 }
@@ -65,3 +79,97 @@ pub fn poseidon_gl_unsafe(mut data: [u64; 12]) -> [u64; 4] {
 
     [data[0], data[1], data[2], data[3]]
 }
+
+/// The BN254 scalar field modulus as 32-bit limbs in little-endian order.
+const BN254: [u32; 8] = [
+    0xf0000001, 0x43e1f593, 0x79b97091, 0x2833e848, 0x8181585d, 0xb85045b6, 0xe131a029, 0x30644e72,
+];
+
+/// Calls the low level Poseidon BN254 coprocessor in PIL, where each of the
+/// three field elements is given as 32-bit limbs in little-endian order and
+/// the last one is the "cap". Returns the first element of the permuted state.
+/// Panics if any of the elements is not less than the BN254 field modulus.
+pub fn poseidon_bn254(mut data: [[u32; 8]; 3]) -> [u32; 8] {
+    for n in data.iter() {
+        assert!(n.iter().rev().lt(BN254.iter().rev()));
+    }
+
+    unsafe {
+        poseidon_bn254_coprocessor(&mut data as *mut [[u32; 8]; 3]);
+    }
+
+    data[0]
+}
+
+/// Applies the Keccak-f[1600] permutation to the state in place,
+/// where lane (x, y) is `data[x + 5 * y]`.
+pub fn keccakf(data: &mut [u64; 25]) {
+    unsafe {
+        keccakf_coprocessor(data as *mut [u64; 25]);
+    }
+}
+
+/// Applies the SHA-256 compression function to the state in place, where
+/// `block` is the 64-byte message block as big-endian 32-bit words.
+pub fn sha256_compress(state: &mut [u32; 8], block: &[u32; 16]) {
+    unsafe {
+        sha256_compress_coprocessor(state as *mut [u32; 8], block as *const [u32; 16]);
+    }
+}
+
+/// Computes `a * b + c` on 256-bit numbers given as 32-bit limbs in
+/// little-endian order and returns the 512-bit result as `(hi, lo)`.
+pub fn affine_256(a: &[u32; 8], b: &[u32; 8], c: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    let mut data = [0u32; 24];
+    data[0..8].copy_from_slice(a);
+    data[8..16].copy_from_slice(b);
+    data[16..24].copy_from_slice(c);
+
+    unsafe {
+        affine_256_coprocessor(&mut data as *mut [u32; 24]);
+    }
+
+    split_result(&data[0..16])
+}
+
+/// Adds two distinct points on the secp256k1 curve, with coordinates given
+/// as 32-bit limbs in little-endian order. The points must not be each other's
+/// negation, i.e. `x1 != x2`.
+pub fn ec_add(
+    (x1, y1): (&[u32; 8], &[u32; 8]),
+    (x2, y2): (&[u32; 8], &[u32; 8]),
+) -> ([u32; 8], [u32; 8]) {
+    let mut data = [0u32; 32];
+    data[0..8].copy_from_slice(x1);
+    data[8..16].copy_from_slice(y1);
+    data[16..24].copy_from_slice(x2);
+    data[24..32].copy_from_slice(y2);
+
+    unsafe {
+        ec_add_coprocessor(&mut data as *mut [u32; 32]);
+    }
+
+    split_result(&data[0..16])
+}
+
+/// Doubles a point on the secp256k1 curve, with coordinates given
+/// as 32-bit limbs in little-endian order.
+pub fn ec_double((x, y): (&[u32; 8], &[u32; 8])) -> ([u32; 8], [u32; 8]) {
+    let mut data = [0u32; 16];
+    data[0..8].copy_from_slice(x);
+    data[8..16].copy_from_slice(y);
+
+    unsafe {
+        ec_double_coprocessor(&mut data as *mut [u32; 16]);
+    }
+
+    split_result(&data)
+}
+
+fn split_result(data: &[u32]) -> ([u32; 8], [u32; 8]) {
+    let mut first = [0u32; 8];
+    let mut second = [0u32; 8];
+    first.copy_from_slice(&data[0..8]);
+    second.copy_from_slice(&data[8..16]);
+    (first, second)
+}
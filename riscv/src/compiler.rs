@@ -16,7 +16,12 @@ use powdr_asm_utils::{
     Architecture,
 };
 
-use crate::continuations::bootloader::{bootloader_and_shutdown_routine, bootloader_preamble};
+use crate::continuations::bootloader::{
+    bootloader_and_shutdown_routine, bootloader_preamble, MemoryLayout,
+};
+use crate::continuations::memory_hash::{
+    MemoryHash, MemoryHashFunction, PoseidonBn254, PoseidonGl,
+};
 use crate::coprocessors::*;
 use crate::disambiguator;
 use crate::parser::RiscParser;
@@ -102,13 +107,12 @@ impl Architecture for RiscvArchitecture {
 }
 
 /// Compiles riscv assembly to a powdr assembly file. Adds required library routines.
+/// If `bootloader` is set, adds a bootloader for continuations using the given memory layout.
 pub fn compile(
     mut assemblies: BTreeMap<String, String>,
     coprocessors: &CoProcessors,
-    with_bootloader: bool,
+    bootloader: Option<MemoryLayout>,
 ) -> String {
-    // stack grows towards zero
-    let stack_start = 0x10000;
     // data grows away from zero
     let data_start = 0x10100;
 
@@ -141,39 +145,93 @@ pub fn compile(
     statements = replace_coprocessor_stubs(statements, coprocessors).collect::<Vec<_>>();
 
     let (data_code, data_positions) =
-        store_data_objects(data_sections, data_start, &mut |addr, value| match value {
-            SingleDataValue::Value(v) => {
-                vec![format!("mstore 0x{addr:x}, 0x{v:x};")]
-            }
-            SingleDataValue::LabelReference(sym) => {
-                // TODO should be possible without temporary
-                vec![
-                    format!("tmp1 <== load_label({});", escape_label(sym)),
-                    format!("mstore 0x{addr:x}, tmp1;"),
-                ]
-            }
-            SingleDataValue::Offset(_, _) => {
-                unimplemented!();
-                /*
-                object_code.push(format!("addr <=X= 0x{pos:x};"));
-
-                I think this solution should be fine but hard to say without
-                an actual code snippet that uses it.
-
-                // TODO should be possible without temporary
-                object_code.extend([
-                    format!("tmp1 <== load_label({});", escape_label(a)),
-                    format!("tmp2 <== load_label({});", escape_label(b)),
-                    // TODO check if registers match
-                    "mstore wrap(tmp1 - tmp2);".to_string(),
-                ]);
-                */
-            }
-        });
+        store_data_objects(data_sections, data_start, &mut data_initialization);
+
+    let file_ids = file_ids
+        .into_iter()
+        .map(|(id, dir, file)| format!(".debug file {id} {} {};", quote(&dir), quote(&file)));
+    let statements = substitute_symbols_with_values(statements, &data_positions);
+
+    riscv_program(
+        file_ids,
+        statements,
+        data_code,
+        "__runtime_start",
+        coprocessors,
+        bootloader,
+    )
+}
+
+/// Generates the code that stores a single data word in memory.
+pub(crate) fn data_initialization(addr: u32, value: SingleDataValue) -> Vec<String> {
+    match value {
+        SingleDataValue::Value(v) => {
+            vec![format!("mstore 0x{addr:x}, 0x{v:x};")]
+        }
+        SingleDataValue::LabelReference(sym) => {
+            // TODO should be possible without temporary
+            vec![
+                format!("tmp1 <== load_label({});", escape_label(sym)),
+                format!("mstore 0x{addr:x}, tmp1;"),
+            ]
+        }
+        SingleDataValue::Offset(_, _) => {
+            unimplemented!();
+            /*
+            object_code.push(format!("addr <=X= 0x{pos:x};"));
+
+            I think this solution should be fine but hard to say without
+            an actual code snippet that uses it.
+
+            // TODO should be possible without temporary
+            object_code.extend([
+                format!("tmp1 <== load_label({});", escape_label(a)),
+                format!("tmp2 <== load_label({});", escape_label(b)),
+                // TODO check if registers match
+                "mstore wrap(tmp1 - tmp2);".to_string(),
+            ]);
+            */
+        }
+    }
+}
+
+/// The initial value of the stack pointer. The stack grows towards zero.
+pub(crate) const STACK_START: u32 = 0x10000;
 
+/// Builds the powdr-asm machine from the (already reduced) RISC-V statements
+/// and the data initialization code. Execution starts at `entry_label`.
+pub(crate) fn riscv_program(
+    debug_files: impl IntoIterator<Item = String>,
+    statements: Vec<Statement>,
+    data_code: Vec<String>,
+    entry_label: &str,
+    coprocessors: &CoProcessors,
+    bootloader: Option<MemoryLayout>,
+) -> String {
     let submachine_init = call_every_submachine(coprocessors);
-    let bootloader_and_shutdown_routine_lines = if with_bootloader {
-        let bootloader_and_shutdown_routine = bootloader_and_shutdown_routine(&submachine_init);
+    let bootloader_and_shutdown_routine_lines = if let Some(layout) = &bootloader {
+        assert!(
+            coprocessors.has(layout.hash.coprocessor()),
+            "The {} coprocessor is required for the bootloader",
+            layout.hash
+        );
+        // With Poseidon GL, the machine would have the 12 `P` registers of Poseidon GL
+        // instead of the 8 expected by the bootloader.
+        assert!(
+            layout.hash == MemoryHashFunction::PoseidonGl
+                || !coprocessors.has(PoseidonGl::COPROCESSOR),
+            "The {} coprocessor cannot be used together with a bootloader hashing with {}",
+            PoseidonGl::COPROCESSOR,
+            layout.hash
+        );
+        let bootloader_and_shutdown_routine = match layout.hash {
+            MemoryHashFunction::PoseidonGl => {
+                bootloader_and_shutdown_routine::<PoseidonGl>(layout, &submachine_init)
+            }
+            MemoryHashFunction::PoseidonBn254 => {
+                bootloader_and_shutdown_routine::<PoseidonBn254>(layout, &submachine_init)
+            }
+        };
         log::debug!("Adding Bootloader:\n{}", bootloader_and_shutdown_routine);
         bootloader_and_shutdown_routine
             .split('\n')
@@ -183,18 +241,17 @@ pub fn compile(
         submachine_init
     };
 
-    let program: Vec<String> = file_ids
+    let program: Vec<String> = debug_files
         .into_iter()
-        .map(|(id, dir, file)| format!(".debug file {id} {} {};", quote(&dir), quote(&file)))
         .chain(bootloader_and_shutdown_routine_lines)
         .chain(["x1 <== jump(__data_init);".to_string()])
         .chain([
-            format!("// Set stack pointer\nx2 <=X= {stack_start};"),
-            "x1 <== jump(__runtime_start);".to_string(),
+            format!("// Set stack pointer\nx2 <=X= {STACK_START};"),
+            format!("x1 <== jump({});", escape_label(entry_label)),
             "return;".to_string(), // This is not "riscv ret", but "return from powdr asm function".
         ])
         .chain(
-            substitute_symbols_with_values(statements, &data_positions)
+            statements
                 .into_iter()
                 .flat_map(|v| process_statement(v, coprocessors)),
         )
@@ -224,7 +281,7 @@ pub fn compile(
 
     riscv_machine(
         &coprocessors.machine_imports(),
-        &preamble(degree, coprocessors, with_bootloader),
+        &preamble(degree, coprocessors, bootloader.map(|layout| layout.hash)),
         &coprocessors.declarations(),
         program,
     )
@@ -434,11 +491,15 @@ machine Main {{
     )
 }
 
-fn preamble(degree: u64, coprocessors: &CoProcessors, with_bootloader: bool) -> String {
-    let bootloader_preamble_if_included = if with_bootloader {
-        bootloader_preamble()
-    } else {
-        "".to_string()
+fn preamble(
+    degree: u64,
+    coprocessors: &CoProcessors,
+    bootloader_hash: Option<MemoryHashFunction>,
+) -> String {
+    let bootloader_preamble_if_included = match bootloader_hash {
+        None => "".to_string(),
+        Some(MemoryHashFunction::PoseidonGl) => bootloader_preamble::<PoseidonGl>(),
+        Some(MemoryHashFunction::PoseidonBn254) => bootloader_preamble::<PoseidonBn254>(),
     };
 
     format!("degree {degree};")
@@ -463,7 +524,7 @@ fn preamble(degree: u64, coprocessors: &CoProcessors, with_bootloader: bool) ->
             .collect::<Vec<_>>()
             .concat()
         + &bootloader_preamble_if_included
-        + &memory(with_bootloader)
+        + &memory(bootloader_hash.is_some())
         + r#"
     // ============== Constraint on x0 =======================
 
@@ -1291,9 +1352,24 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             }
         }
         "jalr" => {
-            // TODO there is also a form that takes more arguments
-            let rs = r(args);
-            vec![format!("x1 <== jump_dyn({rs});")]
+            if let [Argument::Register(rs)] = args {
+                vec![format!("x1 <== jump_dyn({rs});")]
+            } else {
+                let (rd, rs, off) = rro(args);
+                let rd = if rd.is_zero() {
+                    "tmp1".to_string()
+                } else {
+                    rd.to_string()
+                };
+                if off == 0 {
+                    vec![format!("{rd} <== jump_dyn({rs});")]
+                } else {
+                    vec![
+                        format!("tmp1 <== wrap({rs} + {off});"),
+                        format!("{rd} <== jump_dyn(tmp1);"),
+                    ]
+                }
+            }
         }
         "call" | "tail" => {
             // Depending on what symbol is called, the call is replaced by a
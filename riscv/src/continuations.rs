@@ -1,26 +1,44 @@
-use std::collections::{BTreeSet, HashMap};
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Mutex,
+};
 
 use powdr_ast::{
+    analyzed::Analyzed,
     asm_analysis::{AnalysisASMFile, RegisterTy},
     parsed::asm::parse_absolute_path,
 };
-use powdr_number::FieldElement;
-use powdr_pipeline::{Pipeline, Stage};
-use powdr_riscv_executor::ExecutionTrace;
+use powdr_executor::witgen::extract_publics;
+use powdr_number::{DegreeType, FieldElement};
+use powdr_pipeline::{
+    util::{read_poly_set, PolySet, WitnessPolySet},
+    Pipeline, Proof, Stage,
+};
+use powdr_riscv_executor::{ExecutionTrace, MemOperation};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
 pub mod bootloader;
+pub mod manifest;
+pub mod memory_hash;
 mod memory_merkle_tree;
 
-use bootloader::{default_input, PAGE_SIZE_BYTES_LOG, PC_INDEX, REGISTER_NAMES};
+use bootloader::{
+    chained_public_indices, default_input, pc_index, public_names, register_names, MemoryLayout,
+};
+use manifest::{Chunk, ChunkStatus, ContinuationsManifest};
+use memory_hash::{MemoryHash, MemoryHashFunction, PoseidonBn254, PoseidonGl};
 use memory_merkle_tree::MerkleTree;
 
 use crate::continuations::bootloader::{
-    default_register_values, shutdown_routine_upper_bound, BOOTLOADER_INPUTS_PER_PAGE,
-    BOOTLOADER_SPECIFIC_INSTRUCTION_NAMES, DEFAULT_PC, MEMORY_HASH_START_INDEX, PAGE_INPUTS_OFFSET,
-    WORDS_PER_PAGE,
+    bootloader_inputs_per_page, bootloader_upper_bound, default_register_values,
+    memory_hash_start_index, page_inputs_offset, shutdown_routine_upper_bound,
+    BOOTLOADER_SPECIFIC_INSTRUCTION_NAMES, DEFAULT_PC,
 };
 
-fn transposed_trace<F: FieldElement>(trace: &ExecutionTrace) -> HashMap<String, Vec<F>> {
+fn transposed_trace<F: FieldElement>(trace: &ExecutionTrace<F>) -> HashMap<String, Vec<F>> {
     let mut reg_values: HashMap<&str, Vec<F>> = HashMap::with_capacity(trace.reg_map.len());
 
     let mut rows = trace.replay();
@@ -29,7 +47,7 @@ fn transposed_trace<F: FieldElement>(trace: &ExecutionTrace) -> HashMap<String,
             reg_values
                 .entry(reg_name)
                 .or_default()
-                .push(row[index as usize].0.into());
+                .push(row[index as usize].into_fe());
         }
     }
 
@@ -46,7 +64,8 @@ fn render_hash<F: FieldElement>(hash: &[F]) -> String {
         .join("")
 }
 
-/// Calls the provided `pipeline_callback` for each chunk of the execution.
+/// Calls the provided `pipeline_callback` for each chunk of the execution and returns the results
+/// of all calls.
 ///
 /// # Arguments
 /// - `pipeline`: The pipeline that should be the starting point for all the chunks.
@@ -54,13 +73,13 @@ fn render_hash<F: FieldElement>(hash: &[F]) -> String {
 ///   but with the `PilWithEvaluatedFixedCols` stage already advanced to and all chunk-specific parameters set.
 /// - `bootloader_inputs`: The inputs to the bootloader and the index of the row at which the shutdown routine
 ///   is supposed to execute, for each chunk, as returned by `rust_continuations_dry_run`.
-pub fn rust_continuations<F: FieldElement, PipelineCallback, E>(
+pub fn rust_continuations<F: FieldElement, PipelineCallback, R, E>(
     mut pipeline: Pipeline<F>,
     pipeline_callback: PipelineCallback,
     bootloader_inputs: Vec<(Vec<F>, u64)>,
-) -> Result<(), E>
+) -> Result<Vec<R>, E>
 where
-    PipelineCallback: Fn(Pipeline<F>) -> Result<(), E>,
+    PipelineCallback: Fn(Pipeline<F>) -> Result<R, E>,
 {
     let num_chunks = bootloader_inputs.len();
 
@@ -82,33 +101,350 @@ where
         .into_iter()
         .enumerate()
         .map(
-            |(i, (bootloader_inputs, start_of_shutdown_routine))| -> Result<(), E> {
+            |(i, (bootloader_inputs, start_of_shutdown_routine))| -> Result<R, E> {
                 log::info!("\nRunning chunk {} / {}...", i + 1, num_chunks);
-                let pipeline = pipeline.clone();
                 let name = format!("{}_chunk_{}", pipeline.name(), i);
-                let pipeline = pipeline.with_name(name);
-                // The `jump_to_shutdown_routine` column indicates when the execution should jump to the shutdown routine.
-                // In that row, the normal PC update is ignored and the PC is set to the address of the shutdown routine.
-                // In other words, it should be a one-hot encoding of `start_of_shutdown_routine`.
-                let jump_to_shutdown_routine = (0..length)
-                    .map(|i| (i == start_of_shutdown_routine - 1).into())
-                    .collect();
-                let pipeline = pipeline.add_external_witness_values(vec![
-                    ("main.bootloader_input_value".to_string(), bootloader_inputs),
-                    (
-                        "main.jump_to_shutdown_routine".to_string(),
-                        jump_to_shutdown_routine,
-                    ),
-                ]);
-                pipeline_callback(pipeline)?;
-                Ok(())
+                pipeline_callback(chunk_pipeline(
+                    &pipeline,
+                    length,
+                    name,
+                    bootloader_inputs,
+                    start_of_shutdown_routine,
+                ))
             },
         )
-        .collect::<Result<Vec<_>, E>>()?;
+        .collect()
+}
+
+/// Like [rust_continuations], but processes the chunks in parallel on `num_workers` threads and
+/// records their progress in the manifest at `manifest_path`, which is updated whenever a chunk
+/// is finished.
+///
+/// Chunks that have already reached `target_status` (see [manifest::Chunk::is_done]) are skipped,
+/// so that an interrupted run can be resumed. All other chunks are processed again, overwriting
+/// any outputs they might have left in the pipeline's output directory. A chunk whose callback
+/// fails or panics is recorded as [ChunkStatus::Failed].
+///
+/// Returns the results of the `pipeline_callback` for the chunks that were processed, by chunk index.
+pub fn rust_continuations_with_manifest<F: FieldElement, PipelineCallback, R>(
+    mut pipeline: Pipeline<F>,
+    pipeline_callback: PipelineCallback,
+    manifest: ContinuationsManifest<F>,
+    manifest_path: &Path,
+    target_status: ChunkStatus,
+    num_workers: usize,
+) -> Result<BTreeMap<usize, R>, Vec<String>>
+where
+    PipelineCallback: Fn(Pipeline<F>) -> Result<R, Vec<String>> + Sync,
+    R: Send,
+{
+    let output_dir = pipeline
+        .output_dir()
+        .ok_or_else(|| vec!["An output directory is required to resume chunks.".to_string()])?
+        .to_path_buf();
+    manifest.check_name(pipeline.name()).map_err(|e| vec![e])?;
+    manifest.write(manifest_path).map_err(|e| vec![e])?;
+
+    let num_chunks = manifest.chunks.len();
+    let pending_chunks = (0..num_chunks)
+        .filter(|&i| !manifest.chunks[i].is_done(&output_dir, target_status))
+        .collect::<Vec<_>>();
+    log::info!(
+        "{} of {num_chunks} chunks are already done, processing {} chunks on {num_workers} workers.",
+        num_chunks - pending_chunks.len(),
+        pending_chunks.len()
+    );
+
+    log::info!("Advancing pipeline to PilWithEvaluatedFixedCols stage...");
+    pipeline.advance_to(Stage::PilWithEvaluatedFixedCols)?;
+    let length = pipeline.pil_with_evaluated_fixed_cols_ref()?.pil.degree();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_workers)
+        .build()
+        .map_err(|e| vec![format!("Could not create worker pool: {e}")])?;
+    let manifest = Mutex::new(manifest);
+    pool.install(|| {
+        pending_chunks
+            .into_par_iter()
+            .map(|i| {
+                let chunk = manifest.lock().unwrap().chunks[i].clone();
+                log::info!("\nRunning chunk {} / {num_chunks}...", i + 1);
+                let chunk_pipeline = chunk_pipeline(
+                    &pipeline,
+                    length,
+                    chunk.name.clone(),
+                    chunk.bootloader_inputs.clone(),
+                    chunk.start_of_shutdown_routine,
+                )
+                .with_output(output_dir.clone(), true);
+                // A panic in one chunk is recorded as its failure instead of tearing
+                // down the whole worker pool.
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| pipeline_callback(chunk_pipeline)))
+                        .unwrap_or_else(|payload| Err(vec![panic_message(payload.as_ref())]));
+
+                // Outputs beyond the target status may be left over from a run with other
+                // prover settings.
+                let status = match result {
+                    Ok(_) => chunk.status_of_outputs(&output_dir).min(target_status),
+                    Err(_) => ChunkStatus::Failed,
+                };
+                log::info!("Chunk {} / {num_chunks}: {status:?}", i + 1);
+                let mut manifest = manifest.lock().unwrap();
+                manifest.chunks[i].status = status;
+                manifest.write(manifest_path).map_err(|e| vec![e])?;
+
+                result.map(|r| (i, r)).map_err(|errors| {
+                    errors
+                        .into_iter()
+                        .map(|e| format!("Chunk {i}: {e}"))
+                        .collect()
+                })
+            })
+            .collect()
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+    format!("Panicked: {message}")
+}
+
+/// Reads the public values of a chunk that was processed by a previous run from the witness it
+/// saved to `output_dir`, i.e., the witness its proof (if any) was generated from.
+///
+/// Fails if the chunk has not saved a witness.
+pub fn saved_chunk_publics<F: FieldElement>(
+    pil: &Analyzed<F>,
+    chunk: &Chunk<F>,
+    output_dir: &Path,
+) -> Result<Vec<F>, Vec<String>> {
+    let witness_path = output_dir.join(format!("{}_{}", chunk.name, WitnessPolySet::FILE_NAME));
+    if !witness_path.exists() {
+        return Err(vec![format!(
+            "Cannot read the public values of chunk {}: {} does not exist.",
+            chunk.name,
+            witness_path.display()
+        )]);
+    }
+    let (witness, _) = read_poly_set::<WitnessPolySet, F>(pil, output_dir, &chunk.name);
+    Ok(extract_publics(&witness, pil)
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect())
+}
+
+/// Returns the pipeline of a single chunk, with all chunk-specific parameters set.
+fn chunk_pipeline<F: FieldElement>(
+    pipeline: &Pipeline<F>,
+    length: DegreeType,
+    name: String,
+    bootloader_inputs: Vec<F>,
+    start_of_shutdown_routine: u64,
+) -> Pipeline<F> {
+    // The `jump_to_shutdown_routine` column indicates when the execution should jump to the shutdown routine.
+    // In that row, the normal PC update is ignored and the PC is set to the address of the shutdown routine.
+    // In other words, it should be a one-hot encoding of `start_of_shutdown_routine`.
+    let jump_to_shutdown_routine = (0..length)
+        .map(|i| (i == start_of_shutdown_routine - 1).into())
+        .collect();
+    pipeline
+        .clone()
+        .with_name(name)
+        .add_external_witness_values(vec![
+            ("main.bootloader_input_value".to_string(), bootloader_inputs),
+            (
+                "main.jump_to_shutdown_routine".to_string(),
+                jump_to_shutdown_routine,
+            ),
+        ])
+}
+
+/// The names of the public values exposed by each chunk of a program compiled with `layout`,
+/// in the order of their declaration (see [public_names]).
+pub fn chunk_public_names(layout: &MemoryLayout) -> Vec<String> {
+    match layout.hash {
+        MemoryHashFunction::PoseidonGl => public_names::<PoseidonGl>(),
+        MemoryHashFunction::PoseidonBn254 => public_names::<PoseidonBn254>(),
+    }
+}
+
+/// The public values exposed by a chunk with the given bootloader inputs, i.e., its initial and
+/// final register values and memory root hashes, in the order of [chunk_public_names].
+pub fn chunk_publics<F: FieldElement>(bootloader_inputs: &[F], layout: &MemoryLayout) -> Vec<F> {
+    bootloader_inputs[..chunk_public_names(layout).len()].to_vec()
+}
+
+/// Checks that the chunks of an execution are chained correctly, given the public values of
+/// each chunk in the order of their declaration, i.e., in the order in which they are passed
+/// to the verifier (see [chunk_public_names]):
+/// - The first chunk starts with the default register values and an empty memory.
+/// - The final register values and memory root hash of each chunk are equal to the initial
+///   ones of the next chunk.
+/// - The last chunk ends the execution, i.e., its final PC is the one set by the final `return`.
+///
+/// The `layout` is needed to compute the root hash of the empty memory.
+pub fn check_chunk_chaining<F: FieldElement>(
+    chunk_publics: &[Vec<F>],
+    layout: &MemoryLayout,
+) -> Result<(), Vec<String>> {
+    match layout.hash {
+        MemoryHashFunction::PoseidonGl => {
+            check_chunk_chaining_with_hash::<F, PoseidonGl>(chunk_publics, layout)
+        }
+        MemoryHashFunction::PoseidonBn254 => {
+            check_chunk_chaining_with_hash::<F, PoseidonBn254>(chunk_publics, layout)
+        }
+    }
+}
+
+fn check_chunk_chaining_with_hash<F: FieldElement, H: MemoryHash>(
+    chunk_publics: &[Vec<F>],
+    layout: &MemoryLayout,
+) -> Result<(), Vec<String>> {
+    if chunk_publics.is_empty() {
+        return Err(vec!["No chunks to check.".to_string()]);
+    }
+
+    // The values are identified by their position, because that is how the verifier sees them.
+    let names = public_names::<H>();
+    for (i, publics) in chunk_publics.iter().enumerate() {
+        if publics.len() != names.len() {
+            return Err(vec![format!(
+                "Chunk {i}: Expected {} public values, but got {}.",
+                names.len(),
+                publics.len()
+            )]);
+        }
+    }
+    let chained = chained_public_indices::<H>();
+    let state_name = |initial: usize| names[initial].strip_prefix("initial_").unwrap();
+
+    // The first chunk starts from the default register values and an empty memory,
+    // in the order of the chained values.
+    let mut expected_state = default_register_values::<F, H>();
+    expected_state.extend(MerkleTree::<F, H>::empty_hash(layout));
+    assert_eq!(expected_state.len(), chained.len());
+
+    let mut errors = vec![];
+    for (i, publics) in chunk_publics.iter().enumerate() {
+        for (&(initial, _), expected) in chained.iter().zip(&expected_state) {
+            let actual = publics[initial];
+            if actual != *expected {
+                errors.push(format!(
+                    "Chunk {i}: Initial value of {} is {actual}, but expected {expected}.",
+                    state_name(initial)
+                ));
+            }
+        }
+        expected_state = chained.iter().map(|&(_, final_)| publics[final_]).collect();
+    }
+
+    // The execution ends with a `return`, which sets the PC to 0. Otherwise, the chunks
+    // would only prove a prefix of the execution.
+    let final_pc = publics_index(&names, "final_pc");
+    let last = chunk_publics.len() - 1;
+    if chunk_publics[last][final_pc] != F::zero() {
+        errors.push(format!(
+            "Chunk {last}: The last chunk does not end the execution, its final pc is {}.",
+            chunk_publics[last][final_pc]
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The index of the public value with the given name.
+fn publics_index(names: &[String], name: &str) -> usize {
+    names.iter().position(|n| n == name).unwrap()
+}
+
+/// Verifies the proofs of all chunks of an execution and checks that the chunks are chained
+/// correctly (see `check_chunk_chaining`).
+///
+/// # Arguments
+/// - `pipeline`: A pipeline that can verify the proof of a single chunk, i.e., with the backend,
+///   setup and verification key set. All chunks share the same circuit.
+/// - `chunks`: The proof and the public values of each chunk (see [chunk_public_names]), in order.
+/// - `layout`: The memory layout the program was compiled with.
+pub fn verify_rust_continuations<F: FieldElement>(
+    mut pipeline: Pipeline<F>,
+    chunks: Vec<(Proof, Vec<F>)>,
+    layout: &MemoryLayout,
+) -> Result<(), Vec<String>> {
+    let num_chunks = chunks.len();
+    let chunk_publics = chunks
+        .iter()
+        .map(|(_, publics)| publics.clone())
+        .collect::<Vec<_>>();
+
+    log::info!("Checking that {num_chunks} chunks are chained correctly...");
+    check_chunk_chaining(&chunk_publics, layout)?;
+
+    for (i, (proof, publics)) in chunks.into_iter().enumerate() {
+        log::info!("Verifying chunk {} / {}...", i + 1, num_chunks);
+        pipeline.verify(proof, &[publics]).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|e| format!("Chunk {i}: {e}"))
+                .collect::<Vec<_>>()
+        })?;
+    }
     Ok(())
 }
 
-fn sanity_check<T>(program: &AnalysisASMFile<T>) {
+/// Aggregates the proofs of all chunks of an execution into a single proof, after checking that
+/// the chunks are chained correctly (see `check_chunk_chaining`). The aggregation circuit checks
+/// the chaining of the public values as well (see [chained_public_indices]).
+///
+/// # Arguments
+/// - `pipeline`: A pipeline for a single chunk, with the backend and the setup set that were
+///   used to prove the chunks. All chunks share the same circuit.
+/// - `chunks`: The proof and the public values of each chunk (see [chunk_public_names]), in order.
+/// - `layout`: The memory layout the program was compiled with.
+///
+/// Returns the aggregated proof and its public values: The initial state of the first chunk,
+/// followed by the final state of the last chunk.
+pub fn aggregate_rust_continuations<F: FieldElement>(
+    mut pipeline: Pipeline<F>,
+    chunks: Vec<(Proof, Vec<F>)>,
+    layout: &MemoryLayout,
+) -> Result<(Proof, Vec<F>), Vec<String>> {
+    let chunk_publics = chunks
+        .iter()
+        .map(|(_, publics)| publics.clone())
+        .collect::<Vec<_>>();
+
+    log::info!(
+        "Checking that {} chunks are chained correctly...",
+        chunks.len()
+    );
+    check_chunk_chaining(&chunk_publics, layout)?;
+
+    let chained_publics = chunk_chained_publics(layout);
+    log::info!("Aggregating the proofs of all chunks...");
+    pipeline.aggregate_proofs(chunks, &chained_publics)
+}
+
+/// The pairs `(initial, final)` of indices of the public values that are chained between
+/// the chunks of a program compiled with `layout` (see [chained_public_indices]).
+pub fn chunk_chained_publics(layout: &MemoryLayout) -> Vec<(usize, usize)> {
+    match layout.hash {
+        MemoryHashFunction::PoseidonGl => chained_public_indices::<PoseidonGl>(),
+        MemoryHashFunction::PoseidonBn254 => chained_public_indices::<PoseidonBn254>(),
+    }
+}
+
+fn sanity_check<T, H: MemoryHash>(program: &AnalysisASMFile<T>) {
     let main_machine = program.items[&parse_absolute_path("::Main")]
         .try_to_machine()
         .unwrap();
@@ -119,7 +455,7 @@ fn sanity_check<T>(program: &AnalysisASMFile<T>) {
             .any(|i| i.name == expected_instruction)
         {
             log::error!(
-                "Main machine is missing bootloader-specific instruction: {}. Did you set `with_bootloader` to true?",
+                "Main machine is missing bootloader-specific instruction: {}. Did you compile with a bootloader?",
                 expected_instruction
             );
             panic!();
@@ -135,31 +471,60 @@ fn sanity_check<T>(program: &AnalysisASMFile<T>) {
                 .then_some(format!("main.{}", r.name))
         })
         .collect::<BTreeSet<_>>();
-    let expected_registers = REGISTER_NAMES
-        .iter()
-        .map(|s| s.to_string())
-        .collect::<BTreeSet<_>>();
+    let expected_registers = register_names::<H>().into_iter().collect::<BTreeSet<_>>();
     assert_eq!(machine_registers, expected_registers);
 }
 
 /// Runs the entire execution using the RISC-V executor. For each chunk, it collects:
 /// - The inputs to the bootloader, needed to restore the correct state.
 /// - The number of rows after which the prover should jump to the shutdown routine.
+///
+/// The `layout` has to be the memory layout the program was compiled with, and its hash
+/// function has to be usable on the field `F` (see [MemoryHashFunction::for_field]). The dry run also
+/// logs how many chunks, pages and bootloader rows other layouts would need, so that the best
+/// layout for the program can be chosen.
 pub fn rust_continuations_dry_run<F: FieldElement>(
     pipeline: &mut Pipeline<F>,
+    layout: &MemoryLayout,
+) -> Vec<(Vec<F>, u64)> {
+    assert_eq!(
+        layout.hash,
+        MemoryHashFunction::for_field::<F>(),
+        "The memory hash function cannot be used on this field"
+    );
+    match layout.hash {
+        MemoryHashFunction::PoseidonGl => {
+            rust_continuations_dry_run_with_hash::<F, PoseidonGl>(pipeline, layout)
+        }
+        MemoryHashFunction::PoseidonBn254 => {
+            rust_continuations_dry_run_with_hash::<F, PoseidonBn254>(pipeline, layout)
+        }
+    }
+}
+
+fn rust_continuations_dry_run_with_hash<F: FieldElement, H: MemoryHash>(
+    pipeline: &mut Pipeline<F>,
+    layout: &MemoryLayout,
 ) -> Vec<(Vec<F>, u64)> {
+    let digest_size = H::DIGEST_SIZE;
+    let words_per_page = layout.words_per_page();
+    let register_names = register_names::<H>();
+    let bootloader_inputs_per_page = bootloader_inputs_per_page::<H>(layout);
+    let memory_hash_start_index = memory_hash_start_index::<H>();
+    let page_inputs_offset = page_inputs_offset::<H>();
+
     log::info!("Initializing memory merkle tree...");
-    let mut merkle_tree = MerkleTree::<F>::new();
+    let mut merkle_tree = MerkleTree::<F, H>::new(*layout);
 
     // All inputs for all chunks.
     let mut bootloader_inputs_and_num_rows = vec![];
 
     // Initial register values for the current chunk.
-    let mut register_values = default_register_values();
+    let mut register_values = default_register_values::<F, H>();
 
     pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
     let program = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
-    sanity_check(program);
+    sanity_check::<_, H>(program);
 
     log::info!("Executing powdr-asm...");
     let (full_trace, memory_accesses) = {
@@ -170,7 +535,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             // constraints, but the executor does the right thing (read zero if the memory
             // cell has never been accessed). We can't pass the accessed pages here, because
             // we only know them after the full trace has been generated.
-            &default_input(&[]),
+            &default_input::<F, H>(layout),
             usize::MAX,
             powdr_riscv_executor::ExecMode::Trace,
         )
@@ -201,6 +566,14 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
         .degree;
     let length = F::from(length).to_degree() as usize;
 
+    log_layout_estimates::<H>(
+        layout,
+        &memory_accesses,
+        first_real_execution_row,
+        full_trace_length,
+        length,
+    );
+
     loop {
         log::info!("\nRunning chunk {}...", chunk_index);
 
@@ -219,7 +592,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
                 break;
             }
             accessed_addresses.insert(access.address);
-            accessed_pages.insert(access.address >> PAGE_SIZE_BYTES_LOG);
+            accessed_pages.insert(access.address >> layout.page_size_bytes_log);
         }
         log::info!(
             "{} unique memory accesses over {} accessed pages: {:?}",
@@ -228,7 +601,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             accessed_pages
         );
 
-        let shutdown_routine_rows = shutdown_routine_upper_bound(accessed_pages.len());
+        let shutdown_routine_rows = shutdown_routine_upper_bound::<H>(layout, accessed_pages.len());
         log::info!(
             "Estimating the shutdown routine to use {} rows.",
             shutdown_routine_rows
@@ -263,7 +636,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
         log::info!("Bootloader inputs length: {}", bootloader_inputs.len());
 
         log::info!("Simulating chunk execution...");
-        let (chunk_trace, memory_snapshot_update) = {
+        let (chunk_trace, final_register_values, memory_snapshot_update) = {
             let (trace, memory_snapshot_update) = powdr_riscv_executor::execute_ast::<F>(
                 program,
                 pipeline.data_callback().unwrap(),
@@ -271,8 +644,20 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
                 num_rows,
                 powdr_riscv_executor::ExecMode::Trace,
             );
-            (transposed_trace(&trace), memory_snapshot_update)
+            let final_row = trace.replay().final_row();
+            let final_register_values = register_names
+                .iter()
+                .map(|r| {
+                    final_row[trace.reg_map[r.strip_prefix("main.").unwrap()] as usize].into_fe()
+                })
+                .collect::<Vec<_>>();
+            (
+                transposed_trace(&trace),
+                final_register_values,
+                memory_snapshot_update,
+            )
         };
+        let is_last_chunk = chunk_trace["main.pc"].len() < num_rows;
         let mut memory_updates_by_page =
             merkle_tree.organize_updates_by_page(memory_snapshot_update.into_iter());
         for (i, &page_index) in accessed_pages.iter().enumerate() {
@@ -280,10 +665,14 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             let (_, _, proof) = merkle_tree.get(page_index);
 
             // Replace the proof
-            let proof_start_index =
-                PAGE_INPUTS_OFFSET + BOOTLOADER_INPUTS_PER_PAGE * i + 1 + WORDS_PER_PAGE + 4;
+            let proof_start_index = page_inputs_offset
+                + bootloader_inputs_per_page * i
+                + 1
+                + words_per_page
+                + digest_size;
             for (j, sibling) in proof.into_iter().enumerate() {
-                bootloader_inputs[proof_start_index + j * 4..proof_start_index + j * 4 + 4]
+                let sibling_index = proof_start_index + j * digest_size;
+                bootloader_inputs[sibling_index..sibling_index + digest_size]
                     .copy_from_slice(sibling);
             }
 
@@ -300,42 +689,46 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
 
             // Assert the proof hasn't changed (because we didn't update any page except the current).
             for (j, sibling) in proof.into_iter().enumerate() {
+                let sibling_index = proof_start_index + j * digest_size;
                 assert_eq!(
-                    &bootloader_inputs[proof_start_index + j * 4..proof_start_index + j * 4 + 4],
+                    &bootloader_inputs[sibling_index..sibling_index + digest_size],
                     sibling
                 );
             }
 
             // Replace the page hash
             let updated_page_hash_index =
-                PAGE_INPUTS_OFFSET + BOOTLOADER_INPUTS_PER_PAGE * i + 1 + WORDS_PER_PAGE;
-            bootloader_inputs[updated_page_hash_index..updated_page_hash_index + 4]
+                page_inputs_offset + bootloader_inputs_per_page * i + 1 + words_per_page;
+            bootloader_inputs[updated_page_hash_index..updated_page_hash_index + digest_size]
                 .copy_from_slice(page_hash);
         }
 
-        // Update initial register values for the next chunk.
-        register_values = REGISTER_NAMES
-            .iter()
-            .map(|&r| *chunk_trace[r].last().unwrap())
-            .collect();
+        // Update initial register values for the next chunk. The prover jumps to the shutdown
+        // routine in the last row, so these are the values *after* executing the last row,
+        // just like the memory snapshot.
+        register_values = final_register_values;
+        if is_last_chunk {
+            // The execution ended with a `return`, which sets the PC to 0.
+            register_values[pc_index::<H>()] = F::zero();
+        }
 
         // Replace final register values of the current chunk
-        bootloader_inputs[REGISTER_NAMES.len()..2 * REGISTER_NAMES.len()]
+        bootloader_inputs[register_names.len()..2 * register_names.len()]
             .copy_from_slice(&register_values);
 
         // Replace the updated root hash
-        let updated_root_hash_index = MEMORY_HASH_START_INDEX + 4;
-        bootloader_inputs[updated_root_hash_index..updated_root_hash_index + 4]
+        let updated_root_hash_index = memory_hash_start_index + digest_size;
+        bootloader_inputs[updated_root_hash_index..updated_root_hash_index + digest_size]
             .copy_from_slice(merkle_tree.root_hash());
 
         log::info!(
             "Initial memory root hash: {}",
-            render_hash(&bootloader_inputs[MEMORY_HASH_START_INDEX..MEMORY_HASH_START_INDEX + 4])
+            render_hash(&bootloader_inputs[memory_hash_start_index..updated_root_hash_index])
         );
         log::info!(
             "Final memory root hash: {}",
             render_hash(
-                &bootloader_inputs[MEMORY_HASH_START_INDEX + 4..MEMORY_HASH_START_INDEX + 8]
+                &bootloader_inputs[updated_root_hash_index..updated_root_hash_index + digest_size]
             )
         );
 
@@ -347,9 +740,13 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
         let (start, _) = chunk_trace["main.pc"]
             .iter()
             .enumerate()
-            .find(|(_, &pc)| pc == bootloader_inputs[PC_INDEX])
+            .find(|(_, &pc)| pc == bootloader_inputs[pc_index::<H>()])
             .unwrap();
-        log::info!("Bootloader used {} rows.", start);
+        log::info!(
+            "Bootloader used {} rows (estimated at most {} rows plus the submachine initialization).",
+            start,
+            bootloader_upper_bound::<H>(layout, accessed_pages.len())
+        );
         log::info!(
             "  => {} / {} ({}%) of rows are used for the actual computation!",
             length - start - shutdown_routine_rows,
@@ -357,7 +754,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             (length - start - shutdown_routine_rows) * 100 / length
         );
         for i in 0..(chunk_trace["main.pc"].len() - start) {
-            for &reg in REGISTER_NAMES.iter() {
+            for reg in register_names.iter() {
                 let chunk_i = i + start;
                 let full_i = i + proven_trace;
                 if chunk_trace[reg][chunk_i] != full_trace[reg][full_i] {
@@ -381,13 +778,12 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             }
         }
 
-        if chunk_trace["main.pc"].len() < num_rows {
+        if is_last_chunk {
             log::info!("Done!");
             break;
         }
 
-        // Minus one, because the last row will have to be repeated in the next chunk.
-        let new_rows = num_rows - start - 1;
+        let new_rows = num_rows - start;
         proven_trace += new_rows;
         log::info!("Proved {} rows.", new_rows);
 
@@ -395,3 +791,155 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
     }
     bootloader_inputs_and_num_rows
 }
+
+/// The estimated cost of an execution with continuations using a given memory layout.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LayoutEstimate {
+    num_chunks: usize,
+    /// The number of pages that are paged in, summed over all chunks.
+    num_pages: usize,
+    /// An upper bound of the number of rows used by the bootloader and shutdown routine,
+    /// summed over all chunks.
+    overhead_rows: usize,
+}
+
+/// Estimates the cost of an execution with continuations using the given memory layout,
+/// given the memory accesses of the full trace. Returns `None` if the bootloader and
+/// shutdown routine might not fit into a chunk.
+fn estimate_layout<H: MemoryHash>(
+    layout: &MemoryLayout,
+    memory_accesses: &[MemOperation],
+    first_real_execution_row: usize,
+    full_trace_length: usize,
+    length: usize,
+) -> Option<LayoutEstimate> {
+    let mut estimate = LayoutEstimate::default();
+    let mut proven_trace = first_real_execution_row;
+    loop {
+        let start_idx = memory_accesses
+            .binary_search_by_key(&proven_trace, |a| a.row)
+            .unwrap_or_else(|v| v);
+        let num_pages = memory_accesses[start_idx..]
+            .iter()
+            .take_while(|access| access.row < proven_trace + length)
+            .map(|access| access.address >> layout.page_size_bytes_log)
+            .collect::<BTreeSet<_>>()
+            .len();
+        let overhead_rows = bootloader_upper_bound::<H>(layout, num_pages)
+            + shutdown_routine_upper_bound::<H>(layout, num_pages);
+        if overhead_rows >= length {
+            return None;
+        }
+
+        estimate.num_chunks += 1;
+        estimate.num_pages += num_pages;
+        estimate.overhead_rows += overhead_rows;
+
+        // Minus one, because the last row will have to be repeated in the next chunk.
+        let new_rows = length - overhead_rows - 1;
+        if proven_trace + new_rows >= full_trace_length {
+            return Some(estimate);
+        }
+        proven_trace += new_rows;
+    }
+}
+
+/// Logs the estimated cost of the candidate memory layouts (see [MemoryLayout::candidates])
+/// and the layout in use.
+fn log_layout_estimates<H: MemoryHash>(
+    layout: &MemoryLayout,
+    memory_accesses: &[MemOperation],
+    first_real_execution_row: usize,
+    full_trace_length: usize,
+    length: usize,
+) {
+    let mut layouts = layout.candidates();
+    if !layouts.contains(layout) {
+        layouts.push(*layout);
+    }
+    log::info!("Estimated cost of different memory layouts:");
+    for candidate in layouts {
+        let selected = if candidate == *layout {
+            " (selected)"
+        } else {
+            ""
+        };
+        match estimate_layout::<H>(
+            &candidate,
+            memory_accesses,
+            first_real_execution_row,
+            full_trace_length,
+            length,
+        ) {
+            Some(estimate) => log::info!(
+                "  {candidate}: {} chunks, {} pages, at most {} bootloader & shutdown rows{selected}",
+                estimate.num_chunks,
+                estimate.num_pages,
+                estimate.overhead_rows
+            ),
+            None => log::info!("  {candidate}: Too many pages for a chunk{selected}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use powdr_riscv_executor::MemOperationKind;
+
+    use super::*;
+
+    #[test]
+    fn estimate_layouts() {
+        // Writes to 4 words, 2 KB apart, in each of the first 1000 rows.
+        let memory_accesses = (0..1000)
+            .flat_map(|row| {
+                (0..4).map(move |i| MemOperation {
+                    row,
+                    kind: MemOperationKind::Write,
+                    address: i * 2048,
+                })
+            })
+            .collect::<Vec<_>>();
+        let estimate = |page_size_bytes_log, arity| {
+            let layout = MemoryLayout::new(page_size_bytes_log, arity).unwrap();
+            estimate_layout::<PoseidonGl>(&layout, &memory_accesses, 0, 1000, 1 << 16)
+        };
+
+        // With 1 KB pages, the accesses are in 4 different pages. With 4 KB pages, 2 are enough.
+        let small_pages = estimate(10, 2).unwrap();
+        let large_pages = estimate(12, 2).unwrap();
+        assert_eq!(small_pages.num_chunks, 1);
+        assert_eq!(small_pages.num_pages, 4);
+        assert_eq!(large_pages.num_pages, 2);
+
+        // A higher arity means fewer levels, but more siblings per level.
+        let arity_4 = estimate(10, 4).unwrap();
+        assert_eq!(arity_4.num_pages, 4);
+        assert_ne!(arity_4.overhead_rows, small_pages.overhead_rows);
+
+        // The bootloader does not fit into a chunk if there are too many pages.
+        assert_eq!(
+            estimate_layout::<PoseidonGl>(
+                &MemoryLayout::default(),
+                &memory_accesses,
+                0,
+                1000,
+                1000
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn chained_publics() {
+        let names = public_names::<PoseidonBn254>();
+        let chained = chained_public_indices::<PoseidonBn254>();
+        assert_eq!(chained.len() * 2, names.len());
+        for (initial, final_) in chained {
+            assert_eq!(
+                names[initial].strip_prefix("initial_"),
+                names[final_].strip_prefix("final_")
+            );
+        }
+    }
+}
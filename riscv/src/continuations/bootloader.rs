@@ -1,43 +1,217 @@
 use powdr_number::FieldElement;
 
+use super::memory_hash::{MemoryHash, MemoryHashFunction};
 use super::memory_merkle_tree::MerkleTree;
 
 /// 32-Bit architecture -> 2^32 bytes of addressable memory
 pub const MEMORY_SIZE_LOG: usize = 32;
 
-/// Page size is 1KB
-pub const PAGE_SIZE_BYTES_LOG: usize = 10;
-
 /// 32-Bit architecture -> 4 bytes per word
 pub const BYTES_PER_WORD: usize = 4;
 
-// Derived constants
-pub const WORDS_PER_PAGE: usize = (1 << (PAGE_SIZE_BYTES_LOG)) / BYTES_PER_WORD;
-pub const N_LEAVES_LOG: usize = MEMORY_SIZE_LOG - PAGE_SIZE_BYTES_LOG;
-pub const MERKLE_TREE_DEPTH: usize = N_LEAVES_LOG + 1;
-pub const PAGE_SIZE_BYTES: usize = 1 << PAGE_SIZE_BYTES_LOG;
-pub const PAGE_NUMBER_MASK: usize = (1 << N_LEAVES_LOG) - 1;
-pub const BOOTLOADER_INPUTS_PER_PAGE: usize = WORDS_PER_PAGE + 1 + 4 + (MERKLE_TREE_DEPTH - 1) * 4;
-pub const MEMORY_HASH_START_INDEX: usize = 2 * REGISTER_NAMES.len();
-pub const NUM_PAGES_INDEX: usize = MEMORY_HASH_START_INDEX + 8;
-pub const PAGE_INPUTS_OFFSET: usize = NUM_PAGES_INDEX + 1;
+/// How the memory is split into pages and committed to in the memory Merkle tree.
+/// The bootloader assembly and the bootloader inputs depend on it, so the same layout
+/// has to be used when compiling the program and when computing the bootloader inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// Log2 of the size of the memory in bytes. This is [MEMORY_SIZE_LOG] for RISC-V,
+    /// smaller values are only useful for testing.
+    pub memory_size_log: usize,
+    /// Log2 of the size of a page in bytes.
+    pub page_size_bytes_log: usize,
+    /// Log2 of the arity of the Merkle tree.
+    pub arity_log: usize,
+    /// The hash function used in the Merkle tree. Its coprocessor has to be available when
+    /// compiling the program, and it has to be usable on the field the program is run on
+    /// (see [MemoryHashFunction::for_field]).
+    pub hash: MemoryHashFunction,
+}
+
+impl Default for MemoryLayout {
+    /// Pages of 1 KB in a binary Merkle tree, hashed with Poseidon GL.
+    fn default() -> Self {
+        Self {
+            memory_size_log: MEMORY_SIZE_LOG,
+            page_size_bytes_log: 10,
+            arity_log: 1,
+            hash: MemoryHashFunction::PoseidonGl,
+        }
+    }
+}
+
+impl MemoryLayout {
+    /// Creates a layout with pages of `2^page_size_bytes_log` bytes and a Merkle tree of
+    /// the given arity, hashed with Poseidon GL.
+    pub fn new(page_size_bytes_log: usize, arity: usize) -> Result<Self, String> {
+        if !arity.is_power_of_two() || arity < 2 {
+            return Err(format!(
+                "The Merkle tree arity has to be a power of two, but is {arity}."
+            ));
+        }
+        // Pages need to contain at least 4 words, so that they can be hashed in chunks of
+        // `MemoryHash::DIGEST_SIZE` words.
+        if !(4..MEMORY_SIZE_LOG).contains(&page_size_bytes_log) {
+            return Err(format!(
+                "The page size has to be between 2^4 and 2^{} bytes, but is 2^{page_size_bytes_log}.",
+                MEMORY_SIZE_LOG - 1
+            ));
+        }
+        let layout = Self {
+            memory_size_log: MEMORY_SIZE_LOG,
+            page_size_bytes_log,
+            arity_log: arity.ilog2() as usize,
+            hash: MemoryHashFunction::PoseidonGl,
+        };
+        if layout.n_leaves_log() % layout.arity_log != 0 {
+            return Err(format!(
+                "With pages of 2^{page_size_bytes_log} bytes, there are 2^{} pages, which cannot be arranged in a Merkle tree of arity {arity}.",
+                layout.n_leaves_log()
+            ));
+        }
+        Ok(layout)
+    }
+
+    pub fn with_hash(mut self, hash: MemoryHashFunction) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// All valid layouts with pages between 64 bytes and 4 KB and a Merkle tree arity of
+    /// up to 8, using the same hash function as `self`. The dry run compares them to help
+    /// choosing a layout.
+    pub fn candidates(&self) -> Vec<Self> {
+        (6..=12)
+            .flat_map(|page_size_bytes_log| {
+                [2, 4, 8].into_iter().filter_map(move |arity| {
+                    Self::new(page_size_bytes_log, arity)
+                        .ok()
+                        .map(|layout| layout.with_hash(self.hash))
+                })
+            })
+            .collect()
+    }
+
+    pub fn page_size_bytes(&self) -> usize {
+        1 << self.page_size_bytes_log
+    }
+
+    pub fn words_per_page(&self) -> usize {
+        self.page_size_bytes() / BYTES_PER_WORD
+    }
+
+    pub fn arity(&self) -> usize {
+        1 << self.arity_log
+    }
+
+    /// Log2 of the number of pages, i.e., the leaves of the Merkle tree.
+    pub fn n_leaves_log(&self) -> usize {
+        self.memory_size_log - self.page_size_bytes_log
+    }
+
+    /// The number of levels of the Merkle tree, excluding the root.
+    pub fn n_levels(&self) -> usize {
+        self.n_leaves_log() / self.arity_log
+    }
+
+    pub fn page_number_mask(&self) -> usize {
+        (1 << self.n_leaves_log()) - 1
+    }
+}
+
+impl std::fmt::Display for MemoryLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} byte pages, arity {}, {}",
+            self.page_size_bytes(),
+            self.arity(),
+            self.hash
+        )
+    }
+}
+
+// Layout of the bootloader inputs, which depends on the size of the hashes, the
+// number of registers and the memory layout.
+pub fn bootloader_inputs_per_page<H: MemoryHash>(layout: &MemoryLayout) -> usize {
+    1 + layout.words_per_page()
+        + H::DIGEST_SIZE
+        + layout.n_levels() * (layout.arity() - 1) * H::DIGEST_SIZE
+}
+pub fn memory_hash_start_index<H: MemoryHash>() -> usize {
+    2 * register_names::<H>().len()
+}
+pub fn num_pages_index<H: MemoryHash>() -> usize {
+    memory_hash_start_index::<H>() + 2 * H::DIGEST_SIZE
+}
+pub fn page_inputs_offset<H: MemoryHash>() -> usize {
+    num_pages_index::<H>() + 1
+}
+
+/// Computes an upper bound of how long the bootloader will run, for a given number of pages.
+/// The submachine initialization, which runs before the bootloader, is not included.
+pub fn bootloader_upper_bound<H: MemoryHash>(layout: &MemoryLayout, num_pages: usize) -> usize {
+    let digest_size = H::DIGEST_SIZE;
+    let words_per_page = layout.words_per_page();
+
+    // Regardless of the number of pages, we have to:
+    // - Run the first instructions and jump to the submachine initialization (3 instructions)
+    // - Load the number of pages and the memory hash
+    // - Start the page loop
+    // - Assert the final memory hash
+    // - Load all registers (except the PC) and jump to the PC
+    let constant_overhead = 3 + 4 + 2 * digest_size + register_names::<H>().len();
+
+    // For each level of the Merkle tree, we have to:
+    // - Find the position of the current node among its siblings (at most `arity` instructions)
+    // - Move the current hash out of the way and back again (2 * DIGEST_SIZE instructions)
+    // - Load and hash each sibling (DIGEST_SIZE + 1 instructions each)
+    // - Jump to the end of the level
+    let cost_per_level =
+        layout.arity() + 2 * digest_size + (layout.arity() - 1) * (digest_size + 1) + 1;
+
+    // For each page, we have to:
+    // - Load the page number (2 instructions) and reset the hash inputs
+    // - Load and store all words of the page
+    // - Invoke the hash function once every DIGEST_SIZE words
+    // - Validate the Merkle proof and compute the updated root, each starting with setting
+    //   the phase and ending with a branch on it
+    // - Assert the Merkle root, load the updated page hash and jump back (2 * DIGEST_SIZE + 2 instructions)
+    // - Update the memory hash, increment the page index and jump back to the loop start
+    let cost_per_page = 2
+        + 2 * digest_size
+        + 2 * words_per_page
+        + words_per_page / digest_size
+        + 2 * (2 + layout.n_levels() * cost_per_level)
+        + 2 * digest_size
+        + 2
+        + digest_size
+        + 2;
+
+    constant_overhead + num_pages * cost_per_page
+}
 
 /// Computes an upper bound of how long the shutdown routine will run, for a given number of pages.
-pub fn shutdown_routine_upper_bound(num_pages: usize) -> usize {
+pub fn shutdown_routine_upper_bound<H: MemoryHash>(
+    layout: &MemoryLayout,
+    num_pages: usize,
+) -> usize {
+    let words_per_page = layout.words_per_page();
+
     // Regardless of the number of pages, we have to:
     // - Jump to the start of the routine
     // - Assert all register values are correct (except the PC)
-    // - Start the page loop
-    // - Jump to shutdown sink
-    let constant_overhead = 6 + REGISTER_NAMES.len() - 1;
+    // - Start the page loop (4 instructions)
+    // - Jump to shutdown sink, which has to be reached at least in the last row
+    let constant_overhead = 7 + register_names::<H>().len() - 1;
 
     // For each page, we have to:
-    // - Start the page loop (14 instructions)
+    // - Start the page loop (2 instructions + resetting the hash inputs)
     // - Load all words of the page
-    // - Invoke the hash function once every 4 words
-    // - Assert the page hash is as claimed (4 instructions)
+    // - Invoke the hash function once every DIGEST_SIZE words
+    // - Assert the page hash is as claimed (DIGEST_SIZE instructions)
     // - Increment the page index and jump back to the loop start (2 instructions)
-    let cost_per_page = 14 + WORDS_PER_PAGE + WORDS_PER_PAGE / 4 + 4 + 2;
+    let cost_per_page =
+        2 + H::NUM_INPUTS + words_per_page + words_per_page / H::DIGEST_SIZE + H::DIGEST_SIZE + 2;
 
     constant_overhead + num_pages * cost_per_page
 }
@@ -45,94 +219,115 @@ pub fn shutdown_routine_upper_bound(num_pages: usize) -> usize {
 pub const BOOTLOADER_SPECIFIC_INSTRUCTION_NAMES: [&str; 2] =
     ["load_bootloader_input", "jump_to_bootloader_input"];
 
-pub fn bootloader_preamble() -> String {
-    let mut preamble = r#"
+pub fn bootloader_preamble<H: MemoryHash>() -> String {
+    let final_pc_index = register_names::<H>().len() + pc_index::<H>();
+    let mut preamble = format!(
+        r#"
     // ============== bootloader-specific instructions =======================
     // Write-once memory
     let BOOTLOADER_INPUT_ADDRESS = |i| i;
     let bootloader_input_value;
     // Loads a value. If the cell is empty, the prover can choose a value.
-    instr load_bootloader_input X -> Y { {X, Y} in {BOOTLOADER_INPUT_ADDRESS, bootloader_input_value} }
-    instr assert_bootloader_input X, Y { {X, Y} in {BOOTLOADER_INPUT_ADDRESS, bootloader_input_value} }
+    instr load_bootloader_input X -> Y {{ {{X, Y}} in {{BOOTLOADER_INPUT_ADDRESS, bootloader_input_value}} }}
+    instr assert_bootloader_input X, Y {{ {{X, Y}} in {{BOOTLOADER_INPUT_ADDRESS, bootloader_input_value}} }}
 
     let tmp_bootloader_value;
 
     // Sets the PC to the bootloader input at the provided index if it is nonzero
-    instr jump_to_bootloader_input X {
-        // TODO: Putting {X, pc'} on the left-hand side should work, but this leads to a wrong PC update rule.
-        {X, tmp_bootloader_value} in {BOOTLOADER_INPUT_ADDRESS, bootloader_input_value},
+    instr jump_to_bootloader_input X {{
+        // TODO: Putting {{X, pc'}} on the left-hand side should work, but this leads to a wrong PC update rule.
+        {{X, tmp_bootloader_value}} in {{BOOTLOADER_INPUT_ADDRESS, bootloader_input_value}},
         pc' = tmp_bootloader_value
-    }
+    }}
 
     // ============== Shutdown routine constraints =======================
     // Insert a `jump_to_shutdown_routine` witness column, which will let the prover indicate that
-    // the normal PC update rule should be bypassed and instead set to the start of the shutdown routine.
-    // Nothing of this is enforced yet, and the flag will be ignored.
+    // the normal PC update rule should be bypassed and instead set to the start of the shutdown routine
+    // (see `_pc_override` in the asm-to-pil compilation).
     let jump_to_shutdown_routine;
     jump_to_shutdown_routine * (1 - jump_to_shutdown_routine) = 0;
+    pol _pc_override = jump_to_shutdown_routine;
+    pol _pc_override_target = {SHUTDOWN_START};
 
-    // Expose initial register values as public outputs
-"#.to_string();
+    // The PC that would have been next is the final PC of the chunk. It can't be asserted in the
+    // shutdown routine, because the PC has already changed at that point.
+    let _pc_overridden;
+    jump_to_shutdown_routine {{ {final_pc_index}, _pc_overridden }} in {{ BOOTLOADER_INPUT_ADDRESS, bootloader_input_value }};
 
-    for (i, reg) in REGISTER_NAMES.iter().enumerate() {
-        let reg = reg.strip_prefix("main.").unwrap();
-        preamble.push_str(&format!(
-            "    public initial_{reg} = bootloader_input_value({i});\n"
-        ));
-    }
-    for (i, reg) in REGISTER_NAMES.iter().enumerate() {
-        let reg = reg.strip_prefix("main.").unwrap();
+    // The shutdown routine has to run to completion, i.e., the last row has to be in the shutdown sink.
+    LAST * (pc - {SHUTDOWN_SINK}) = 0;
+
+    // Expose the initial and final register values and memory root hashes as public outputs.
+    // The ith public is the ith bootloader input.
+"#
+    );
+
+    for (i, name) in public_names::<H>().iter().enumerate() {
         preamble.push_str(&format!(
-            "    public final_{reg} = bootloader_input_value({});\n",
-            i + REGISTER_NAMES.len()
+            "    public {name} = bootloader_input_value({i});\n"
         ));
     }
-    preamble.push_str(&format!(
-        r#"
-    public initial_memory_hash_1 = bootloader_input_value({});
-    public initial_memory_hash_2 = bootloader_input_value({});
-    public initial_memory_hash_3 = bootloader_input_value({});
-    public initial_memory_hash_4 = bootloader_input_value({});
-"#,
-        MEMORY_HASH_START_INDEX,
-        MEMORY_HASH_START_INDEX + 1,
-        MEMORY_HASH_START_INDEX + 2,
-        MEMORY_HASH_START_INDEX + 3,
-    ));
-    preamble.push_str(&format!(
-        r#"
-    public final_memory_hash_1 = bootloader_input_value({});
-    public final_memory_hash_2 = bootloader_input_value({});
-    public final_memory_hash_3 = bootloader_input_value({});
-    public final_memory_hash_4 = bootloader_input_value({});
-"#,
-        MEMORY_HASH_START_INDEX + 4,
-        MEMORY_HASH_START_INDEX + 5,
-        MEMORY_HASH_START_INDEX + 6,
-        MEMORY_HASH_START_INDEX + 7,
-    ));
 
     preamble
 }
 
+/// Renders a range of `len` registers starting at `{prefix}{start}`, e.g. "P0-P3".
+fn register_range(prefix: &str, start: usize, len: usize) -> String {
+    match len {
+        1 => format!("{prefix}{start}"),
+        _ => format!("{prefix}{start}-{prefix}{}", start + len - 1),
+    }
+}
+
 /// The bootloader: An assembly program that can be executed at the beginning a RISC-V execution.
 /// It lets the prover provide arbitrary memory pages and writes them to memory, as well as values for
 /// the registers (including the PC, which is set last).
-/// This can be used to implement continuations. Note that this is completely non-sound. Progress to
-/// make it sound is tracked in https://github.com/powdr-labs/powdr/issues/814.
+/// This can be used to implement continuations. The initial and final register values and memory root
+/// hashes are exposed as public values (see [public_names]), which lets the verifier check that chunks
+/// are chained correctly. The final values are enforced by the shutdown routine, which the prover has
+/// to jump to at the end of the chunk's computation (see [bootloader_preamble]).
+/// The memory is committed to using the hash function `H`, whose digests consist of
+/// `H::DIGEST_SIZE` field elements.
 /// Bootloader inputs are in the format:
-/// - First 49 values: Values of x1-x31, tmp1-tmp4, lr_sc_reservation, P0-P11, and the PC
-/// - Second 49 values: The same values, but after this chunk's execution
-/// - The root hash of the memory Merkle tree (4 elements)
-/// - The root hash of the memory Merkle tree *after this chunk's execution* (4 elements)
+/// - First values: Values of x1-x31, tmp1-tmp4, lr_sc_reservation, the `P` registers used by the
+///   hash coprocessors (P0-P11 for Poseidon GL), and the PC
+/// - Then the same values again, but after this chunk's execution
+/// - The root hash of the memory Merkle tree (`H::DIGEST_SIZE` elements)
+/// - The root hash of the memory Merkle tree *after this chunk's execution* (`H::DIGEST_SIZE` elements)
 /// - Number of pages
 /// - For each page:
 ///   - The page number
-///   - The 256 words of the page
+///   - The words of the page (256 for the default layout)
 ///   - The hash of the page *after* this chunk's execution
-///   - For each level of the Merkle tree, except the root (1..=22):
-///     - The hash (4 elements) of the sibling page
-pub fn bootloader_and_shutdown_routine(submachine_initialization: &[String]) -> String {
+///   - For each level of the Merkle tree, except the root (22 for the default layout):
+///     - The hashes (`H::DIGEST_SIZE` elements each) of the siblings of the node on the path
+///       to the page, in order (1 for a binary tree)
+pub fn bootloader_and_shutdown_routine<H: MemoryHash>(
+    layout: &MemoryLayout,
+    submachine_initialization: &[String],
+) -> String {
+    let digest_size = H::DIGEST_SIZE;
+    let hash_instruction = H::HASH_INSTRUCTION;
+    let words_per_page = layout.words_per_page();
+    let page_size_bytes = layout.page_size_bytes();
+    let page_number_mask = layout.page_number_mask();
+    let arity = layout.arity();
+    let n_levels = layout.n_levels();
+    let bootloader_inputs_per_page = bootloader_inputs_per_page::<H>(layout);
+    let memory_hash_start_index = memory_hash_start_index::<H>();
+    let num_pages_index = num_pages_index::<H>();
+    let page_inputs_offset = page_inputs_offset::<H>();
+
+    // The current memory hash is kept in registers x5, x6, ... and x9 holds the phase.
+    assert!(digest_size <= 4);
+    let memory_hash_registers = register_range("x", 5, digest_size);
+    // The "current" hash, the other input of the hash function and its capacity.
+    let current_hash_registers = register_range("P", 0, digest_size);
+    let other_input_registers = register_range("P", digest_size, digest_size);
+    let capacity_registers = register_range("P", 2 * digest_size, H::NUM_INPUTS - 2 * digest_size);
+    let hash_input_registers = register_range("P", 0, H::NUM_INPUTS);
+    let saved_hash_registers = register_range("x", 10, digest_size);
+
     let mut bootloader = String::new();
 
     bootloader.push_str(&format!(
@@ -146,9 +341,12 @@ tmp1 <== jump(submachine_init);
 tmp1 <== jump(computation_start);
 
 // Similarly, this instruction has a known fixed PC ({SHUTDOWN_START}) and just jumps
-// to the shutdown routine.
-tmp1 <== jump(shutdown_start);
+// to the shutdown routine. Unlike `jump`, a branch does not write to any register, so
+// that the shutdown routine can still assert their final values.
+branch_if_zero x0, shutdown_start;
 
+// The shutdown routine ends here, at another known fixed PC ({SHUTDOWN_SINK}), and loops
+// until the end of the chunk.
 shutdown_sink:
 tmp1 <== jump(shutdown_sink);
 
@@ -168,24 +366,31 @@ submachine_init:
 // - x2: Current page index
 // - x3: Current page number
 // - x4: The ith bit of the page number (during Merkle proof validation)
-// - x5-x8: The current memory hash
+// - {memory_hash_registers}: The current memory hash
 // - x9: 0: Merkle tree validation phase; 1: Merkle tree update phase
-// - P0-P11: Hash registers:
-//   - P0-P3 will usually contain the "current" hash (either in the context
+// - {saved_hash_registers}: The current hash, while hashing the siblings to its left (for arities > 2)
+// - {hash_input_registers}: Hash registers:
+//   - {current_hash_registers} will usually contain the "current" hash (either in the context
 //     of page hashing or Merkle proof validation)
-//   - P4-P7 will contain some other inputs to the hash function
-//   - P8-P11 will contain the capacity elements (0 throughout the execution)
+//   - {other_input_registers} will contain some other inputs to the hash function
+//   - {capacity_registers} will contain the capacity elements (0 throughout the execution)
 
 // Number of pages
-x1 <== load_bootloader_input({NUM_PAGES_INDEX});
+x1 <== load_bootloader_input({num_pages_index});
 x1 <== wrap(x1);
 
 // Initialize memory hash
-x5 <== load_bootloader_input({MEMORY_HASH_START_INDEX});
-x6 <== load_bootloader_input({MEMORY_HASH_START_INDEX} + 1);
-x7 <== load_bootloader_input({MEMORY_HASH_START_INDEX} + 2);
-x8 <== load_bootloader_input({MEMORY_HASH_START_INDEX} + 3);
+"#,
+    ));
+    for i in 0..digest_size {
+        bootloader.push_str(&format!(
+            "x{} <== load_bootloader_input({memory_hash_start_index} + {i});\n",
+            5 + i
+        ));
+    }
 
+    bootloader.push_str(&format!(
+        r#"
 // Current page index
 x2 <=X= 0;
 
@@ -194,61 +399,54 @@ branch_if_zero x1, bootloader_end_page_loop;
 bootloader_start_page_loop:
 
 // Page number
-x3 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET});
-x3 <== and(x3, {PAGE_NUMBER_MASK});
+x3 <== load_bootloader_input(x2 * {bootloader_inputs_per_page} + {page_inputs_offset});
+x3 <== and(x3, {page_number_mask});
 
-// Store & hash {WORDS_PER_PAGE} page words. This is an unrolled loop that for each each word:
-// - Loads the word into the P{{(i % 4) + 4}} register
-// - Stores the word at the address x3 * {PAGE_SIZE_BYTES} + i * {BYTES_PER_WORD}
-// - If i % 4 == 3: Hashes registers P0-P11, storing the result in P0-P3
+// Store & hash {words_per_page} page words. This is an unrolled loop that for each each word:
+// - Loads the word into the P{{(i % {digest_size}) + {digest_size}}} register
+// - Stores the word at the address x3 * {page_size_bytes} + i * {BYTES_PER_WORD}
+// - If i % {digest_size} == {}: Hashes registers {hash_input_registers}, storing the result in {current_hash_registers}
 //
-// At the end of the loop, we'll have a linear hash of the page in P0-P3, using a Merkle-Damgård
-// construction. The initial P0-P3 values are 0, and the capacity (P8-P11) is 0 throughout the
+// At the end of the loop, we'll have a linear hash of the page in {current_hash_registers}, using a Merkle-Damgård
+// construction. The initial {current_hash_registers} values are 0, and the capacity ({capacity_registers}) is 0 throughout the
 // booloader execution.
 
-P0 <=X= 0;
-P1 <=X= 0;
-P2 <=X= 0;
-P3 <=X= 0;
-P4 <=X= 0;
-P5 <=X= 0;
-P6 <=X= 0;
-P7 <=X= 0;
 "#,
+        digest_size - 1
     ));
+    for i in 0..2 * digest_size {
+        bootloader.push_str(&format!("P{i} <=X= 0;\n"));
+    }
 
-    for i in 0..WORDS_PER_PAGE {
-        let reg_index = (i % 4) + 4;
+    for i in 0..words_per_page {
+        let reg_index = (i % digest_size) + digest_size;
         bootloader.push_str(&format!(
             r#"
-P{reg_index} <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {i});
-mstore_bootloader x3 * {PAGE_SIZE_BYTES} + {i} * {BYTES_PER_WORD}, P{reg_index};"#
+P{reg_index} <== load_bootloader_input(x2 * {bootloader_inputs_per_page} + {page_inputs_offset} + 1 + {i});
+mstore_bootloader x3 * {page_size_bytes} + {i} * {BYTES_PER_WORD}, P{reg_index};"#
         ));
 
         // Hash if buffer is full
-        if i % 4 == 3 {
-            bootloader.push_str(
-                r#"
-P0, P1, P2, P3 <== poseidon_gl(P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);
-"#,
-            );
+        if i % digest_size == digest_size - 1 {
+            bootloader.push_str(&format!("\n{hash_instruction}\n"));
         }
     }
 
     bootloader.push_str(&format!(
         r#"
 // == Merkle proof validation ==
-// We commit to the memory content by hashing it in pages of {WORDS_PER_PAGE} words each.
-// These hashes are stored in a binary Merkle tree of depth {MERKLE_TREE_DEPTH}.
-// At this point, the current page hash is in P0-P3.
+// We commit to the memory content by hashing it in pages of {words_per_page} words each.
+// These hashes are stored in a Merkle tree of arity {arity} with {n_levels} levels below the root.
+// The hash of an inner node is the linear hash of its children, i.e., hash(hash(c0, c1), c2), ...
+// At this point, the current page hash is in {current_hash_registers}.
 // 
 // Now, we re-computed the Merkle root twice, in two phases:
 // - First using the current page hash, as computed by the bootloader. The prover provides
 //   the sibling values. At the end of this phase, the re-computed Merkle root is asserted
-//   to be equal to the "current" Merkle root, stored in x5-x8.
+//   to be equal to the "current" Merkle root, stored in {memory_hash_registers}.
 // - Second, we repeat the same process (using the *same* siblings!), but using the claimed
 //   updated page hash. At the end of this phase, the re-computed Merkle root stored as the
-//   "current" Merkle root in x5-x8.
+//   "current" Merkle root in {memory_hash_registers}.
 //
 // So, any Merkle proof is expected to be based on the Merkle tree with all previous pages
 // already updated. In the shutdown routine, we will validate that the final page hashes
@@ -261,53 +459,90 @@ x9 <=X= 0;
 bootloader_merkle_proof_validation_loop:
 
 // This is an unrolled loop that for each level:
-// - If the ith bit of the page number is 0:
-//   - Load sibling into registers P4-P7
-// - Else:
-//   - Write registers P0-P3 to registers P4-P7
-//   - Load sibling into registers P0-P3
-// - Hash registers P0-P11, storing the result in P0-P3
+// - Extracts the position of the current node among its siblings from the page number
+// - Hashes the siblings and the current hash in {current_hash_registers} in the right order, by:
+//   - Loading the first child into {current_hash_registers}
+//   - Loading each further child into {other_input_registers} and hashing registers
+//     {hash_input_registers}, storing the result in {current_hash_registers}
 //
-// At the end of the loop, we'll have the Merkle root in P0-P3.
+// At the end of the loop, we'll have the Merkle root in {current_hash_registers}.
 "#,
     ));
 
-    for i in 0..N_LEAVES_LOG {
-        let mask = 1 << i;
-        bootloader.push_str(&format!(
-            r#"
-x4 <== and(x3, {mask});
-branch_if_nonzero x4, bootloader_level_{i}_is_right;
-P4 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 0);
-P5 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 1);
-P6 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 2);
-P7 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 3);
-tmp1 <== jump(bootloader_level_{i}_end);
-bootloader_level_{i}_is_right:
-P4 <=X= P0;
-P5 <=X= P1;
-P6 <=X= P2;
-P7 <=X= P3;
-P0 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 0);
-P1 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 1);
-P2 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 2);
-P3 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 4 + {i} * 4 + 3);
-bootloader_level_{i}_end:
-P0, P1, P2, P3 <== poseidon_gl(P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);
-"#
-        ));
+    for i in 0..n_levels {
+        let shift = i * layout.arity_log;
+        let load_sibling = |sibling: usize, first_register: usize| {
+            let sibling_index = i * (arity - 1) + sibling;
+            (0..digest_size)
+                .map(|j| {
+                    format!(
+                        "P{} <== load_bootloader_input(x2 * {bootloader_inputs_per_page} + {page_inputs_offset} + 1 + {words_per_page} + {digest_size} + {sibling_index} * {digest_size} + {j});\n",
+                        first_register + j
+                    )
+                })
+                .collect::<String>()
+        };
+        let move_registers = |to: &str, to_start: usize, from: &str, from_start: usize| {
+            (0..digest_size)
+                .map(|j| format!("{to}{} <=X= {from}{};\n", to_start + j, from_start + j))
+                .collect::<String>()
+        };
+
+        bootloader.push_str(&format!("\nx4 <== and(x3, {});\n", (arity - 1) << shift));
+        for position in 1..arity {
+            bootloader.push_str(&format!(
+                "branch_if_zero x4 - {}, bootloader_level_{i}_position_{position};\n",
+                position << shift
+            ));
+        }
+        for position in 0..arity {
+            if position > 0 {
+                bootloader.push_str(&format!("bootloader_level_{i}_position_{position}:\n"));
+            }
+            match position {
+                0 => {}
+                1 => {
+                    bootloader.push_str(&move_registers("P", digest_size, "P", 0));
+                    bootloader.push_str(&load_sibling(0, 0));
+                    bootloader.push_str(&format!("{hash_instruction}\n"));
+                }
+                _ => {
+                    bootloader.push_str(&move_registers("x", 10, "P", 0));
+                    bootloader.push_str(&load_sibling(0, 0));
+                    for sibling in 1..position {
+                        bootloader.push_str(&load_sibling(sibling, digest_size));
+                        bootloader.push_str(&format!("{hash_instruction}\n"));
+                    }
+                    bootloader.push_str(&move_registers("P", digest_size, "x", 10));
+                    bootloader.push_str(&format!("{hash_instruction}\n"));
+                }
+            }
+            for sibling in position..arity - 1 {
+                bootloader.push_str(&load_sibling(sibling, digest_size));
+                bootloader.push_str(&format!("{hash_instruction}\n"));
+            }
+            if position < arity - 1 {
+                bootloader.push_str(&format!("tmp1 <== jump(bootloader_level_{i}_end);\n"));
+            }
+        }
+        bootloader.push_str(&format!("bootloader_level_{i}_end:\n"));
     }
 
-    bootloader.push_str(&format!(
+    bootloader.push_str(
         r#"
 branch_if_nonzero x9, bootloader_update_memory_hash;
 
 // Assert Correct Merkle Root
-branch_if_nonzero P0 - x5, bootloader_memory_hash_mismatch;
-branch_if_nonzero P1 - x6, bootloader_memory_hash_mismatch;
-branch_if_nonzero P2 - x7, bootloader_memory_hash_mismatch;
-branch_if_nonzero P3 - x8, bootloader_memory_hash_mismatch;
-tmp1 <== jump(bootloader_memory_hash_ok);
+"#,
+    );
+    for i in 0..digest_size {
+        bootloader.push_str(&format!(
+            "branch_if_nonzero P{i} - x{}, bootloader_memory_hash_mismatch;\n",
+            5 + i
+        ));
+    }
+    bootloader.push_str(&format!(
+        r#"tmp1 <== jump(bootloader_memory_hash_ok);
 bootloader_memory_hash_mismatch:
 fail;
 bootloader_memory_hash_ok:
@@ -315,22 +550,28 @@ bootloader_memory_hash_ok:
 // Set phase to update
 x9 <=X= 1;
 
-// Load claimed updated page hash into P0-P3
-P0 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 0);
-P1 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 1);
-P2 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 2);
-P3 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + 1 + {WORDS_PER_PAGE} + 3);
-
+// Load claimed updated page hash into {current_hash_registers}
+"#
+    ));
+    for i in 0..digest_size {
+        bootloader.push_str(&format!(
+            "P{i} <== load_bootloader_input(x2 * {bootloader_inputs_per_page} + {page_inputs_offset} + 1 + {words_per_page} + {i});\n"
+        ));
+    }
+    bootloader.push_str(
+        r#"
 // Repeat Merkle proof validation loop to compute updated Merkle root
 tmp1 <== jump(bootloader_merkle_proof_validation_loop);
 
 bootloader_update_memory_hash:
 
-x5 <=X= P0;
-x6 <=X= P1;
-x7 <=X= P2;
-x8 <=X= P3;
-
+"#,
+    );
+    for i in 0..digest_size {
+        bootloader.push_str(&format!("x{} <=X= P{i};\n", 5 + i));
+    }
+    bootloader.push_str(
+        r#"
 // Increment page index
 x2 <=X= x2 + 1;
 
@@ -339,17 +580,24 @@ branch_if_nonzero x2 - x1, bootloader_start_page_loop;
 bootloader_end_page_loop:
 
 // Assert final Merkle root is as claimed
-assert_bootloader_input {MEMORY_HASH_START_INDEX} + 4, x5;
-assert_bootloader_input {MEMORY_HASH_START_INDEX} + 5, x6;
-assert_bootloader_input {MEMORY_HASH_START_INDEX} + 6, x7;
-assert_bootloader_input {MEMORY_HASH_START_INDEX} + 7, x8;
-
+"#,
+    );
+    for i in 0..digest_size {
+        bootloader.push_str(&format!(
+            "assert_bootloader_input {memory_hash_start_index} + {}, x{};\n",
+            digest_size + i,
+            5 + i
+        ));
+    }
+    bootloader.push_str(
+        r#"
 // Initialize registers, starting with index 0
-"#
-    ));
+"#,
+    );
 
     // Go over all registers except the PC
-    let register_iter = REGISTER_NAMES.iter().take(REGISTER_NAMES.len() - 1);
+    let register_names = register_names::<H>();
+    let register_iter = register_names.iter().take(register_names.len() - 1);
 
     for (i, reg) in register_iter.enumerate() {
         let reg = reg.strip_prefix("main.").unwrap();
@@ -360,20 +608,20 @@ assert_bootloader_input {MEMORY_HASH_START_INDEX} + 7, x8;
         r#"
 // Default PC is 0, but we already started from 0, so in that case we do nothing.
 // Otherwise, we jump to the PC.
-jump_to_bootloader_input {PC_INDEX};
+jump_to_bootloader_input {};
 
 // END OF BOOTLOADER
 
-"#
+"#,
+        pc_index::<H>()
     ));
 
-    bootloader.push_str(
+    bootloader.push_str(&format!(
         r#"
 // START OF SHUTDOWN ROUTINE
 //
-// This code is currently never executed in practice!
-//
-// The shutdown routine is responsible for:
+// The prover jumps here (via {SHUTDOWN_START}) by setting `jump_to_shutdown_routine` in the last
+// row of the chunk's computation. The shutdown routine is responsible for:
 // - Validating that the final register values are equal to those in the bootloader inputs
 //   (which are exposed as public outputs)
 // - Validating that the final page hashes are equal to the claimed values provided in the
@@ -383,32 +631,31 @@ jump_to_bootloader_input {PC_INDEX};
 // - x1: Number of pages (constant throughout the execution)
 // - x2: Current page index
 // - x3: Current page number
-// - P0-P11: Hash registers, used to compute the page hash
+// - {hash_input_registers}: Hash registers, used to compute the page hash
 
 shutdown_start:
 
 // Assert final register values are as claimed
 // Note that we cannot assert that the final PC is correct, because it will already
-// have changed at this point. This will need to be done by whatever mechanism is used
-// to jump to the shutdown routine.
+// have changed at this point. This is done when jumping to the shutdown routine.
 "#,
-    );
+    ));
 
     // Go over all registers except the PC
-    let register_iter = REGISTER_NAMES.iter().take(REGISTER_NAMES.len() - 1);
+    let register_iter = register_names.iter().take(register_names.len() - 1);
 
     for (i, reg) in register_iter.enumerate() {
         let reg = reg.strip_prefix("main.").unwrap();
         bootloader.push_str(&format!(
             "assert_bootloader_input {}, {reg};\n",
-            i + REGISTER_NAMES.len()
+            i + register_names.len()
         ));
     }
 
     bootloader.push_str(&format!(
         r#"
 // Number of pages
-x1 <== load_bootloader_input({NUM_PAGES_INDEX});
+x1 <== load_bootloader_input({num_pages_index});
 x1 <== wrap(x1);
 
 // Current page index
@@ -419,44 +666,34 @@ branch_if_zero x1, shutdown_end_page_loop;
 shutdown_start_page_loop:
 
 // Page number
-x3 <== load_bootloader_input(x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET});
-x3 <== and(x3, {PAGE_NUMBER_MASK});
+x3 <== load_bootloader_input(x2 * {bootloader_inputs_per_page} + {page_inputs_offset});
+x3 <== and(x3, {page_number_mask});
 
-// Store & hash {WORDS_PER_PAGE} page words. This is an unrolled loop that for each each word:
-// - Loads the word at the address x3 * {PAGE_SIZE_BYTES} + i * {BYTES_PER_WORD}
-//   into the P{{(i % 4) + 4}} register
-// - If i % 4 == 3: Hashes registers P0-P11, storing the result in P0-P3
+// Store & hash {words_per_page} page words. This is an unrolled loop that for each each word:
+// - Loads the word at the address x3 * {page_size_bytes} + i * {BYTES_PER_WORD}
+//   into the P{{(i % {digest_size}) + {digest_size}}} register
+// - If i % {digest_size} == {}: Hashes registers {hash_input_registers}, storing the result in {current_hash_registers}
 //
-// At the end of the loop, we'll have a linear hash of the page in P0-P3, using a Merkle-Damgård
-// construction. The initial P0-P3 values are 0, and the capacity (P8-P11) is 0 throughout the
+// At the end of the loop, we'll have a linear hash of the page in {current_hash_registers}, using a Merkle-Damgård
+// construction. The initial {current_hash_registers} values are 0, and the capacity ({capacity_registers}) is 0 throughout the
 // execution of the shutdown routine.
 
-P0 <=X= 0;
-P1 <=X= 0;
-P2 <=X= 0;
-P3 <=X= 0;
-P4 <=X= 0;
-P5 <=X= 0;
-P6 <=X= 0;
-P7 <=X= 0;
-P8 <=X= 0;
-P9 <=X= 0;
-P10 <=X= 0;
-P11 <=X= 0;
 "#,
+        digest_size - 1
     ));
+    for i in 0..H::NUM_INPUTS {
+        bootloader.push_str(&format!("P{i} <=X= 0;\n"));
+    }
 
-    for i in 0..WORDS_PER_PAGE {
-        let reg_index = (i % 4) + 4;
+    for i in 0..words_per_page {
+        let reg_index = (i % digest_size) + digest_size;
         bootloader.push_str(&format!(
-            "P{reg_index}, x0 <== mload(x3 * {PAGE_SIZE_BYTES} + {i} * {BYTES_PER_WORD});\n"
+            "P{reg_index}, x0 <== mload(x3 * {page_size_bytes} + {i} * {BYTES_PER_WORD});\n"
         ));
 
         // Hash if buffer is full
-        if i % 4 == 3 {
-            bootloader.push_str(
-                "P0, P1, P2, P3 <== poseidon_gl(P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);\n",
-            );
+        if i % digest_size == digest_size - 1 {
+            bootloader.push_str(&format!("{hash_instruction}\n"));
         }
     }
 
@@ -464,12 +701,17 @@ P11 <=X= 0;
         r#"
 
 // Assert page hash is as claimed
-// At this point, P0-P3 contain the actual page hash at the end of the execution.
-assert_bootloader_input x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + {WORDS_PER_PAGE} + 1 + 0, P0;
-assert_bootloader_input x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + {WORDS_PER_PAGE} + 1 + 1, P1;
-assert_bootloader_input x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + {WORDS_PER_PAGE} + 1 + 2, P2;
-assert_bootloader_input x2 * {BOOTLOADER_INPUTS_PER_PAGE} + {PAGE_INPUTS_OFFSET} + {WORDS_PER_PAGE} + 1 + 3, P3;
+// At this point, {current_hash_registers} contain the actual page hash at the end of the execution.
+"#
+    ));
+    for i in 0..digest_size {
+        bootloader.push_str(&format!(
+            "assert_bootloader_input x2 * {bootloader_inputs_per_page} + {page_inputs_offset} + {words_per_page} + 1 + {i}, P{i};\n"
+        ));
+    }
 
+    bootloader.push_str(
+        r#"
 // Increment page index
 x2 <=X= x2 + 1;
 
@@ -484,66 +726,66 @@ tmp1 <== jump(shutdown_sink);
 
 computation_start:
 "#,
-    ));
+    );
 
     bootloader
 }
 
-/// The names of the registers in the order in which they are expected by the bootloader.
-pub const REGISTER_NAMES: [&str; 49] = [
-    "main.x1",
-    "main.x2",
-    "main.x3",
-    "main.x4",
-    "main.x5",
-    "main.x6",
-    "main.x7",
-    "main.x8",
-    "main.x9",
-    "main.x10",
-    "main.x11",
-    "main.x12",
-    "main.x13",
-    "main.x14",
-    "main.x15",
-    "main.x16",
-    "main.x17",
-    "main.x18",
-    "main.x19",
-    "main.x20",
-    "main.x21",
-    "main.x22",
-    "main.x23",
-    "main.x24",
-    "main.x25",
-    "main.x26",
-    "main.x27",
-    "main.x28",
-    "main.x29",
-    "main.x30",
-    "main.x31",
-    "main.tmp1",
-    "main.tmp2",
-    "main.tmp3",
-    "main.tmp4",
-    "main.lr_sc_reservation",
-    "main.P0",
-    "main.P1",
-    "main.P2",
-    "main.P3",
-    "main.P4",
-    "main.P5",
-    "main.P6",
-    "main.P7",
-    "main.P8",
-    "main.P9",
-    "main.P10",
-    "main.P11",
-    "main.pc",
-];
+/// The names of the registers in the order in which they are expected by the bootloader,
+/// when using the hash function `H`.
+pub fn register_names<H: MemoryHash>() -> Vec<String> {
+    (1..32)
+        .map(|i| format!("main.x{i}"))
+        .chain((1..5).map(|i| format!("main.tmp{i}")))
+        .chain(std::iter::once("main.lr_sc_reservation".to_string()))
+        .chain((0..H::NUM_REGISTERS).map(|i| format!("main.P{i}")))
+        .chain(std::iter::once("main.pc".to_string()))
+        .collect()
+}
+
+/// The names of the public values exposed by the bootloader, in the order of their declaration:
+/// The initial and final register values (see [register_names], without the `main.` prefix),
+/// followed by the initial and final memory root hashes.
+/// The public value at index `i` is the bootloader input at index `i`.
+pub fn public_names<H: MemoryHash>() -> Vec<String> {
+    let register_names = register_names::<H>()
+        .into_iter()
+        .map(|reg| reg.strip_prefix("main.").unwrap().to_string())
+        .collect::<Vec<_>>();
+    let memory_hash_names = (1..=H::DIGEST_SIZE)
+        .map(|i| format!("memory_hash_{i}"))
+        .collect::<Vec<_>>();
+    let names = register_names
+        .iter()
+        .map(|reg| format!("initial_{reg}"))
+        .chain(register_names.iter().map(|reg| format!("final_{reg}")))
+        .chain(memory_hash_names.iter().map(|h| format!("initial_{h}")))
+        .chain(memory_hash_names.iter().map(|h| format!("final_{h}")))
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), num_pages_index::<H>());
+    names
+}
+
+/// Pairs `(initial, final)` of indices into [public_names] that refer to the same part of the
+/// state, at the beginning and at the end of a chunk. The final value of each chunk has to be
+/// the initial value of the next chunk.
+pub fn chained_public_indices<H: MemoryHash>() -> Vec<(usize, usize)> {
+    let names = public_names::<H>();
+    let index = |name: &str| names.iter().position(|n| n == name).unwrap();
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let state = name.strip_prefix("initial_")?;
+            Some((i, index(&format!("final_{state}"))))
+        })
+        .collect()
+}
 
 /// Index of the PC in the bootloader input.
-pub const PC_INDEX: usize = REGISTER_NAMES.len() - 1;
+pub fn pc_index<H: MemoryHash>() -> usize {
+    register_names::<H>().len() - 1
+}
 
 /// The default PC that can be used in first chunk, will just continue with whatever comes after the bootloader.
 /// The value is 3, because we added a jump instruction at the beginning of the code.
@@ -552,14 +794,19 @@ pub const PC_INDEX: usize = REGISTER_NAMES.len() - 1;
 /// 1: jump_to_operation
 /// 2: jump submachine_init
 /// 3: jump computation_start
+/// 4: jump shutdown_start
+/// 5: shutdown_sink
 pub const DEFAULT_PC: u64 = 3;
 
 /// Analogous to the `DEFAULT_PC`, this well-known PC jumps to the shutdown routine.
 pub const SHUTDOWN_START: u64 = 4;
 
-pub fn default_register_values<T: FieldElement>() -> Vec<T> {
-    let mut register_values = vec![T::zero(); REGISTER_NAMES.len()];
-    register_values[PC_INDEX] = T::from(DEFAULT_PC);
+/// The PC at which the shutdown routine ends. The last row of each chunk has to be at this PC.
+pub const SHUTDOWN_SINK: u64 = 5;
+
+pub fn default_register_values<T: FieldElement, H: MemoryHash>() -> Vec<T> {
+    let mut register_values = vec![T::zero(); register_names::<H>().len()];
+    register_values[pc_index::<H>()] = T::from(DEFAULT_PC);
     register_values
 }
 
@@ -567,37 +814,16 @@ pub fn default_register_values<T: FieldElement>() -> Vec<T> {
 /// - No pages are initialized
 /// - All registers are set to 0 (including the PC, which causes the bootloader to do nothing)
 /// - The state at the end of the execution is the same as the beginning
-pub fn default_input<T: FieldElement>(accessed_pages: &[u64]) -> Vec<T> {
+pub fn default_input<T: FieldElement, H: MemoryHash>(layout: &MemoryLayout) -> Vec<T> {
     // Set all registers and the number of pages to zero
-    let mut bootloader_inputs = default_register_values();
+    let mut bootloader_inputs = default_register_values::<T, H>();
 
     // Claim that the final register values are the same as the initial ones
-    bootloader_inputs.extend(default_register_values::<T>());
-
-    if accessed_pages.is_empty() {
-        bootloader_inputs.extend(MerkleTree::<T>::empty_hash());
-        bootloader_inputs.extend(MerkleTree::<T>::empty_hash());
-        bootloader_inputs.push(T::zero());
-    } else {
-        // TODO: We don't have a way to know the memory state *after* the execution.
-        // For now, we'll just claim that the memory doesn't change.
-        // This is fine for now, because the bootloader does not yet enforce that the memory
-        // state is actually as claimed. In the future, the `accessed_pages` argument won't be
-        // supported anymore (it's anyway only used by the benchmark).
-        let merkle_tree = MerkleTree::<T>::new();
-        bootloader_inputs.extend(merkle_tree.root_hash());
-        bootloader_inputs.extend(merkle_tree.root_hash());
-        bootloader_inputs.push((accessed_pages.len() as u64).into());
-        for &page_index in accessed_pages.iter() {
-            bootloader_inputs.push(page_index.into());
-            let (page, page_hash, proof) = merkle_tree.get(page_index as usize);
-            bootloader_inputs.extend(page);
-            bootloader_inputs.extend(page_hash);
-            for sibling in proof {
-                bootloader_inputs.extend(sibling);
-            }
-        }
-    }
+    bootloader_inputs.extend(default_register_values::<T, H>());
+
+    bootloader_inputs.extend(MerkleTree::<T, H>::empty_hash(layout));
+    bootloader_inputs.extend(MerkleTree::<T, H>::empty_hash(layout));
+    bootloader_inputs.push(T::zero());
 
     bootloader_inputs
 }
@@ -0,0 +1,285 @@
+//! The manifest of a continuations run, which records the chunks of the execution
+//! and how far each of them has been processed. It is stored in the output directory,
+//! so that an interrupted run can be resumed without re-doing finished chunks.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use powdr_number::FieldElement;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How far a chunk has been processed. The variants are ordered by progress, a failed
+/// chunk having made the least progress, because it has to be processed again.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChunkStatus {
+    /// Processing the chunk failed.
+    Failed,
+    /// The chunk has not been processed yet.
+    Pending,
+    /// The witness of the chunk has been written to the output directory.
+    WitnessGenerated,
+    /// The proof of the chunk has been written to the output directory.
+    Proven,
+}
+
+/// A chunk of the execution, with everything needed to process it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(bound = "F: FieldElement")]
+pub struct Chunk<F: FieldElement> {
+    /// The name of the chunk, which is used as the prefix of its output files.
+    pub name: String,
+    /// The inputs to the bootloader.
+    pub bootloader_inputs: Vec<F>,
+    /// The index of the row at which the shutdown routine is supposed to execute.
+    pub start_of_shutdown_routine: u64,
+    pub status: ChunkStatus,
+}
+
+impl<F: FieldElement> Chunk<F> {
+    /// The status of the chunk as evidenced by the files in the output directory.
+    pub fn status_of_outputs(&self, output_dir: &Path) -> ChunkStatus {
+        let exists = |suffix: &str| output_dir.join(format!("{}{suffix}", self.name)).exists();
+        if exists("_proof.bin") {
+            ChunkStatus::Proven
+        } else if exists("_commits.bin") {
+            ChunkStatus::WitnessGenerated
+        } else {
+            ChunkStatus::Pending
+        }
+    }
+
+    /// Whether the chunk has reached the given status and its outputs still exist,
+    /// so that it does not need to be processed again.
+    pub fn is_done(&self, output_dir: &Path, status: ChunkStatus) -> bool {
+        self.status >= status && self.status_of_outputs(output_dir) >= status
+    }
+}
+
+/// The settings the chunks are proven with. The proofs of a previous run can only be
+/// reused if they were generated with the same settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProverSettings {
+    /// The backend, if the chunks are proven.
+    pub backend: Option<String>,
+    pub backend_options: String,
+    /// The SHA-256 hash of the setup file, if one is used.
+    pub setup_hash: Option<String>,
+}
+
+impl ProverSettings {
+    pub fn new(
+        backend: Option<String>,
+        backend_options: String,
+        setup_file: Option<&Path>,
+    ) -> Result<Self, String> {
+        let setup_hash = setup_file
+            .map(|path| {
+                fs::read(path)
+                    .map(|setup| format!("{:x}", Sha256::digest(setup)))
+                    .map_err(|e| format!("Could not read {}: {e}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            backend,
+            backend_options,
+            setup_hash,
+        })
+    }
+}
+
+/// All chunks of the execution, in order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(bound = "F: FieldElement")]
+pub struct ContinuationsManifest<F: FieldElement> {
+    /// Manifests written before the settings were recorded are read with the default
+    /// settings, so that their proofs are not reused.
+    #[serde(default)]
+    pub prover: ProverSettings,
+    pub chunks: Vec<Chunk<F>>,
+}
+
+impl<F: FieldElement> ContinuationsManifest<F> {
+    /// Creates the manifest of a new run of the pipeline called `name`, given the bootloader
+    /// inputs and shutdown rows as returned by `rust_continuations_dry_run`.
+    pub fn new(name: &str, bootloader_inputs: Vec<(Vec<F>, u64)>) -> Self {
+        let chunks = bootloader_inputs
+            .into_iter()
+            .enumerate()
+            .map(
+                |(i, (bootloader_inputs, start_of_shutdown_routine))| Chunk {
+                    name: format!("{name}_chunk_{i}"),
+                    bootloader_inputs,
+                    start_of_shutdown_routine,
+                    status: ChunkStatus::Pending,
+                },
+            )
+            .collect();
+        Self {
+            prover: ProverSettings::default(),
+            chunks,
+        }
+    }
+
+    /// Sets the settings the chunks are proven with.
+    pub fn with_prover(mut self, prover: ProverSettings) -> Self {
+        self.prover = prover;
+        self
+    }
+
+    /// The path of the manifest of the pipeline called `name`.
+    pub fn path(output_dir: &Path, name: &str) -> PathBuf {
+        output_dir.join(format!("{name}_continuations.json"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {e}", path.to_str().unwrap()))?;
+        serde_json::from_str(&content).map_err(|e| {
+            format!(
+                "Invalid continuations manifest {}: {e}",
+                path.to_str().unwrap()
+            )
+        })
+    }
+
+    /// Writes the manifest to a temporary file next to `path` and renames it to `path`,
+    /// so that an interrupted write does not leave a truncated manifest behind.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        fs::write(&temp_path, serde_json::to_string(self).unwrap())
+            .map_err(|e| format!("Could not write {}: {e}", temp_path.display()))?;
+        fs::rename(&temp_path, path).map_err(|e| format!("Could not write {}: {e}", path.display()))
+    }
+
+    /// Takes over the status of all chunks that are unchanged in the `previous` manifest,
+    /// e.g. the manifest of an interrupted run of the same program with the same inputs.
+    /// If the previous run used other prover settings, its proofs are not taken over.
+    pub fn with_progress_from(mut self, previous: &Self) -> Self {
+        let same_prover = self.prover == previous.prover;
+        for (chunk, previous_chunk) in self.chunks.iter_mut().zip(&previous.chunks) {
+            if chunk.name == previous_chunk.name
+                && chunk.bootloader_inputs == previous_chunk.bootloader_inputs
+                && chunk.start_of_shutdown_routine == previous_chunk.start_of_shutdown_routine
+            {
+                chunk.status = if same_prover {
+                    previous_chunk.status
+                } else {
+                    previous_chunk.status.min(ChunkStatus::WitnessGenerated)
+                };
+            }
+        }
+        self
+    }
+
+    /// Checks that the manifest belongs to a run of the pipeline called `name`.
+    pub fn check_name(&self, name: &str) -> Result<(), String> {
+        match self
+            .chunks
+            .iter()
+            .enumerate()
+            .find(|(i, chunk)| chunk.name != format!("{name}_chunk_{i}"))
+        {
+            Some((_, chunk)) => Err(format!(
+                "The continuations manifest contains chunk {}, which does not belong to {name}.",
+                chunk.name
+            )),
+            None if self.chunks.is_empty() => {
+                Err("The continuations manifest does not contain any chunks.".to_string())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use powdr_number::GoldilocksField;
+
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let temp_dir = mktemp::Temp::new_dir().unwrap();
+        let mut manifest = ContinuationsManifest::new(
+            "test",
+            vec![
+                (
+                    vec![GoldilocksField::from(1u64), GoldilocksField::from(2u64)],
+                    10,
+                ),
+                (vec![GoldilocksField::from(3u64)], 20),
+            ],
+        );
+        manifest.chunks[0].status = ChunkStatus::Proven;
+        assert_eq!(manifest.chunks[1].name, "test_chunk_1");
+        assert!(manifest.check_name("test").is_ok());
+        assert!(manifest.check_name("other").is_err());
+
+        let path = ContinuationsManifest::<GoldilocksField>::path(temp_dir.as_path(), "test");
+        manifest.write(&path).unwrap();
+        assert_eq!(ContinuationsManifest::read(&path).unwrap(), manifest);
+
+        // Overwriting replaces the manifest without leaving the temporary file behind.
+        manifest.chunks[1].status = ChunkStatus::Failed;
+        manifest.write(&path).unwrap();
+        assert_eq!(ContinuationsManifest::read(&path).unwrap(), manifest);
+        assert_eq!(fs::read_dir(temp_dir.as_path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn is_done() {
+        let temp_dir = mktemp::Temp::new_dir().unwrap();
+        let mut manifest =
+            ContinuationsManifest::new("test", vec![(vec![GoldilocksField::from(1u64)], 10)]);
+        let chunk = &mut manifest.chunks[0];
+        assert!(!chunk.is_done(temp_dir.as_path(), ChunkStatus::WitnessGenerated));
+
+        // The status in the manifest alone is not enough, the outputs need to exist.
+        chunk.status = ChunkStatus::WitnessGenerated;
+        assert!(!chunk.is_done(temp_dir.as_path(), ChunkStatus::WitnessGenerated));
+
+        fs::write(temp_dir.as_path().join("test_chunk_0_commits.bin"), b"").unwrap();
+        assert!(chunk.is_done(temp_dir.as_path(), ChunkStatus::WitnessGenerated));
+        assert!(!chunk.is_done(temp_dir.as_path(), ChunkStatus::Proven));
+
+        // A failed chunk has to be processed again, even if it left outputs behind.
+        chunk.status = ChunkStatus::Failed;
+        assert!(!chunk.is_done(temp_dir.as_path(), ChunkStatus::Pending));
+    }
+
+    #[test]
+    fn with_progress_from() {
+        let inputs = |values: &[u64]| {
+            values
+                .iter()
+                .map(|&v| (vec![GoldilocksField::from(v)], 10))
+                .collect::<Vec<_>>()
+        };
+        let mut previous = ContinuationsManifest::new("test", inputs(&[1, 2, 3]));
+        for chunk in &mut previous.chunks {
+            chunk.status = ChunkStatus::Proven;
+        }
+
+        // Only the chunks with unchanged inputs keep their status.
+        let manifest =
+            ContinuationsManifest::new("test", inputs(&[1, 5])).with_progress_from(&previous);
+        let statuses = manifest.chunks.iter().map(|c| c.status).collect::<Vec<_>>();
+        assert_eq!(statuses, vec![ChunkStatus::Proven, ChunkStatus::Pending]);
+
+        // With other prover settings, only the witnesses are taken over.
+        let prover = ProverSettings::new(Some("halo2".to_string()), String::new(), None).unwrap();
+        let manifest = ContinuationsManifest::new("test", inputs(&[1, 5]))
+            .with_prover(prover)
+            .with_progress_from(&previous);
+        let statuses = manifest.chunks.iter().map(|c| c.status).collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![ChunkStatus::WitnessGenerated, ChunkStatus::Pending]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use powdr_number::{FieldElement, KnownField};
+use powdr_riscv_executor::{poseidon_bn254::poseidon_bn254, poseidon_gl::poseidon_gl};
+
+/// The hash functions available to commit to the memory, see [MemoryHash].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryHashFunction {
+    #[default]
+    PoseidonGl,
+    PoseidonBn254,
+}
+
+impl MemoryHashFunction {
+    /// The hash function that can be used on the field `F`: Poseidon BN254 on the BN254 field
+    /// and Poseidon GL otherwise.
+    pub fn for_field<F: FieldElement>() -> Self {
+        match F::known_field() {
+            Some(KnownField::Bn254Field) => Self::PoseidonBn254,
+            _ => Self::PoseidonGl,
+        }
+    }
+
+    /// The name of the coprocessor that computes the hash.
+    pub fn coprocessor(&self) -> &'static str {
+        match self {
+            Self::PoseidonGl => PoseidonGl::COPROCESSOR,
+            Self::PoseidonBn254 => PoseidonBn254::COPROCESSOR,
+        }
+    }
+}
+
+impl std::fmt::Display for MemoryHashFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.coprocessor())
+    }
+}
+
+/// A hash function used to commit to the memory in the memory Merkle tree,
+/// together with how the bootloader invokes the coprocessor computing it.
+pub trait MemoryHash {
+    const FUNCTION: MemoryHashFunction;
+    /// The name of the coprocessor that computes the hash.
+    const COPROCESSOR: &'static str;
+    /// The number of field elements in a digest.
+    const DIGEST_SIZE: usize;
+    /// The number of inputs of the hash function: Two digests, followed by the capacity.
+    const NUM_INPUTS: usize;
+    /// The number of `P` registers of the machine, all of which are part of the state saved and
+    /// restored by the bootloader.
+    const NUM_REGISTERS: usize;
+    /// The instruction that hashes the registers P0, ..., P{NUM_INPUTS - 1} and stores
+    /// the digest in P0, ..., P{DIGEST_SIZE - 1}.
+    const HASH_INSTRUCTION: &'static str;
+
+    /// Computes the hash of two digests, using a capacity of 0.
+    fn compress<T: FieldElement>(left: &[T], right: &[T]) -> Vec<T>;
+}
+
+/// Poseidon on the Goldilocks field, with digests of 4 field elements.
+pub struct PoseidonGl;
+
+impl MemoryHash for PoseidonGl {
+    const FUNCTION: MemoryHashFunction = MemoryHashFunction::PoseidonGl;
+    const COPROCESSOR: &'static str = "poseidon_gl";
+    const DIGEST_SIZE: usize = 4;
+    const NUM_INPUTS: usize = 12;
+    const NUM_REGISTERS: usize = 12;
+    const HASH_INSTRUCTION: &'static str =
+        "P0, P1, P2, P3 <== poseidon_gl(P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11);";
+
+    fn compress<T: FieldElement>(left: &[T], right: &[T]) -> Vec<T> {
+        let mut buffer = [T::zero(); 12];
+        buffer[..4].copy_from_slice(left);
+        buffer[4..8].copy_from_slice(right);
+        poseidon_gl(&buffer).to_vec()
+    }
+}
+
+/// Poseidon on the BN254 field, with digests of a single field element.
+pub struct PoseidonBn254;
+
+impl MemoryHash for PoseidonBn254 {
+    const FUNCTION: MemoryHashFunction = MemoryHashFunction::PoseidonBn254;
+    const COPROCESSOR: &'static str = "poseidon_bn254";
+    const DIGEST_SIZE: usize = 1;
+    const NUM_INPUTS: usize = 3;
+    // Poseidon BN254 only reads P0, P1, P2, but it always comes with the BN254 split
+    // machine, which writes the 8 limbs of its input to P0, ..., P7.
+    const NUM_REGISTERS: usize = 8;
+    const HASH_INSTRUCTION: &'static str = "poseidon_bn254;";
+
+    fn compress<T: FieldElement>(left: &[T], right: &[T]) -> Vec<T> {
+        vec![poseidon_bn254(&[left[0], right[0], T::zero()])]
+    }
+}
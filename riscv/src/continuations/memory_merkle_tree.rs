@@ -1,88 +1,93 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, marker::PhantomData};
 
-use super::bootloader::{
-    BYTES_PER_WORD, N_LEAVES_LOG as N_LEAVES_LOG_BOOTLOADER,
-    WORDS_PER_PAGE as WORDS_PER_PAGE_BOOTLOADER,
-};
+use super::bootloader::{MemoryLayout, BYTES_PER_WORD};
+use super::memory_hash::MemoryHash;
 use powdr_number::FieldElement;
-use powdr_riscv_executor::poseidon_gl::poseidon_gl;
-
-/// A Merkle tree of memory pages.
-pub struct MerkleTree<
-    T: FieldElement,
-    const N_LEAVES_LOG: usize = N_LEAVES_LOG_BOOTLOADER,
-    const WORDS_PER_PAGE: usize = WORDS_PER_PAGE_BOOTLOADER,
-> {
-    /// Hashes of all nodes of the Merkle tree. The vector has N_LEAVES_LOG + 1 entries
-    /// where hashes[0] is [root_hash], hashes[1] is [level_1_hash_0, level_1_hash_1], etc.
-    /// The last entry hashes[N_LEAVES_LOG] is the hash of the leaves.
-    /// These hashes should be updated whenever the data is updated.
-    hashes: Vec<Vec<[T; 4]>>,
-    /// Memory pages, contiguous.
-    data: Vec<[T; WORDS_PER_PAGE]>,
-}
 
-/// Computes the Poseidon hash of two 4-field-element inputs, using a capacity of 0.
-fn hash_cap0<T: FieldElement>(data1: &[T; 4], data2: &[T; 4]) -> [T; 4] {
-    let mut buffer = [T::zero(); 12];
-    buffer[..4].copy_from_slice(data1);
-    buffer[4..8].copy_from_slice(data2);
-    poseidon_gl(&buffer)
+/// A sparse Merkle tree of memory pages, using the hash function `H` and the
+/// page size and arity of a [MemoryLayout].
+/// The hash of an inner node is the linear hash of its children, i.e., for children
+/// `c0, c1, c2, ...`, it is `H::compress(H::compress(c0, c1), c2), ...`.
+pub struct MerkleTree<T: FieldElement, H: MemoryHash> {
+    layout: MemoryLayout,
+    /// Hashes of all nodes of the Merkle tree that differ from the hash of an all-zero
+    /// subtree, by level and index. The vector has `layout.n_levels() + 1` entries,
+    /// where hashes[0] contains the root and hashes[layout.n_levels()] the hashes of the pages.
+    /// These hashes should be updated whenever the data is updated.
+    hashes: Vec<BTreeMap<usize, Vec<T>>>,
+    /// The hash of an all-zero subtree, by level.
+    empty_hashes: Vec<Vec<T>>,
+    /// Memory pages that have been updated, by page index. All other pages are zero.
+    data: BTreeMap<usize, Vec<T>>,
+    zero_page: Vec<T>,
+    _hash: PhantomData<H>,
 }
 
-impl<T: FieldElement, const N_LEAVES_LOG: usize, const WORDS_PER_PAGE: usize>
-    MerkleTree<T, N_LEAVES_LOG, WORDS_PER_PAGE>
-{
+impl<T: FieldElement, H: MemoryHash> MerkleTree<T, H> {
     /// Build a new Merkle tree starting from an all-zero memory.
-    pub fn new() -> Self {
-        let zero_page = [T::zero(); WORDS_PER_PAGE];
-        let mut hash = Self::hash_page(&zero_page);
-        let mut hashes = vec![vec![]; N_LEAVES_LOG + 1];
-
-        assert!(usize::BITS >= N_LEAVES_LOG as u32);
-        for level in (0..=N_LEAVES_LOG).rev() {
-            hashes[level] = vec![hash; 1 << level];
-            hash = hash_cap0(&hash, &hash);
+    pub fn new(layout: MemoryLayout) -> Self {
+        assert_eq!(layout.hash, H::FUNCTION);
+        let zero_page = vec![T::zero(); layout.words_per_page()];
+        let mut empty_hashes = vec![Self::hash_page(&zero_page)];
+        for _ in 0..layout.n_levels() {
+            let child = empty_hashes.last().unwrap();
+            let hash = Self::hash_children(&vec![child.as_slice(); layout.arity()]);
+            empty_hashes.push(hash);
         }
+        empty_hashes.reverse();
 
-        assert_eq!(hashes[0].len(), 1);
+        assert_eq!(empty_hashes[0].len(), H::DIGEST_SIZE);
 
-        let data = vec![zero_page; 1 << N_LEAVES_LOG];
-
-        Self { hashes, data }
+        Self {
+            layout,
+            hashes: vec![BTreeMap::new(); layout.n_levels() + 1],
+            empty_hashes,
+            data: BTreeMap::new(),
+            zero_page,
+            _hash: PhantomData,
+        }
     }
 
     /// The root hash of an empty Merkle tree.
-    /// This is equivalent to `Self::new().root_hash()`, but more memory-efficient,
-    /// because it doesn't materialize the tree.
-    pub fn empty_hash() -> [T; 4] {
-        assert!(usize::BITS >= N_LEAVES_LOG as u32);
-
-        let zero_page = [T::zero(); WORDS_PER_PAGE];
-        (0..N_LEAVES_LOG).fold(Self::hash_page(&zero_page), |hash, _| {
-            hash_cap0(&hash, &hash)
-        })
+    pub fn empty_hash(layout: &MemoryLayout) -> Vec<T> {
+        Self::new(*layout).root_hash().to_vec()
     }
 
     /// Computes the linearly iterated hash of a single page
-    fn hash_page(page: &[T; WORDS_PER_PAGE]) -> [T; 4] {
-        let mut hash = [T::zero(); 4];
-        for chunk in page.chunks_exact(4) {
-            hash = hash_cap0(&hash, chunk.try_into().unwrap());
-        }
-        hash
+    fn hash_page(page: &[T]) -> Vec<T> {
+        page.chunks_exact(H::DIGEST_SIZE)
+            .fold(vec![T::zero(); H::DIGEST_SIZE], |hash, chunk| {
+                H::compress(&hash, chunk)
+            })
+    }
+
+    /// Computes the hash of an inner node from the hashes of its children.
+    fn hash_children(children: &[&[T]]) -> Vec<T> {
+        children[1..]
+            .iter()
+            .fold(children[0].to_vec(), |hash, child| {
+                H::compress(&hash, child)
+            })
+    }
+
+    /// Returns the hash of the node with the given index at the given level.
+    fn hash(&self, level: usize, index: usize) -> &[T] {
+        self.hashes[level]
+            .get(&index)
+            .unwrap_or(&self.empty_hashes[level])
     }
 
     pub fn organize_updates_by_page(
         &self,
         updates: impl Iterator<Item = (u32, u32)>,
     ) -> BTreeMap<usize, Vec<(usize, u32)>> {
+        let words_per_page = self.layout.words_per_page();
         let mut updates_by_page: BTreeMap<usize, Vec<(usize, u32)>> = BTreeMap::new();
         for (addr, value) in updates {
             assert!(addr % BYTES_PER_WORD as u32 == 0);
             let word_index = addr as usize / BYTES_PER_WORD;
-            let page_index = word_index / WORDS_PER_PAGE;
-            let index_within_page = word_index - page_index * WORDS_PER_PAGE;
+            let page_index = word_index / words_per_page;
+            let index_within_page = word_index - page_index * words_per_page;
             updates_by_page
                 .entry(page_index)
                 .or_default()
@@ -103,7 +108,10 @@ impl<T: FieldElement, const N_LEAVES_LOG: usize, const WORDS_PER_PAGE: usize>
     /// Applies updates to a single page, given an iterator of (word index, value) pairs.
     /// Word indices addresses are assumed to be word-aligned.
     pub fn update_page(&mut self, page_index: usize, updates: impl Iterator<Item = (usize, u32)>) {
-        let page = &mut self.data[page_index];
+        let page = self
+            .data
+            .entry(page_index)
+            .or_insert_with(|| self.zero_page.clone());
         for (index, value) in updates {
             page[index] = T::from(value);
         }
@@ -112,38 +120,51 @@ impl<T: FieldElement, const N_LEAVES_LOG: usize, const WORDS_PER_PAGE: usize>
 
     /// Updates the hashes of a page and all its ancestors.
     fn update_hashes(&mut self, page_index: usize) {
-        self.hashes[N_LEAVES_LOG][page_index] = Self::hash_page(&self.data[page_index]);
+        let n_levels = self.layout.n_levels();
+        let page_hash = Self::hash_page(&self.data[&page_index]);
+        self.hashes[n_levels].insert(page_index, page_hash);
         for (level, index) in self.iter_path(page_index).skip(1) {
-            self.hashes[level][index] = hash_cap0(
-                &self.hashes[level + 1][index * 2],
-                &self.hashes[level + 1][index * 2 + 1],
-            );
+            let first_child = index * self.layout.arity();
+            let children = (first_child..first_child + self.layout.arity())
+                .map(|child| self.hash(level + 1, child))
+                .collect::<Vec<_>>();
+            let hash = Self::hash_children(&children);
+            self.hashes[level].insert(index, hash);
         }
     }
 
     /// Returns the root hash of the Merkle tree.
-    pub fn root_hash(&self) -> &[T; 4] {
-        &self.hashes[0][0]
+    pub fn root_hash(&self) -> &[T] {
+        self.hash(0, 0)
     }
 
     /// Returns the data and Merkle proof for a given page.
-    pub fn get(&self, page_index: usize) -> (&[T; WORDS_PER_PAGE], &[T; 4], Vec<&[T; 4]>) {
+    /// The proof contains the siblings of each node on the path from the page to the root
+    /// (excluding the root), in order.
+    pub fn get(&self, page_index: usize) -> (&[T], &[T], Vec<&[T]>) {
+        let arity = self.layout.arity();
         let mut proof = vec![];
-        for (level, index) in self.iter_path(page_index).take(N_LEAVES_LOG) {
-            let sibling_index = index ^ 1;
-            proof.push(&self.hashes[level][sibling_index]);
+        for (level, index) in self.iter_path(page_index).take(self.layout.n_levels()) {
+            let first_sibling = index - index % arity;
+            for sibling_index in (first_sibling..first_sibling + arity).filter(|&i| i != index) {
+                proof.push(self.hash(level, sibling_index));
+            }
         }
-        assert_eq!(proof.len(), N_LEAVES_LOG);
+        assert_eq!(proof.len(), self.layout.n_levels() * (arity - 1));
 
-        let page_hash = &self.hashes[N_LEAVES_LOG][page_index];
+        let page_hash = self.hash(self.layout.n_levels(), page_index);
 
-        (&self.data[page_index], page_hash, proof)
+        let page = self.data.get(&page_index).unwrap_or(&self.zero_page);
+
+        (page, page_hash, proof)
     }
 
     /// Yields (level, index) pairs for the path from the given page to the root.
     fn iter_path(&self, page_index: usize) -> impl Iterator<Item = (usize, usize)> {
-        (0..=N_LEAVES_LOG).rev().map(move |level| {
-            let index = page_index >> (N_LEAVES_LOG - level);
+        let n_levels = self.layout.n_levels();
+        let arity_log = self.layout.arity_log;
+        (0..=n_levels).rev().map(move |level| {
+            let index = page_index >> ((n_levels - level) * arity_log);
             (level, index)
         })
     }
@@ -151,79 +172,102 @@ impl<T: FieldElement, const N_LEAVES_LOG: usize, const WORDS_PER_PAGE: usize>
 
 #[cfg(test)]
 mod test {
-    use powdr_number::GoldilocksField;
+    use powdr_number::{Bn254Field, GoldilocksField};
 
+    use super::super::memory_hash::{PoseidonBn254, PoseidonGl};
     use super::*;
 
-    fn hash_page<T: FieldElement>(page: &[u64; 8]) -> [T; 4] {
+    /// 4 pages of 8 words each, in a binary Merkle tree.
+    fn test_layout<H: MemoryHash>() -> MemoryLayout {
+        MemoryLayout {
+            memory_size_log: 7,
+            page_size_bytes_log: 5,
+            arity_log: 1,
+            hash: H::FUNCTION,
+        }
+    }
+
+    fn hash_cap0(data1: &[GoldilocksField], data2: &[GoldilocksField]) -> Vec<GoldilocksField> {
+        PoseidonGl::compress(data1, data2)
+    }
+
+    fn hash_page<T: FieldElement, H: MemoryHash>(page: &[u64; 8]) -> Vec<T> {
         // Convert to field elements
-        let page: [T; 8] = page
-            .iter()
-            .map(|&x| T::from(x))
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
+        let page = page.iter().map(|&x| T::from(x)).collect::<Vec<_>>();
 
         // Linearly hash page
-        let hash = hash_cap0(&[T::zero(); 4], &page[..4].try_into().unwrap());
-        hash_cap0(&hash, &page[4..].try_into().unwrap())
+        page.chunks(H::DIGEST_SIZE)
+            .fold(vec![T::zero(); H::DIGEST_SIZE], |hash, chunk| {
+                H::compress(&hash, chunk)
+            })
     }
 
-    fn root_hash<T: FieldElement>(pages: &[[u64; 8]; 4]) -> [T; 4] {
-        let page_hashes = pages.iter().map(|page| hash_page(page)).collect::<Vec<_>>();
+    fn root_hash<T: FieldElement, H: MemoryHash>(pages: &[[u64; 8]; 4]) -> Vec<T> {
+        let page_hashes = pages
+            .iter()
+            .map(|page| hash_page::<T, H>(page))
+            .collect::<Vec<_>>();
 
-        let hash_1_0 = hash_cap0(&page_hashes[0], &page_hashes[1]);
-        let hash_1_1 = hash_cap0(&page_hashes[2], &page_hashes[3]);
-        hash_cap0(&hash_1_0, &hash_1_1)
+        let hash_1_0 = H::compress(&page_hashes[0], &page_hashes[1]);
+        let hash_1_1 = H::compress(&page_hashes[2], &page_hashes[3]);
+        H::compress(&hash_1_0, &hash_1_1)
     }
 
-    #[test]
-    fn test_zero_root_hash() {
-        let tree = MerkleTree::<GoldilocksField, 2, 8>::new();
+    fn test_zero_root_hash<T: FieldElement, H: MemoryHash>() {
+        let tree = MerkleTree::<T, H>::new(test_layout::<H>());
         let data = [[0; 8]; 4];
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
-        let empty_hash = MerkleTree::<GoldilocksField, 2, 8>::empty_hash();
+        let expected_root_hash = root_hash::<T, H>(&data);
+        let empty_hash = MerkleTree::<T, H>::empty_hash(&test_layout::<H>());
         assert_eq!(tree.root_hash(), &expected_root_hash);
         assert_eq!(tree.root_hash(), &empty_hash);
     }
 
     #[test]
-    fn test_update() {
-        let mut tree = MerkleTree::<GoldilocksField, 2, 8>::new();
+    fn test_zero_root_hash_gl() {
+        test_zero_root_hash::<GoldilocksField, PoseidonGl>();
+    }
+
+    #[test]
+    fn test_zero_root_hash_bn254() {
+        test_zero_root_hash::<Bn254Field, PoseidonBn254>();
+    }
+
+    fn test_update<T: FieldElement, H: MemoryHash>() {
+        let mut tree = MerkleTree::<T, H>::new(test_layout::<H>());
         let mut data = [[0; 8]; 4];
 
         // Update page 0
         data[0][4] = 1;
         tree.update([(4 * 4, 1)].into_iter());
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
+        let expected_root_hash = root_hash::<T, H>(&data);
         assert_eq!(tree.root_hash(), &expected_root_hash);
 
         // Update page 1
         data[1][3] = 2;
         tree.update([((8 + 3) * 4, 2)].into_iter());
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
+        let expected_root_hash = root_hash::<T, H>(&data);
         assert_eq!(tree.root_hash(), &expected_root_hash);
 
         // Update page 2
         data[2][7] = 3;
         tree.update([((2 * 8 + 7) * 4, 3)].into_iter());
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
+        let expected_root_hash = root_hash::<T, H>(&data);
         assert_eq!(tree.root_hash(), &expected_root_hash);
 
         // Update page 3
         data[3][6] = 4;
         tree.update([((3 * 8 + 6) * 4, 4)].into_iter());
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
+        let expected_root_hash = root_hash::<T, H>(&data);
         assert_eq!(tree.root_hash(), &expected_root_hash);
 
         // Update page 0, again
         data[0][3] = 5;
         tree.update([(4 * 3, 5)].into_iter());
-        let expected_root_hash = root_hash::<GoldilocksField>(&data);
+        let expected_root_hash = root_hash::<T, H>(&data);
         assert_eq!(tree.root_hash(), &expected_root_hash);
 
         // Update all at once
-        let mut tree = MerkleTree::<GoldilocksField, 2, 8>::new();
+        let mut tree = MerkleTree::<T, H>::new(test_layout::<H>());
         tree.update(
             [
                 (4 * 4, 1),
@@ -237,10 +281,20 @@ mod test {
         assert_eq!(tree.root_hash(), &expected_root_hash);
     }
 
+    #[test]
+    fn test_update_gl() {
+        test_update::<GoldilocksField, PoseidonGl>();
+    }
+
+    #[test]
+    fn test_update_bn254() {
+        test_update::<Bn254Field, PoseidonBn254>();
+    }
+
     #[test]
     fn test_get() {
         let g = GoldilocksField::from;
-        let mut tree = MerkleTree::<GoldilocksField, 2, 8>::new();
+        let mut tree = MerkleTree::<GoldilocksField, PoseidonGl>::new(test_layout::<PoseidonGl>());
         tree.update(
             [
                 (4 * 4, 1),
@@ -257,48 +311,98 @@ mod test {
         let (page, page_hash, proof) = tree.get(0);
         let expected_page = [g(0), g(0), g(0), g(5), g(1), g(0), g(0), g(0)];
         assert_eq!(page, &expected_page);
-        assert_eq!(page_hash, &hash_page(&[0, 0, 0, 5, 1, 0, 0, 0]));
+        assert_eq!(
+            page_hash,
+            &hash_page::<_, PoseidonGl>(&[0, 0, 0, 5, 1, 0, 0, 0])
+        );
 
         // Verify Merkle proof
         assert_eq!(proof.len(), 2);
         let computed_hash = hash_cap0(page_hash, proof[0]);
         let computed_hash = hash_cap0(&computed_hash, proof[1]);
-        assert_eq!(computed_hash, *root_hash);
+        assert_eq!(computed_hash, root_hash);
 
         // Get page 1
         let (page, page_hash, proof) = tree.get(1);
         let expected_page = [g(0), g(0), g(0), g(2), g(0), g(0), g(0), g(0)];
         assert_eq!(page, &expected_page);
-        assert_eq!(page_hash, &hash_page(&[0, 0, 0, 2, 0, 0, 0, 0]));
+        assert_eq!(
+            page_hash,
+            &hash_page::<_, PoseidonGl>(&[0, 0, 0, 2, 0, 0, 0, 0])
+        );
 
         // Verify Merkle proof
         assert_eq!(proof.len(), 2);
         let computed_hash = hash_cap0(proof[0], page_hash);
         let computed_hash = hash_cap0(&computed_hash, proof[1]);
-        assert_eq!(computed_hash, *root_hash);
+        assert_eq!(computed_hash, root_hash);
 
         // Get page 2
         let (page, page_hash, proof) = tree.get(2);
         let expected_page = [g(0), g(0), g(0), g(0), g(0), g(0), g(0), g(3)];
         assert_eq!(page, &expected_page);
-        assert_eq!(page_hash, &hash_page(&[0, 0, 0, 0, 0, 0, 0, 3]));
+        assert_eq!(
+            page_hash,
+            &hash_page::<_, PoseidonGl>(&[0, 0, 0, 0, 0, 0, 0, 3])
+        );
 
         // Verify Merkle proof
         assert_eq!(proof.len(), 2);
         let computed_hash = hash_cap0(page_hash, proof[0]);
         let computed_hash = hash_cap0(proof[1], &computed_hash);
-        assert_eq!(computed_hash, *root_hash);
+        assert_eq!(computed_hash, root_hash);
 
         // Get page 3
         let (page, page_hash, proof) = tree.get(3);
         let expected_page = [g(0), g(0), g(0), g(0), g(0), g(0), g(4), g(0)];
         assert_eq!(page, &expected_page);
-        assert_eq!(page_hash, &hash_page(&[0, 0, 0, 0, 0, 0, 4, 0]));
+        assert_eq!(
+            page_hash,
+            &hash_page::<_, PoseidonGl>(&[0, 0, 0, 0, 0, 0, 4, 0])
+        );
 
         // Verify Merkle proof
         assert_eq!(proof.len(), 2);
         let computed_hash = hash_cap0(proof[0], page_hash);
         let computed_hash = hash_cap0(proof[1], &computed_hash);
-        assert_eq!(computed_hash, *root_hash);
+        assert_eq!(computed_hash, root_hash);
+    }
+
+    #[test]
+    fn test_arity_4() {
+        let layout = MemoryLayout {
+            arity_log: 2,
+            ..test_layout::<PoseidonGl>()
+        };
+        let mut tree = MerkleTree::<GoldilocksField, PoseidonGl>::new(layout);
+        tree.update([(4 * 4, 1), ((2 * 8 + 7) * 4, 3)].into_iter());
+
+        let page_hashes = [
+            hash_page::<_, PoseidonGl>(&[0, 0, 0, 0, 1, 0, 0, 0]),
+            hash_page::<_, PoseidonGl>(&[0; 8]),
+            hash_page::<_, PoseidonGl>(&[0, 0, 0, 0, 0, 0, 0, 3]),
+            hash_page::<_, PoseidonGl>(&[0; 8]),
+        ];
+        let expected_root_hash = hash_cap0(
+            &hash_cap0(
+                &hash_cap0(&page_hashes[0], &page_hashes[1]),
+                &page_hashes[2],
+            ),
+            &page_hashes[3],
+        );
+        assert_eq!(tree.root_hash(), &expected_root_hash);
+
+        // The proof of page 2 consists of the hashes of pages 0, 1 and 3.
+        let (_, page_hash, proof) = tree.get(2);
+        assert_eq!(page_hash, &page_hashes[2]);
+        assert_eq!(
+            proof,
+            vec![&page_hashes[0][..], &page_hashes[1], &page_hashes[3]]
+        );
+
+        assert_eq!(
+            MerkleTree::<GoldilocksField, PoseidonGl>::new(layout).root_hash(),
+            MerkleTree::<GoldilocksField, PoseidonGl>::empty_hash(&layout)
+        );
     }
 }
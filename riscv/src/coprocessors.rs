@@ -1,8 +1,13 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::TryFrom,
+    path::Path,
 };
 
+use manifest::CustomCoProcessor;
+
+mod manifest;
+
 type RuntimeFunctionImpl = (&'static str, fn() -> String);
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -11,7 +16,7 @@ struct CoProcessor {
     ty: &'static str,
     import: &'static str,
     instructions: &'static str,
-    runtime_function_impl: Option<RuntimeFunctionImpl>,
+    runtime_function_impls: &'static [RuntimeFunctionImpl],
 }
 
 static BINARY_COPROCESSOR: CoProcessor = CoProcessor {
@@ -25,7 +30,7 @@ static BINARY_COPROCESSOR: CoProcessor = CoProcessor {
     instr xor Y, Z -> X = binary.xor;
 
             "#,
-    runtime_function_impl: None,
+    runtime_function_impls: &[],
 };
 
 static SHIFT_COPROCESSOR: CoProcessor = CoProcessor {
@@ -38,7 +43,7 @@ static SHIFT_COPROCESSOR: CoProcessor = CoProcessor {
     instr shr Y, Z -> X = shift.shr;
 
             "#,
-    runtime_function_impl: None,
+    runtime_function_impls: &[],
 };
 
 static SPLIT_GL_COPROCESSOR: CoProcessor = CoProcessor {
@@ -50,7 +55,7 @@ static SPLIT_GL_COPROCESSOR: CoProcessor = CoProcessor {
 instr split_gl Z -> X, Y = split_gl.split;
 
     "#,
-    runtime_function_impl: None,
+    runtime_function_impls: &[],
 };
 
 static POSEIDON_GL_COPROCESSOR: CoProcessor = CoProcessor {
@@ -62,7 +67,73 @@ static POSEIDON_GL_COPROCESSOR: CoProcessor = CoProcessor {
 instr poseidon_gl A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11 -> X, Y, Z, W = poseidon_gl.poseidon_permutation;
 
 "#,
-    runtime_function_impl: Some(("poseidon_gl_coprocessor", poseidon_gl_call)),
+    runtime_function_impls: &[("poseidon_gl_coprocessor", poseidon_gl_call)],
+};
+
+static SPLIT_BN254_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "split_bn254",
+    ty: "SplitBN254",
+    import: "use std::split::split_bn254::SplitBN254;",
+    instructions: r#"
+// ================== wrapping instructions ==============
+instr split_bn254 = split_bn254.split P0 -> P0, P1, P2, P3, P4, P5, P6, P7;
+
+"#,
+    runtime_function_impls: &[],
+};
+
+static POSEIDON_BN254_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "poseidon_bn254",
+    ty: "PoseidonBN254",
+    import: "use std::hash::poseidon_bn254::PoseidonBN254;",
+    instructions: r#"
+// ================== hashing instructions ==============
+instr poseidon_bn254 = poseidon_bn254.poseidon_permutation P0, P1, P2 -> P0;
+
+"#,
+    runtime_function_impls: &[("poseidon_bn254_coprocessor", poseidon_bn254_call)],
+};
+
+static KECCAKF_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "keccakf",
+    ty: "KeccakF",
+    import: "use std::hash::keccakf::KeccakF;",
+    instructions: r#"
+// ================== hashing instructions ==============
+instr keccakf = keccakf.keccakf K0, K1, K2, K3, K4, K5, K6, K7, K8, K9, K10, K11, K12, K13, K14, K15, K16, K17, K18, K19, K20, K21, K22, K23, K24, K25, K26, K27, K28, K29, K30, K31, K32, K33, K34, K35, K36, K37, K38, K39, K40, K41, K42, K43, K44, K45, K46, K47, K48, K49 -> K0, K1, K2, K3, K4, K5, K6, K7, K8, K9, K10, K11, K12, K13, K14, K15, K16, K17, K18, K19, K20, K21, K22, K23, K24, K25, K26, K27, K28, K29, K30, K31, K32, K33, K34, K35, K36, K37, K38, K39, K40, K41, K42, K43, K44, K45, K46, K47, K48, K49;
+
+"#,
+    runtime_function_impls: &[("keccakf_coprocessor", keccakf_call)],
+};
+
+static SHA256_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "sha256",
+    ty: "SHA256",
+    import: "use std::hash::sha256::SHA256;",
+    instructions: r#"
+// ================== hashing instructions ==============
+instr sha256_compress = sha256.sha256_compress H0, H1, H2, H3, H4, H5, H6, H7, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12, M13, M14, M15 -> H0, H1, H2, H3, H4, H5, H6, H7;
+
+"#,
+    runtime_function_impls: &[("sha256_compress_coprocessor", sha256_compress_call)],
+};
+
+static ARITH_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "arith",
+    ty: "Arith",
+    import: "use std::arith::Arith;",
+    instructions: r#"
+// ================== 256-bit arithmetic instructions ==============
+instr affine_256 = arith.affine_256 L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15, L16, L17, L18, L19, L20, L21, L22, L23 -> L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15;
+instr ec_add = arith.ec_add L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15, L16, L17, L18, L19, L20, L21, L22, L23, L24, L25, L26, L27, L28, L29, L30, L31 -> L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15;
+instr ec_double = arith.ec_double L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15 -> L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15;
+
+"#,
+    runtime_function_impls: &[
+        ("affine_256_coprocessor", affine_256_call),
+        ("ec_add_coprocessor", ec_add_call),
+        ("ec_double_coprocessor", ec_double_call),
+    ],
 };
 
 static INPUT_COPROCESSOR: CoProcessor = CoProcessor {
@@ -70,14 +141,19 @@ static INPUT_COPROCESSOR: CoProcessor = CoProcessor {
     ty: "",
     import: "",
     instructions: "",
-    runtime_function_impl: Some(("input_coprocessor", prover_input_call)),
+    runtime_function_impls: &[("input_coprocessor", prover_input_call)],
 };
 
-static ALL_COPROCESSORS: [(&str, &CoProcessor); 5] = [
+static ALL_COPROCESSORS: [(&str, &CoProcessor); 10] = [
     (BINARY_COPROCESSOR.name, &BINARY_COPROCESSOR),
     (SHIFT_COPROCESSOR.name, &SHIFT_COPROCESSOR),
     (SPLIT_GL_COPROCESSOR.name, &SPLIT_GL_COPROCESSOR),
     (POSEIDON_GL_COPROCESSOR.name, &POSEIDON_GL_COPROCESSOR),
+    (SPLIT_BN254_COPROCESSOR.name, &SPLIT_BN254_COPROCESSOR),
+    (POSEIDON_BN254_COPROCESSOR.name, &POSEIDON_BN254_COPROCESSOR),
+    (KECCAKF_COPROCESSOR.name, &KECCAKF_COPROCESSOR),
+    (SHA256_COPROCESSOR.name, &SHA256_COPROCESSOR),
+    (ARITH_COPROCESSOR.name, &ARITH_COPROCESSOR),
     (INPUT_COPROCESSOR.name, &INPUT_COPROCESSOR),
 ];
 
@@ -87,13 +163,18 @@ static ALL_COPROCESSORS: [(&str, &CoProcessor); 5] = [
 #[derive(Default)]
 pub struct CoProcessors {
     coprocessors: BTreeMap<&'static str, &'static CoProcessor>,
+    custom: BTreeMap<String, CustomCoProcessor>,
 }
 
 impl TryFrom<Vec<&str>> for CoProcessors {
     type Error = String;
 
+    /// Items ending in `.json` are loaded as manifests of user-defined
+    /// coprocessors, all others have to be names of built-in coprocessors.
     fn try_from(list: Vec<&str>) -> Result<Self, Self::Error> {
-        let items: BTreeSet<&str> = list.into_iter().collect();
+        let (manifests, items): (Vec<&str>, Vec<&str>) =
+            list.into_iter().partition(|item| item.ends_with(".json"));
+        let items: BTreeSet<&str> = items.into_iter().collect();
 
         if !items.iter().all(|co_processor| {
             ALL_COPROCESSORS
@@ -103,7 +184,7 @@ impl TryFrom<Vec<&str>> for CoProcessors {
             return Err("Invalid co-processor specified.".to_string());
         }
 
-        Ok(Self {
+        let mut coprocessors = Self {
             coprocessors: ALL_COPROCESSORS
                 .iter()
                 .filter_map(|(name, co_processor)| {
@@ -114,7 +195,13 @@ impl TryFrom<Vec<&str>> for CoProcessors {
                     }
                 })
                 .collect(),
-        })
+            custom: Default::default(),
+        };
+        for manifest in manifests {
+            coprocessors =
+                coprocessors.with_custom(CustomCoProcessor::load(Path::new(manifest))?)?;
+        }
+        Ok(coprocessors)
     }
 }
 
@@ -127,9 +214,39 @@ impl CoProcessors {
                 (SHIFT_COPROCESSOR.name, &SHIFT_COPROCESSOR),
                 (INPUT_COPROCESSOR.name, &INPUT_COPROCESSOR),
             ]),
+            custom: Default::default(),
         }
     }
 
+    /// Adds a user-defined coprocessor, checking that its names do not clash
+    /// with the other coprocessors.
+    fn with_custom(mut self, coprocessor: CustomCoProcessor) -> Result<Self, String> {
+        if ALL_COPROCESSORS
+            .iter()
+            .any(|(name, _)| *name == coprocessor.name)
+            || self.custom.contains_key(&coprocessor.name)
+        {
+            return Err(format!(
+                "Coprocessor {} is defined more than once.",
+                coprocessor.name
+            ));
+        }
+        if let Some((symbol, _)) = &coprocessor.runtime_function_impl {
+            if ALL_COPROCESSORS
+                .iter()
+                .flat_map(|(_, c)| c.runtime_function_impls)
+                .any(|(name, _)| name == symbol)
+                || self.runtime_names().contains(&symbol.as_str())
+            {
+                return Err(format!(
+                    "Runtime function {symbol} is defined more than once."
+                ));
+            }
+        }
+        self.custom.insert(coprocessor.name.clone(), coprocessor);
+        Ok(self)
+    }
+
     /// Poseidon also uses the Split machine.
     pub fn with_poseidon(mut self) -> Self {
         self.coprocessors
@@ -139,26 +256,59 @@ impl CoProcessors {
         self
     }
 
+    /// Poseidon BN254 also uses the BN254 Split machine.
+    pub fn with_poseidon_bn254(mut self) -> Self {
+        self.coprocessors
+            .insert(SPLIT_BN254_COPROCESSOR.name, &SPLIT_BN254_COPROCESSOR);
+        self.coprocessors
+            .insert(POSEIDON_BN254_COPROCESSOR.name, &POSEIDON_BN254_COPROCESSOR);
+        self
+    }
+
+    pub fn with_keccakf(mut self) -> Self {
+        self.coprocessors
+            .insert(KECCAKF_COPROCESSOR.name, &KECCAKF_COPROCESSOR);
+        self
+    }
+
+    pub fn with_sha256(mut self) -> Self {
+        self.coprocessors
+            .insert(SHA256_COPROCESSOR.name, &SHA256_COPROCESSOR);
+        self
+    }
+
+    pub fn with_arith(mut self) -> Self {
+        self.coprocessors
+            .insert(ARITH_COPROCESSOR.name, &ARITH_COPROCESSOR);
+        self
+    }
+
     pub fn has(&self, key: &str) -> bool {
-        self.coprocessors.contains_key(key)
+        self.coprocessors.contains_key(key) || self.custom.contains_key(key)
     }
 
-    pub fn declarations(&self) -> Vec<(&'static str, &'static str)> {
+    pub fn declarations(&self) -> Vec<(&str, &str)> {
         self.coprocessors
             .values()
             .filter(|c| !c.ty.is_empty())
             .map(|c| (c.name, c.ty))
+            .chain(self.custom.values().map(|c| (&c.name[..], &c.ty[..])))
             .collect()
     }
 
-    pub fn machine_imports(&self) -> Vec<&'static str> {
-        self.coprocessors.values().map(|c| c.import).collect()
+    pub fn machine_imports(&self) -> Vec<&str> {
+        self.coprocessors
+            .values()
+            .map(|c| c.import)
+            .chain(self.custom.values().map(|c| &c.import[..]))
+            .collect()
     }
 
     pub fn instructions(&self) -> String {
         self.coprocessors
             .values()
             .map(|c| c.instructions)
+            .chain(self.custom.values().map(|c| &c.instructions[..]))
             .collect::<Vec<&str>>()
             .join("")
     }
@@ -166,8 +316,14 @@ impl CoProcessors {
     pub fn runtime_names(&self) -> Vec<&str> {
         self.coprocessors
             .values()
-            .filter_map(|c| c.runtime_function_impl)
+            .flat_map(|c| c.runtime_function_impls)
             .map(|f| f.0)
+            .chain(
+                self.custom
+                    .values()
+                    .filter_map(|c| c.runtime_function_impl.as_ref())
+                    .map(|f| &f.0[..]),
+            )
             .collect()
     }
 
@@ -188,29 +344,61 @@ impl CoProcessors {
             .join("\n")
     }
 
-    pub fn substitutions(&self) -> Vec<(&'static str, String)> {
+    pub fn substitutions(&self) -> Vec<(&str, String)> {
         self.coprocessors
             .values()
-            .filter_map(|c| c.runtime_function_impl)
+            .flat_map(|c| c.runtime_function_impls)
             .map(|f| (f.0, f.1()))
+            .chain(
+                self.custom
+                    .values()
+                    .filter_map(|c| c.runtime_function_impl.as_ref())
+                    .map(|f| (&f.0[..], f.1.clone())),
+            )
             .collect()
     }
 
     pub fn registers(&self) -> String {
+        let mut registers = vec![];
+
         // Poseidon has 12 inputs and 4 outputs.
         // The base RISCV machine has 4 assignment registers.
         // Therefore we need to add 12 assignment registers when using Poseidon.
         // Moreover, we also need 12 extra general purpose registers to store the
         // input values.
+        if self.coprocessors.contains_key(POSEIDON_GL_COPROCESSOR.name) {
+            registers.extend((0..12).map(|i| format!("reg A{}[<=];", i)));
+            registers.extend((0..12).map(|i| format!("reg P{};", i)));
+        } else if self
+            .coprocessors
+            .contains_key(POSEIDON_BN254_COPROCESSOR.name)
+            || self.coprocessors.contains_key(SPLIT_BN254_COPROCESSOR.name)
+        {
+            // Poseidon BN254 reads its 3 inputs from general purpose registers and
+            // the BN254 split machine returns 8 limbs, so P0, ..., P7 are enough.
+            registers.extend((0..8).map(|i| format!("reg P{};", i)));
+        }
+
+        // Keccak-f reads its 50 input limbs from and writes its output limbs
+        // to general purpose registers, so that we do not need 100 extra
+        // assignment registers.
+        if self.coprocessors.contains_key(KECCAKF_COPROCESSOR.name) {
+            registers.extend((0..50).map(|i| format!("reg K{};", i)));
+        }
 
-        if !self.coprocessors.contains_key(POSEIDON_GL_COPROCESSOR.name) {
-            return String::new();
+        // The same holds for the SHA-256 state (H0, ..., H7) and message block (M0, ..., M15).
+        if self.coprocessors.contains_key(SHA256_COPROCESSOR.name) {
+            registers.extend((0..8).map(|i| format!("reg H{};", i)));
+            registers.extend((0..16).map(|i| format!("reg M{};", i)));
         }
 
-        let a_regs: Vec<String> = (0..12).map(|i| format!("reg A{}[<=];", i)).collect();
-        let p_regs: Vec<String> = (0..12).map(|i| format!("reg P{};", i)).collect();
+        // The arithmetic operations take up to four and return two 256-bit numbers,
+        // which are passed as 8 32-bit limbs each in the registers L0, ..., L31.
+        if self.coprocessors.contains_key(ARITH_COPROCESSOR.name) {
+            registers.extend((0..32).map(|i| format!("reg L{};", i)));
+        }
 
-        [a_regs, p_regs].concat().join("\n")
+        registers.join("\n")
     }
 }
 
@@ -253,6 +441,91 @@ fn poseidon_gl_call() -> String {
         .collect()
 }
 
+fn poseidon_bn254_call() -> String {
+    // The x10 register is RISCV's a0 register, which has the first function argument in function
+    // calls. The poseidon coprocessor has a single argument, the memory address of the 3 field
+    // element input array, each of them stored as 8 32-bit limbs (least significant first).
+    // The output is written back to the memory of the first input element.
+    let decoding = |i| {
+        (0..8)
+            .map(|j| {
+                format!(
+                    r#"
+        tmp1, tmp2 <== mload({} + x10);
+        P{i} <=X= P{i} + tmp1 * 2**{};
+    "#,
+                    (i * 8 + j) * 4,
+                    32 * j
+                )
+            })
+            .collect::<String>()
+    };
+    let clear = (0..3).map(|i| format!("P{i} <=X= 0;"));
+    let call = "poseidon_bn254;\nsplit_bn254;".to_string();
+    let encoding = (0..8).map(|i| format!("mstore {} + x10, P{i};", i * 4));
+
+    clear
+        .chain((0..3).map(decoding))
+        .chain(std::iter::once(call))
+        .chain(encoding)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn keccakf_call() -> String {
+    // The x10 register is RISCV's a0 register, which has the first function argument in function
+    // calls. The keccakf coprocessor has a single argument, the memory address of the state of
+    // 25 u64 lanes, which we load into and store from the registers K0, ..., K49 as 32-bit limbs.
+    let load = (0..50).map(|i| format!("K{i}, tmp2 <== mload({} + x10);", i * 4));
+    let store = (0..50).map(|i| format!("mstore {} + x10, K{i};", i * 4));
+
+    load.chain(std::iter::once("keccakf;".to_string()))
+        .chain(store)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn sha256_compress_call() -> String {
+    // The x10 and x11 registers are RISCV's a0 and a1 registers, which have the first two
+    // function arguments in function calls: the memory address of the state of 8 u32 words
+    // and the memory address of the message block of 16 u32 words.
+    let load_state = (0..8).map(|i| format!("H{i}, tmp2 <== mload({} + x10);", i * 4));
+    let load_block = (0..16).map(|i| format!("M{i}, tmp2 <== mload({} + x11);", i * 4));
+    let store = (0..8).map(|i| format!("mstore {} + x10, H{i};", i * 4));
+
+    load_state
+        .chain(load_block)
+        .chain(std::iter::once("sha256_compress;".to_string()))
+        .chain(store)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Loads `inputs` 32-bit words from the memory address in x10 (RISCV's a0 register, the first
+/// function argument) into the registers L0, ..., and stores `outputs` words back after
+/// executing the given instruction.
+fn arith_call(instruction: &str, inputs: usize, outputs: usize) -> String {
+    let load = (0..inputs).map(|i| format!("L{i}, tmp2 <== mload({} + x10);", i * 4));
+    let store = (0..outputs).map(|i| format!("mstore {} + x10, L{i};", i * 4));
+
+    load.chain(std::iter::once(format!("{instruction};")))
+        .chain(store)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn affine_256_call() -> String {
+    arith_call("affine_256", 24, 16)
+}
+
+fn ec_add_call() -> String {
+    arith_call("ec_add", 32, 16)
+}
+
+fn ec_double_call() -> String {
+    arith_call("ec_double", 16, 16)
+}
+
 fn prover_input_call() -> String {
     "x10 <=X= ${ (\"data_identifier\", x11, x10) };".to_string()
 }
@@ -285,6 +558,36 @@ pub fn call_every_submachine(coprocessors: &CoProcessors) -> Vec<String> {
         ]);
     }
 
+    if coprocessors.has(POSEIDON_BN254_COPROCESSOR.name) {
+        calls.extend(vec![
+            "poseidon_bn254;".to_string(),
+            "P0 <=X= 0;".to_string(),
+        ]);
+    }
+    if coprocessors.has(SPLIT_BN254_COPROCESSOR.name) {
+        // P0 is zero, so this sets P0, ..., P7 to zero.
+        calls.push("split_bn254;".to_string());
+    }
+
+    if coprocessors.has(KECCAKF_COPROCESSOR.name) {
+        calls.push("keccakf;".to_string());
+        calls.extend((0..50).map(|i| format!("K{i} <=X= 0;")));
+    }
+
+    if coprocessors.has(SHA256_COPROCESSOR.name) {
+        calls.push("sha256_compress;".to_string());
+        calls.extend((0..8).map(|i| format!("H{i} <=X= 0;")));
+    }
+
+    if coprocessors.has(ARITH_COPROCESSOR.name) {
+        // The registers are all zero, so this computes 0 * 0 + 0 = 0.
+        calls.push("affine_256;".to_string());
+    }
+
+    for coprocessor in coprocessors.custom.values() {
+        calls.extend(coprocessor.initialization.iter().cloned());
+    }
+
     calls.extend(vec!["x10 <=X= 0;".to_string(), "x11 <=X= 0;".to_string()]);
 
     calls
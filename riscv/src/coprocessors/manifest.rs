@@ -0,0 +1,424 @@
+//! User-defined coprocessors, loaded from a JSON manifest.
+//!
+//! A manifest looks like this:
+//! ```json
+//! {
+//!     "name": "my_hash",
+//!     "machine": "MyHash",
+//!     "source": "my_hash.asm",
+//!     "instructions": ["instr my_hash Y, Z -> X = my_hash.hash;"],
+//!     "runtime_function": {
+//!         "symbol": "my_hash_coprocessor",
+//!         "instruction": "my_hash",
+//!         "inputs": ["x10", "x11"],
+//!         "outputs": ["x10"]
+//!     }
+//! }
+//! ```
+//! The powdr-asm file `source` (relative to the manifest) has to define the
+//! machine `machine`, which is instantiated as `name` in the RISCV machine.
+//! The instructions can only call operations of that machine.
+//!
+//! If `runtime_function` is given, calls to `symbol` in the guest are replaced
+//! by the instruction, reading its inputs from and writing its outputs to the
+//! given registers. Following the RISCV calling convention, these are usually
+//! the argument registers `x10` to `x17`.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use powdr_ast::parsed::asm::{
+    InstructionBody, Machine, MachineStatement, ModuleStatement, Params, SymbolValue,
+};
+use powdr_number::GoldilocksField;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    machine: String,
+    source: String,
+    instructions: Vec<String>,
+    runtime_function: Option<RuntimeFunction>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuntimeFunction {
+    symbol: String,
+    instruction: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+/// A coprocessor defined outside of powdr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomCoProcessor {
+    pub name: String,
+    pub ty: String,
+    /// The module containing the source of the machine.
+    pub import: String,
+    pub instructions: String,
+    /// The runtime symbol and the powdr-asm code that replaces calls to it.
+    pub runtime_function_impl: Option<(String, String)>,
+    /// Code that calls the machine once, see `call_every_submachine`.
+    pub initialization: Vec<String>,
+}
+
+impl CustomCoProcessor {
+    /// Loads and validates the manifest at the given path.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let read = |path: &Path| {
+            fs::read_to_string(path)
+                .map_err(|e| format!("Could not read {}: {e}", path.to_str().unwrap()))
+        };
+        let manifest: Manifest = serde_json::from_str(&read(path)?).map_err(|e| {
+            format!(
+                "Invalid coprocessor manifest {}: {e}",
+                path.to_str().unwrap()
+            )
+        })?;
+        let source_path = path.parent().unwrap().join(&manifest.source);
+        let source = read(&source_path)?;
+        Self::new(manifest, source_path.to_str().unwrap(), &source)
+    }
+
+    fn new(manifest: Manifest, source_file: &str, source: &str) -> Result<Self, String> {
+        let name = &manifest.name;
+        if !is_identifier(name) {
+            return Err(format!("Invalid coprocessor name: {name}"));
+        }
+
+        let machine = find_machine(source_file, source, &manifest.machine)?;
+        let operations = machine
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                MachineStatement::OperationDeclaration(_, name, _, params) => {
+                    Some((name.as_str(), params))
+                }
+                _ => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let instructions = parse_instructions(&manifest.instructions)?;
+        for (instr, params, body) in &instructions {
+            let InstructionBody::CallableRef(callable) = body else {
+                return Err(format!(
+                    "Instruction {instr} of coprocessor {name} has to call an operation of {name}."
+                ));
+            };
+            if &callable.instance != name {
+                return Err(format!(
+                    "Instruction {instr} calls {}, but coprocessor {name} can only call operations of {name}.",
+                    callable.instance
+                ));
+            }
+            let Some(operation) = operations.get(callable.callable.as_str()) else {
+                return Err(format!(
+                    "Instruction {instr} calls {}, but machine {} does not have such an operation.",
+                    callable.callable, manifest.machine
+                ));
+            };
+            let arguments = if callable.params.inputs_and_outputs().next().is_some() {
+                &callable.params
+            } else {
+                params
+            };
+            if arity(arguments) != arity(operation) {
+                return Err(format!(
+                    "Instruction {instr} passes {} inputs and {} outputs to {}.{}, which expects {} inputs and {} outputs.",
+                    arguments.inputs.len(),
+                    arguments.outputs.len(),
+                    name,
+                    callable.callable,
+                    operation.inputs.len(),
+                    operation.outputs.len()
+                ));
+            }
+        }
+
+        let runtime_function_impl = manifest
+            .runtime_function
+            .map(|f| {
+                let (_, params, _) = instructions
+                    .iter()
+                    .find(|(instr, _, _)| instr == &f.instruction)
+                    .ok_or_else(|| {
+                        format!(
+                            "Runtime function {} uses the undeclared instruction {}.",
+                            f.symbol, f.instruction
+                        )
+                    })?;
+                if !is_identifier(&f.symbol) {
+                    return Err(format!("Invalid runtime function name: {}", f.symbol));
+                }
+                if let Some(reg) = f
+                    .inputs
+                    .iter()
+                    .chain(&f.outputs)
+                    .find(|reg| !is_register(reg))
+                {
+                    return Err(format!(
+                        "Runtime function {} uses {reg}, but only the registers x0 to x31 are allowed.",
+                        f.symbol
+                    ));
+                }
+                if f.outputs.iter().any(|reg| reg == "x0") {
+                    return Err(format!("Runtime function {} writes to x0.", f.symbol));
+                }
+                if (f.inputs.len(), f.outputs.len()) != arity(params) {
+                    return Err(format!(
+                        "Runtime function {} passes {} inputs and {} outputs to instruction {}, which expects {} inputs and {} outputs.",
+                        f.symbol,
+                        f.inputs.len(),
+                        f.outputs.len(),
+                        f.instruction,
+                        params.inputs.len(),
+                        params.outputs.len()
+                    ));
+                }
+                Ok((f.symbol, call(&f.instruction, &f.inputs, &f.outputs)))
+            })
+            .transpose()?;
+
+        // Call the first instruction with zeros and reset the registers written to.
+        let initialization = instructions
+            .first()
+            .map(|(instr, params, _)| {
+                let inputs = vec!["0".to_string(); params.inputs.len()];
+                let outputs = (0..params.outputs.len())
+                    .map(|i| format!("x{}", 10 + i))
+                    .collect::<Vec<_>>();
+                std::iter::once(call(instr, &inputs, &outputs))
+                    .chain(outputs.iter().map(|reg| format!("{reg} <=X= 0;")))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            name: name.clone(),
+            ty: format!("{name}_machine::{}", manifest.machine),
+            import: format!("mod {name}_machine {{\n{source}\n}}"),
+            instructions: manifest
+                .instructions
+                .iter()
+                .map(|instr| format!("{instr}\n"))
+                .collect(),
+            runtime_function_impl,
+            initialization,
+        })
+    }
+}
+
+/// Finds the machine with the given name at the top level of the source.
+fn find_machine(
+    source_file: &str,
+    source: &str,
+    name: &str,
+) -> Result<Machine<GoldilocksField>, String> {
+    let module = powdr_parser::parse_module(Some(source_file), source).map_err(|err| {
+        err.output_to_stderr();
+        format!("Could not parse {source_file}.")
+    })?;
+    module
+        .statements
+        .into_iter()
+        .find_map(|s| match s {
+            ModuleStatement::SymbolDefinition(d) if d.name == name => match d.value {
+                SymbolValue::Machine(machine) => Some(machine),
+                _ => None,
+            },
+            _ => None,
+        })
+        .ok_or_else(|| format!("Machine {name} not found in {source_file}."))
+}
+
+type InstructionDeclaration = (
+    String,
+    Params<GoldilocksField>,
+    InstructionBody<GoldilocksField>,
+);
+
+/// Parses the instruction declarations by wrapping them in a machine.
+fn parse_instructions(instructions: &[String]) -> Result<Vec<InstructionDeclaration>, String> {
+    let source = format!("machine Instructions {{\n{}\n}}", instructions.join("\n"));
+    let machine = find_machine("instructions", &source, "Instructions")?;
+    machine
+        .statements
+        .into_iter()
+        .map(|s| match s {
+            MachineStatement::InstructionDeclaration(_, name, instr) => {
+                Ok((name, instr.params, instr.body))
+            }
+            _ => Err(format!(
+                "Expected only instruction declarations in: {}",
+                instructions.join(" ")
+            )),
+        })
+        .collect()
+}
+
+fn arity<T>(params: &Params<T>) -> (usize, usize) {
+    (params.inputs.len(), params.outputs.len())
+}
+
+fn call(instr: &str, inputs: &[String], outputs: &[String]) -> String {
+    if outputs.is_empty() {
+        format!("{instr} {};", inputs.join(", "))
+    } else {
+        format!("{} <== {instr}({});", outputs.join(", "), inputs.join(", "))
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_register(name: &str) -> bool {
+    name.strip_prefix('x')
+        .and_then(|n| n.parse::<u8>().ok())
+        .is_some_and(|n| n < 32 && name == format!("x{n}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"
+machine MyHash(latch, operation_id) {
+    operation hash<0> A, B -> C;
+    operation absorb<1> A;
+
+    col witness operation_id;
+    col fixed latch = [1]*;
+    col witness A, B, C;
+    C = A + B;
+}
+"#;
+
+    fn load(manifest: &str) -> Result<CustomCoProcessor, String> {
+        CustomCoProcessor::new(
+            serde_json::from_str(manifest).unwrap(),
+            "my_hash.asm",
+            SOURCE,
+        )
+    }
+
+    #[test]
+    fn valid_manifest() {
+        let coprocessor = load(
+            r#"{
+                "name": "my_hash",
+                "machine": "MyHash",
+                "source": "my_hash.asm",
+                "instructions": [
+                    "instr my_hash Y, Z -> X = my_hash.hash;",
+                    "instr my_absorb Y = my_hash.absorb;"
+                ],
+                "runtime_function": {
+                    "symbol": "my_hash_coprocessor",
+                    "instruction": "my_hash",
+                    "inputs": ["x10", "x11"],
+                    "outputs": ["x10"]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(coprocessor.ty, "my_hash_machine::MyHash");
+        assert_eq!(
+            coprocessor.runtime_function_impl,
+            Some((
+                "my_hash_coprocessor".to_string(),
+                "x10 <== my_hash(x10, x11);".to_string()
+            ))
+        );
+        assert_eq!(
+            coprocessor.initialization,
+            vec!["x10 <== my_hash(0, 0);", "x10 <=X= 0;"]
+        );
+    }
+
+    #[test]
+    fn unknown_operation() {
+        let err = load(
+            r#"{
+                "name": "my_hash",
+                "machine": "MyHash",
+                "source": "my_hash.asm",
+                "instructions": ["instr my_hash Y, Z -> X = my_hash.squeeze;"]
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Instruction my_hash calls squeeze, but machine MyHash does not have such an operation."
+        );
+    }
+
+    #[test]
+    fn wrong_arity() {
+        let err = load(
+            r#"{
+                "name": "my_hash",
+                "machine": "MyHash",
+                "source": "my_hash.asm",
+                "instructions": ["instr my_hash Y -> X = my_hash.hash;"]
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Instruction my_hash passes 1 inputs and 1 outputs to my_hash.hash, which expects 2 inputs and 1 outputs."
+        );
+    }
+
+    #[test]
+    fn wrong_calling_convention() {
+        let err = load(
+            r#"{
+                "name": "my_hash",
+                "machine": "MyHash",
+                "source": "my_hash.asm",
+                "instructions": ["instr my_hash Y, Z -> X = my_hash.hash;"],
+                "runtime_function": {
+                    "symbol": "my_hash_coprocessor",
+                    "instruction": "my_hash",
+                    "inputs": ["x10", "a1"],
+                    "outputs": ["x10"]
+                }
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Runtime function my_hash_coprocessor uses a1, but only the registers x0 to x31 are allowed."
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let err = serde_json::from_str::<Manifest>(
+            r#"{
+                "name": "my_hash",
+                "machine": "MyHash",
+                "source": "my_hash.asm",
+                "instructions": [],
+                "runtime_function": {
+                    "symbol": "my_hash_coprocessor",
+                    "instruction": "my_hash",
+                    "inputs": ["x10"],
+                    "outputs": [],
+                    "clobbers": ["x11"]
+                }
+            }"#,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().starts_with("unknown field `clobbers`"));
+    }
+}
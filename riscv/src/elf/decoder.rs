@@ -0,0 +1,300 @@
+//! Decoder for the RV32IM (plus the `A` subset we support) instruction encoding.
+
+use crate::compiler::Register;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Lui {
+        rd: Register,
+        imm: u32,
+    },
+    Auipc {
+        rd: Register,
+        imm: u32,
+    },
+    Jal {
+        rd: Register,
+        offset: i32,
+    },
+    Jalr {
+        rd: Register,
+        rs1: Register,
+        offset: i32,
+    },
+    Branch {
+        op: &'static str,
+        rs1: Register,
+        rs2: Register,
+        offset: i32,
+    },
+    Load {
+        op: &'static str,
+        rd: Register,
+        rs1: Register,
+        offset: i32,
+    },
+    Store {
+        op: &'static str,
+        rs1: Register,
+        rs2: Register,
+        offset: i32,
+    },
+    /// Arithmetic with an immediate operand (including shifts by a constant).
+    Immediate {
+        op: &'static str,
+        rd: Register,
+        rs1: Register,
+        imm: i32,
+    },
+    /// Arithmetic with two register operands.
+    Register {
+        op: &'static str,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+    },
+    /// Atomic memory operations, `op` includes the ordering suffix.
+    Atomic {
+        op: &'static str,
+        rd: Register,
+        rs1: Register,
+        rs2: Register,
+    },
+    Fence,
+    Ecall,
+    Ebreak,
+    Unimp,
+}
+
+impl Instruction {
+    /// Returns true if execution never continues with the next instruction.
+    pub fn ends_control_flow(&self) -> bool {
+        match self {
+            Instruction::Jal { rd, .. } | Instruction::Jalr { rd, .. } => rd.is_zero(),
+            Instruction::Unimp => true,
+            _ => false,
+        }
+    }
+}
+
+/// Decodes a single 32 bit instruction word, returns None if the
+/// instruction is not supported.
+pub fn decode(word: u32) -> Option<Instruction> {
+    let opcode = word & 0x7f;
+    let rd = Register::new(((word >> 7) & 0x1f) as u8);
+    let funct3 = (word >> 12) & 0x7;
+    let rs1 = Register::new(((word >> 15) & 0x1f) as u8);
+    let rs2 = Register::new(((word >> 20) & 0x1f) as u8);
+    let funct7 = word >> 25;
+
+    let imm_i = (word as i32) >> 20;
+    let imm_s = ((word as i32) >> 25 << 5) | ((word >> 7) & 0x1f) as i32;
+    let imm_b = ((word as i32) >> 31 << 12)
+        | (((word >> 7) & 0x1) << 11) as i32
+        | (((word >> 25) & 0x3f) << 5) as i32
+        | (((word >> 8) & 0xf) << 1) as i32;
+    let imm_j = ((word as i32) >> 31 << 20)
+        | (word & 0xff000) as i32
+        | (((word >> 20) & 0x1) << 11) as i32
+        | (((word >> 21) & 0x3ff) << 1) as i32;
+
+    Some(match opcode {
+        0x37 => Instruction::Lui {
+            rd,
+            imm: word >> 12,
+        },
+        0x17 => Instruction::Auipc {
+            rd,
+            imm: word >> 12,
+        },
+        0x6f => Instruction::Jal { rd, offset: imm_j },
+        0x67 if funct3 == 0 => Instruction::Jalr {
+            rd,
+            rs1,
+            offset: imm_i,
+        },
+        0x63 => Instruction::Branch {
+            op: match funct3 {
+                0 => "beq",
+                1 => "bne",
+                4 => "blt",
+                5 => "bge",
+                6 => "bltu",
+                7 => "bgeu",
+                _ => return None,
+            },
+            rs1,
+            rs2,
+            offset: imm_b,
+        },
+        0x03 => Instruction::Load {
+            op: match funct3 {
+                0 => "lb",
+                1 => "lh",
+                2 => "lw",
+                4 => "lbu",
+                5 => "lhu",
+                _ => return None,
+            },
+            rd,
+            rs1,
+            offset: imm_i,
+        },
+        0x23 => Instruction::Store {
+            op: match funct3 {
+                0 => "sb",
+                1 => "sh",
+                2 => "sw",
+                _ => return None,
+            },
+            rs1,
+            rs2,
+            offset: imm_s,
+        },
+        0x13 => {
+            let (op, imm) = match (funct3, funct7) {
+                (0, _) => ("addi", imm_i),
+                (2, _) => ("slti", imm_i),
+                (3, _) => ("sltiu", imm_i),
+                (4, _) => ("xori", imm_i),
+                (6, _) => ("ori", imm_i),
+                (7, _) => ("andi", imm_i),
+                (1, 0x00) => ("slli", imm_i & 0x1f),
+                (5, 0x00) => ("srli", imm_i & 0x1f),
+                (5, 0x20) => ("srai", imm_i & 0x1f),
+                _ => return None,
+            };
+            Instruction::Immediate { op, rd, rs1, imm }
+        }
+        // "sra", "div" and "rem" are not supported by the compiler yet.
+        0x33 => Instruction::Register {
+            op: match (funct7, funct3) {
+                (0x00, 0) => "add",
+                (0x20, 0) => "sub",
+                (0x00, 1) => "sll",
+                (0x00, 2) => "slt",
+                (0x00, 3) => "sltu",
+                (0x00, 4) => "xor",
+                (0x00, 5) => "srl",
+                (0x00, 6) => "or",
+                (0x00, 7) => "and",
+                (0x01, 0) => "mul",
+                (0x01, 1) => "mulh",
+                (0x01, 2) => "mulhsu",
+                (0x01, 3) => "mulhu",
+                (0x01, 5) => "divu",
+                (0x01, 7) => "remu",
+                _ => return None,
+            },
+            rd,
+            rs1,
+            rs2,
+        },
+        0x2f if funct3 == 2 => {
+            // The two lowest bits of funct7 are the acquire and release flags.
+            let op = match (funct7 >> 2, funct7 & 0x3) {
+                (0x00, 0) => "amoadd.w",
+                (0x00, 1) => "amoadd.w.rl",
+                (0x00, 2) => "amoadd.w.aq",
+                (0x00, 3) => "amoadd.w.aqrl",
+                (0x02, 0) => "lr.w",
+                (0x02, 1) => "lr.w.rl",
+                (0x02, 2) => "lr.w.aq",
+                (0x02, 3) => "lr.w.aqrl",
+                (0x03, 0) => "sc.w",
+                (0x03, 1) => "sc.w.rl",
+                (0x03, 2) => "sc.w.aq",
+                (0x03, 3) => "sc.w.aqrl",
+                _ => return None,
+            };
+            Instruction::Atomic { op, rd, rs1, rs2 }
+        }
+        0x0f => Instruction::Fence,
+        0x73 => match word {
+            0x0000_0073 => Instruction::Ecall,
+            0x0010_0073 => Instruction::Ebreak,
+            // "csrrw x0, cycle, x0", the canonical encoding of "unimp".
+            0xc000_1073 => Instruction::Unimp,
+            _ => return None,
+        },
+        _ if word == 0 => Instruction::Unimp,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reg(r: u8) -> Register {
+        Register::new(r)
+    }
+
+    #[test]
+    fn immediates() {
+        // addi a0, a0, -1
+        assert_eq!(
+            decode(0xfff50513),
+            Some(Instruction::Immediate {
+                op: "addi",
+                rd: reg(10),
+                rs1: reg(10),
+                imm: -1
+            })
+        );
+        // sw ra, -4(sp)
+        assert_eq!(
+            decode(0xfe112e23),
+            Some(Instruction::Store {
+                op: "sw",
+                rs1: reg(2),
+                rs2: reg(1),
+                offset: -4
+            })
+        );
+        // bne a0, a1, -8
+        assert_eq!(
+            decode(0xfeb51ce3),
+            Some(Instruction::Branch {
+                op: "bne",
+                rs1: reg(10),
+                rs2: reg(11),
+                offset: -8
+            })
+        );
+        // jal ra, 2048
+        assert_eq!(
+            decode(0x001000ef),
+            Some(Instruction::Jal {
+                rd: reg(1),
+                offset: 2048
+            })
+        );
+        // lui a0, 0xfffff
+        assert_eq!(
+            decode(0xfffff537),
+            Some(Instruction::Lui {
+                rd: reg(10),
+                imm: 0xfffff
+            })
+        );
+        // srai a0, a0, 3
+        assert_eq!(
+            decode(0x40355513),
+            Some(Instruction::Immediate {
+                op: "srai",
+                rd: reg(10),
+                rs1: reg(10),
+                imm: 3
+            })
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        // csrr a0, mhartid
+        assert_eq!(decode(0xf1402573), None);
+        // A compressed instruction pair.
+        assert_eq!(decode(0x00014501), None);
+    }
+}
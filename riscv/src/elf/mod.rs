@@ -0,0 +1,786 @@
+//! Compiles statically linked RV32IM ELF executables to powdr-asm, as an
+//! alternative to compiling the assembly files emitted by the Rust compiler.
+//!
+//! The text sections are decoded and lifted to the same statements the
+//! assembly parser produces, so the code generation is shared with
+//! [`crate::compiler::compile`]. There are no code addresses in powdr-asm, so
+//! every value that refers to an instruction is replaced by a label:
+//! - targets of jumps and branches,
+//! - `auipc`/`lui` pairs that compute the address of an instruction,
+//! - words in the data sections that point to an instruction (function
+//!   pointers, vtables, jump tables). These are found using the relocations
+//!   kept by linking with `--emit-relocs` or, if there are none, by checking
+//!   if the value is the address of an instruction.
+//!
+//! Data is stored at its original addresses, so it must not overlap with the
+//! stack, which starts at `0x10000` and grows towards zero. Files with data
+//! below that address are rejected.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use powdr_asm_utils::{data_parser::DataValue, data_storage::store_data_objects};
+
+use crate::compiler::{self, Register, STACK_START};
+use crate::continuations::bootloader::MemoryLayout;
+use crate::coprocessors::CoProcessors;
+use crate::{Argument, Expression, Statement};
+
+use self::decoder::{decode, Instruction};
+
+mod decoder;
+
+/// Compiles an ELF executable to a powdr assembly file. Adds required library routines.
+/// If `bootloader` is set, adds a bootloader for continuations using the given memory layout.
+/// Fails if the file is malformed or uses features that are not supported.
+pub fn compile(
+    elf: &[u8],
+    coprocessors: &CoProcessors,
+    bootloader: Option<MemoryLayout>,
+) -> Result<String, String> {
+    let program = lift(&ElfFile::parse(elf)?, coprocessors)?;
+
+    let (data_code, _) = store_data_objects(
+        vec![program.data],
+        program.data_start,
+        &mut compiler::data_initialization,
+    );
+
+    Ok(compiler::riscv_program(
+        std::iter::empty(),
+        program.statements,
+        data_code,
+        &program.entry_label,
+        coprocessors,
+        bootloader,
+    ))
+}
+
+const EM_RISCV: u16 = 243;
+const ET_EXEC: u16 = 2;
+const EF_RISCV_RVC: u32 = 0x1;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+const SHN_UNDEF: u16 = 0;
+const SHN_LORESERVE: u16 = 0xff00;
+
+const R_RISCV_32: u32 = 1;
+
+/// The parts of an ELF file we need for compilation.
+struct ElfFile<'a> {
+    entry: u32,
+    /// The sections that are loaded into memory, sorted by address.
+    sections: Vec<Section<'a>>,
+    /// The names of the defined symbols by address.
+    symbols: BTreeMap<u32, Vec<&'a str>>,
+    /// The addresses of the absolute 32 bit relocations, or None if the file
+    /// was linked without keeping relocations.
+    relocations: Option<BTreeSet<u32>>,
+}
+
+struct Section<'a> {
+    addr: u32,
+    size: u32,
+    executable: bool,
+    /// The contents of the section, None if it does not occupy space in the
+    /// file (`.bss`).
+    contents: Option<&'a [u8]>,
+}
+
+struct SectionHeader {
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+    info: usize,
+}
+
+impl SectionHeader {
+    fn parse(bytes: &[u8], offset: usize) -> Result<Self, String> {
+        Ok(Self {
+            kind: read_u32(bytes, offset + 4)?,
+            flags: read_u32(bytes, offset + 8)?,
+            addr: read_u32(bytes, offset + 12)?,
+            offset: read_u32(bytes, offset + 16)? as usize,
+            size: read_u32(bytes, offset + 20)? as usize,
+            link: read_u32(bytes, offset + 24)? as usize,
+            info: read_u32(bytes, offset + 28)? as usize,
+        })
+    }
+
+    fn contents<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], String> {
+        bytes
+            .get(self.offset..self.offset + self.size)
+            .ok_or_else(|| format!("Section at offset {} exceeds the file.", self.offset))
+    }
+}
+
+/// Returns the section header with the given index.
+fn header(headers: &[SectionHeader], index: usize) -> Result<&SectionHeader, String> {
+    headers
+        .get(index)
+        .ok_or_else(|| format!("Invalid section index {index}."))
+}
+
+/// Returns an error with the given message if the condition does not hold.
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message())
+    }
+}
+
+impl<'a> ElfFile<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        ensure(bytes.len() >= 52 && bytes.starts_with(b"\x7fELF"), || {
+            "Not an ELF file.".to_string()
+        })?;
+        ensure(bytes[4] == 1, || {
+            "Only 32 bit ELF files are supported.".to_string()
+        })?;
+        ensure(bytes[5] == 1, || {
+            "Only little-endian ELF files are supported.".to_string()
+        })?;
+        ensure(read_u16(bytes, 18)? == EM_RISCV, || {
+            "Not a RISC-V ELF file.".to_string()
+        })?;
+        ensure(read_u16(bytes, 16)? == ET_EXEC, || {
+            "Only statically linked executables are supported.".to_string()
+        })?;
+        ensure(read_u32(bytes, 36)? & EF_RISCV_RVC == 0, || {
+            "Compressed instructions are not supported, please compile for rv32ima.".to_string()
+        })?;
+
+        let entry = read_u32(bytes, 24)?;
+        let section_headers_start = read_u32(bytes, 32)? as usize;
+        let section_header_size = read_u16(bytes, 46)? as usize;
+        let headers = (0..read_u16(bytes, 48)? as usize)
+            .map(|i| SectionHeader::parse(bytes, section_headers_start + i * section_header_size))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut sections = headers
+            .iter()
+            .filter(|h| h.flags & SHF_ALLOC != 0 && h.size > 0)
+            .map(|h| {
+                Ok(Section {
+                    addr: h.addr,
+                    size: h.size as u32,
+                    executable: h.flags & SHF_EXECINSTR != 0,
+                    contents: (h.kind != SHT_NOBITS)
+                        .then(|| h.contents(bytes))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        sections.sort_by_key(|s| s.addr);
+
+        let mut symbols: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+        for header in headers.iter().filter(|h| h.kind == SHT_SYMTAB) {
+            let names = self::header(&headers, header.link)?.contents(bytes)?;
+            for symbol in header.contents(bytes)?.chunks_exact(16) {
+                let name = c_str(names, read_u32(symbol, 0)? as usize)?;
+                let value = read_u32(symbol, 4)?;
+                let kind = symbol[12] & 0xf;
+                let section = read_u16(symbol, 14)?;
+                if name.is_empty()
+                    || kind == STT_SECTION
+                    || kind == STT_FILE
+                    || section == SHN_UNDEF
+                    || section >= SHN_LORESERVE
+                {
+                    continue;
+                }
+                symbols.entry(value).or_default().push(name);
+            }
+        }
+
+        let mut relocation_headers = vec![];
+        for h in headers.iter().filter(|h| h.kind == SHT_RELA) {
+            if self::header(&headers, h.info)?.flags & SHF_ALLOC != 0 {
+                relocation_headers.push(h);
+            }
+        }
+        let relocations = if relocation_headers.is_empty() {
+            None
+        } else {
+            let mut relocations = BTreeSet::new();
+            for h in relocation_headers {
+                for relocation in h.contents(bytes)?.chunks_exact(12) {
+                    if read_u32(relocation, 4)? & 0xff == R_RISCV_32 {
+                        relocations.insert(read_u32(relocation, 0)?);
+                    }
+                }
+            }
+            Some(relocations)
+        };
+
+        Ok(Self {
+            entry,
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| format!("Unexpected end of data at offset {offset}."))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| format!("Unexpected end of data at offset {offset}."))
+}
+
+/// Reads a null-terminated string, returns the empty string if it is not valid UTF-8.
+fn c_str(bytes: &[u8], offset: usize) -> Result<&str, String> {
+    let bytes = bytes.get(offset..).unwrap_or_default();
+    let len = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| format!("Unterminated string at offset {offset}."))?;
+    Ok(std::str::from_utf8(&bytes[..len]).unwrap_or_default())
+}
+
+/// Named data objects, as expected by [`store_data_objects`].
+type DataSection = Vec<(Option<String>, Vec<DataValue>)>;
+
+/// The lifted program, ready for code generation.
+struct Program {
+    statements: Vec<Statement>,
+    entry_label: String,
+    /// The address of the first data object, aligned to 8 bytes.
+    data_start: u32,
+    data: DataSection,
+}
+
+fn lift(elf: &ElfFile, coprocessors: &CoProcessors) -> Result<Program, String> {
+    let mut code = BTreeMap::new();
+    for s in elf.sections.iter().filter(|s| s.executable) {
+        ensure(s.addr % 4 == 0, || {
+            format!("Misaligned text section at 0x{:08x}.", s.addr)
+        })?;
+        let contents = s
+            .contents
+            .ok_or_else(|| format!("Text section at 0x{:08x} has no contents.", s.addr))?;
+        for (i, word) in contents.chunks_exact(4).enumerate() {
+            code.insert(
+                s.addr + 4 * i as u32,
+                u32::from_le_bytes(word.try_into().unwrap()),
+            );
+        }
+    }
+    let stubs = coprocessors.runtime_names().into_iter().collect();
+    let lifter = Lifter {
+        labels: Labels::new(&elf.symbols, &code, &stubs),
+        code,
+        stubs,
+    };
+
+    let (data_start, data, code_pointers) = lifter.data_objects(elf)?;
+    let (reachable, targets) =
+        lifter.reachable(std::iter::once(elf.entry).chain(code_pointers).collect())?;
+
+    Ok(Program {
+        statements: lifter.statements(&reachable, &targets)?,
+        entry_label: lifter.labels.name(elf.entry),
+        data_start,
+        data,
+    })
+}
+
+/// Label names of instruction addresses.
+struct Labels {
+    symbols: HashMap<u32, String>,
+}
+
+impl Labels {
+    /// Uses the symbol names that are valid and unique, preferring coprocessor
+    /// stubs, so that calls to them can be recognized.
+    fn new(
+        symbols: &BTreeMap<u32, Vec<&str>>,
+        code: &BTreeMap<u32, u32>,
+        stubs: &HashSet<&str>,
+    ) -> Self {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for name in symbols.values().flatten() {
+            *counts.entry(name).or_default() += 1;
+        }
+        Self {
+            symbols: symbols
+                .iter()
+                .filter(|(addr, _)| code.contains_key(addr))
+                .filter_map(|(addr, names)| {
+                    names
+                        .iter()
+                        .filter(|name| counts[*name] == 1 && is_valid_label(name))
+                        .min_by_key(|name| !stubs.contains(*name))
+                        .map(|name| (*addr, name.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    fn has_symbol(&self, addr: u32) -> bool {
+        self.symbols.contains_key(&addr)
+    }
+
+    fn name(&self, addr: u32) -> String {
+        self.symbols
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| format!("__elf_{addr:08x}"))
+    }
+}
+
+/// Checks if the name can be used as a label after escaping.
+fn is_valid_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && !name.starts_with("__elf_")
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_$@./".contains(c))
+}
+
+/// An `auipc` or `lui` that, together with the next instruction, computes
+/// the address of an instruction.
+struct AddressPair {
+    /// The register written by the first instruction and its value.
+    rd: Register,
+    value: u32,
+    /// The register written by the second instruction.
+    second_rd: Register,
+    target: u32,
+    /// True if the second instruction is a call or a tail call.
+    is_call: bool,
+}
+
+struct Lifter<'a> {
+    /// The instruction words by address.
+    code: BTreeMap<u32, u32>,
+    labels: Labels,
+    stubs: HashSet<&'a str>,
+}
+
+impl<'a> Lifter<'a> {
+    fn decode(&self, addr: u32) -> Option<Instruction> {
+        self.code.get(&addr).and_then(|word| decode(*word))
+    }
+
+    fn instruction(&self, addr: u32) -> Result<Instruction, String> {
+        let word = self
+            .code
+            .get(&addr)
+            .ok_or_else(|| format!("No instruction at 0x{addr:08x}."))?;
+        decode(*word)
+            .ok_or_else(|| format!("Unsupported instruction 0x{word:08x} at 0x{addr:08x}."))
+    }
+
+    fn is_stub(&self, addr: u32) -> bool {
+        self.labels.has_symbol(addr) && self.stubs.contains(self.labels.name(addr).as_str())
+    }
+
+    fn address_pair(&self, addr: u32) -> Option<AddressPair> {
+        let (rd, value, is_auipc) = match self.decode(addr)? {
+            Instruction::Auipc { rd, imm } => (rd, addr.wrapping_add(imm << 12), true),
+            Instruction::Lui { rd, imm } => (rd, imm << 12, false),
+            _ => return None,
+        };
+        let (second_rd, offset, is_jump) = match self.decode(addr + 4)? {
+            Instruction::Immediate {
+                op: "addi",
+                rd: second_rd,
+                rs1,
+                imm,
+            } if rs1 == rd => (second_rd, imm, false),
+            Instruction::Jalr {
+                rd: second_rd,
+                rs1,
+                offset,
+            } if rs1 == rd => (second_rd, offset, true),
+            _ => return None,
+        };
+        let target = value.wrapping_add(offset as u32);
+        // Constants built with "lui" are only considered to be code addresses
+        // if they point to a symbol.
+        let is_code = if is_auipc {
+            self.code.contains_key(&target)
+        } else {
+            self.labels.has_symbol(target)
+        };
+        (!rd.is_zero() && is_code).then_some(AddressPair {
+            rd,
+            value,
+            second_rd,
+            target,
+            is_call: is_jump && is_call(second_rd),
+        })
+    }
+
+    /// Returns the addresses of all instructions reachable from the given
+    /// roots and the addresses that need a label.
+    fn reachable(&self, roots: Vec<u32>) -> Result<(BTreeSet<u32>, BTreeSet<u32>), String> {
+        let mut targets: BTreeSet<u32> = roots.iter().copied().collect();
+        let mut reachable = BTreeSet::new();
+        let mut queue = roots;
+        while let Some(addr) = queue.pop() {
+            if !reachable.insert(addr) {
+                continue;
+            }
+            let instr = self.instruction(addr)?;
+            if !instr.ends_control_flow() {
+                queue.push(addr + 4);
+            }
+            let target = match instr {
+                Instruction::Jal { rd, offset } => {
+                    Some((addr.wrapping_add(offset as u32), is_call(rd)))
+                }
+                Instruction::Branch { offset, .. } => {
+                    Some((addr.wrapping_add(offset as u32), false))
+                }
+                _ => self
+                    .address_pair(addr)
+                    .map(|pair| (pair.target, pair.is_call)),
+            };
+            if let Some((target, is_call)) = target {
+                targets.insert(target);
+                // Calls to coprocessors are replaced, so we do not need their stubs.
+                if !(is_call && self.is_stub(target)) {
+                    queue.push(target);
+                }
+            }
+        }
+        Ok((reachable, targets))
+    }
+
+    fn statements(
+        &self,
+        reachable: &BTreeSet<u32>,
+        targets: &BTreeSet<u32>,
+    ) -> Result<Vec<Statement>, String> {
+        let mut statements = vec![];
+        // The target address computed by the previous instruction.
+        let mut pending_target = None;
+        for &addr in reachable {
+            if targets.contains(&addr) || self.labels.has_symbol(addr) {
+                statements.push(Statement::Label(self.labels.name(addr)));
+            }
+            let instr = self.instruction(addr)?;
+            if let Some(target) = pending_target.take() {
+                statements.push(self.lift_paired(instr, target));
+                continue;
+            }
+            match self.address_pair(addr) {
+                // If the second instruction is a jump target, the first one
+                // might not have been executed.
+                Some(pair) if !targets.contains(&(addr + 4)) => {
+                    if pair.rd != pair.second_rd {
+                        statements.push(instruction("li", [reg(pair.rd), num(pair.value)]));
+                    }
+                    pending_target = Some(pair.target);
+                }
+                _ => statements.push(self.lift_instruction(addr, instr)),
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Lifts the second instruction of an address pair.
+    fn lift_paired(&self, instr: Instruction, target: u32) -> Statement {
+        let label = self.label(target);
+        match instr {
+            Instruction::Immediate { rd, .. } => instruction("la", [reg(rd), label]),
+            Instruction::Jalr { rd, .. } if rd == Register::new(1) => instruction("call", [label]),
+            Instruction::Jalr { rd, .. } if rd.is_zero() => instruction("tail", [label]),
+            Instruction::Jalr { rd, .. } => instruction("jal", [reg(rd), label]),
+            _ => unreachable!(),
+        }
+    }
+
+    fn lift_instruction(&self, addr: u32, instr: Instruction) -> Statement {
+        let ra = Register::new(1);
+        match instr {
+            Instruction::Lui { rd, imm } => instruction("lui", [reg(rd), num(imm)]),
+            Instruction::Auipc { rd, imm } => {
+                instruction("li", [reg(rd), num(addr.wrapping_add(imm << 12))])
+            }
+            Instruction::Jal { rd, offset } => {
+                let target = addr.wrapping_add(offset as u32);
+                let label = self.label(target);
+                if rd == ra {
+                    instruction("call", [label])
+                } else if rd.is_zero() {
+                    instruction(if self.is_stub(target) { "tail" } else { "j" }, [label])
+                } else {
+                    instruction("jal", [reg(rd), label])
+                }
+            }
+            Instruction::Jalr { rd, rs1, offset } => match (rd, offset) {
+                (rd, 0) if rd.is_zero() && rs1 == ra => instruction("ret", []),
+                (rd, 0) if rd.is_zero() => instruction("jr", [reg(rs1)]),
+                (rd, 0) if rd == ra => instruction("jalr", [reg(rs1)]),
+                _ => instruction("jalr", [reg(rd), reg_offset(offset, rs1)]),
+            },
+            Instruction::Branch {
+                op,
+                rs1,
+                rs2,
+                offset,
+            } => instruction(
+                op,
+                [
+                    reg(rs1),
+                    reg(rs2),
+                    self.label(addr.wrapping_add(offset as u32)),
+                ],
+            ),
+            Instruction::Load {
+                op,
+                rd,
+                rs1,
+                offset,
+            } => instruction(op, [reg(rd), reg_offset(offset, rs1)]),
+            Instruction::Store {
+                op,
+                rs1,
+                rs2,
+                offset,
+            } => instruction(op, [reg(rs2), reg_offset(offset, rs1)]),
+            Instruction::Immediate { op, rd, rs1, imm } => {
+                instruction(op, [reg(rd), reg(rs1), num(imm as u32)])
+            }
+            Instruction::Register { op, rd, rs1, rs2 } => {
+                instruction(op, [reg(rd), reg(rs1), reg(rs2)])
+            }
+            Instruction::Atomic { op, rd, rs1, .. } if op.starts_with("lr.w") => {
+                instruction(op, [reg(rd), reg_offset(0, rs1)])
+            }
+            Instruction::Atomic { op, rd, rs1, rs2 } => {
+                instruction(op, [reg(rd), reg(rs2), reg_offset(0, rs1)])
+            }
+            Instruction::Fence => instruction("fence", []),
+            Instruction::Ecall => instruction("ecall", []),
+            Instruction::Ebreak => instruction("ebreak", []),
+            Instruction::Unimp => instruction("unimp", []),
+        }
+    }
+
+    fn label(&self, addr: u32) -> Argument {
+        Argument::Expression(Expression::Symbol(self.labels.name(addr)))
+    }
+
+    /// Converts the data sections to data objects at their original
+    /// addresses. Returns the start address, the objects and the addresses of
+    /// the instructions they refer to.
+    fn data_objects(&self, elf: &ElfFile) -> Result<(u32, DataSection, Vec<u32>), String> {
+        let sections = elf.sections.iter().filter(|s| !s.executable);
+        let data_start = sections.clone().next().map_or(STACK_START, |s| s.addr & !7);
+        ensure(data_start >= STACK_START, || {
+            format!(
+                "Data at 0x{data_start:08x} overlaps with the stack below \
+                 0x{STACK_START:08x}. Please link the data sections to a higher address."
+            )
+        })?;
+
+        let is_code_pointer = |addr: u32, value: u32| match &elf.relocations {
+            Some(relocations) => relocations.contains(&addr) && self.code.contains_key(&value),
+            None => self.decode(value).is_some(),
+        };
+
+        let mut values = vec![];
+        let mut code_pointers = vec![];
+        let mut pos = data_start;
+        for section in sections {
+            ensure(section.addr >= pos, || {
+                format!("Overlapping data sections at 0x{:08x}.", section.addr)
+            })?;
+            if section.addr > pos {
+                values.push(DataValue::Zero((section.addr - pos) as usize));
+            }
+            let Some(contents) = section.contents else {
+                values.push(DataValue::Zero(section.size as usize));
+                pos = section.addr + section.size;
+                continue;
+            };
+            let mut bytes = vec![];
+            let mut i = 0;
+            while i < contents.len() {
+                let addr = section.addr + i as u32;
+                if addr & 3 == 0 && i + 4 <= contents.len() {
+                    let value = read_u32(contents, i)?;
+                    if is_code_pointer(addr, value) {
+                        values.push(DataValue::Direct(std::mem::take(&mut bytes)));
+                        values.push(DataValue::Reference(self.labels.name(value)));
+                        code_pointers.push(value);
+                        i += 4;
+                        continue;
+                    }
+                }
+                bytes.push(contents[i]);
+                i += 1;
+            }
+            values.push(DataValue::Direct(bytes));
+            pos = section.addr + section.size;
+        }
+
+        if elf.relocations.is_none() && !code_pointers.is_empty() {
+            log::warn!(
+                "The ELF file does not contain relocations, guessed {} code pointers in the data \
+                 sections from their values. Link with --emit-relocs to avoid wrong guesses.",
+                code_pointers.len()
+            );
+        }
+
+        Ok((data_start, vec![(None, values)], code_pointers))
+    }
+}
+
+/// Returns true if a jump writing the return address to `rd` is a call or a tail call.
+fn is_call(rd: Register) -> bool {
+    rd.is_zero() || rd == Register::new(1)
+}
+
+fn instruction<const N: usize>(name: &str, args: [Argument; N]) -> Statement {
+    Statement::Instruction(name.to_string(), args.into())
+}
+
+fn reg(r: Register) -> Argument {
+    Argument::Register(r)
+}
+
+fn num(n: u32) -> Argument {
+    Argument::Expression(Expression::Number(n as i64))
+}
+
+fn reg_offset(offset: i32, r: Register) -> Argument {
+    Argument::RegOffset(Some(Expression::Number(offset as i64)), r)
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+
+    /// Builds an ELF file with a text section at 0x1000, a data section at
+    /// `data_addr` and the given symbols.
+    fn elf_file(text: &[u32], data_addr: u32, data: &[u32], symbols: &[(&str, u32)]) -> Vec<u8> {
+        let words = |words: &[u32]| words.iter().flat_map(|w| w.to_le_bytes()).collect_vec();
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 16];
+        for (name, value) in symbols {
+            symtab.extend(words(&[strtab.len() as u32, *value, 0]));
+            // STT_FUNC, defined in the text section
+            symtab.extend([2, 0, 1, 0]);
+            strtab.extend(name.bytes().chain([0]));
+        }
+
+        // (type, flags, address, contents, link)
+        let sections = [
+            (1, SHF_ALLOC | SHF_EXECINSTR, 0x1000, words(text), 0),
+            (1, SHF_ALLOC, data_addr, words(data), 0),
+            (SHT_SYMTAB, 0, 0, symtab, 4),
+            (3, 0, 0, strtab, 0),
+        ];
+        let mut contents = vec![];
+        let mut headers = vec![0; 40];
+        for (kind, flags, addr, bytes, link) in sections {
+            let offset = 52 + contents.len() as u32;
+            headers.extend(words(&[
+                0,
+                kind,
+                flags,
+                addr,
+                offset,
+                bytes.len() as u32,
+                link,
+            ]));
+            headers.extend([0; 12]);
+            contents.extend(bytes);
+        }
+
+        let mut elf = b"\x7fELF\x01\x01\x01".to_vec();
+        elf.resize(16, 0);
+        elf.extend(ET_EXEC.to_le_bytes());
+        elf.extend(EM_RISCV.to_le_bytes());
+        elf.extend(words(&[1, 0x1000, 0, 52 + contents.len() as u32, 0]));
+        elf.extend([52, 0, 0, 0, 0, 0, 40, 0, 5, 0, 0, 0]);
+        elf.extend(contents);
+        elf.extend(headers);
+        elf
+    }
+
+    #[test]
+    fn code_addresses_become_labels() {
+        let text = [
+            0x00000517, // auipc a0, 0
+            0x00c50513, // addi a0, a0, 12
+            0x004000ef, // jal ra, 4
+            0x00008067, // ret
+        ];
+        let elf = elf_file(
+            &text,
+            0x20000,
+            &[0x100c, 0x1003],
+            &[("main", 0x1000), ("f", 0x100c)],
+        );
+        let program = lift(&ElfFile::parse(&elf).unwrap(), &CoProcessors::base()).unwrap();
+
+        assert_eq!(program.entry_label, "main");
+        assert_eq!(
+            program.statements.iter().format("").to_string(),
+            "main:\n  la x10, f\n  call f\nf:\n  ret \n"
+        );
+        assert_eq!(program.data_start, 0x20000);
+        assert_eq!(
+            format!("{:?}", program.data),
+            r#"[(None, [Direct([]), Reference("f"), Direct([3, 16, 0, 0])])]"#
+        );
+    }
+
+    #[test]
+    fn unsupported_instruction() {
+        // sra a0, a0, a1
+        let elf = elf_file(&[0x40b55533], 0x20000, &[], &[]);
+        assert_eq!(
+            compile(&elf, &CoProcessors::base(), None).unwrap_err(),
+            "Unsupported instruction 0x40b55533 at 0x00001000."
+        );
+    }
+
+    #[test]
+    fn data_below_stack() {
+        // ret
+        let elf = elf_file(&[0x00008067], 0x2000, &[1], &[]);
+        assert_eq!(
+            compile(&elf, &CoProcessors::base(), None).unwrap_err(),
+            "Data at 0x00002000 overlaps with the stack below 0x00010000. \
+             Please link the data sections to a higher address."
+        );
+    }
+
+    #[test]
+    fn truncated_file() {
+        let elf = elf_file(&[0x00008067], 0x20000, &[], &[]);
+        assert_eq!(
+            compile(&elf[..100], &CoProcessors::base(), None).unwrap_err(),
+            "Unexpected end of data at offset 97."
+        );
+    }
+}
@@ -8,17 +8,22 @@ use std::{
     process::Command,
 };
 
+use itertools::Itertools;
 use mktemp::Temp;
+use powdr_number::FieldElement;
+use powdr_pipeline::{pipeline::PilWithEvaluatedFixedCols, Pipeline, Stage};
 use serde_json::Value as JsonValue;
 use std::fs;
 
 use crate::compiler::{FunctionKind, Register};
+use crate::continuations::bootloader::MemoryLayout;
 pub use crate::coprocessors::CoProcessors;
 
 pub mod compiler;
 pub mod continuations;
 mod coprocessors;
 mod disambiguator;
+pub mod elf;
 pub mod parser;
 
 type Statement = powdr_asm_utils::ast::Statement<Register, FunctionKind>;
@@ -27,21 +32,15 @@ type Expression = powdr_asm_utils::ast::Expression<FunctionKind>;
 
 /// Compiles a rust file all the way down to PIL and generates
 /// fixed and witness columns.
+/// If `bootloader` is set, adds a bootloader for continuations using the given memory layout.
 #[allow(clippy::print_stderr)]
 pub fn compile_rust(
     file_name: &str,
     output_dir: &Path,
     force_overwrite: bool,
     coprocessors: &CoProcessors,
-    with_bootloader: bool,
+    bootloader: Option<MemoryLayout>,
 ) -> Option<(PathBuf, String)> {
-    if with_bootloader {
-        assert!(
-            coprocessors.has("poseidon_gl"),
-            "PoseidonGL coprocessor is required for bootloader"
-        );
-    }
-
     let riscv_asm = if file_name.ends_with("Cargo.toml") {
         compile_rust_crate_to_riscv_asm(file_name, output_dir)
     } else if fs::metadata(file_name).unwrap().is_dir() {
@@ -75,7 +74,7 @@ pub fn compile_rust(
         output_dir,
         force_overwrite,
         coprocessors,
-        with_bootloader,
+        bootloader,
     )
 }
 
@@ -86,7 +85,7 @@ pub fn compile_riscv_asm_bundle(
     output_dir: &Path,
     force_overwrite: bool,
     coprocessors: &CoProcessors,
-    with_bootloader: bool,
+    bootloader: Option<MemoryLayout>,
 ) -> Option<(PathBuf, String)> {
     let powdr_asm_file_name = output_dir.join(format!(
         "{}.asm",
@@ -104,7 +103,7 @@ pub fn compile_riscv_asm_bundle(
         return None;
     }
 
-    let powdr_asm = compiler::compile(riscv_asm_files, coprocessors, with_bootloader);
+    let powdr_asm = compiler::compile(riscv_asm_files, coprocessors, bootloader);
 
     fs::write(powdr_asm_file_name.clone(), &powdr_asm).unwrap();
     log::info!("Wrote {}", powdr_asm_file_name.to_str().unwrap());
@@ -120,7 +119,7 @@ pub fn compile_riscv_asm(
     output_dir: &Path,
     force_overwrite: bool,
     coprocessors: &CoProcessors,
-    with_bootloader: bool,
+    bootloader: Option<MemoryLayout>,
 ) -> Option<(PathBuf, String)> {
     compile_riscv_asm_bundle(
         original_file_name,
@@ -133,10 +132,108 @@ pub fn compile_riscv_asm(
         output_dir,
         force_overwrite,
         coprocessors,
-        with_bootloader,
+        bootloader,
     )
 }
 
+/// Compiles a statically linked RV32IM ELF executable all the way down to
+/// PIL and generates fixed and witness columns.
+/// Returns `Ok(None)` if the output file exists and `force_overwrite` is not
+/// set, and an error if the ELF file cannot be read or compiled.
+#[allow(clippy::print_stderr)]
+pub fn compile_riscv_elf(
+    input_file: &str,
+    output_dir: &Path,
+    force_overwrite: bool,
+    coprocessors: &CoProcessors,
+    bootloader: Option<MemoryLayout>,
+) -> Result<Option<(PathBuf, String)>, String> {
+    let powdr_asm_file_name = output_dir.join(format!(
+        "{}.asm",
+        Path::new(input_file).file_stem().unwrap().to_str().unwrap()
+    ));
+    if powdr_asm_file_name.exists() && !force_overwrite {
+        eprintln!(
+            "Target file {} already exists. Not overwriting.",
+            powdr_asm_file_name.to_str().unwrap()
+        );
+        return Ok(None);
+    }
+
+    let elf = fs::read(input_file).map_err(|e| format!("Could not read {input_file}: {e}"))?;
+    let powdr_asm = elf::compile(&elf, coprocessors, bootloader)
+        .map_err(|e| format!("Could not compile {input_file}: {e}"))?;
+
+    fs::write(powdr_asm_file_name.clone(), &powdr_asm).unwrap();
+    log::info!("Wrote {}", powdr_asm_file_name.to_str().unwrap());
+
+    Ok(Some((powdr_asm_file_name, powdr_asm)))
+}
+
+/// Returns an error if the program of the pipeline uses instructions the
+/// RISC-V executor cannot execute, like the ones of user-defined coprocessors.
+pub fn check_executor_support<F: FieldElement>(
+    pipeline: &mut Pipeline<F>,
+) -> Result<(), Vec<String>> {
+    pipeline.advance_to(Stage::AnalyzedAsm)?;
+    let program = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+    let unsupported = powdr_riscv_executor::unsupported_instructions(program);
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(vec![format!(
+            "The RISC-V executor does not support the instructions {}.",
+            unsupported.iter().format(", ")
+        )])
+    }
+}
+
+/// Runs the program of the pipeline in the RISC-V executor and adds all
+/// witness columns of the main machine as external witness values, so that
+/// witness generation only has to solve the submachines. Since all arguments
+/// of the calls to the submachines are known then, multi-threaded witness
+/// generation is enabled to process them in parallel.
+///
+/// If the executor does not support some instructions of the program (see
+/// [check_executor_support]) or cannot compute the main machine witness, the
+/// pipeline is returned unchanged, so that witness generation computes the
+/// whole witness.
+/// The program must have been compiled without a bootloader.
+pub fn add_executor_witness<F: FieldElement>(
+    mut pipeline: Pipeline<F>,
+) -> Result<Pipeline<F>, Vec<String>> {
+    pipeline.advance_to(Stage::AnalyzedAsm)?;
+    if let Err(errors) = check_executor_support(&mut pipeline) {
+        log::warn!(
+            "{} Falling back to witness generation.",
+            errors.iter().format(" ")
+        );
+        return Ok(pipeline);
+    }
+    let program = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+
+    log::info!("Executing powdr-asm...");
+    let (trace, _) = powdr_riscv_executor::execute_ast::<F>(
+        program,
+        pipeline.data_callback().unwrap(),
+        &[],
+        usize::MAX,
+        powdr_riscv_executor::ExecMode::Trace,
+    );
+
+    let PilWithEvaluatedFixedCols { pil, fixed_cols } =
+        pipeline.pil_with_evaluated_fixed_cols_ref()?;
+    match powdr_riscv_executor::witness::main_machine_witness(&trace, pil, fixed_cols) {
+        Ok(witness) => Ok(pipeline
+            .add_external_witness_values(witness)
+            .with_multi_threaded_witgen(true)),
+        Err(error) => {
+            log::warn!("{error} Falling back to witness generation.");
+            Ok(pipeline)
+        }
+    }
+}
+
 pub fn compile_rust_to_riscv_asm(input_file: &str, output_dir: &Path) -> BTreeMap<String, String> {
     let crate_dir = Temp::new_dir().unwrap();
     // TODO is there no easier way?
@@ -1,8 +1,10 @@
-use powdr_number::GoldilocksField;
+use powdr_number::{FieldElement, GoldilocksField};
 use powdr_pipeline::{test_util::verify_pipeline, Pipeline, Stage};
 use std::path::PathBuf;
 
-/// Like compiler::test_util::verify_asm_string, but also runs RISCV executor.
+/// Like compiler::test_util::verify_asm_string, but also runs RISCV executor
+/// and verifies the pipeline both with the generic witness generation and with
+/// the witness of the main machine generated by the executor.
 pub fn verify_riscv_asm_string(file_name: &str, contents: &str, inputs: Vec<GoldilocksField>) {
     let temp_dir = mktemp::Temp::new_dir().unwrap().release();
 
@@ -10,15 +12,35 @@ pub fn verify_riscv_asm_string(file_name: &str, contents: &str, inputs: Vec<Gold
         .with_prover_inputs(inputs.clone())
         .with_output(temp_dir.to_path_buf(), false)
         .from_asm_string(contents.to_string(), Some(PathBuf::from(file_name)));
+    compare_executors(&mut pipeline);
+    verify_pipeline(pipeline.clone());
+    verify_pipeline(powdr_riscv::add_executor_witness(pipeline).unwrap());
+}
+
+/// Runs the program with the pre-decoded RISC-V executor and the reference interpreter,
+/// which have to behave exactly the same, including the full execution trace.
+pub fn compare_executors<T: FieldElement>(pipeline: &mut Pipeline<T>) {
     pipeline.advance_to(Stage::AnalyzedAsm).unwrap();
     let analyzed = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
-    powdr_riscv_executor::execute_ast(
-        analyzed,
-        pipeline.data_callback().unwrap(),
-        // Assume the RISC-V program was compiled without a bootloader, otherwise this will fail.
-        &[],
-        usize::MAX,
+    for mode in [
         powdr_riscv_executor::ExecMode::Fast,
-    );
-    verify_pipeline(pipeline);
+        powdr_riscv_executor::ExecMode::Trace,
+    ] {
+        let execution = powdr_riscv_executor::execute_ast(
+            analyzed,
+            pipeline.data_callback().unwrap(),
+            // Assume the RISC-V program was compiled without a bootloader, otherwise this will fail.
+            &[],
+            usize::MAX,
+            mode,
+        );
+        let reference_execution = powdr_riscv_executor::execute_ast_reference(
+            analyzed,
+            pipeline.data_callback().unwrap(),
+            &[],
+            usize::MAX,
+            mode,
+        );
+        assert_eq!(execution, reference_execution);
+    }
 }
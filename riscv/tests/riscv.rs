@@ -1,45 +1,103 @@
 mod common;
 
-use common::verify_riscv_asm_string;
+use common::{compare_executors, verify_riscv_asm_string};
 use mktemp::Temp;
 use powdr_backend::BackendType;
-use powdr_number::GoldilocksField;
+use powdr_number::{Bn254Field, FieldElement, GoldilocksField};
 use powdr_pipeline::{test_util::verify_asm_string, verify::verify, Pipeline, Stage};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use test_log::test;
 
 use powdr_riscv::{
-    continuations::{rust_continuations, rust_continuations_dry_run},
+    continuations::{
+        bootloader::MemoryLayout, check_chunk_chaining, chunk_public_names, chunk_publics,
+        memory_hash::MemoryHashFunction, rust_continuations, rust_continuations_dry_run,
+    },
     CoProcessors,
 };
 
-/// Compiles and runs a rust file with continuations, runs the full
-/// witness generation & verifies it using Pilcom.
-pub fn test_continuations(case: &str) {
-    let rust_file = format!("{case}.rs");
-    let coprocessors = CoProcessors::base().with_poseidon();
+/// Compiles a rust file of the test data to RISC-V assembly.
+fn compile_rust_case(case: &str) -> BTreeMap<String, String> {
     let temp_dir = Temp::new_dir().unwrap();
-    let riscv_asm =
-        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{rust_file}"), &temp_dir);
-    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &coprocessors, true);
+    powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}.rs"), &temp_dir)
+}
+
+/// The assembly version of the many_chunks.rs example, which does not need a Rust toolchain.
+fn many_chunks_asm() -> BTreeMap<String, String> {
+    let riscv_asm = std::fs::read_to_string("tests/riscv_data/many_chunks_asm/main.s").unwrap();
+    [("main".to_string(), riscv_asm)].into()
+}
+
+/// The public values of the chunk pipeline, in the order in which they are passed to the verifier.
+fn public_values<F: FieldElement>(pipeline: &mut Pipeline<F>) -> Result<Vec<F>, Vec<String>> {
+    Ok(pipeline
+        .publics()?
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect())
+}
+
+/// Compiles RISC-V assembly with a bootloader using the given memory layout and runs all chunks,
+/// calling `prove_chunk` on the pipeline of each chunk. Checks that the public values returned
+/// by `prove_chunk` are chained correctly.
+fn run_continuations<F: FieldElement>(
+    case: &str,
+    riscv_asm: BTreeMap<String, String>,
+    coprocessors: &CoProcessors,
+    layout: MemoryLayout,
+    prove_chunk: impl Fn(Pipeline<F>) -> Result<Vec<F>, ()>,
+) {
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, Some(layout));
 
     // Manually create tmp dir, so that it is the same in all chunks.
     let tmp_dir = mktemp::Temp::new_dir().unwrap();
 
-    let mut pipeline = Pipeline::<GoldilocksField>::default()
-        .from_asm_string(powdr_asm.clone(), Some(PathBuf::from(&rust_file)))
+    let mut pipeline = Pipeline::<F>::default()
+        .from_asm_string(powdr_asm.clone(), Some(PathBuf::from(case)))
         .with_prover_inputs(Default::default())
         .with_output(tmp_dir.to_path_buf(), false);
-    let pipeline_callback = |pipeline: Pipeline<GoldilocksField>| -> Result<(), ()> {
+    let bootloader_inputs = rust_continuations_dry_run(&mut pipeline, &layout);
+    let chunk_publics = rust_continuations(pipeline, prove_chunk, bootloader_inputs).unwrap();
+    check_chunk_chaining(&chunk_publics, &layout).unwrap();
+}
+
+/// Runs RISC-V assembly with continuations, runs the full witness generation
+/// & verifies it using Pilcom.
+pub fn test_continuations(case: &str, riscv_asm: BTreeMap<String, String>) {
+    let pipeline_callback = |pipeline: Pipeline<GoldilocksField>| -> Result<_, ()> {
         // Can't use `verify_pipeline`, because the pipeline was renamed in the middle of after
         // computing the constants file.
         let mut pipeline = pipeline.with_backend(BackendType::PilStarkCli);
         pipeline.advance_to(Stage::Proof).unwrap();
         verify(pipeline.output_dir().unwrap(), pipeline.name(), Some(case));
-        Ok(())
+        Ok(public_values(&mut pipeline).unwrap())
+    };
+    run_continuations(
+        case,
+        riscv_asm,
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::default(),
+        pipeline_callback,
+    );
+}
+
+/// Like [test_continuations], but on the BN254 field, hashing the memory with Poseidon BN254
+/// and generating a mock proof with Halo2 for each chunk.
+#[cfg(feature = "halo2")]
+pub fn test_continuations_bn254(case: &str, riscv_asm: BTreeMap<String, String>) {
+    let pipeline_callback = |pipeline: Pipeline<Bn254Field>| -> Result<_, ()> {
+        let mut pipeline = pipeline.with_backend(BackendType::Halo2Mock);
+        pipeline.advance_to(Stage::Proof).unwrap();
+        Ok(public_values(&mut pipeline).unwrap())
     };
-    let bootloader_inputs = rust_continuations_dry_run(&mut pipeline);
-    rust_continuations(pipeline, pipeline_callback, bootloader_inputs).unwrap();
+    run_continuations(
+        case,
+        riscv_asm,
+        &CoProcessors::base().with_poseidon_bn254(),
+        MemoryLayout::default().with_hash(MemoryHashFunction::PoseidonBn254),
+        pipeline_callback,
+    );
 }
 
 #[test]
@@ -67,6 +125,68 @@ fn test_poseidon_gl() {
     );
 }
 
+#[test]
+#[ignore = "Too slow"]
+fn test_poseidon_bn254() {
+    let case = "poseidon_bn254_via_coprocessor.rs";
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm =
+        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
+    let coprocessors = CoProcessors::base().with_poseidon_bn254();
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &coprocessors, None);
+
+    verify_riscv_asm_string_bn254(&format!("{case}.asm"), &powdr_asm);
+}
+
+/// Like `verify_riscv_asm_string`, but on the BN254 field and without inputs.
+/// The witness is checked by generating a mock proof with Halo2, so without the
+/// `halo2` feature, only the executor is run.
+fn verify_riscv_asm_string_bn254(file_name: &str, contents: &str) {
+    let mut pipeline = Pipeline::<Bn254Field>::default()
+        .with_prover_inputs(vec![])
+        .from_asm_string(contents.to_string(), Some(PathBuf::from(file_name)));
+    compare_executors(&mut pipeline);
+    #[cfg(feature = "halo2")]
+    for pipeline in [
+        pipeline.clone(),
+        powdr_riscv::add_executor_witness(pipeline).unwrap(),
+    ] {
+        pipeline
+            .with_backend(powdr_backend::BackendType::Halo2Mock)
+            .proof()
+            .unwrap();
+    }
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_keccakf() {
+    let case = "keccakf_via_coprocessor.rs";
+    verify_riscv_file(
+        case,
+        Default::default(),
+        &CoProcessors::base().with_keccakf(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_sha256() {
+    let case = "sha256_via_coprocessor.rs";
+    verify_riscv_file(
+        case,
+        Default::default(),
+        &CoProcessors::base().with_sha256(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_ec() {
+    let case = "ec_via_coprocessor.rs";
+    verify_riscv_file(case, Default::default(), &CoProcessors::base().with_arith());
+}
+
 #[test]
 #[ignore = "Too slow"]
 fn test_sum() {
@@ -78,6 +198,81 @@ fn test_sum() {
     );
 }
 
+#[test]
+#[ignore = "Too slow"]
+fn test_elf() {
+    let elf = std::fs::read("tests/riscv_data/elf/sum.elf").unwrap();
+    let powdr_asm = powdr_riscv::elf::compile(&elf, &CoProcessors::base(), None).unwrap();
+    verify_riscv_asm_string(
+        "sum_elf.asm",
+        &powdr_asm,
+        [16, 4, 1, 2, 8, 5].iter().map(|&x| x.into()).collect(),
+    );
+}
+
+fn custom_coprocessor_pipeline() -> Pipeline<GoldilocksField> {
+    let coprocessors =
+        CoProcessors::try_from(vec!["tests/riscv_data/custom_coprocessor/manifest.json"]).unwrap();
+    let riscv_asm = std::fs::read_to_string("tests/riscv_data/custom_coprocessor/main.s").unwrap();
+    let powdr_asm = powdr_riscv::compiler::compile(
+        [("main".to_string(), riscv_asm)].into(),
+        &coprocessors,
+        None,
+    );
+    Pipeline::default()
+        .from_asm_string(powdr_asm, Some(PathBuf::from("custom_coprocessor.asm")))
+        .with_prover_inputs(vec![])
+}
+
+#[test]
+fn test_custom_coprocessor_not_executable() {
+    let mut pipeline = custom_coprocessor_pipeline();
+    assert_eq!(
+        powdr_riscv::check_executor_support(&mut pipeline),
+        Err(vec![
+            "The RISC-V executor does not support the instructions add_mul.".to_string()
+        ])
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_custom_coprocessor() {
+    // The executor cannot run the instruction of the coprocessor,
+    // so the whole witness is computed by witness generation.
+    let pipeline = powdr_riscv::add_executor_witness(custom_coprocessor_pipeline()).unwrap();
+    powdr_pipeline::test_util::verify_pipeline(pipeline);
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_executor_witness_multi_threaded() {
+    // With the witness of the main machine provided by the executor, all calls to
+    // the submachines are deferred and processed in parallel.
+    let riscv_asm = std::fs::read_to_string("tests/instruction_tests/generated/mulhsu.S").unwrap();
+    let powdr_asm = powdr_riscv::compiler::compile(
+        [("mulhsu".to_string(), riscv_asm)].into(),
+        &CoProcessors::base(),
+        None,
+    );
+    let pipeline = powdr_riscv::add_executor_witness(
+        Pipeline::<GoldilocksField>::default()
+            .from_asm_string(powdr_asm, Some(PathBuf::from("mulhsu.asm")))
+            .with_prover_inputs(vec![]),
+    )
+    .unwrap();
+    let witness = |multi_threaded| {
+        pipeline
+            .clone()
+            .with_multi_threaded_witgen(multi_threaded)
+            .generated_witness()
+            .unwrap()
+            .witness
+            .unwrap()
+    };
+    assert_eq!(witness(false), witness(true));
+}
+
 #[test]
 #[ignore = "Too slow"]
 fn test_byte_access() {
@@ -193,41 +388,294 @@ fn test_print() {
     verify_file(case, Default::default(), &CoProcessors::base());
 }
 
-#[test]
-fn test_many_chunks_dry() {
-    // Compiles and runs the many_chunks.rs example with continuations, just computing
-    // and validating the bootloader inputs.
-    // Doesn't do a full witness generation, verification, or proving.
-    let case = "many_chunks.rs";
-    let coprocessors = CoProcessors::base().with_poseidon();
-    let temp_dir = Temp::new_dir().unwrap();
-    let riscv_asm =
-        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
-    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &coprocessors, true);
+/// Compiles and runs the many_chunks.rs example with continuations, just computing
+/// and validating the bootloader inputs.
+/// Doesn't do a full witness generation, verification, or proving.
+fn many_chunks_dry<T: FieldElement>(coprocessors: &CoProcessors, layout: MemoryLayout) {
+    let case = "many_chunks";
+    continuations_dry::<T>(case, compile_rust_case(case), coprocessors, layout);
+}
+
+/// Like [many_chunks_dry], but for the assembly version of the example,
+/// which does not need a Rust toolchain.
+fn many_chunks_asm_dry<T: FieldElement>(coprocessors: &CoProcessors, layout: MemoryLayout) {
+    continuations_dry::<T>("many_chunks_asm", many_chunks_asm(), coprocessors, layout);
+}
+
+fn continuations_dry<T: FieldElement>(
+    case: &str,
+    riscv_asm: BTreeMap<String, String>,
+    coprocessors: &CoProcessors,
+    layout: MemoryLayout,
+) {
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, Some(layout));
 
     let mut pipeline = Pipeline::default()
         .from_asm_string(powdr_asm, Some(PathBuf::from(case)))
         .with_prover_inputs(Default::default());
-    rust_continuations_dry_run::<GoldilocksField>(&mut pipeline);
+    let bootloader_inputs = rust_continuations_dry_run::<T>(&mut pipeline, &layout);
+    assert!(bootloader_inputs.len() > 1);
+
+    let mut publics = bootloader_inputs
+        .iter()
+        .map(|(inputs, _)| chunk_publics(inputs, &layout))
+        .collect::<Vec<_>>();
+    check_chunk_chaining(&publics, &layout).unwrap();
+
+    // Leaving out the last chunk only proves a prefix of the execution.
+    let errors = check_chunk_chaining(&publics[..publics.len() - 1], &layout).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0].contains("The last chunk does not end the execution"),
+        "{errors:?}"
+    );
+
+    // Public values are identified by their position, so there has to be one for each name.
+    let mut extended = publics.clone();
+    extended[0].push(T::one());
+    assert!(check_chunk_chaining(&extended, &layout).is_err());
+
+    // Tampering with the initial state of a chunk breaks the chain.
+    let index = chunk_public_names(&layout)
+        .iter()
+        .position(|name| name == "initial_x10")
+        .unwrap();
+    publics[1][index] += T::one();
+    let errors = check_chunk_chaining(&publics, &layout).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("Chunk 1: Initial value of x10 is"));
+}
+
+#[test]
+fn test_many_chunks_dry() {
+    many_chunks_dry::<GoldilocksField>(
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::default(),
+    );
+}
+
+#[test]
+fn test_many_chunks_dry_small_pages() {
+    // 256 byte pages in a Merkle tree of arity 4.
+    many_chunks_dry::<GoldilocksField>(
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::new(8, 4).unwrap(),
+    );
+}
+
+#[test]
+fn test_many_chunks_dry_bn254() {
+    // On the BN254 field, the memory is hashed using Poseidon BN254.
+    many_chunks_dry::<Bn254Field>(
+        &CoProcessors::base().with_poseidon_bn254(),
+        MemoryLayout::default().with_hash(MemoryHashFunction::PoseidonBn254),
+    );
+}
+
+#[test]
+fn test_many_chunks_asm_dry() {
+    many_chunks_asm_dry::<GoldilocksField>(
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::default(),
+    );
+}
+
+#[test]
+fn test_many_chunks_asm_dry_arity_4() {
+    // 256 byte pages in a Merkle tree of arity 4.
+    many_chunks_asm_dry::<GoldilocksField>(
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::new(8, 4).unwrap(),
+    );
+}
+
+#[test]
+fn test_many_chunks_asm_dry_bn254() {
+    many_chunks_asm_dry::<Bn254Field>(
+        &CoProcessors::base().with_poseidon_bn254(),
+        MemoryLayout::default().with_hash(MemoryHashFunction::PoseidonBn254),
+    );
+}
+
+#[test]
+#[should_panic(expected = "The memory hash function cannot be used on this field")]
+fn test_many_chunks_asm_dry_wrong_hash() {
+    many_chunks_asm_dry::<Bn254Field>(
+        &CoProcessors::base().with_poseidon(),
+        MemoryLayout::default(),
+    );
 }
 
 #[test]
 #[ignore = "Too slow"]
 fn test_many_chunks() {
-    test_continuations("many_chunks")
+    let case = "many_chunks";
+    test_continuations(case, compile_rust_case(case))
 }
 
 #[test]
 #[ignore = "Too slow"]
 fn test_many_chunks_memory() {
-    test_continuations("many_chunks_memory")
+    let case = "many_chunks_memory";
+    test_continuations(case, compile_rust_case(case))
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_many_chunks_asm() {
+    test_continuations("many_chunks_asm", many_chunks_asm())
+}
+
+#[test]
+#[ignore = "Too slow"]
+#[cfg(feature = "halo2")]
+fn test_many_chunks_bn254() {
+    let case = "many_chunks";
+    test_continuations_bn254(case, compile_rust_case(case))
+}
+
+/// Proves all chunks of the many_chunks_asm example with Halo2 and verifies them with
+/// [verify_rust_continuations], which has to reject a chunk whose final state is tampered with.
+#[test]
+#[ignore = "Too slow"]
+#[cfg(feature = "halo2")]
+fn test_many_chunks_asm_verify_bn254() {
+    use powdr_pipeline::util::write_or_panic;
+    use powdr_riscv::continuations::verify_rust_continuations;
+    use std::{fs::File, io::BufWriter};
+
+    let layout = MemoryLayout::default().with_hash(MemoryHashFunction::PoseidonBn254);
+    let powdr_asm = powdr_riscv::compiler::compile(
+        many_chunks_asm(),
+        &CoProcessors::base().with_poseidon_bn254(),
+        Some(layout),
+    );
+
+    let tmp_dir = Temp::new_dir().unwrap();
+    let mut pipeline = Pipeline::<Bn254Field>::default()
+        .from_asm_string(powdr_asm, Some(PathBuf::from("many_chunks_asm")))
+        .with_prover_inputs(Default::default())
+        .with_output(tmp_dir.to_path_buf(), false)
+        .with_backend(BackendType::Halo2);
+    let bootloader_inputs = rust_continuations_dry_run(&mut pipeline, &layout);
+
+    // All chunks share the same circuit, and therefore the same setup and verification key.
+    let degree = pipeline.optimized_pil_ref().unwrap().degree();
+    let setup_file_path = tmp_dir.as_path().join("params.bin");
+    write_or_panic(
+        BufWriter::new(File::create(&setup_file_path).unwrap()),
+        |writer| {
+            BackendType::Halo2
+                .factory::<Bn254Field>()
+                .generate_setup(degree, writer)
+                .unwrap()
+        },
+    );
+    let mut pipeline = pipeline.with_setup_file(Some(setup_file_path));
+    let vkey_file_path = tmp_dir.as_path().join("verification_key.bin");
+    write_or_panic(
+        BufWriter::new(File::create(&vkey_file_path).unwrap()),
+        |writer| pipeline.export_verification_key(writer).unwrap(),
+    );
+    let pipeline = pipeline.with_vkey_file(Some(vkey_file_path));
+
+    let prove_chunk = |mut pipeline: Pipeline<Bn254Field>| -> Result<_, Vec<String>> {
+        let publics = public_values(&mut pipeline)?;
+        let proof = pipeline.proof()?.proof.unwrap();
+        Ok((proof, publics))
+    };
+    let mut chunks = rust_continuations(pipeline.clone(), prove_chunk, bootloader_inputs).unwrap();
+    verify_rust_continuations(pipeline.clone(), chunks.clone(), &layout).unwrap();
+
+    // Claim a different final state for the first chunk, consistently with the initial state
+    // of the second chunk, so that only the proof of the first chunk can reject it.
+    let names = chunk_public_names(&layout);
+    for (i, name) in [(0, "final_x10"), (1, "initial_x10")] {
+        let index = names.iter().position(|n| n == name).unwrap();
+        chunks[i].1[index] += Bn254Field::one();
+    }
+    let errors = verify_rust_continuations(pipeline, chunks, &layout).unwrap_err();
+    assert!(errors[0].starts_with("Chunk 0:"), "{errors:?}");
+}
+
+/// Proves all chunks of `many_chunks_asm` with Halo2 and aggregates them with
+/// [aggregate_rust_continuations], which has to reject chunks in the wrong order.
+#[test]
+#[ignore = "Too slow"]
+#[cfg(feature = "halo2")]
+fn test_many_chunks_asm_aggregate_bn254() {
+    use powdr_pipeline::util::write_or_panic;
+    use powdr_riscv::continuations::{
+        aggregate_rust_continuations, bootloader::chained_public_indices,
+        memory_hash::PoseidonBn254,
+    };
+    use std::{fs::File, io::BufWriter};
+
+    let layout = MemoryLayout::default().with_hash(MemoryHashFunction::PoseidonBn254);
+    let powdr_asm = powdr_riscv::compiler::compile(
+        many_chunks_asm(),
+        &CoProcessors::base().with_poseidon_bn254(),
+        Some(layout),
+    );
+
+    let tmp_dir = Temp::new_dir().unwrap();
+    let mut pipeline = Pipeline::<Bn254Field>::default()
+        .from_asm_string(powdr_asm, Some(PathBuf::from("many_chunks_asm")))
+        .with_prover_inputs(Default::default())
+        .with_output(tmp_dir.to_path_buf(), false)
+        .with_backend(BackendType::Halo2);
+    let bootloader_inputs = rust_continuations_dry_run(&mut pipeline, &layout);
+
+    // The chunks are proven with the same setup as the aggregation circuit, so it has to be
+    // large enough for the latter.
+    let setup_file_path = tmp_dir.as_path().join("params.bin");
+    write_or_panic(
+        BufWriter::new(File::create(&setup_file_path).unwrap()),
+        |writer| {
+            BackendType::Halo2
+                .factory::<Bn254Field>()
+                .generate_setup(1 << 20, writer)
+                .unwrap()
+        },
+    );
+    let mut pipeline = pipeline.with_setup_file(Some(setup_file_path));
+    let vkey_file_path = tmp_dir.as_path().join("verification_key.bin");
+    write_or_panic(
+        BufWriter::new(File::create(&vkey_file_path).unwrap()),
+        |writer| pipeline.export_verification_key(writer).unwrap(),
+    );
+    let pipeline = pipeline.with_vkey_file(Some(vkey_file_path));
+
+    let prove_chunk = |mut pipeline: Pipeline<Bn254Field>| -> Result<_, Vec<String>> {
+        let publics = public_values(&mut pipeline)?;
+        let proof = pipeline.proof()?.proof.unwrap();
+        Ok((proof, publics))
+    };
+    let chunks = rust_continuations(pipeline.clone(), prove_chunk, bootloader_inputs).unwrap();
+    assert!(chunks.len() > 1);
+
+    let (_, publics) =
+        aggregate_rust_continuations(pipeline.clone(), chunks.clone(), &layout).unwrap();
+    // The initial state of the first chunk, followed by the final state of the last chunk.
+    let chained = chained_public_indices::<PoseidonBn254>();
+    let (first, last) = (&chunks[0].1, &chunks[chunks.len() - 1].1);
+    let expected = chained
+        .iter()
+        .map(|(initial, _)| first[*initial])
+        .chain(chained.iter().map(|(_, final_)| last[*final_]))
+        .collect::<Vec<_>>();
+    assert_eq!(publics, expected);
+
+    let mut reversed = chunks;
+    reversed.reverse();
+    assert!(aggregate_rust_continuations(pipeline, reversed, &layout).is_err());
 }
 
 fn verify_file(case: &str, inputs: Vec<GoldilocksField>, coprocessors: &CoProcessors) {
     let temp_dir = Temp::new_dir().unwrap();
     let riscv_asm =
         powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
-    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, false);
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, None);
 
     verify_asm_string(&format!("{case}.asm"), &powdr_asm, inputs, vec![]);
 }
@@ -246,7 +694,7 @@ fn verify_riscv_file(case: &str, inputs: Vec<GoldilocksField>, coprocessors: &Co
     let temp_dir = Temp::new_dir().unwrap();
     let riscv_asm =
         powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
-    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, false);
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, coprocessors, None);
 
     verify_riscv_asm_string(&format!("{case}.asm"), &powdr_asm, inputs);
 }
@@ -263,5 +711,5 @@ fn compile_riscv_crate(case: &str, coprocessors: &CoProcessors) -> String {
         &format!("tests/riscv_data/{case}/Cargo.toml"),
         &temp_dir,
     );
-    powdr_riscv::compiler::compile(riscv_asm, coprocessors, false)
+    powdr_riscv::compiler::compile(riscv_asm, coprocessors, None)
 }
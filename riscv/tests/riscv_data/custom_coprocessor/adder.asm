@@ -0,0 +1,8 @@
+machine Adder(latch, operation_id) {
+    operation add_mul<0> A, B -> C;
+
+    col witness operation_id;
+    col fixed latch = [1]*;
+    col witness A, B, C;
+    C = A * B + A;
+}
@@ -0,0 +1,12 @@
+# Calls the runtime function of the coprocessor defined in manifest.json,
+# which is replaced by its instruction.
+.globl __runtime_start
+__runtime_start:
+    li a0, 6
+    li a1, 7
+    call add_mul_coprocessor
+    li t0, 48
+    bne a0, t0, fail
+    ret
+fail:
+    unimp
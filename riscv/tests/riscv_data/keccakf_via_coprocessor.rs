@@ -0,0 +1,20 @@
+#![no_std]
+
+use powdr_riscv_runtime::coprocessors::keccakf;
+
+#[no_mangle]
+fn main() {
+    // See test vectors at:
+    // https://github.com/XKCP/XKCP/blob/master/tests/TestVectors/KeccakF-1600-IntermediateValues.txt
+    let mut state = [0u64; 25];
+    keccakf(&mut state);
+    assert_eq!(state[0], 0xf1258f7940e1dde7);
+    assert_eq!(state[1], 0x84d5ccf933c0478a);
+    assert_eq!(state[12], 0x81a57c16dbcf555f);
+    assert_eq!(state[24], 0xeaf1ff7b5ceca249);
+
+    keccakf(&mut state);
+    assert_eq!(state[0], 0x2d5c954df96ecb3c);
+    assert_eq!(state[1], 0x6a332cd07057b56d);
+    assert_eq!(state[24], 0x20d06cd26a8fbf5c);
+}
@@ -0,0 +1,20 @@
+# Like many_chunks.rs, but written in assembly, so that it can be compiled without
+# a Rust toolchain: Computes Fibonacci numbers and writes them to several pages.
+.globl __runtime_start
+__runtime_start:
+    li s0, 0
+    li s1, 100000
+    li s2, 1
+    li s3, 1
+    lui s4, 0x20
+loop:
+    add t0, s2, s3
+    mv s2, s3
+    mv s3, t0
+    andi t1, s0, 3
+    slli t1, t1, 10
+    add t1, t1, s4
+    sw s3, 0(t1)
+    addi s0, s0, 1
+    bne s0, s1, loop
+    ret
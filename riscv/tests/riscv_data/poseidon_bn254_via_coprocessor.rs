@@ -0,0 +1,53 @@
+#![no_std]
+
+use powdr_riscv_runtime::coprocessors::poseidon_bn254;
+
+// Field elements are given as 32-bit limbs in little-endian order.
+// The test vectors are the same as in test_data/std/poseidon_bn254_test.asm.
+
+#[no_mangle]
+fn main() {
+    let zero = [0; 8];
+    let one = [1, 0, 0, 0, 0, 0, 0, 0];
+    let two = [2, 0, 0, 0, 0, 0, 0, 0];
+
+    let h = poseidon_bn254([zero, zero, zero]);
+    assert_eq!(
+        h,
+        [
+            0x46b64864, 0xa839ee84, 0x5ffed523, 0xdc3124d5, 0x7b81e481, 0x3ceac3f2, 0x9e239eab,
+            0x2098f5fb
+        ]
+    );
+
+    let h = poseidon_bn254([zero, one, two]);
+    assert_eq!(
+        h,
+        [
+            0x4417189a, 0x9e19607a, 0x74324551, 0x2a3617f2, 0x9662e9cf, 0x3df64c6b, 0xe7d69041,
+            0x115cc0f5
+        ]
+    );
+
+    // -1, -2 and -3 in the BN254 field
+    let minus_one = [
+        0xf0000000, 0x43e1f593, 0x79b97091, 0x2833e848, 0x8181585d, 0xb85045b6, 0xe131a029,
+        0x30644e72,
+    ];
+    let minus_two = [
+        0xefffffff, 0x43e1f593, 0x79b97091, 0x2833e848, 0x8181585d, 0xb85045b6, 0xe131a029,
+        0x30644e72,
+    ];
+    let minus_three = [
+        0xeffffffe, 0x43e1f593, 0x79b97091, 0x2833e848, 0x8181585d, 0xb85045b6, 0xe131a029,
+        0x30644e72,
+    ];
+    let h = poseidon_bn254([minus_one, minus_two, minus_three]);
+    assert_eq!(
+        h,
+        [
+            0x0971b70c, 0x8645a779, 0x981fb7ca, 0xcac1c924, 0x650795c4, 0x254f2d44, 0xe5ae9f3d,
+            0x15492e60
+        ]
+    );
+}
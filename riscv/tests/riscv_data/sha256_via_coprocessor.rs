@@ -0,0 +1,24 @@
+#![no_std]
+
+use powdr_riscv_runtime::coprocessors::sha256_compress;
+
+#[no_mangle]
+fn main() {
+    // The hash of "abc", see:
+    // https://csrc.nist.gov/csrc/media/projects/cryptographic-standards-and-guidelines/documents/examples/sha256.pdf
+    let mut state = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut block = [0; 16];
+    block[0] = 0x61626380;
+    block[15] = 0x18;
+    sha256_compress(&mut state, &block);
+    assert_eq!(
+        state,
+        [
+            0xba7816bf, 0x8f01cfea, 0x414140de, 0x5dae2223, 0xb00361a3, 0x96177a9c, 0xb410ff61,
+            0xf20015ad
+        ]
+    );
+}
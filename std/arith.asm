@@ -2,9 +2,12 @@ use std::array;
 use std::utils::unchanged_until;
 use std::utils::force_bool;
 use std::utils::sum;
+use std::math::ff;
+use std::convert::int;
 
 // Arithmetic machine, ported mainly from Polygon: https://github.com/0xPolygonHermez/zkevm-proverjs/blob/main/pil/arith.pil
-// Currently only supports "Equation 0", i.e., 256-Bit addition and multiplication.
+// Supports 256-Bit addition and multiplication ("Equation 0") as well as
+// addition and doubling of points on the secp256k1 curve (Equations 1 to 4).
 machine Arith(CLK32_31, operation_id){
     
     // The operation ID will be bit-decomosed to yield selEq[], controlling which equations are activated.
@@ -17,18 +20,138 @@ machine Arith(CLK32_31, operation_id){
     
     // Performs elliptic curve addition of points (x1, y2) and (x2, y2).
     // Operation ID is 10 = 0b1010, i.e., we activate equations 1, 3, and 4.
-    // TODO: Witgen doesn't work for that yet.
     operation ec_add<10> x1c[0], x1c[1], x1c[2], x1c[3], x1c[4], x1c[5], x1c[6], x1c[7], y1c[0], y1c[1], y1c[2], y1c[3], y1c[4], y1c[5], y1c[6], y1c[7], x2c[0], x2c[1], x2c[2], x2c[3], x2c[4], x2c[5], x2c[6], x2c[7], y2c[0], y2c[1], y2c[2], y2c[3], y2c[4], y2c[5], y2c[6], y2c[7] -> x3c[0], x3c[1], x3c[2], x3c[3], x3c[4], x3c[5], x3c[6], x3c[7], y3c[0], y3c[1], y3c[2], y3c[3], y3c[4], y3c[5], y3c[6], y3c[7];
     
     // Performs elliptic curve doubling of point (x1, y2).
     // Operation ID is 12 = 0b1100, i.e., we activate equations 2, 3, and 4.
-    // TODO: Witgen doesn't work for that yet.
     operation ec_double<12> x1c[0], x1c[1], x1c[2], x1c[3], x1c[4], x1c[5], x1c[6], x1c[7], y1c[0], y1c[1], y1c[2], y1c[3], y1c[4], y1c[5], y1c[6], y1c[7] -> x3c[0], x3c[1], x3c[2], x3c[3], x3c[4], x3c[5], x3c[6], x3c[7], y3c[0], y3c[1], y3c[2], y3c[3], y3c[4], y3c[5], y3c[6], y3c[7];
     
     let BYTE = |i| i & 0xff;
     let BYTE2 = |i| i & 0xffff;
 
-    pol commit x1[16], y1[16], x2[16], y2[16], x3[16], y3[16], s[16], q0[16], q1[16], q2[16];
+    pol commit x1[16], y1[16], x2[16], y2[16], x3[16], y3[16];
+
+    /****
+    *
+    * HINTS for s, q0, q1, q2
+    *
+    * Witgen cannot compute the slope s and the quotients q0, q1, q2 of the elliptic
+    * curve equations by itself, so they are provided as hints. Given these, x3 and y3
+    * are computed from the equations like y2 and y3 of equation 0.
+    *
+    *****/
+
+    // 0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f
+    let secp_modulus = 2**256 - 2**32 - 977;
+
+    // Converts the limbs of x in the given row to an integer.
+    let limbs_to_int = |x, row| sum(16, |i| int(x[i](row)) << (16 * i));
+    // Returns the i-th limb of x, where the most significant limb can have up to 32 bits.
+    let select_limb = |x, i| if i < 15 { (x >> (16 * i)) & 0xffff } else { x >> (16 * i) };
+
+    // The equations are of the form `... + (q - 2**258) * p = 0`, see `product_with_p` below.
+    let quotient: int -> int = |x| x / secp_modulus + 2**258;
+    let s_for = |op, x_1, y_1, x_2, y_2| match op {
+        10 => ff::div(ff::sub(y_2, y_1, secp_modulus), ff::sub(x_2, x_1, secp_modulus), secp_modulus),
+        12 => ff::div(ff::mul(3, ff::mul(x_1, x_1, secp_modulus), secp_modulus), ff::mul(2, y_1, secp_modulus), secp_modulus)
+    };
+    let q0_for = |op, s, x_1, y_1, x_2, y_2| match op {
+        10 => quotient(y_2 - y_1 - s * (x_2 - x_1)),
+        12 => quotient(3 * x_1 * x_1 - 2 * s * y_1)
+    };
+    let x3_for = |s, x_1, x_2| ff::reduce(s * s - x_1 - x_2, secp_modulus);
+    let q1_for = |s, x_1, x_2, x_3| quotient(x_1 + x_2 + x_3 - s * s);
+    let y3_for = |s, x_1, y_1, x_3| ff::reduce(s * (x_1 - x_3) - y_1, secp_modulus);
+    let q2_for = |s, x_1, y_1, x_3, y_3| quotient(y_1 + y_3 - s * x_1 + s * x_3);
+
+    // Returns [s, q0, q1, q2] for the elliptic curve operation `op` on the given points.
+    let ec_hints = |op, x_1, y_1, x_2, y_2| (|s| (|x_3| [
+        s,
+        q0_for(op, s, x_1, y_1, x_2, y_2),
+        q1_for(s, x_1, x_2, x_3),
+        q2_for(s, x_1, y_1, x_3, y3_for(s, x_1, y_1, x_3))
+    ])(x3_for(s, x_1, x_2)))(s_for(op, x_1, y_1, x_2, y_2));
+
+    // Returns [s, q0, q1, q2] for the operation in the given row.
+    // There is no hint for affine_256, which does not use these columns.
+    // Because of its type `int -> int[]`, witness generation evaluates it only once
+    // per row for the queries of all 64 columns below.
+    let ec_hints_in_row: int -> int[] = |row| (|op, x_1, y_1| match op {
+        10 => ec_hints(op, x_1, y_1, limbs_to_int(x2, row), limbs_to_int(y2, row)),
+        // For ec_double, x2 is constrained to be equal to x1 below.
+        12 => ec_hints(op, x_1, y_1, x_1, y_1)
+    })(int(operation_id(row)), limbs_to_int(x1, row), limbs_to_int(y1, row));
+
+    let limb_hint = |value, limb, row| ("hint", select_limb(ec_hints_in_row(row)[value], limb));
+
+    col witness s_0(i) query limb_hint(0, 0, i);
+    col witness s_1(i) query limb_hint(0, 1, i);
+    col witness s_2(i) query limb_hint(0, 2, i);
+    col witness s_3(i) query limb_hint(0, 3, i);
+    col witness s_4(i) query limb_hint(0, 4, i);
+    col witness s_5(i) query limb_hint(0, 5, i);
+    col witness s_6(i) query limb_hint(0, 6, i);
+    col witness s_7(i) query limb_hint(0, 7, i);
+    col witness s_8(i) query limb_hint(0, 8, i);
+    col witness s_9(i) query limb_hint(0, 9, i);
+    col witness s_10(i) query limb_hint(0, 10, i);
+    col witness s_11(i) query limb_hint(0, 11, i);
+    col witness s_12(i) query limb_hint(0, 12, i);
+    col witness s_13(i) query limb_hint(0, 13, i);
+    col witness s_14(i) query limb_hint(0, 14, i);
+    col witness s_15(i) query limb_hint(0, 15, i);
+    let s: expr[16] = [s_0, s_1, s_2, s_3, s_4, s_5, s_6, s_7, s_8, s_9, s_10, s_11, s_12, s_13, s_14, s_15];
+    col witness q0_0(i) query limb_hint(1, 0, i);
+    col witness q0_1(i) query limb_hint(1, 1, i);
+    col witness q0_2(i) query limb_hint(1, 2, i);
+    col witness q0_3(i) query limb_hint(1, 3, i);
+    col witness q0_4(i) query limb_hint(1, 4, i);
+    col witness q0_5(i) query limb_hint(1, 5, i);
+    col witness q0_6(i) query limb_hint(1, 6, i);
+    col witness q0_7(i) query limb_hint(1, 7, i);
+    col witness q0_8(i) query limb_hint(1, 8, i);
+    col witness q0_9(i) query limb_hint(1, 9, i);
+    col witness q0_10(i) query limb_hint(1, 10, i);
+    col witness q0_11(i) query limb_hint(1, 11, i);
+    col witness q0_12(i) query limb_hint(1, 12, i);
+    col witness q0_13(i) query limb_hint(1, 13, i);
+    col witness q0_14(i) query limb_hint(1, 14, i);
+    col witness q0_15(i) query limb_hint(1, 15, i);
+    let q0: expr[16] = [q0_0, q0_1, q0_2, q0_3, q0_4, q0_5, q0_6, q0_7, q0_8, q0_9, q0_10, q0_11, q0_12, q0_13, q0_14, q0_15];
+    col witness q1_0(i) query limb_hint(2, 0, i);
+    col witness q1_1(i) query limb_hint(2, 1, i);
+    col witness q1_2(i) query limb_hint(2, 2, i);
+    col witness q1_3(i) query limb_hint(2, 3, i);
+    col witness q1_4(i) query limb_hint(2, 4, i);
+    col witness q1_5(i) query limb_hint(2, 5, i);
+    col witness q1_6(i) query limb_hint(2, 6, i);
+    col witness q1_7(i) query limb_hint(2, 7, i);
+    col witness q1_8(i) query limb_hint(2, 8, i);
+    col witness q1_9(i) query limb_hint(2, 9, i);
+    col witness q1_10(i) query limb_hint(2, 10, i);
+    col witness q1_11(i) query limb_hint(2, 11, i);
+    col witness q1_12(i) query limb_hint(2, 12, i);
+    col witness q1_13(i) query limb_hint(2, 13, i);
+    col witness q1_14(i) query limb_hint(2, 14, i);
+    col witness q1_15(i) query limb_hint(2, 15, i);
+    let q1: expr[16] = [q1_0, q1_1, q1_2, q1_3, q1_4, q1_5, q1_6, q1_7, q1_8, q1_9, q1_10, q1_11, q1_12, q1_13, q1_14, q1_15];
+    col witness q2_0(i) query limb_hint(3, 0, i);
+    col witness q2_1(i) query limb_hint(3, 1, i);
+    col witness q2_2(i) query limb_hint(3, 2, i);
+    col witness q2_3(i) query limb_hint(3, 3, i);
+    col witness q2_4(i) query limb_hint(3, 4, i);
+    col witness q2_5(i) query limb_hint(3, 5, i);
+    col witness q2_6(i) query limb_hint(3, 6, i);
+    col witness q2_7(i) query limb_hint(3, 7, i);
+    col witness q2_8(i) query limb_hint(3, 8, i);
+    col witness q2_9(i) query limb_hint(3, 9, i);
+    col witness q2_10(i) query limb_hint(3, 10, i);
+    col witness q2_11(i) query limb_hint(3, 11, i);
+    col witness q2_12(i) query limb_hint(3, 12, i);
+    col witness q2_13(i) query limb_hint(3, 13, i);
+    col witness q2_14(i) query limb_hint(3, 14, i);
+    col witness q2_15(i) query limb_hint(3, 15, i);
+    let q2: expr[16] = [q2_0, q2_1, q2_2, q2_3, q2_4, q2_5, q2_6, q2_7, q2_8, q2_9, q2_10, q2_11, q2_12, q2_13, q2_14, q2_15];
 
     let combine: expr[] -> expr[] = |x| array::new(array::len(x) / 2, |i| x[2 * i + 1] * 2**16 + x[2 * i]);
     // Intermediate polynomials, arrays of 8 columns, 32 bit per column.
@@ -0,0 +1,113 @@
+use std::array;
+use std::utils::sum;
+use std::utils::force_bool;
+
+// Implements the Keccak-f[1600] permutation, computing one round per row.
+machine KeccakF(LASTBLOCK, operation_id) {
+
+    // Applies the permutation to a state of 25 lanes of 64 bits each.
+    // Lane x + 5 * y is passed as two 32-bit limbs (least significant first),
+    // i.e. the state is laid out as in memory on a little-endian machine.
+    operation keccakf<0> preimage[0], preimage[1], preimage[2], preimage[3], preimage[4], preimage[5], preimage[6], preimage[7], preimage[8], preimage[9], preimage[10], preimage[11], preimage[12], preimage[13], preimage[14], preimage[15], preimage[16], preimage[17], preimage[18], preimage[19], preimage[20], preimage[21], preimage[22], preimage[23], preimage[24], preimage[25], preimage[26], preimage[27], preimage[28], preimage[29], preimage[30], preimage[31], preimage[32], preimage[33], preimage[34], preimage[35], preimage[36], preimage[37], preimage[38], preimage[39], preimage[40], preimage[41], preimage[42], preimage[43], preimage[44], preimage[45], preimage[46], preimage[47], preimage[48], preimage[49] -> a_prime_prime[0], a_prime_prime[1], a_prime_prime[2], a_prime_prime[3], a_prime_prime[4], a_prime_prime[5], a_prime_prime[6], a_prime_prime[7], a_prime_prime[8], a_prime_prime[9], a_prime_prime[10], a_prime_prime[11], a_prime_prime[12], a_prime_prime[13], a_prime_prime[14], a_prime_prime[15], a_prime_prime[16], a_prime_prime[17], a_prime_prime[18], a_prime_prime[19], a_prime_prime[20], a_prime_prime[21], a_prime_prime[22], a_prime_prime[23], a_prime_prime[24], a_prime_prime[25], a_prime_prime[26], a_prime_prime[27], a_prime_prime[28], a_prime_prime[29], a_prime_prime[30], a_prime_prime[31], a_prime_prime[32], a_prime_prime[33], a_prime_prime[34], a_prime_prime[35], a_prime_prime[36], a_prime_prime[37], a_prime_prime[38], a_prime_prime[39], a_prime_prime[40], a_prime_prime[41], a_prime_prime[42], a_prime_prime[43], a_prime_prime[44], a_prime_prime[45], a_prime_prime[46], a_prime_prime[47], a_prime_prime[48], a_prime_prime[49];
+
+    col witness operation_id;
+
+    // The layout follows Plonky3's keccak-air
+    // (https://github.com/Plonky3/Plonky3/tree/main/keccak-air), but as the Goldilocks field
+    // fits 32-bit limbs, we do not need to split lanes into 16-bit limbs.
+    // The gate-based Polygon implementation (test_data/polygon-hermez/keccakf.pil) would
+    // need copy constraints between arbitrary rows, which we do not support.
+
+    constant %rounds = 24;
+
+    pol constant FIRSTBLOCK(i) { match i % %rounds {
+        0 => 1,
+        _ => 0
+    }};
+    pol constant LASTBLOCK(i) { match i % %rounds {
+        %rounds - 1 => 1,
+        _ => 0
+    }};
+    // Like LASTBLOCK, but also 1 in the last row of the table
+    // Specified this way because we can't access the degree in the match statement
+    pol constant LAST = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]* + [1];
+
+    // The round constants. Only the bits 2**j - 1 (j = 0, ..., 6) are ever set,
+    // so we only need fixed columns for these.
+    let RC = [
+        0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+        0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+        0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+        0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+        0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+        0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008
+    ];
+    let RC_BITS: col[7] = array::new(7, |j| |i| (RC[i % %rounds] >> ((1 << j) - 1)) & 1);
+    let rc_bit: int -> expr = |z| match z {
+        0 => RC_BITS[0],
+        1 => RC_BITS[1],
+        3 => RC_BITS[2],
+        7 => RC_BITS[3],
+        15 => RC_BITS[4],
+        31 => RC_BITS[5],
+        63 => RC_BITS[6],
+        _ => 0
+    };
+
+    // The rotation offsets of the rho step, indexed by lane.
+    let R = [
+        0, 1, 62, 28, 27,
+        36, 44, 6, 55, 20,
+        3, 10, 43, 25, 39,
+        41, 45, 15, 21, 8,
+        18, 2, 61, 56, 14
+    ];
+
+    let xor = |a, b| a + b - 2 * a * b;
+    let andn = |a, b| (1 - a) * b;
+
+    // Lane x + 5 * y of the input, constant throughout the block.
+    pol commit preimage[50];
+    // The state at the beginning of the round, bit z of lane x + 5 * y is a[64 * (x + 5 * y) + z].
+    pol commit a[1600];
+    // The parity of the columns of a, bit z of column x is c[64 * x + z].
+    pol commit c[320];
+    // The state after the theta step.
+    pol commit a_prime[1600];
+    // The state after the round, as 32-bit limbs.
+    pol commit a_prime_prime[50];
+
+    let bit = |state, x, y, z| state[64 * (x + 5 * y) + z];
+    let limb = |bits, l| sum(32, |i| bits(32 * l + i) * 2**i);
+
+    array::map(a, force_bool);
+
+    // Theta
+    array::new(320, |i| c[i] = xor(xor(xor(xor(
+        bit(a, i / 64, 0, i % 64),
+        bit(a, i / 64, 1, i % 64)),
+        bit(a, i / 64, 2, i % 64)),
+        bit(a, i / 64, 3, i % 64)),
+        bit(a, i / 64, 4, i % 64)));
+    let d = |x, z| xor(c[64 * ((x + 4) % 5) + z], c[64 * ((x + 1) % 5) + (z + 63) % 64]);
+    array::new(1600, |i| a_prime[i] = xor(a[i], d((i / 64) % 5, i % 64)));
+
+    // Rho and pi: lane (x, y) of b is lane (x + 3 * y, x) of a_prime, rotated.
+    let b = |x, y, z| bit(
+        a_prime,
+        (x + 3 * y) % 5,
+        x,
+        (z + 64 - R[(x + 3 * y) % 5 + 5 * x]) % 64
+    );
+
+    // Chi and iota
+    let chi = |x, y, z| xor(b(x, y, z), andn(b((x + 1) % 5, y, z), b((x + 2) % 5, y, z)));
+    let round_output = |x, y, z| if x + 5 * y == 0 { xor(chi(x, y, z), rc_bit(z)) } else { chi(x, y, z) };
+    array::new(50, |i| a_prime_prime[i] = limb(|z| round_output((i / 2) % 5, i / 10, z), i % 2));
+
+    // The input is the state of the first round, the output of
+    // each round is the state of the next one.
+    array::new(50, |i| FIRSTBLOCK * (preimage[i] - limb(|z| a[64 * (i / 2) + z], i % 2)) = 0);
+    array::new(50, |i| (1 - LAST) * (a_prime_prime[i] - limb(|z| a[64 * (i / 2) + z]', i % 2)) = 0);
+    array::map(preimage, |p| (1 - LAST) * (p' - p) = 0);
+}
@@ -0,0 +1,130 @@
+use std::array;
+use std::utils::sum;
+use std::utils::force_bool;
+
+// Implements the SHA-256 compression function, computing one round per row.
+machine SHA256(LASTBLOCK, operation_id) {
+
+    // Compresses a block of 16 message words into the hash state of 8 words
+    // and returns the new hash state. The message words are the big-endian
+    // 32-bit words of the 64-byte block, as in the SHA-256 specification.
+    // The inputs are expected to be 32-bit words.
+    operation sha256_compress<0> state_in[0], state_in[1], state_in[2], state_in[3], state_in[4], state_in[5], state_in[6], state_in[7], block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7], block[8], block[9], block[10], block[11], block[12], block[13], block[14], block[15] -> state_out[0], state_out[1], state_out[2], state_out[3], state_out[4], state_out[5], state_out[6], state_out[7];
+
+    col witness operation_id;
+
+    // Each block has 64 rows for the rounds and one row holding the output.
+    // Bitwise operations are done on bit columns, while additions are done on
+    // words and range-checked by decomposing the sum (modulo 2**32) into bits.
+
+    constant %rounds = 64;
+    constant %rowsPerBlock = %rounds + 1;
+
+    pol constant FIRSTBLOCK(i) { match i % %rowsPerBlock {
+        0 => 1,
+        _ => 0
+    }};
+    pol constant LASTBLOCK(i) { match i % %rowsPerBlock {
+        %rowsPerBlock - 1 => 1,
+        _ => 0
+    }};
+    // Like LASTBLOCK, but also 1 in the last row of the table
+    // Specified this way because we can't access the degree in the match statement
+    pol constant LAST = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]* + [1];
+    // 1 in the row of the last round, where the input state is added to the working variables
+    pol constant LASTROUND(i) { match i % %rowsPerBlock {
+        %rounds - 1 => 1,
+        _ => 0
+    }};
+
+    // The round constants
+    pol constant ROUND_CONSTANT = [0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2, 0]*;
+
+    let xor = |a, b| a + b - 2 * a * b;
+    let xor3 = |a, b, c| xor(xor(a, b), c);
+    let word: (int -> expr) -> expr = |bits| sum(32, |z| bits(z) * 2**z);
+    // Bit z of the word with bits `bits`, rotated right by n
+    let rotr = |bits, n, z| bits((z + n) % 32);
+    // Bit z of the word with bits `bits`, shifted right by n
+    let shr = |bits, n, z| if z + n < 32 { bits(z + n) } else { 0 };
+
+    // The input, constant throughout the block.
+    pol commit state_in[8];
+    pol commit block[16];
+
+    // The working variables a, ..., h at the beginning of the round,
+    // bit z of the i-th variable is state[32 * i + z].
+    // In the last row of the block, they hold the output state.
+    pol commit state[256];
+    // The output state as words.
+    pol commit state_out[8];
+    // The (3-bit) carries of the additions computing the working variables of the next row.
+    pol commit carry[24];
+
+    // The message schedule: w[k] is the message word W_(t + k) in round t.
+    pol commit w[16];
+    // The bits of w[0], w[1] and w[14]. Decomposing w[0] also range-checks the
+    // first message word, which is never in one of the other positions.
+    pol commit w0_bits[32];
+    pol commit w1_bits[32];
+    pol commit w14_bits[32];
+    // The bits of W_(t + 16) and the (2-bit) carry of the addition computing it.
+    pol commit w_new_bits[32];
+    pol commit w_carry[2];
+
+    array::map(state, force_bool);
+    array::map(carry, force_bool);
+    array::map(w0_bits, force_bool);
+    array::map(w1_bits, force_bool);
+    array::map(w14_bits, force_bool);
+    array::map(w_new_bits, force_bool);
+    array::map(w_carry, force_bool);
+
+    let var_bits: int -> (int -> expr) = |i| |z| state[32 * i + z];
+    let var: int -> expr = |i| word(var_bits(i));
+    let next_var: int -> expr = |i| word(|z| state[32 * i + z]');
+    let carry_value: int -> expr = |i| carry[3 * i] + 2 * carry[3 * i + 1] + 4 * carry[3 * i + 2];
+
+    array::new(8, |i| state_out[i] = var(i));
+
+    // The message schedule
+    w[0] = word(|z| w0_bits[z]);
+    let w1: int -> expr = |z| w1_bits[z];
+    let w14: int -> expr = |z| w14_bits[z];
+    w[1] = word(w1);
+    w[14] = word(w14);
+    let sigma0 = word(|z| xor3(rotr(w1, 7, z), rotr(w1, 18, z), shr(w1, 3, z)));
+    let sigma1 = word(|z| xor3(rotr(w14, 17, z), rotr(w14, 19, z), shr(w14, 10, z)));
+    word(|z| w_new_bits[z]) + 2**32 * (w_carry[0] + 2 * w_carry[1]) = sigma1 + w[9] + sigma0 + w[0];
+    array::new(15, |k| (1 - LAST) * (w[k]' - w[k + 1]) = 0);
+    (1 - LAST) * (w[15]' - word(|z| w_new_bits[z])) = 0;
+
+    // The round
+    let a = var_bits(0);
+    let b = var_bits(1);
+    let c = var_bits(2);
+    let e = var_bits(4);
+    let f = var_bits(5);
+    let g = var_bits(6);
+    let big_sigma0 = word(|z| xor3(rotr(a, 2, z), rotr(a, 13, z), rotr(a, 22, z)));
+    let big_sigma1 = word(|z| xor3(rotr(e, 6, z), rotr(e, 11, z), rotr(e, 25, z)));
+    let ch = word(|z| e(z) * f(z) + (1 - e(z)) * g(z));
+    let maj = word(|z| a(z) * b(z) + a(z) * c(z) + b(z) * c(z) - 2 * a(z) * b(z) * c(z));
+    let t1 = var(7) + big_sigma1 + ch + ROUND_CONSTANT + w[0];
+    let t2 = big_sigma0 + maj;
+    let round_output: int -> expr = |i| match i {
+        0 => t1 + t2,
+        4 => var(3) + t1,
+        _ => var(i - 1)
+    };
+
+    // The working variables of the next row are the output of the round,
+    // plus the input state after the last round.
+    array::new(8, |i| (1 - LAST) * (next_var(i) + 2**32 * carry_value(i) - round_output(i) - LASTROUND * state_in[i]) = 0);
+
+    // The input is the state and the message schedule of the first round.
+    array::new(8, |i| FIRSTBLOCK * (state_in[i] - var(i)) = 0);
+    array::new(16, |k| FIRSTBLOCK * (block[k] - w[k]) = 0);
+    array::map(state_in, |s| (1 - LAST) * (s' - s) = 0);
+    array::map(block, |m| (1 - LAST) * (m' - m) = 0);
+}
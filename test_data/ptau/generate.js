@@ -0,0 +1,131 @@
+// Writes a powers-of-tau file for the bn254 curve in the format of snarkjs
+// (`snarkjs powersoftau new bn128 4`, with a known secret), including all of its sections:
+//   node generate.js > bn254_4.ptau
+// Curve points are stored as uncompressed affine coordinates in Montgomery form.
+
+const q = 21888242871839275222246405745257275088696311157297823662689037894645226208583n;
+const r = 21888242871839275222246405745257275088548364400416034343698204186575808495617n;
+
+const POWER = 4;
+// The secrets, which are only known because this is a test fixture.
+const TAU = 7n;
+const ALPHA = 11n;
+const BETA = 13n;
+
+const mod = (a, m = q) => ((a % m) + m) % m;
+const pow = (b, e, m = q) => {
+  let res = 1n;
+  b = mod(b, m);
+  while (e > 0n) {
+    if (e & 1n) res = (res * b) % m;
+    b = (b * b) % m;
+    e >>= 1n;
+  }
+  return res;
+};
+const inv = (a) => pow(a, q - 2n);
+
+// Fq and Fq2 = Fq[i] / (i^2 + 1).
+const F1 = {
+  zero: 0n,
+  one: 1n,
+  add: (a, b) => mod(a + b),
+  sub: (a, b) => mod(a - b),
+  mul: (a, b) => mod(a * b),
+  inv,
+  isZero: (a) => a === 0n,
+  eq: (a, b) => a === b,
+};
+const F2 = {
+  zero: [0n, 0n],
+  one: [1n, 0n],
+  add: (a, b) => [mod(a[0] + b[0]), mod(a[1] + b[1])],
+  sub: (a, b) => [mod(a[0] - b[0]), mod(a[1] - b[1])],
+  mul: (a, b) => [mod(a[0] * b[0] - a[1] * b[1]), mod(a[0] * b[1] + a[1] * b[0])],
+  inv: (a) => {
+    const n = inv(mod(a[0] * a[0] + a[1] * a[1]));
+    return [mod(a[0] * n), mod(-a[1] * n)];
+  },
+  isZero: (a) => a[0] === 0n && a[1] === 0n,
+  eq: (a, b) => a[0] === b[0] && a[1] === b[1],
+};
+
+// Affine points, `null` is the point at infinity.
+function add(F, p, s) {
+  if (p === null) return s;
+  if (s === null) return p;
+  let l;
+  if (F.eq(p[0], s[0])) {
+    if (!F.eq(p[1], s[1]) || F.isZero(p[1])) return null;
+    const x2 = F.mul(p[0], p[0]);
+    l = F.mul(F.add(F.add(x2, x2), x2), F.inv(F.add(p[1], p[1])));
+  } else {
+    l = F.mul(F.sub(s[1], p[1]), F.inv(F.sub(s[0], p[0])));
+  }
+  const x = F.sub(F.sub(F.mul(l, l), p[0]), s[0]);
+  return [x, F.sub(F.mul(l, F.sub(p[0], x)), p[1])];
+}
+function mul(F, p, k) {
+  let res = null;
+  for (; k > 0n; k >>= 1n) {
+    if (k & 1n) res = add(F, res, p);
+    p = add(F, p, p);
+  }
+  return res;
+}
+
+const G1 = [1n, 2n];
+const G2 = [
+  [
+    10857046999023057135944570762232829481370756359578518086990519993285655852781n,
+    11559732032986387107991004021392285783925812861821192530917403151452391805634n,
+  ],
+  [
+    8495653923123431417604973247489272438418190587263600148770280649306958101930n,
+    4082367875863433681332203403145435568316851327593401208105741076214120093531n,
+  ],
+];
+
+const R = pow(2n, 256n);
+const u32 = (x) => {
+  const b = Buffer.alloc(4);
+  b.writeUInt32LE(x);
+  return b;
+};
+const u64 = (x) => {
+  const b = Buffer.alloc(8);
+  b.writeBigUInt64LE(BigInt(x));
+  return b;
+};
+const le = (x) => {
+  const b = Buffer.alloc(32);
+  for (let i = 0; i < 32; i++) b[i] = Number((x >> BigInt(8 * i)) & 0xffn);
+  return b;
+};
+const fq = (x) => le(mod(x * R));
+const g1 = (p) => Buffer.concat([fq(p[0]), fq(p[1])]);
+const g2 = (p) => Buffer.concat([fq(p[0][0]), fq(p[0][1]), fq(p[1][0]), fq(p[1][1])]);
+
+const powers = (n, factor) =>
+  Array.from({ length: n }, (_, i) => mod(factor * pow(TAU, BigInt(i), r), r));
+const n = 1 << POWER;
+
+const sections = [
+  [1, Buffer.concat([u32(32), le(q), u32(POWER), u32(POWER)])],
+  [2, Buffer.concat(powers(2 * n - 1, 1n).map((k) => g1(mul(F1, G1, k))))],
+  [3, Buffer.concat(powers(n, 1n).map((k) => g2(mul(F2, G2, k))))],
+  [4, Buffer.concat(powers(n, ALPHA).map((k) => g1(mul(F1, G1, k))))],
+  [5, Buffer.concat(powers(n, BETA).map((k) => g1(mul(F1, G1, k))))],
+  [6, g2(mul(F2, G2, BETA))],
+  // No contributions.
+  [7, u32(0)],
+];
+
+process.stdout.write(
+  Buffer.concat([
+    Buffer.from("ptau"),
+    u32(1),
+    u32(sections.length),
+    ...sections.flatMap(([type, data]) => [u32(type), u64(data.length), data]),
+  ])
+);
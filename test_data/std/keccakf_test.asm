@@ -0,0 +1,135 @@
+use std::hash::keccakf::KeccakF;
+
+machine Main {
+    degree 256;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg S0;
+    reg S1;
+    reg S2;
+    reg S3;
+    reg S4;
+    reg S5;
+    reg S6;
+    reg S7;
+    reg S8;
+    reg S9;
+    reg S10;
+    reg S11;
+    reg S12;
+    reg S13;
+    reg S14;
+    reg S15;
+    reg S16;
+    reg S17;
+    reg S18;
+    reg S19;
+    reg S20;
+    reg S21;
+    reg S22;
+    reg S23;
+    reg S24;
+    reg S25;
+    reg S26;
+    reg S27;
+    reg S28;
+    reg S29;
+    reg S30;
+    reg S31;
+    reg S32;
+    reg S33;
+    reg S34;
+    reg S35;
+    reg S36;
+    reg S37;
+    reg S38;
+    reg S39;
+    reg S40;
+    reg S41;
+    reg S42;
+    reg S43;
+    reg S44;
+    reg S45;
+    reg S46;
+    reg S47;
+    reg S48;
+    reg S49;
+
+    KeccakF keccakf;
+
+    // The state is kept in the registers S0, ..., S49, two 32-bit limbs per lane.
+    instr keccakf = keccakf.keccakf S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49 -> S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49;
+
+    instr assert_eq X, Y {
+        X = Y
+    }
+
+    function main {
+
+        // See test vectors at:
+        // https://github.com/XKCP/XKCP/blob/master/tests/TestVectors/KeccakF-1600-IntermediateValues.txt
+        // The registers are initialized to zero, i.e. this permutes the zero state.
+        keccakf;
+        assert_eq S0, 0x40e1dde7;
+        assert_eq S1, 0xf1258f79;
+        assert_eq S2, 0x33c0478a;
+        assert_eq S3, 0x84d5ccf9;
+        assert_eq S4, 0xa65aa9ee;
+        assert_eq S5, 0xd598261e;
+        assert_eq S6, 0x6f80494d;
+        assert_eq S7, 0xbd154730;
+        assert_eq S8, 0x6253d057;
+        assert_eq S9, 0x8b284e05;
+        assert_eq S10, 0x7f8e6fd4;
+        assert_eq S11, 0xff97a42d;
+        assert_eq S12, 0xa44647c4;
+        assert_eq S13, 0x90fee5a0;
+        assert_eq S14, 0xd6192e76;
+        assert_eq S15, 0x8c5bda0c;
+        assert_eq S16, 0x1b19059c;
+        assert_eq S17, 0xad30a6f7;
+        assert_eq S18, 0xd08ffc64;
+        assert_eq S19, 0x30935ab7;
+        assert_eq S20, 0x2317d635;
+        assert_eq S21, 0xeb5aa93f;
+        assert_eq S22, 0x0d712103;
+        assert_eq S23, 0xa9a6e626;
+        assert_eq S24, 0xdbcf555f;
+        assert_eq S25, 0x81a57c16;
+        assert_eq S26, 0x0347c826;
+        assert_eq S27, 0x43b831cd;
+        assert_eq S28, 0x11a5569f;
+        assert_eq S29, 0x01f22f1a;
+        assert_eq S30, 0x21d9ae61;
+        assert_eq S31, 0x05e5635a;
+        assert_eq S32, 0x8cc970f2;
+        assert_eq S33, 0x64befef2;
+        assert_eq S34, 0x7bc46611;
+        assert_eq S35, 0x61367095;
+        assert_eq S36, 0x4fd00ecb;
+        assert_eq S37, 0xb87c5a55;
+        assert_eq S38, 0x1ccf32c8;
+        assert_eq S39, 0x8c3ee88a;
+        assert_eq S40, 0xae3a2614;
+        assert_eq S41, 0x940c7922;
+        assert_eq S42, 0xa2c509e4;
+        assert_eq S43, 0x1841f924;
+        assert_eq S44, 0xe70465c2;
+        assert_eq S45, 0x16f53526;
+        assert_eq S46, 0x7f30a13b;
+        assert_eq S47, 0x75f644e9;
+        assert_eq S48, 0x5ceca249;
+        assert_eq S49, 0xeaf1ff7b;
+
+        // Permuting the result again.
+        keccakf;
+        assert_eq S0, 0xf96ecb3c;
+        assert_eq S1, 0x2d5c954d;
+        assert_eq S2, 0x7057b56d;
+        assert_eq S3, 0x6a332cd0;
+
+        return;
+    }
+}
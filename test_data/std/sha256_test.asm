@@ -0,0 +1,113 @@
+use std::hash::sha256::SHA256;
+
+machine Main {
+    degree 256;
+
+    reg pc[@pc];
+    reg X[<=];
+    reg Y[<=];
+    reg H0;
+    reg H1;
+    reg H2;
+    reg H3;
+    reg H4;
+    reg H5;
+    reg H6;
+    reg H7;
+    reg M0;
+    reg M1;
+    reg M2;
+    reg M3;
+    reg M4;
+    reg M5;
+    reg M6;
+    reg M7;
+    reg M8;
+    reg M9;
+    reg M10;
+    reg M11;
+    reg M12;
+    reg M13;
+    reg M14;
+    reg M15;
+
+    SHA256 sha256;
+
+    // The hash state is kept in the registers H0, ..., H7 and the message block in M0, ..., M15.
+    instr sha256_compress = sha256.sha256_compress H0, H1, H2, H3, H4, H5, H6, H7, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12, M13, M14, M15 -> H0, H1, H2, H3, H4, H5, H6, H7;
+
+    instr assert_eq X, Y {
+        X = Y
+    }
+
+    function main {
+
+        // See test vectors at:
+        // https://csrc.nist.gov/csrc/media/projects/cryptographic-standards-and-guidelines/documents/examples/sha256.pdf
+        // Initial hash value
+        H0 <=X= 0x6a09e667;
+        H1 <=X= 0xbb67ae85;
+        H2 <=X= 0x3c6ef372;
+        H3 <=X= 0xa54ff53a;
+        H4 <=X= 0x510e527f;
+        H5 <=X= 0x9b05688c;
+        H6 <=X= 0x1f83d9ab;
+        H7 <=X= 0x5be0cd19;
+
+        // First block of the padded message "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        M0 <=X= 0x61626364;
+        M1 <=X= 0x62636465;
+        M2 <=X= 0x63646566;
+        M3 <=X= 0x64656667;
+        M4 <=X= 0x65666768;
+        M5 <=X= 0x66676869;
+        M6 <=X= 0x6768696a;
+        M7 <=X= 0x68696a6b;
+        M8 <=X= 0x696a6b6c;
+        M9 <=X= 0x6a6b6c6d;
+        M10 <=X= 0x6b6c6d6e;
+        M11 <=X= 0x6c6d6e6f;
+        M12 <=X= 0x6d6e6f70;
+        M13 <=X= 0x6e6f7071;
+        M14 <=X= 0x80000000;
+        M15 <=X= 0x00000000;
+        sha256_compress;
+        assert_eq H0, 0x85e655d6;
+        assert_eq H1, 0x417a1795;
+        assert_eq H2, 0x3363376a;
+        assert_eq H3, 0x624cde5c;
+        assert_eq H4, 0x76e09589;
+        assert_eq H5, 0xcac5f811;
+        assert_eq H6, 0xcc4b32c1;
+        assert_eq H7, 0xf20e533a;
+
+        // Second block, resulting in the hash of the message
+        M0 <=X= 0x00000000;
+        M1 <=X= 0x00000000;
+        M2 <=X= 0x00000000;
+        M3 <=X= 0x00000000;
+        M4 <=X= 0x00000000;
+        M5 <=X= 0x00000000;
+        M6 <=X= 0x00000000;
+        M7 <=X= 0x00000000;
+        M8 <=X= 0x00000000;
+        M9 <=X= 0x00000000;
+        M10 <=X= 0x00000000;
+        M11 <=X= 0x00000000;
+        M12 <=X= 0x00000000;
+        M13 <=X= 0x00000000;
+        M14 <=X= 0x00000000;
+        M15 <=X= 0x000001c0;
+        sha256_compress;
+        assert_eq H0, 0x248d6a61;
+        assert_eq H1, 0xd20638b8;
+        assert_eq H2, 0xe5c02693;
+        assert_eq H3, 0x0c3e6039;
+        assert_eq H4, 0xa33ce459;
+        assert_eq H5, 0x64ff2167;
+        assert_eq H6, 0xf6ecedd4;
+        assert_eq H7, 0x19db06c1;
+
+        return;
+    }
+}